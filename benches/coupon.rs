@@ -0,0 +1,67 @@
+//! The hot path of the coupon lookups and writes, without a database: `cargo bench --bench coupon`.
+use chrono::{Duration, Utc};
+use coupon_api::coupon::{
+    coupon_repository_memory::InMemoryCouponStore,
+    coupon_service,
+    coupon_store::CouponStore,
+    Coupon, CouponDiscount, CouponInsert, CouponInsertRequest, CouponResponse, Cursor,
+};
+use coupon_api::feature_flags::FeatureFlags;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::collections::BTreeMap;
+use tokio::runtime::Runtime;
+
+
+fn insert_request() -> CouponInsertRequest {
+    return CouponInsertRequest {
+        code: "BENCH10".to_string(),
+        discount: 10,
+        active: true,
+        max_usage_count: Some(100),
+        expiration_date: Some(Utc::now().naive_utc() + Duration::days(30)),
+    };
+}
+
+fn coupon() -> Coupon {
+    let coupon = CouponInsert::try_from(insert_request()).unwrap();
+    return Coupon::inserted(1, coupon, Utc::now().naive_utc());
+}
+
+fn validation(c: &mut Criterion) {
+    c.bench_function("discount parse", |b| b.iter(|| CouponDiscount::parse(black_box(10))));
+    c.bench_function("discount parse rejected", |b| b.iter(|| CouponDiscount::parse(black_box(91))));
+    c.bench_function("cursor decode", |b| {
+        let cursor = Cursor::After(123_456).encode();
+        b.iter(|| Cursor::decode(black_box(&cursor)))
+    });
+}
+
+fn conversions(c: &mut Criterion) {
+    c.bench_function("insert request to coupon", |b| b.iter(|| CouponInsert::try_from(black_box(insert_request()))));
+    c.bench_function("coupon to response", |b| b.iter(|| CouponResponse::try_from(black_box(coupon()))));
+    c.bench_function("response to json", |b| {
+        let response = CouponResponse::try_from(coupon()).unwrap();
+        b.iter(|| serde_json::to_vec(black_box(&response)))
+    });
+}
+
+fn service(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let store = InMemoryCouponStore::new();
+    runtime.block_on(async {
+        let mut transaction = store.begin().await.unwrap();
+        transaction.insert(CouponInsert::try_from(insert_request()).unwrap()).await.unwrap();
+        transaction.commit().await.unwrap();
+    });
+    let flags = FeatureFlags::new(BTreeMap::new());
+
+    c.bench_function("get coupon by code", |b| {
+        b.to_async(&runtime).iter(|| coupon_service::get_by_id_or_code(black_box("BENCH10".to_string()), &store))
+    });
+    c.bench_function("verify coupon", |b| {
+        b.to_async(&runtime).iter(|| coupon_service::is_valid(black_box("BENCH10".to_string()), &flags, &store))
+    });
+}
+
+criterion_group!(benches, validation, conversions, service);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+CREATE TABLE webhooks (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  url varchar(2048) NOT NULL,
+  -- comma separated list of the subscribed events, e.g. `coupon.created,coupon.updated`
+  events varchar(255) NOT NULL,
+  -- used to sign the payloads, so receivers can verify they were sent by us
+  secret varchar(255) NOT NULL,
+  active BOOLEAN NOT NULL DEFAULT 1,
+  date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  date_updated TIMESTAMP NULL DEFAULT NULL ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (id)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci
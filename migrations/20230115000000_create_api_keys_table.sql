@@ -0,0 +1,11 @@
+CREATE TABLE api_keys (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  -- identifies the system using the key, e.g. `storefront`
+  name varchar(255) NOT NULL,
+  api_key varchar(255) NOT NULL,
+  date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  -- revoked keys are kept for auditing, but can't be used to authenticate anymore
+  date_revoked TIMESTAMP NULL DEFAULT NULL,
+  PRIMARY KEY (id),
+  UNIQUE KEY api_key (api_key)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci
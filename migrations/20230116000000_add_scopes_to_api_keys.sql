@@ -0,0 +1,4 @@
+-- comma separated list of the scopes granted to the key, e.g. `coupon:read,coupon:redeem`
+ALTER TABLE api_keys ADD COLUMN scopes varchar(255) NOT NULL DEFAULT '' AFTER api_key;
+-- the keys issued before scopes existed had access to everything
+UPDATE api_keys SET scopes = 'admin,coupon:read,coupon:redeem,coupon:write';
@@ -0,0 +1,3 @@
+ALTER TABLE api_keys ADD COLUMN role varchar(32) NOT NULL DEFAULT 'readonly' AFTER api_key;
+-- the keys issued before roles existed had access to everything
+UPDATE api_keys SET role = 'admin';
@@ -0,0 +1,11 @@
+CREATE TABLE users (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  username varchar(255) NOT NULL,
+  -- argon2 PHC string, which includes the salt and the parameters
+  password_hash varchar(255) NOT NULL,
+  role varchar(32) NOT NULL DEFAULT 'readonly',
+  date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  date_updated TIMESTAMP NULL DEFAULT NULL ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (id),
+  UNIQUE KEY username (username)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci
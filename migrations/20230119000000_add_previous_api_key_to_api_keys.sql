@@ -0,0 +1,3 @@
+-- after a rotation the previous key keeps working until `previous_api_key_expires_at`
+ALTER TABLE api_keys ADD COLUMN previous_api_key varchar(255) NULL DEFAULT NULL AFTER api_key;
+ALTER TABLE api_keys ADD COLUMN previous_api_key_expires_at TIMESTAMP NULL DEFAULT NULL AFTER previous_api_key;
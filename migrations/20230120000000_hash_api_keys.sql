@@ -0,0 +1,29 @@
+-- keep only a salted hash of the keys, in the `sha256$<salt>$<hash>` format of `ApiKeyHash`.
+-- the first characters of the key are kept in `key_prefix`, to find the key without knowing its salt
+ALTER TABLE api_keys
+  ADD COLUMN key_prefix varchar(16) NOT NULL DEFAULT '' AFTER name,
+  ADD COLUMN key_hash varchar(255) NOT NULL DEFAULT '' AFTER key_prefix,
+  ADD COLUMN previous_key_prefix varchar(16) NULL DEFAULT NULL AFTER key_hash,
+  ADD COLUMN previous_key_hash varchar(255) NULL DEFAULT NULL AFTER previous_key_prefix;
+
+-- the salt is stored in the hash columns first, then used to hash the keys
+UPDATE api_keys SET
+  key_prefix = LEFT(api_key, 8),
+  key_hash = REPLACE(UUID(), '-', '');
+UPDATE api_keys SET
+  key_hash = CONCAT('sha256$', key_hash, '$', SHA2(CONCAT(key_hash, api_key), 256));
+
+UPDATE api_keys SET
+  previous_key_prefix = LEFT(previous_api_key, 8),
+  previous_key_hash = REPLACE(UUID(), '-', '')
+  WHERE previous_api_key IS NOT NULL;
+UPDATE api_keys SET
+  previous_key_hash = CONCAT('sha256$', previous_key_hash, '$', SHA2(CONCAT(previous_key_hash, previous_api_key), 256))
+  WHERE previous_api_key IS NOT NULL;
+
+ALTER TABLE api_keys
+  DROP INDEX api_key,
+  DROP COLUMN api_key,
+  DROP COLUMN previous_api_key,
+  ADD INDEX key_prefix (key_prefix),
+  ADD INDEX previous_key_prefix (previous_key_prefix);
@@ -0,0 +1,3 @@
+-- comma separated list of the CIDR ranges the key can be used from, e.g. `10.0.0.0/8,203.0.113.7/32`
+-- `NULL` allows any IP, as the keys issued before the allowlist existed
+ALTER TABLE api_keys ADD COLUMN allowed_ips varchar(1024) NULL DEFAULT NULL AFTER scopes;
@@ -0,0 +1,2 @@
+-- secret of the HMAC signed requests, the keys issued before have none and can only be used on `/auth`
+ALTER TABLE api_keys ADD COLUMN signing_secret varchar(255) NULL DEFAULT NULL AFTER allowed_ips;
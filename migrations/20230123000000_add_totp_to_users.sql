@@ -0,0 +1,6 @@
+-- two-factor authentication of `/login`, the hex of the secret is set on `/account/totp`
+-- and only checked once a code of it was verified on `/account/totp/verify`
+ALTER TABLE users ADD COLUMN totp_secret varchar(64) NULL DEFAULT NULL AFTER role;
+ALTER TABLE users ADD COLUMN totp_enabled tinyint(1) NOT NULL DEFAULT 0 AFTER totp_secret;
+-- time step of the last code accepted, so a code can't be used again
+ALTER TABLE users ADD COLUMN totp_last_step bigint NULL DEFAULT NULL AFTER totp_enabled;
@@ -0,0 +1,17 @@
+CREATE TABLE auth_audit_log (
+  id bigint(20) NOT NULL AUTO_INCREMENT,
+  -- `authenticate`, `login` or `logout`
+  event varchar(32) NOT NULL,
+  -- `success`, `failure` or `locked_out`
+  outcome varchar(32) NOT NULL,
+  -- who tried to authenticate, e.g. `api_key:<prefix>` or `user:<username>`
+  identifier varchar(255) NULL DEFAULT NULL,
+  -- the issued API key, when it was found
+  api_key_id int(11) NULL DEFAULT NULL,
+  ip varchar(64) NULL DEFAULT NULL,
+  user_agent varchar(512) NULL DEFAULT NULL,
+  date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (id),
+  KEY date_created (date_created),
+  KEY identifier (identifier)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci
@@ -0,0 +1,2 @@
+-- bumped on every update, an update with an outdated `version` is rejected instead of overwriting a concurrent one
+ALTER TABLE coupon ADD COLUMN version int(11) NOT NULL DEFAULT 1 AFTER active;
@@ -0,0 +1,18 @@
+-- the coupons expired for longer than `archive.retention_days`, moved out of `coupon` by the archival job
+CREATE TABLE coupons_archive (
+  archive_id bigint(20) NOT NULL AUTO_INCREMENT,
+  -- the `id` the coupon had, a new coupon can reuse its `code`
+  id int(11) NOT NULL,
+  code varchar(255) NOT NULL,
+  discount int(11) NOT NULL,
+  max_usage_count int(11) NULL,
+  expiration_date DATETIME NULL,
+  active BOOLEAN NOT NULL,
+  version int(11) NOT NULL,
+  date_created DATETIME NOT NULL,
+  date_updated TIMESTAMP NULL DEFAULT NULL,
+  date_archived TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (archive_id),
+  KEY id (id),
+  KEY code (code)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci
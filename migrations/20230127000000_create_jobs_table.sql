@@ -0,0 +1,20 @@
+-- the deferred work (e.g. webhook deliveries), run by the job worker of any instance
+CREATE TABLE jobs (
+  id bigint(20) NOT NULL AUTO_INCREMENT,
+  -- what the worker runs, e.g. `webhook_delivery`
+  kind varchar(64) NOT NULL,
+  -- the JSON arguments of the kind
+  payload TEXT NOT NULL,
+  -- `pending`, `running`, `succeeded` or `failed`
+  status varchar(16) NOT NULL DEFAULT 'pending',
+  attempts int(11) NOT NULL DEFAULT 0,
+  -- when a pending job is due, later after a failed attempt
+  run_at DATETIME NOT NULL,
+  -- a running job not finished by then is taken over by another worker
+  locked_until DATETIME NULL,
+  last_error TEXT NULL,
+  date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  date_updated TIMESTAMP NULL DEFAULT NULL ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (id),
+  KEY status_run_at (status, run_at)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci
@@ -0,0 +1,18 @@
+-- every attempt to deliver a webhook, listed on `GET /webhooks/{id}/deliveries`
+CREATE TABLE webhook_deliveries (
+  id bigint(20) NOT NULL AUTO_INCREMENT,
+  webhook_id int(11) NOT NULL,
+  -- the `webhook_delivery` job, also sent in the `X-Webhook-Delivery` header: the same for every attempt of a delivery
+  job_id bigint(20) NOT NULL,
+  event varchar(64) NOT NULL,
+  attempt int(11) NOT NULL,
+  -- NULL when the receiver didn't respond (e.g. timeout, connection refused)
+  status_code int(11) NULL,
+  succeeded BOOLEAN NOT NULL,
+  error TEXT NULL,
+  duration_milliseconds int(11) NOT NULL,
+  date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (id),
+  KEY webhook_id (webhook_id, id),
+  KEY date_created (date_created)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci
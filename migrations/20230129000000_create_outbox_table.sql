@@ -0,0 +1,15 @@
+-- the coupon events, written in the transaction of the change and published by the relay (`events::outbox`)
+CREATE TABLE outbox (
+  id bigint(20) NOT NULL AUTO_INCREMENT,
+  -- e.g. `coupon.created`
+  event varchar(64) NOT NULL,
+  -- the coupon it is about, the key of the message
+  event_key varchar(255) NOT NULL,
+  -- the JSON `EventMessage`
+  payload TEXT NOT NULL,
+  date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  -- NULL until published
+  published_at DATETIME NULL,
+  PRIMARY KEY (id),
+  KEY published_at (published_at, id)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci
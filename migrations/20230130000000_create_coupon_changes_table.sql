@@ -0,0 +1,32 @@
+-- every write of a coupon, for the changes feed (`GET /coupon/changes`), written by the triggers below
+-- so the deletions (including the archival) are listed too
+CREATE TABLE coupon_changes (
+  seq bigint(20) NOT NULL AUTO_INCREMENT,
+  coupon_id int(11) NOT NULL,
+  code varchar(255) NOT NULL,
+  changed_at DATETIME NOT NULL,
+  PRIMARY KEY (seq),
+  KEY changed_at (changed_at)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci;
+
+-- the existing coupons, so a feed read from the start has every coupon
+INSERT INTO coupon_changes (coupon_id, code, changed_at)
+SELECT id, code, COALESCE(date_updated, date_created) FROM coupon ORDER BY COALESCE(date_updated, date_created), id;
+
+CREATE TRIGGER on_after_insert_coupon_changes
+    AFTER INSERT
+    ON coupon
+    FOR EACH ROW
+    INSERT INTO coupon_changes (coupon_id, code, changed_at) VALUES (new.id, new.code, NOW());
+
+CREATE TRIGGER on_after_update_coupon_changes
+    AFTER UPDATE
+    ON coupon
+    FOR EACH ROW
+    INSERT INTO coupon_changes (coupon_id, code, changed_at) VALUES (new.id, new.code, NOW());
+
+CREATE TRIGGER on_after_delete_coupon_changes
+    AFTER DELETE
+    ON coupon
+    FOR EACH ROW
+    INSERT INTO coupon_changes (coupon_id, code, changed_at) VALUES (old.id, old.code, NOW());
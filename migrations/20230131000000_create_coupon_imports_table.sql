@@ -0,0 +1,27 @@
+-- the CSV files of `POST /coupon/import`, imported by an `import_coupons` job
+CREATE TABLE coupon_imports (
+  id bigint(20) NOT NULL AUTO_INCREMENT,
+  -- emptied once imported
+  csv MEDIUMTEXT NOT NULL,
+  -- without the header row
+  total_rows int(11) NOT NULL,
+  -- imported or rejected, a retried job resumes after them
+  processed_rows int(11) NOT NULL DEFAULT 0,
+  imported_rows int(11) NOT NULL DEFAULT 0,
+  rejected_rows int(11) NOT NULL DEFAULT 0,
+  date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  date_finished TIMESTAMP NULL DEFAULT NULL,
+  PRIMARY KEY (id)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci;
+
+-- why the rejected rows were not imported
+CREATE TABLE coupon_import_errors (
+  id bigint(20) NOT NULL AUTO_INCREMENT,
+  import_id bigint(20) NOT NULL,
+  -- of the file, the header is line 1
+  line bigint(20) NOT NULL,
+  code varchar(255) NULL,
+  error TEXT NOT NULL,
+  PRIMARY KEY (id),
+  KEY import_id_line (import_id, line)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci;
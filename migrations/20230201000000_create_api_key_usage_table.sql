@@ -0,0 +1,11 @@
+-- requests made with each issued API key, added to every `api_keys.usage_flush_interval_seconds` by each instance
+CREATE TABLE api_key_usage (
+  api_key_id int(11) NOT NULL,
+  requests bigint(20) UNSIGNED NOT NULL DEFAULT 0,
+  -- responses with a 4xx status
+  client_errors bigint(20) UNSIGNED NOT NULL DEFAULT 0,
+  -- responses with a 5xx status
+  server_errors bigint(20) UNSIGNED NOT NULL DEFAULT 0,
+  last_used_at TIMESTAMP NULL DEFAULT NULL,
+  PRIMARY KEY (api_key_id)
+) ENGINE=InnoDB CHARSET=utf8 COLLATE=utf8_unicode_ci;
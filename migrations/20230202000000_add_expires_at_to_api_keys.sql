@@ -0,0 +1,2 @@
+-- the key can't be used anymore after it, `NULL` never expires, as the keys issued before expirations existed
+ALTER TABLE api_keys ADD COLUMN expires_at TIMESTAMP NULL DEFAULT NULL AFTER signing_secret;
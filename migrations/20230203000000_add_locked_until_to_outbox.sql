@@ -0,0 +1,2 @@
+-- the events claimed by a relay are skipped by the others until then, taken over if it stopped before publishing them
+ALTER TABLE outbox ADD COLUMN locked_until DATETIME NULL AFTER published_at;
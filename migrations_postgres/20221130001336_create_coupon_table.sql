@@ -0,0 +1,26 @@
+-- Postgres schema of the `coupon` table, used when `database.coupon_backend` is `postgres`.
+-- Matches `migrations/20221130001336_create_cupom_table.sql` and `migrations/20221220030820_create_triggers.sql`.
+CREATE TABLE coupon (
+  id SERIAL PRIMARY KEY,
+  code VARCHAR(255) NOT NULL UNIQUE,
+  discount INTEGER NOT NULL,
+  max_usage_count INTEGER NULL, -- not actually being used currently, we will also need a new field to track the `current usage` count for the coupon
+  expiration_date TIMESTAMP NULL,
+  active BOOLEAN NOT NULL DEFAULT TRUE,
+  date_created TIMESTAMP NOT NULL DEFAULT LOCALTIMESTAMP,
+  date_updated TIMESTAMP NULL DEFAULT NULL
+);
+
+-- Postgres has no `ON UPDATE CURRENT_TIMESTAMP`, the trigger sets `date_updated` instead
+CREATE FUNCTION set_coupon_date_updated() RETURNS TRIGGER AS $$
+BEGIN
+  NEW.date_updated = LOCALTIMESTAMP;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER on_before_update
+    BEFORE UPDATE
+    ON coupon
+    FOR EACH ROW
+    EXECUTE FUNCTION set_coupon_date_updated();
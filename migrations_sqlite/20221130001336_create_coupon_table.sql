@@ -0,0 +1,21 @@
+-- SQLite schema of the `coupon` table, used when `database.coupon_backend` is `sqlite`.
+-- Matches `migrations/20221130001336_create_cupom_table.sql` and `migrations/20221220030820_create_triggers.sql`.
+CREATE TABLE coupon (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  code VARCHAR(255) NOT NULL UNIQUE,
+  discount INTEGER NOT NULL,
+  max_usage_count INTEGER NULL, -- not actually being used currently, we will also need a new field to track the `current usage` count for the coupon
+  expiration_date DATETIME NULL,
+  active BOOLEAN NOT NULL DEFAULT 1,
+  date_created DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  date_updated DATETIME NULL DEFAULT NULL
+);
+
+-- SQLite has no `ON UPDATE CURRENT_TIMESTAMP`, the trigger sets `date_updated` instead
+CREATE TRIGGER on_after_update
+    AFTER UPDATE
+    ON coupon
+    FOR EACH ROW
+    BEGIN
+        UPDATE coupon SET date_updated = CURRENT_TIMESTAMP WHERE id = NEW.id;
+    END;
@@ -0,0 +1,2 @@
+-- Matches `migrations/20230125000000_add_version_to_coupon.sql`.
+ALTER TABLE coupon ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
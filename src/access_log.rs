@@ -0,0 +1,186 @@
+use crate::authentication::Session;
+use crate::client_ip::client_ip;
+use crate::configuration::{AccessLogFormat, AccessLogSettings};
+use crate::request_id;
+use actix_web::{
+    Error, HttpMessage,
+    body::{BodySize, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    future::{ready, Future, Ready},
+    io::Write,
+    pin::Pin,
+    rc::Rc,
+    time::Instant,
+};
+
+
+/// One line of the access log.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AccessLogEntry {
+    pub time: DateTime<Utc>,
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_milliseconds: f64,
+    // the issued API key of the session, `None` for the configured key, the logins and the routes not authenticated
+    pub api_key_id: Option<i32>,
+    // size of the response body, `None` when it is streamed
+    pub bytes: Option<u64>,
+    pub request_id: Option<String>,
+}
+
+impl AccessLogEntry {
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        return match format {
+            AccessLogFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+            // like the common log format of the web servers, `-` for the missing values
+            AccessLogFormat::Text => format!(
+                "{} {} [{}] \"{} {}\" {} {} {:.3}ms {}",
+                self.client_ip.as_deref().unwrap_or("-"),
+                self.api_key_id.map_or("-".to_string(), |id| id.to_string()),
+                self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+                self.method,
+                self.path,
+                self.status,
+                self.bytes.map_or("-".to_string(), |bytes| bytes.to_string()),
+                self.latency_milliseconds,
+                self.request_id.as_deref().unwrap_or("-"),
+            ),
+        };
+    }
+}
+
+/// Writes one line per request to stdout when `access_log.enabled`, apart from the logs of the tracing spans,
+/// in the `json` or `text` format of `access_log.format`.
+#[derive(Clone)]
+pub struct AccessLog {
+    settings: AccessLogSettings,
+}
+
+impl AccessLog {
+    pub fn new(settings: AccessLogSettings) -> Self {
+        return Self { settings };
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AccessLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AccessLogMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(AccessLogMiddleware { service: Rc::new(service), settings: self.settings.clone() }));
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: Rc<S>,
+    settings: AccessLogSettings,
+}
+
+impl<S, B> Service<ServiceRequest> for AccessLogMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        if (!self.settings.enabled){
+            return Box::pin(async move { service.call(request).await });
+        }
+        let format = self.settings.format;
+        let time = Utc::now();
+        let start = Instant::now();
+        let method = request.method().to_string();
+        let path = request.path().to_string();
+        // `X-Forwarded-For` is only read from the trusted proxies, see `client_ip`
+        let client_ip = client_ip(request.request()).map(|ip| ip.to_string());
+        return Box::pin(async move {
+            let result = service.call(request).await;
+            let (status, api_key_id, bytes, request_id) = match &result {
+                Ok(response) => (
+                    response.status().as_u16(),
+                    // set by the authentication, on the request shared with the handlers
+                    response.request().extensions().get::<Session>().and_then(|session| session.api_key_id),
+                    match response.response().body().size() {
+                        BodySize::Sized(bytes) => Some(bytes),
+                        BodySize::None => Some(0),
+                        BodySize::Stream => None,
+                    },
+                    request_id::get(response.request()),
+                ),
+                Err(error) => (error.as_response_error().status_code().as_u16(), None, None, None),
+            };
+            let entry = AccessLogEntry {
+                time,
+                client_ip,
+                method,
+                path,
+                status,
+                latency_milliseconds: start.elapsed().as_secs_f64() * 1000.0,
+                api_key_id,
+                bytes,
+                request_id,
+            };
+            // a single write per line, so the lines of concurrent requests don't interleave
+            let _ = std::io::stdout().lock().write_all(format!("{}\n", entry.format(format)).as_bytes());
+            return result;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AccessLogEntry;
+    use crate::configuration::AccessLogFormat;
+    use chrono::{TimeZone, Utc};
+
+    fn entry() -> AccessLogEntry {
+        return AccessLogEntry {
+            time: Utc.with_ymd_and_hms(2023, 1, 28, 10, 30, 0).unwrap(),
+            client_ip: Some("10.0.0.1".to_string()),
+            method: "GET".to_string(),
+            path: "/coupon/WELCOME10".to_string(),
+            status: 200,
+            latency_milliseconds: 12.5,
+            api_key_id: Some(7),
+            bytes: Some(231),
+            request_id: None,
+        };
+    }
+
+    #[test]
+    fn text_lines_are_like_the_common_log_format(){
+        assert_eq!(
+            entry().format(AccessLogFormat::Text),
+            "10.0.0.1 7 [28/Jan/2023:10:30:00 +0000] \"GET /coupon/WELCOME10\" 200 231 12.500ms -",
+        );
+    }
+
+    #[test]
+    fn json_lines_have_every_field(){
+        let line: serde_json::Value = serde_json::from_str(&entry().format(AccessLogFormat::Json)).unwrap();
+
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["api_key_id"], 7);
+        assert_eq!(line["bytes"], 231);
+        assert_eq!(line["request_id"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,56 @@
+use super::model::{ApiKeyCreateRequest, ApiKeyError, ApiKeyListQuery};
+use super::api_key_service;
+use super::api_key_usage::ApiKeyUsageRecorder;
+use crate::configuration::{ApiKeySettings, RequestSigningSettings};
+use crate::envelope::Envelope;
+use actix_web::{
+    web, get, post, delete, HttpRequest, HttpResponse,
+    web::Data,
+};
+use sqlx::MySqlPool;
+
+
+#[tracing::instrument( name = "Get all API keys", skip(pool, http_request) )]
+#[get("/api-keys")]
+pub async fn get_all_api_keys(http_request: HttpRequest, query: web::Query<ApiKeyListQuery>, pool: Data::<MySqlPool>) -> Result<HttpResponse, ApiKeyError> {
+    let api_keys = api_key_service::get_all(&query, &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, api_keys)));
+}
+
+#[tracing::instrument( name = "Get API key", skip(pool, http_request) )]
+#[get("/api-keys/{id}")]
+pub async fn get_api_key(http_request: HttpRequest, param: web::Path<i32>, pool: Data::<MySqlPool>) -> Result<HttpResponse, ApiKeyError> {
+    let api_key = api_key_service::get_by_id(param.into_inner(), &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, api_key)));
+}
+
+#[tracing::instrument( name = "Post API key", skip(pool, request_signing, http_request) )]
+#[post("/api-keys")]
+pub async fn add_api_key(http_request: HttpRequest, request: web::Json<ApiKeyCreateRequest>, request_signing: Data<RequestSigningSettings>, pool: Data::<MySqlPool>) -> Result<HttpResponse, ApiKeyError> {
+    let api_key = api_key_service::insert(request.0, &request_signing, &pool).await?;
+    return Ok(HttpResponse::Created().json(Envelope::new(&http_request, api_key)));
+}
+
+#[tracing::instrument( name = "Revoke API key", skip(pool, redis) )]
+#[delete("/api-keys/{id}")]
+pub async fn revoke_api_key(param: web::Path<i32>, pool: Data::<MySqlPool>, redis: Data<redis::Client>) -> Result<HttpResponse, ApiKeyError> {
+    api_key_service::revoke(param.into_inner(), &pool, &redis).await?;
+    return Ok(HttpResponse::NoContent().finish());
+}
+
+#[tracing::instrument( name = "Rotate API key", skip(pool, http_request, settings) )]
+#[post("/api-keys/{id}/rotate")]
+pub async fn rotate_api_key(http_request: HttpRequest, param: web::Path<i32>, pool: Data::<MySqlPool>, settings: Data<ApiKeySettings>) -> Result<HttpResponse, ApiKeyError> {
+    let api_key = api_key_service::rotate(param.into_inner(), settings.rotation_grace_period_seconds, &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, api_key)));
+}
+
+/// The counts of this instance not recorded yet are added first, the ones of the other instances are added on their next flush.
+#[tracing::instrument( name = "Get API key usage", skip(pool, http_request, recorder) )]
+#[get("/api-keys/{id}/usage")]
+pub async fn get_api_key_usage(http_request: HttpRequest, param: web::Path<i32>, pool: Data::<MySqlPool>, recorder: Data<ApiKeyUsageRecorder>) -> Result<HttpResponse, ApiKeyError> {
+    recorder.flush(&pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+    let usage = api_key_service::get_usage(param.into_inner(), &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, usage)));
+}
@@ -0,0 +1,113 @@
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+
+const SCHEME: &str = "sha256";
+// length of the part of the key stored in plain text, used to find the key without knowing its salt
+pub const KEY_PREFIX_LENGTH: usize = 8;
+
+/// Salted SHA-256 of an API key, stored as `sha256$<salt>$<hex hash>`.
+/// The keys are random and long, so a fast hash is enough (unlike passwords, they can't be guessed from a dictionary).
+/// The same format is produced by the `api_keys` hashing migration with MySQL's `SHA2(CONCAT(salt, key), 256)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyHash {
+    salt: String,
+    hash: String,
+}
+
+impl ApiKeyHash {
+    pub fn new(api_key: &str) -> Self {
+        let salt = Uuid::new_v4().simple().to_string();
+        let hash = compute_hash(&salt, api_key);
+        return Self { salt, hash };
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut parts = value.split('$');
+        return match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(SCHEME), Some(salt), Some(hash), None) if !salt.is_empty() && !hash.is_empty() => {
+                Ok(Self { salt: salt.to_string(), hash: hash.to_string() })
+            },
+            _ => Err("Invalid API key hash, expected `sha256$<salt>$<hash>`.".to_string()),
+        };
+    }
+
+    /// Constant-time comparison, so the response time doesn't tell how much of the key matched.
+    pub fn verify(&self, api_key: &str) -> bool {
+        let hash = compute_hash(&self.salt, api_key);
+        return bool::from(hash.as_bytes().ct_eq(self.hash.as_bytes()));
+    }
+}
+
+impl std::fmt::Display for ApiKeyHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}${}${}", SCHEME, self.salt, self.hash);
+    }
+}
+
+/// The `application.api_key` can be configured either with the hash (see the `hash_api_key` binary) or,
+/// for backwards compatibility, with the key itself.
+pub fn verify_configured_api_key(configured: &str, api_key: &str) -> bool {
+    return match ApiKeyHash::parse(configured) {
+        Ok(hash) => hash.verify(api_key),
+        Err(_) => bool::from(configured.as_bytes().ct_eq(api_key.as_bytes())),
+    };
+}
+
+pub fn is_hashed(configured: &str) -> bool {
+    return ApiKeyHash::parse(configured).is_ok();
+}
+
+pub fn key_prefix(api_key: &str) -> String {
+    return api_key.chars().take(KEY_PREFIX_LENGTH).collect();
+}
+
+fn compute_hash(salt: &str, api_key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(api_key.as_bytes());
+    return hex::encode(hasher.finalize());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_configured_api_key, ApiKeyHash};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn key_verifies_only_against_its_own_hash(){
+        let hash = ApiKeyHash::new("correct key");
+        assert!(hash.verify("correct key"));
+        assert!(!hash.verify("wrong key"));
+        // the salt is random, the same key never has the same hash
+        assert_ne!(hash, ApiKeyHash::new("correct key"));
+    }
+
+    #[test]
+    fn hash_roundtrips_through_its_string_format(){
+        let hash = ApiKeyHash::new("key");
+        let parsed = assert_ok!(ApiKeyHash::parse(&hash.to_string()));
+        assert!(parsed.verify("key"));
+
+        assert_err!(ApiKeyHash::parse("key"));
+        assert_err!(ApiKeyHash::parse("md5$salt$hash"));
+        assert_err!(ApiKeyHash::parse("sha256$$hash"));
+    }
+
+    #[test]
+    fn configured_api_key_can_be_hashed_or_in_plain_text(){
+        let hash = ApiKeyHash::new("key").to_string();
+        assert!(verify_configured_api_key(&hash, "key"));
+        assert!(!verify_configured_api_key(&hash, &hash));
+        assert!(verify_configured_api_key("key", "key"));
+        assert!(!verify_configured_api_key("key", "other"));
+    }
+
+    #[test]
+    fn hash_matches_the_one_computed_by_mysql(){
+        // SELECT SHA2(CONCAT('salt', 'key'), 256)
+        let hash = assert_ok!(ApiKeyHash::parse("sha256$salt$4a466ea0657e479545b1d6c2d994824f80d8eecd7030f3092ff42a9bcad751d8"));
+        assert!(hash.verify("key"));
+    }
+}
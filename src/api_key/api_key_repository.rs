@@ -0,0 +1,176 @@
+use super::model::{ApiKeyInsert, ApiKeyRecord, ApiKeyUsage};
+use sqlx::MySqlPool;
+
+
+const SELECT_API_KEY: &str = r#"SELECT id
+        , name
+        , key_prefix
+        , key_hash
+        , IF(previous_api_key_expires_at > CURRENT_TIMESTAMP, previous_key_hash, NULL) AS previous_key_hash
+        , role
+        , scopes
+        , allowed_ips
+        , signing_secret
+        , expires_at
+        , date_created
+        , date_revoked
+        FROM api_keys"#;
+
+pub async fn insert(api_key: ApiKeyInsert, pool: &MySqlPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+            INSERT INTO api_keys
+            (name, key_prefix, key_hash, role, scopes, allowed_ips, signing_secret, expires_at)
+            VALUES
+            (?, ?, ?, ?, ?, ?, ?, ?)
+        "#)
+    .bind(api_key.name)
+    .bind(api_key.key_prefix)
+    .bind(api_key.key_hash)
+    .bind(api_key.role)
+    .bind(api_key.scopes)
+    .bind(api_key.allowed_ips)
+    .bind(api_key.signing_secret)
+    .bind(api_key.expires_at)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(result.last_insert_id());
+}
+
+pub async fn get_all(pool: &MySqlPool) -> Result<Vec<ApiKeyRecord>, sqlx::Error> {
+    let api_keys = sqlx::query_as::<_, ApiKeyRecord>(SELECT_API_KEY)
+    .fetch_all(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(api_keys);
+}
+
+pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<Option<ApiKeyRecord>, sqlx::Error> {
+    let api_key = sqlx::query_as::<_, ApiKeyRecord>(&format!("{} WHERE id = ?", SELECT_API_KEY))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(api_key);
+}
+
+/// Keys not revoked (but possibly expired) whose prefix (or the prefix of the previous key, during the grace period of a rotation) is `key_prefix`.
+/// Different keys can share a prefix, the hash tells which one (if any) matches.
+pub async fn get_active_by_prefix(key_prefix: &str, pool: &MySqlPool) -> Result<Vec<ApiKeyRecord>, sqlx::Error> {
+    let api_keys = sqlx::query_as::<_, ApiKeyRecord>(&format!(r#"{}
+            WHERE (key_prefix = ? OR (previous_key_prefix = ? AND previous_api_key_expires_at > CURRENT_TIMESTAMP))
+            AND date_revoked IS NULL"#, SELECT_API_KEY))
+    .bind(key_prefix)
+    .bind(key_prefix)
+    .fetch_all(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(api_keys);
+}
+
+/// Replace the key, keeping the current one as the previous key for `grace_period_seconds`.
+pub async fn rotate(id: i32, key_prefix: &str, key_hash: &str, grace_period_seconds: u64, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            UPDATE api_keys SET
+            previous_key_prefix = key_prefix,
+            previous_key_hash = key_hash,
+            previous_api_key_expires_at = CURRENT_TIMESTAMP + INTERVAL ? SECOND,
+            key_prefix = ?,
+            key_hash = ?
+            WHERE id = ?
+        "#)
+    .bind(grace_period_seconds)
+    .bind(key_prefix)
+    .bind(key_hash)
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+pub async fn revoke(id: i32, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE api_keys SET date_revoked = CURRENT_TIMESTAMP WHERE id = ? AND date_revoked IS NULL")
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+/// Returns `1` when the signing secret was `previous` and is now `signing_secret`, `0` otherwise.
+pub async fn replace_signing_secret(id: i32, previous: &str, signing_secret: &str, pool: &MySqlPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("UPDATE api_keys SET signing_secret = ? WHERE id = ? AND signing_secret = ?")
+    .bind(signing_secret)
+    .bind(id)
+    .bind(previous)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(result.rows_affected());
+}
+
+/// Add the requests counted since the last call to the usage of their key.
+pub async fn record_usage(usage: &[ApiKeyUsage], pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    for usage in usage {
+        sqlx::query(
+            r#"
+                INSERT INTO api_key_usage (api_key_id, requests, client_errors, server_errors, last_used_at)
+                VALUES (?, ?, ?, ?, ?)
+                ON DUPLICATE KEY UPDATE
+                requests = requests + VALUES(requests),
+                client_errors = client_errors + VALUES(client_errors),
+                server_errors = server_errors + VALUES(server_errors),
+                last_used_at = GREATEST(COALESCE(last_used_at, VALUES(last_used_at)), VALUES(last_used_at))
+            "#)
+        .bind(usage.api_key_id)
+        .bind(usage.requests)
+        .bind(usage.client_errors)
+        .bind(usage.server_errors)
+        .bind(usage.last_used_at)
+        .execute(&mut transaction)
+        .await
+        .map_err(|error| {
+            tracing::error!("Failed to execute insert query: {:?}", error);
+            error
+        })?;
+    }
+    transaction.commit().await?;
+    return Ok(());
+}
+
+pub async fn get_usage(api_key_id: i32, pool: &MySqlPool) -> Result<Option<ApiKeyUsage>, sqlx::Error> {
+    let usage = sqlx::query_as::<_, ApiKeyUsage>("SELECT api_key_id, requests, client_errors, server_errors, last_used_at FROM api_key_usage WHERE api_key_id = ?")
+    .bind(api_key_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(usage);
+}
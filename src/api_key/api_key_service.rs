@@ -0,0 +1,163 @@
+use super::model::{ApiKeyCreateRequest, ApiKeyError, ApiKeyInsert, ApiKeyListQuery, ApiKeyRecord, ApiKeyResponse, ApiKeyUsage};
+use super::api_key_hash::{key_prefix, ApiKeyHash};
+use super::api_key_repository;
+use super::signing_secret::{self, SigningSecretCipher};
+use crate::authentication::sessions;
+use crate::configuration::RequestSigningSettings;
+use anyhow::anyhow;
+use chrono::Utc;
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+
+pub async fn get_all(query: &ApiKeyListQuery, pool: &MySqlPool) -> Result<Vec<ApiKeyResponse>, ApiKeyError> {
+    let api_keys = api_key_repository::get_all(pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+    let mut api_keys: Vec<ApiKeyResponse> = api_keys.into_iter().map(|api_key| api_key.into()).collect();
+    if (query.expiring_soon){
+        api_keys.retain(|api_key| api_key.expiring_soon);
+        api_keys.sort_by_key(|api_key| api_key.expires_at);
+    }
+    return Ok(api_keys);
+}
+
+pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<ApiKeyResponse, ApiKeyError> {
+    let api_key = api_key_repository::get_by_id(id, pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?
+        .ok_or(ApiKeyError::NotFoundError(anyhow!(format!("API key with id `{}` not found.", id))))?;
+    return Ok(api_key.into());
+}
+
+/// Issue a new key, the response is the only time the key itself (and its signing secret) is returned.
+/// It only gets a signing secret when `request_signing.encryption_key` is set.
+pub async fn insert(request: ApiKeyCreateRequest, request_signing: &RequestSigningSettings, pool: &MySqlPool) -> Result<ApiKeyResponse, ApiKeyError> {
+    let name = request.parse_name().map_err(ApiKeyError::ValidationError)?;
+    let scopes = request.parse_scopes().map_err(ApiKeyError::ValidationError)?;
+    let allowed_ips = request.parse_allowed_ips();
+    let expires_at = request.parse_expires_at(Utc::now().naive_utc()).map_err(ApiKeyError::ValidationError)?;
+    let key = Uuid::new_v4().simple().to_string();
+    let key_hash = ApiKeyHash::new(&key).to_string();
+    // unlike the key, the secret can't be hashed since the signature can only be verified with it, it is encrypted instead
+    let cipher = request_signing.cipher().map_err(|e| ApiKeyError::UnexpectedError(anyhow!(e)))?;
+    let signing_secret = Uuid::new_v4().simple().to_string();
+
+    let api_key = ApiKeyInsert {
+        name,
+        key_prefix: key_prefix(&key),
+        key_hash,
+        role: request.role.as_str().to_string(),
+        scopes,
+        allowed_ips,
+        signing_secret: cipher.as_ref().map(|cipher| cipher.encrypt(&signing_secret)),
+        expires_at,
+    };
+    let inserted_id = api_key_repository::insert(api_key, pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+    let inserted_id = i32::try_from(inserted_id)
+        .map_err(|e| ApiKeyError::UnexpectedError(anyhow!(format!("Failed to read inserted_id: {}", e))))?;
+
+    let mut api_key = get_by_id(inserted_id, pool).await?;
+    api_key.api_key = Some(key);
+    api_key.signing_secret = cipher.map(|_| signing_secret);
+    return Ok(api_key);
+}
+
+/// Issue a new key for the same client, the response is the only time it is returned.
+/// The previous key keeps working for the grace period, so the client can be updated without downtime.
+pub async fn rotate(id: i32, grace_period_seconds: u64, pool: &MySqlPool) -> Result<ApiKeyResponse, ApiKeyError> {
+    let api_key = get_by_id(id, pool).await?;
+    if (api_key.revoked){
+        return Err(ApiKeyError::ValidationError(format!("API key with id `{}` was revoked.", id)));
+    }
+    let key = Uuid::new_v4().simple().to_string();
+    let key_hash = ApiKeyHash::new(&key).to_string();
+
+    api_key_repository::rotate(id, &key_prefix(&key), &key_hash, grace_period_seconds, pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+
+    let mut api_key = get_by_id(id, pool).await?;
+    api_key.api_key = Some(key);
+    return Ok(api_key);
+}
+
+/// Encrypt the signing secrets stored in plain text before `request_signing.encryption_key` was set, returns how many were.
+pub async fn encrypt_signing_secrets(cipher: &SigningSecretCipher, pool: &MySqlPool) -> Result<u64, ApiKeyError> {
+    let api_keys = api_key_repository::get_all(pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+    let mut encrypted = 0;
+    for api_key in api_keys {
+        let secret = match api_key.signing_secret {
+            Some(secret) if !signing_secret::is_encrypted(&secret) => secret,
+            _ => continue,
+        };
+        // only replaced if it wasn't changed in between, e.g. by another instance starting
+        encrypted += api_key_repository::replace_signing_secret(api_key.id, &secret, &cipher.encrypt(&secret), pool).await
+            .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+    }
+    return Ok(encrypted);
+}
+
+/// Revoked keys can't be used on `/auth` anymore, the sessions already created with them are ended.
+pub async fn revoke(id: i32, pool: &MySqlPool, redis: &redis::Client) -> Result<(), ApiKeyError> {
+    // check if api key exists
+    get_by_id(id, pool).await?;
+
+    api_key_repository::revoke(id, pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+    sessions::end_sessions(redis, |session| session.api_key_id == Some(id)).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+    return Ok(());
+}
+
+/// The requests made with the key, as recorded by the instances so far.
+pub async fn get_usage(id: i32, pool: &MySqlPool) -> Result<ApiKeyUsage, ApiKeyError> {
+    // check if api key exists
+    get_by_id(id, pool).await?;
+
+    let usage = api_key_repository::get_usage(id, pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+    return Ok(usage.unwrap_or(ApiKeyUsage { api_key_id: id, ..ApiKeyUsage::default() }));
+}
+
+/// The issued key with the `id`, `None` once it was revoked. `ExpiredError` when it expired.
+pub async fn find_active_by_id(id: i32, pool: &MySqlPool) -> Result<Option<ApiKeyRecord>, ApiKeyError> {
+    let issued_api_key = api_key_repository::get_by_id(id, pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?
+        .filter(|api_key| api_key.date_revoked.is_none());
+    if let Some(issued_api_key) = &issued_api_key {
+        check_not_expired(issued_api_key)?;
+    }
+    return Ok(issued_api_key);
+}
+
+/// The issued key matching `api_key`, not revoked. `ExpiredError` when it expired.
+pub async fn find_active(api_key: &str, pool: &MySqlPool) -> Result<Option<ApiKeyRecord>, ApiKeyError> {
+    let candidates = api_key_repository::get_active_by_prefix(&key_prefix(api_key), pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+
+    let matches = |key_hash: &str| ApiKeyHash::parse(key_hash)
+        .map(|key_hash| key_hash.verify(api_key))
+        .unwrap_or(false);
+    let issued_api_key = candidates.into_iter().find(|candidate| {
+        matches(&candidate.key_hash) || candidate.previous_key_hash.as_deref().map(matches).unwrap_or(false)
+    });
+    if let Some(issued_api_key) = &issued_api_key {
+        check_not_expired(issued_api_key)?;
+    }
+    return Ok(issued_api_key);
+}
+
+/// The ids of the issued keys `api_key` could be a guess of, the ones with the same prefix.
+pub async fn find_ids_by_prefix(api_key: &str, pool: &MySqlPool) -> Result<Vec<i32>, ApiKeyError> {
+    let candidates = api_key_repository::get_active_by_prefix(&key_prefix(api_key), pool).await
+        .map_err(|error| ApiKeyError::UnexpectedError(error.into()))?;
+    return Ok(candidates.into_iter().map(|candidate| candidate.id).collect());
+}
+
+/// `ExpiredError` once the key is past its `expires_at`, with the `api_key_expired` code in the body.
+pub fn check_not_expired(api_key: &ApiKeyRecord) -> Result<(), ApiKeyError> {
+    return match api_key.expires_at {
+        Some(expires_at) if (api_key.is_expired(Utc::now().naive_utc())) => Err(ApiKeyError::expired(expires_at)),
+        _ => Ok(()),
+    };
+}
@@ -0,0 +1,161 @@
+use super::model::ApiKeyUsage;
+use super::api_key_repository;
+use crate::authentication::Session;
+use actix_web::{
+    Error, HttpMessage,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+};
+use chrono::{NaiveDateTime, Utc};
+use sqlx::MySqlPool;
+use std::{
+    collections::HashMap,
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+
+/// Counts the requests of each issued API key, recorded by wrapping the authenticated scopes with it, inside `Authenticate`
+/// and outside the rate limit and the authorization, so the requests they reject are counted. The counts are kept in memory
+/// and added to the `api_key_usage` table by `flush`, so the requests don't each write to the database.
+#[derive(Clone, Default)]
+pub struct ApiKeyUsageRecorder {
+    usage: Arc<Mutex<HashMap<i32, ApiKeyUsage>>>,
+}
+
+impl ApiKeyUsageRecorder {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn record(&self, api_key_id: i32, status: u16, time: NaiveDateTime) {
+        self.add(ApiKeyUsage {
+            api_key_id,
+            requests: 1,
+            client_errors: u64::from((400..500).contains(&status)),
+            server_errors: u64::from((500..600).contains(&status)),
+            last_used_at: Some(time),
+        });
+    }
+
+    fn add(&self, usage: ApiKeyUsage) {
+        let mut counts = self.usage.lock().unwrap();
+        let counted = counts.entry(usage.api_key_id).or_insert_with(|| ApiKeyUsage { api_key_id: usage.api_key_id, ..ApiKeyUsage::default() });
+        counted.requests += usage.requests;
+        counted.client_errors += usage.client_errors;
+        counted.server_errors += usage.server_errors;
+        counted.last_used_at = counted.last_used_at.max(usage.last_used_at);
+    }
+
+    // the counts since the last call
+    fn take(&self) -> Vec<ApiKeyUsage> {
+        return std::mem::take(&mut *self.usage.lock().unwrap()).into_values().collect();
+    }
+
+    /// Add the counts since the last flush to the database, they are counted again when it fails.
+    pub async fn flush(&self, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+        let usage = self.take();
+        if (usage.is_empty()){
+            return Ok(());
+        }
+        if let Err(error) = api_key_repository::record_usage(&usage, pool).await {
+            usage.into_iter().for_each(|usage| self.add(usage));
+            return Err(error);
+        }
+        return Ok(());
+    }
+
+    /// `flush` every `interval`, the counts of the last interval are lost when the instance stops.
+    pub fn spawn_flush(&self, pool: MySqlPool, interval: Duration) {
+        let recorder = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(error) = recorder.flush(&pool).await {
+                    tracing::error!("Failed to record the usage of the API keys: {:?}", error);
+                }
+            }
+        });
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyUsageRecorder
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ApiKeyUsageMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(ApiKeyUsageMiddleware { service: Rc::new(service), recorder: self.clone() }));
+    }
+}
+
+pub struct ApiKeyUsageMiddleware<S> {
+    service: Rc<S>,
+    recorder: ApiKeyUsageRecorder,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyUsageMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        // set by the authentication, the configured `application.api_key` and the user logins have none
+        let api_key_id = request.extensions().get::<Session>().and_then(|session| session.api_key_id);
+        let api_key_id = match api_key_id {
+            Some(api_key_id) => api_key_id,
+            None => return Box::pin(async move { service.call(request).await }),
+        };
+        let recorder = self.recorder.clone();
+        return Box::pin(async move {
+            let result = service.call(request).await;
+            let status = match &result {
+                Ok(response) => response.status(),
+                Err(error) => error.as_response_error().status_code(),
+            };
+            recorder.record(api_key_id, status.as_u16(), Utc::now().naive_utc());
+            return result;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiKeyUsageRecorder;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn requests_and_errors_are_counted_per_api_key(){
+        let recorder = ApiKeyUsageRecorder::new();
+        let time = |hour| NaiveDate::from_ymd_opt(2023, 2, 1).unwrap().and_hms_opt(hour, 0, 0).unwrap();
+        recorder.record(1, 200, time(10));
+        recorder.record(1, 429, time(12));
+        recorder.record(1, 503, time(11));
+        recorder.record(2, 204, time(9));
+
+        let mut usage = recorder.take();
+        usage.sort_by_key(|usage| usage.api_key_id);
+
+        assert_eq!(usage.len(), 2);
+        assert_eq!((usage[0].requests, usage[0].client_errors, usage[0].server_errors), (3, 1, 1));
+        assert_eq!(usage[0].last_used_at, Some(time(12)));
+        assert_eq!((usage[1].requests, usage[1].client_errors, usage[1].server_errors), (1, 0, 0));
+        assert!(recorder.take().is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Serialize, Deserialize};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+
+/// IP range in CIDR notation, e.g. `10.0.0.0/8` or `2001:db8::/32`.
+/// A single IP (without the `/<prefix>`) is the range with only that IP.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_length: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        return match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                mask(u128::from(u32::from(network)), 32, self.prefix_length) == mask(u128::from(u32::from(ip)), 32, self.prefix_length)
+            },
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                mask(u128::from(network), 128, self.prefix_length) == mask(u128::from(ip), 128, self.prefix_length)
+            },
+            _ => false,
+        };
+    }
+
+    /// Parse a comma separated list of ranges, as stored in the database.
+    pub fn parse_list(ranges: &str) -> Result<Vec<Cidr>, String> {
+        return ranges
+            .split(',')
+            .map(|range| range.trim())
+            .filter(|range| !range.is_empty())
+            .map(|range| range.parse::<Cidr>())
+            .collect();
+    }
+
+    pub fn join(ranges: &[Cidr]) -> String {
+        return ranges.iter().map(|range| range.to_string()).collect::<Vec<String>>().join(",");
+    }
+}
+
+/// An empty allowlist allows every IP.
+pub fn is_allowed(allowlist: &[Cidr], ip: Option<IpAddr>) -> bool {
+    if (allowlist.is_empty()){
+        return true;
+    }
+    return match ip {
+        Some(ip) => allowlist.iter().any(|range| range.contains(&ip)),
+        None => false,
+    };
+}
+
+// keep only the first `prefix_length` bits of an address of `bits` bits
+fn mask(address: u128, bits: u8, prefix_length: u8) -> u128 {
+    if (prefix_length == 0){
+        return 0;
+    }
+    return address >> (bits - prefix_length);
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid IP range `{}`, expected e.g. `10.0.0.0/8`.", range);
+        let (network, prefix_length) = match range.split_once('/') {
+            Some((network, prefix_length)) => (network, Some(prefix_length)),
+            None => (range, None),
+        };
+        let network: IpAddr = network.trim().parse().map_err(|_| invalid())?;
+        let max_prefix_length = if (network.is_ipv4()) { 32 } else { 128 };
+        let prefix_length = match prefix_length {
+            Some(prefix_length) => prefix_length.trim().parse::<u8>().map_err(|_| invalid())?,
+            None => max_prefix_length,
+        };
+        if (prefix_length > max_prefix_length){
+            return Err(invalid());
+        }
+        return Ok(Self { network, prefix_length });
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+    fn try_from(range: String) -> Result<Self, Self::Error> {
+        return range.parse();
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(range: Cidr) -> Self {
+        return range.to_string();
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "{}/{}", self.network, self.prefix_length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_allowed, Cidr};
+    use claim::{assert_err, assert_ok};
+
+    fn allowed(allowlist: &str, ip: &str) -> bool {
+        return is_allowed(&Cidr::parse_list(allowlist).unwrap(), Some(ip.parse().unwrap()));
+    }
+
+    #[test]
+    fn ip_must_be_in_one_of_the_ranges(){
+        assert!(allowed("10.0.0.0/8, 192.168.1.10", "10.20.30.40"));
+        assert!(allowed("10.0.0.0/8, 192.168.1.10", "192.168.1.10"));
+        assert!(!allowed("10.0.0.0/8, 192.168.1.10", "192.168.1.11"));
+        assert!(allowed("2001:db8::/32", "2001:db8::1"));
+        assert!(!allowed("2001:db8::/32", "10.0.0.1"));
+        // IPv4 clients connected to an IPv6 socket
+        assert!(allowed("127.0.0.1", "::ffff:127.0.0.1"));
+        assert!(allowed("0.0.0.0/0", "203.0.113.7"));
+    }
+
+    #[test]
+    fn empty_allowlist_allows_every_ip(){
+        assert!(allowed("", "203.0.113.7"));
+        assert!(is_allowed(&[], None));
+        assert!(!is_allowed(&Cidr::parse_list("10.0.0.0/8").unwrap(), None));
+    }
+
+    #[test]
+    fn invalid_ranges_are_rejected(){
+        assert_err!("10.0.0.0/33".parse::<Cidr>());
+        assert_err!("10.0.0/8".parse::<Cidr>());
+        assert_err!("not an ip".parse::<Cidr>());
+        assert_ok!("::1/128".parse::<Cidr>());
+        assert_eq!(Cidr::join(&Cidr::parse_list("10.0.0.0/8,127.0.0.1").unwrap()), "10.0.0.0/8,127.0.0.1/32");
+    }
+}
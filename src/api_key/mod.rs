@@ -0,0 +1,11 @@
+pub mod api_key_controller;
+pub mod api_key_hash;
+pub mod ip_allowlist;
+pub mod api_key_service;
+pub mod api_key_repository;
+pub mod api_key_usage;
+pub mod signing_secret;
+pub mod model;
+
+pub use api_key_controller::*;
+pub use model::*;
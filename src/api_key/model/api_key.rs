@@ -0,0 +1,289 @@
+use crate::api_key::ip_allowlist::Cidr;
+use crate::authentication::{Role, Scope};
+use actix_web::{
+    ResponseError,
+    http::{StatusCode},
+};
+use serde::{Serialize, Deserialize};
+use chrono::Duration;
+use sqlx::types::chrono::{NaiveDateTime, Utc};
+
+
+// the keys expiring within these days are `expiring_soon`
+pub const EXPIRATION_WARNING_DAYS: i64 = 14;
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct ApiKeyRecord {
+    pub id: i32,
+    pub name: String,
+    // only the first characters of the key and a salted hash of it are stored, see `ApiKeyHash`
+    pub key_prefix: String,
+    pub key_hash: String,
+    // only selected during the grace period of a rotation
+    pub previous_key_hash: Option<String>,
+    pub role: String,
+    // comma separated, e.g. `coupon:read,coupon:redeem`
+    pub scopes: String,
+    // comma separated CIDR ranges the key can be used from, any IP when `NULL`
+    pub allowed_ips: Option<String>,
+    // HMAC key of the signed requests encrypted with `request_signing.encryption_key`, see `SigningSecretCipher`.
+    // `NULL` for the keys issued before request signing existed or while the encryption key wasn't set
+    pub signing_secret: Option<String>,
+    // `NULL` never expires
+    pub expires_at: Option<NaiveDateTime>,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_revoked: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeyCreateRequest {
+    pub name: String,
+    pub role: Role,
+    pub scopes: Vec<Scope>,
+    // e.g. `["10.0.0.0/8", "203.0.113.7"]`, the key can be used from any IP when empty
+    #[serde(default)]
+    pub allowed_ips: Vec<Cidr>,
+    // UTC, e.g. `2023-12-31T23:59:59`, the key never expires without it
+    #[serde(default)]
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// `expiring_soon` only lists the keys not revoked that expire within `EXPIRATION_WARNING_DAYS`, the first to expire first.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ApiKeyListQuery {
+    #[serde(default)]
+    pub expiring_soon: bool,
+}
+
+/// A validated `ApiKeyCreateRequest` with the hash of the new key, ready to be persisted.
+#[derive(Debug, Clone)]
+pub struct ApiKeyInsert {
+    pub name: String,
+    pub key_prefix: String,
+    pub key_hash: String,
+    pub role: String,
+    pub scopes: String,
+    pub allowed_ips: Option<String>,
+    // encrypted
+    pub signing_secret: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeyResponse {
+    pub id: i32,
+    pub name: String,
+    // only returned when the key is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    // only returned when the key is created, see `request_signing`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_secret: Option<String>,
+    pub role: Role,
+    pub scopes: Vec<Scope>,
+    pub allowed_ips: Vec<Cidr>,
+    pub revoked: bool,
+    pub expires_at: Option<NaiveDateTime>,
+    pub expired: bool,
+    // not revoked and expiring within `EXPIRATION_WARNING_DAYS`
+    pub expiring_soon: bool,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_revoked: Option<NaiveDateTime>,
+}
+
+/// The requests made with a key, `last_used_at` is `None` when it was never used.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, sqlx::FromRow)]
+pub struct ApiKeyUsage {
+    pub api_key_id: i32,
+    pub requests: u64,
+    // responses with a 4xx status, e.g. rejected by the rate limit
+    pub client_errors: u64,
+    // responses with a 5xx status
+    pub server_errors: u64,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+impl ApiKeyCreateRequest {
+    pub fn parse_name(&self) -> Result<String, String> {
+        let name = self.name.trim();
+        if (name.is_empty()){
+            return Err("API key name can't be empty.".to_string());
+        }
+        if (name.chars().count() > 255){
+            return Err("API key name can't be longer than 255 characters.".to_string());
+        }
+        return Ok(name.to_string());
+    }
+
+    pub fn parse_scopes(&self) -> Result<String, String> {
+        if (self.scopes.is_empty()){
+            return Err("At least one scope must be granted.".to_string());
+        }
+        return Ok(Scope::join(&self.scopes));
+    }
+
+    pub fn parse_allowed_ips(&self) -> Option<String> {
+        if (self.allowed_ips.is_empty()){
+            return None;
+        }
+        return Some(Cidr::join(&self.allowed_ips));
+    }
+
+    pub fn parse_expires_at(&self, now: NaiveDateTime) -> Result<Option<NaiveDateTime>, String> {
+        return match self.expires_at {
+            Some(expires_at) if (expires_at <= now) => Err("`expires_at` must be in the future.".to_string()),
+            expires_at => Ok(expires_at),
+        };
+    }
+}
+
+impl ApiKeyRecord {
+    /// Ranges that can't be parsed are ignored, they were validated when the key was issued.
+    pub fn allowed_ips(&self) -> Vec<Cidr> {
+        return self.allowed_ips.as_deref()
+            .map(|ranges| ranges.split(',').flat_map(|range| range.parse::<Cidr>().ok()).collect())
+            .unwrap_or_default();
+    }
+
+    pub fn is_expired(&self, now: NaiveDateTime) -> bool {
+        return self.expires_at.map_or(false, |expires_at| expires_at <= now);
+    }
+}
+
+impl From<ApiKeyRecord> for ApiKeyResponse {
+    fn from(record: ApiKeyRecord) -> Self {
+        return Self::new(record, Utc::now().naive_utc());
+    }
+}
+
+impl ApiKeyResponse {
+    /// `expired` and `expiring_soon` as of `now`.
+    pub fn new(record: ApiKeyRecord, now: NaiveDateTime) -> Self {
+        let expired = record.is_expired(now);
+        let expiring_soon = !expired && record.date_revoked.is_none()
+            && record.expires_at.map_or(false, |expires_at| expires_at <= now + Duration::days(EXPIRATION_WARNING_DAYS));
+        let allowed_ips = record.allowed_ips();
+        return Self {
+            id: record.id,
+            name: record.name,
+            api_key: None,
+            signing_secret: None,
+            role: record.role.parse().unwrap_or(Role::Readonly),
+            scopes: Scope::parse_list(&record.scopes),
+            allowed_ips,
+            revoked: record.date_revoked.is_some(),
+            expires_at: record.expires_at,
+            expired,
+            expiring_soon,
+            date_created: record.date_created,
+            date_revoked: record.date_revoked,
+        };
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApiKeyError {
+    #[error("{0}")]
+    NotFoundError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    // the key was right, so the client knows to ask for a new one rather than retry
+    #[error("{0}")]
+    ExpiredError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ApiKeyError {
+    pub fn expired(expires_at: NaiveDateTime) -> Self {
+        return ApiKeyError::ExpiredError(format!("The API key expired on {}.", expires_at.format("%Y-%m-%dT%H:%M:%SZ")));
+    }
+}
+
+impl ResponseError for ApiKeyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiKeyError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            ApiKeyError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiKeyError::ExpiredError(_) => StatusCode::UNAUTHORIZED,
+            ApiKeyError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiKeyCreateRequest, ApiKeyRecord, ApiKeyResponse};
+    use crate::authentication::{Role, Scope};
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+    use claim::{assert_err, assert_ok_eq};
+
+    fn request(name: &str, scopes: Vec<Scope>) -> ApiKeyCreateRequest {
+        return ApiKeyCreateRequest { name: name.to_string(), role: Role::Readonly, scopes, allowed_ips: vec![], expires_at: None };
+    }
+
+    fn now() -> NaiveDateTime {
+        return NaiveDate::from_ymd_opt(2023, 2, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+    }
+
+    fn record(expires_at: Option<NaiveDateTime>) -> ApiKeyRecord {
+        return ApiKeyRecord {
+            id: 1,
+            name: "storefront".to_string(),
+            key_prefix: "abcdefgh".to_string(),
+            key_hash: "sha256$salt$hash".to_string(),
+            previous_key_hash: None,
+            role: "readonly".to_string(),
+            scopes: "coupon:read".to_string(),
+            allowed_ips: None,
+            signing_secret: None,
+            expires_at,
+            date_created: None,
+            date_revoked: None,
+        };
+    }
+
+    #[test]
+    fn name_is_trimmed(){
+        assert_ok_eq!(request("  storefront ", vec![Scope::CouponRead]).parse_name(), "storefront".to_string());
+    }
+
+    #[test]
+    fn empty_or_too_long_name_is_rejected(){
+        assert_err!(request(" ", vec![Scope::CouponRead]).parse_name());
+        assert_err!(request(&"a".repeat(256), vec![Scope::CouponRead]).parse_name());
+    }
+
+    #[test]
+    fn api_key_without_scopes_is_rejected(){
+        assert_err!(request("storefront", vec![]).parse_scopes());
+        assert_ok_eq!(
+            request("storefront", vec![Scope::CouponRedeem, Scope::CouponRead]).parse_scopes(),
+            "coupon:read,coupon:redeem".to_string()
+        );
+    }
+
+    #[test]
+    fn expiration_must_be_in_the_future(){
+        let mut request = request("storefront", vec![Scope::CouponRead]);
+        assert_ok_eq!(request.parse_expires_at(now()), None);
+        request.expires_at = Some(now() + Duration::days(30));
+        assert_ok_eq!(request.parse_expires_at(now()), Some(now() + Duration::days(30)));
+        request.expires_at = Some(now());
+        assert_err!(request.parse_expires_at(now()));
+    }
+
+    #[test]
+    fn keys_expiring_within_the_warning_days_are_expiring_soon(){
+        let never = ApiKeyResponse::new(record(None), now());
+        let soon = ApiKeyResponse::new(record(Some(now() + Duration::days(3))), now());
+        let later = ApiKeyResponse::new(record(Some(now() + Duration::days(60))), now());
+        let expired = ApiKeyResponse::new(record(Some(now() - Duration::seconds(1))), now());
+
+        assert!(!never.expired && !never.expiring_soon);
+        assert!(!soon.expired && soon.expiring_soon);
+        assert!(!later.expired && !later.expiring_soon);
+        assert!(expired.expired && !expired.expiring_soon);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod api_key;
+
+pub use self::api_key::*;
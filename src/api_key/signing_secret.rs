@@ -0,0 +1,79 @@
+use aes_gcm::{
+    Aes256Gcm, Nonce,
+    aead::{Aead, KeyInit},
+};
+
+
+// the secrets encrypted by `encrypt`, the others were stored before `request_signing.encryption_key` existed
+const ENCRYPTED_PREFIX: &str = "aes256gcm:";
+const NONCE_BYTES: usize = 12;
+
+/// Encrypts the signing secrets of the API keys with `request_signing.encryption_key` (AES-256-GCM), the signatures
+/// can only be verified with the secret itself, so it can't be hashed like the key. A copy of the database alone
+/// doesn't give the secrets, they are only returned in plain text when the key is created.
+#[derive(Clone)]
+pub struct SigningSecretCipher(Aes256Gcm);
+
+impl SigningSecretCipher {
+    /// `key` is 32 bytes in hex, e.g. the output of `openssl rand -hex 32`.
+    pub fn new(key: &str) -> Result<Self, String> {
+        let key = hex::decode(key.trim())
+            .map_err(|_| "`request_signing.encryption_key` must be in hex.".to_string())?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| "`request_signing.encryption_key` must be 32 bytes (64 hex characters).".to_string())?;
+        return Ok(Self(cipher));
+    }
+
+    /// The stored form of `secret`, a random nonce and the ciphertext, in hex.
+    pub fn encrypt(&self, secret: &str) -> String {
+        let nonce: [u8; NONCE_BYTES] = rand::random();
+        let ciphertext = self.0.encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+            .expect("AES-GCM can encrypt a secret of any size");
+        return format!("{}{}{}", ENCRYPTED_PREFIX, hex::encode(nonce), hex::encode(ciphertext));
+    }
+
+    /// `None` when it wasn't encrypted with this key, or was changed since.
+    pub fn decrypt(&self, encrypted: &str) -> Option<String> {
+        let encrypted = hex::decode(encrypted.strip_prefix(ENCRYPTED_PREFIX)?).ok()?;
+        if (encrypted.len() < NONCE_BYTES){
+            return None;
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_BYTES);
+        let secret = self.0.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+        return String::from_utf8(secret).ok();
+    }
+}
+
+pub fn is_encrypted(secret: &str) -> bool {
+    return secret.starts_with(ENCRYPTED_PREFIX);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_encrypted, SigningSecretCipher};
+    use claim::{assert_none, assert_ok};
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn secrets_are_only_decrypted_with_their_key(){
+        let cipher = assert_ok!(SigningSecretCipher::new(KEY));
+        let other = assert_ok!(SigningSecretCipher::new(&KEY.replace("00", "ff")));
+
+        let encrypted = cipher.encrypt("secret");
+
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("secret"));
+        assert_ne!(encrypted, cipher.encrypt("secret"));
+        assert_eq!(cipher.decrypt(&encrypted), Some("secret".to_string()));
+        assert_none!(other.decrypt(&encrypted));
+        assert_none!(cipher.decrypt("secret"));
+        assert_none!(cipher.decrypt(&format!("{}00", encrypted)));
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected(){
+        assert!(SigningSecretCipher::new("not hex").is_err());
+        assert!(SigningSecretCipher::new("0001").is_err());
+    }
+}
@@ -0,0 +1,16 @@
+use super::model::{AuditLogError, AuthAuditFilter};
+use super::audit_log_service;
+use crate::envelope::Envelope;
+use actix_web::{
+    web, get, HttpRequest, HttpResponse,
+    web::Data,
+};
+use sqlx::MySqlPool;
+
+
+#[tracing::instrument( name = "Get authentication audit log", skip(pool, http_request) )]
+#[get("/audit-log")]
+pub async fn get_audit_log(http_request: HttpRequest, filter: web::Query<AuthAuditFilter>, pool: Data::<MySqlPool>) -> Result<HttpResponse, AuditLogError> {
+    let entries = audit_log_service::get_all(&filter, &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, entries)));
+}
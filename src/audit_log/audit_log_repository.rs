@@ -0,0 +1,85 @@
+use super::model::{AuthAuditEntry, AuthAuditFilter, AuthAuditInsert};
+use sqlx::MySqlPool;
+use sqlx::types::chrono::{NaiveDateTime};
+
+
+pub async fn insert(entry: AuthAuditInsert, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            INSERT INTO auth_audit_log
+            (event, outcome, identifier, api_key_id, ip, user_agent)
+            VALUES
+            (?, ?, ?, ?, ?, ?)
+        "#)
+    .bind(entry.event.as_str())
+    .bind(entry.outcome.as_str())
+    .bind(entry.identifier)
+    .bind(entry.api_key_id)
+    .bind(entry.ip)
+    .bind(entry.user_agent)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+pub async fn get_all(filter: &AuthAuditFilter, limit: u32, pool: &MySqlPool) -> Result<Vec<AuthAuditEntry>, sqlx::Error> {
+    let entries = sqlx::query_as::<_, AuthAuditEntry>(
+        r#"SELECT id
+        , event
+        , outcome
+        , identifier
+        , api_key_id
+        , ip
+        , user_agent
+        , date_created
+        FROM auth_audit_log
+        WHERE (? IS NULL OR event = ?)
+        AND (? IS NULL OR outcome = ?)
+        AND (? IS NULL OR identifier = ?)
+        AND (? IS NULL OR api_key_id = ?)
+        AND (? IS NULL OR ip = ?)
+        AND (? IS NULL OR date_created >= ?)
+        AND (? IS NULL OR date_created <= ?)
+        ORDER BY id DESC
+        LIMIT ?"#)
+    .bind(filter.event.map(|event| event.as_str()))
+    .bind(filter.event.map(|event| event.as_str()))
+    .bind(filter.outcome.map(|outcome| outcome.as_str()))
+    .bind(filter.outcome.map(|outcome| outcome.as_str()))
+    .bind(&filter.identifier)
+    .bind(&filter.identifier)
+    .bind(filter.api_key_id)
+    .bind(filter.api_key_id)
+    .bind(&filter.ip)
+    .bind(&filter.ip)
+    .bind(filter.since)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(filter.until)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(entries);
+}
+
+/// Delete up to `limit` entries created before `cutoff`, returns how many were deleted.
+pub async fn delete_before(cutoff: NaiveDateTime, limit: u32, pool: &MySqlPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM auth_audit_log WHERE date_created < ? ORDER BY id LIMIT ?")
+    .bind(cutoff)
+    .bind(limit)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+    return Ok(result.rows_affected());
+}
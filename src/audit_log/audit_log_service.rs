@@ -0,0 +1,19 @@
+use super::model::{AuditLogError, AuthAuditEntry, AuthAuditFilter, AuthAuditInsert};
+use super::audit_log_repository;
+use sqlx::MySqlPool;
+
+
+/// Record the authentication event, a failure to do so is logged but doesn't fail the request.
+pub async fn record(entry: AuthAuditInsert, pool: &MySqlPool) {
+    let event = entry.event;
+    if let Err(error) = audit_log_repository::insert(entry, pool).await {
+        tracing::error!("Failed to record `{}` authentication event: {:?}", event.as_str(), error);
+    }
+}
+
+pub async fn get_all(filter: &AuthAuditFilter, pool: &MySqlPool) -> Result<Vec<AuthAuditEntry>, AuditLogError> {
+    let limit = filter.limit().map_err(AuditLogError::ValidationError)?;
+    let entries = audit_log_repository::get_all(filter, limit, pool).await
+        .map_err(|error| AuditLogError::UnexpectedError(error.into()))?;
+    return Ok(entries);
+}
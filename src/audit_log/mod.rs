@@ -0,0 +1,7 @@
+pub mod audit_log_controller;
+pub mod audit_log_service;
+pub mod audit_log_repository;
+pub mod model;
+
+pub use audit_log_controller::*;
+pub use model::*;
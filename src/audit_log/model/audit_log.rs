@@ -0,0 +1,154 @@
+use actix_web::{
+    HttpRequest, ResponseError,
+    http::{StatusCode, header::USER_AGENT},
+};
+use crate::client_ip::client_ip;
+use serde::{Serialize, Deserialize};
+use sqlx::types::chrono::{NaiveDateTime};
+
+
+pub const DEFAULT_LIMIT: u32 = 100;
+pub const MAX_LIMIT: u32 = 1000;
+// longer user agents are truncated to fit the column
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthEvent {
+    Authenticate,
+    Login,
+    Logout,
+}
+
+impl AuthEvent {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            AuthEvent::Authenticate => "authenticate",
+            AuthEvent::Login => "login",
+            AuthEvent::Logout => "logout",
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthOutcome {
+    Success,
+    Failure,
+    // rejected without checking the credentials, see `authentication::lockout`
+    LockedOut,
+}
+
+impl AuthOutcome {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            AuthOutcome::Success => "success",
+            AuthOutcome::Failure => "failure",
+            AuthOutcome::LockedOut => "locked_out",
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct AuthAuditEntry {
+    pub id: i64,
+    pub event: String,
+    pub outcome: String,
+    pub identifier: Option<String>,
+    pub api_key_id: Option<i32>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub date_created: Option<NaiveDateTime>,
+}
+
+/// An authentication event to be recorded, with the client of the `HttpRequest`.
+#[derive(Debug, Clone)]
+pub struct AuthAuditInsert {
+    pub event: AuthEvent,
+    pub outcome: AuthOutcome,
+    pub identifier: Option<String>,
+    pub api_key_id: Option<i32>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AuthAuditInsert {
+    pub fn new(http_request: &HttpRequest, event: AuthEvent, outcome: AuthOutcome) -> Self {
+        let user_agent = http_request.headers().get(USER_AGENT)
+            .and_then(|header| header.to_str().ok())
+            .map(|user_agent| user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        return Self {
+            event,
+            outcome,
+            identifier: None,
+            api_key_id: None,
+            // the same IP the rate limiter and the lockout use
+            ip: client_ip(http_request).map(|ip| ip.to_string()),
+            user_agent,
+        };
+    }
+
+    pub fn identifier(mut self, identifier: &str) -> Self {
+        self.identifier = Some(identifier.to_string());
+        return self;
+    }
+
+    pub fn api_key_id(mut self, api_key_id: Option<i32>) -> Self {
+        self.api_key_id = api_key_id;
+        return self;
+    }
+}
+
+// Query string filters of `/admin/audit-log`, the newest entries are returned first
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuthAuditFilter {
+    pub event: Option<AuthEvent>,
+    pub outcome: Option<AuthOutcome>,
+    pub identifier: Option<String>,
+    pub api_key_id: Option<i32>,
+    pub ip: Option<String>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+    pub limit: Option<u32>,
+}
+
+impl AuthAuditFilter {
+    pub fn limit(&self) -> Result<u32, String> {
+        return match self.limit {
+            None => Ok(DEFAULT_LIMIT),
+            Some(limit) if (limit == 0 || limit > MAX_LIMIT) => Err(format!("`limit` must be between 1 and {}.", MAX_LIMIT)),
+            Some(limit) => Ok(limit),
+        };
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuditLogError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for AuditLogError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuditLogError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AuditLogError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthAuditFilter, DEFAULT_LIMIT, MAX_LIMIT};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn limit_must_be_in_range(){
+        assert_eq!(AuthAuditFilter::default().limit(), Ok(DEFAULT_LIMIT));
+        assert_ok!(AuthAuditFilter { limit: Some(MAX_LIMIT), ..Default::default() }.limit());
+        assert_err!(AuthAuditFilter { limit: Some(0), ..Default::default() }.limit());
+        assert_err!(AuthAuditFilter { limit: Some(MAX_LIMIT + 1), ..Default::default() }.limit());
+    }
+}
@@ -1,20 +1,34 @@
 
 use actix_web::{
     web, post,
-    dev::{ServiceRequest}, HttpResponse,
+    dev::{ServiceRequest}, HttpMessage, HttpRequest, HttpResponse,
     web::Data,
 };
 use redis::{AsyncCommands};
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 use anyhow::{Result};
+use chrono::Utc;
+use sqlx::MySqlPool;
 use uuid::Uuid;
 
-use crate::configuration::ApiKey;
+use crate::api_key::{api_key_hash, api_key_service, ip_allowlist, ApiKeyError};
+use crate::client_ip::client_ip;
+use crate::audit_log::{audit_log_service, AuthAuditInsert, AuthEvent, AuthOutcome};
+use crate::configuration::{ApiKey, AuthLockoutSettings, Reloadable, SessionSettings};
+use crate::user::{user_service, User, UserError};
+use super::{lockout, sessions, Role, Scope, Session};
+use crate::envelope::Envelope;
 
+/// Returned by `/auth`, `/login` and `/token/refresh`.
+/// The `bearer` is sent in the `Authorization` header, when it expires the `refresh_token`
+/// can be exchanged for new tokens, so the API key or password don't have to be sent again.
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Bearer {
-    token: String,
+pub struct AuthTokens {
+    pub bearer: String,
+    pub refresh_token: String,
+    // seconds until the `bearer` expires
+    pub expires_in: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -22,6 +36,30 @@ pub struct ApiKeyRequest {
     pub api_key: String,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: Secret<String>,
+}
+
+/// Either the `Bearer` or the refresh token.
+#[derive(Deserialize, Debug)]
+pub struct RevokeTokenRequest {
+    pub token: Secret<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LogoutRequest {
+    pub refresh_token: Option<Secret<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: Secret<String>,
+    // required once the user enabled two-factor authentication
+    pub totp_code: Option<String>,
+}
+
 #[tracing::instrument(name = "Validator", skip(request))]
 // when sending a request to any route under auth middleware send a dummy bearer authentication token
 pub async fn validator(request: ServiceRequest, _: actix_web_httpauth::extractors::bearer::BearerAuth,) -> Result<ServiceRequest, actix_web::Error> {
@@ -43,21 +81,7 @@ pub async fn validator(request: ServiceRequest, _: actix_web_httpauth::extractor
         return Err(actix_web::error::ErrorInternalServerError("Failed to get `redis` data from app data."));
     }
 
-    // Decode the `Authorization` header value from base64
-    let decoded = base64::decode(request_bearer.replace("Bearer ", ""))
-        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to decode base64 header: {}.", e)))?;
-
-    let bearer = String::from_utf8(decoded)
-        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to parse decoded base64 to string: {}.", e)))?;
-
-    // Convert base64 to string
-    let session_id;
-    // split Bearer token and get the first part, that contains the session id
-    if let Some(bearer_request) = bearer.split(":").next(){
-        session_id = bearer_request;
-    } else {
-        return Err(actix_web::error::ErrorBadRequest("Bearer header is invalid."));
-    }
+    let session_id = session_id_from_bearer(request_bearer)?;
 
     // get connection to the redis database
     let mut con = redis.unwrap().get_async_connection()
@@ -65,26 +89,298 @@ pub async fn validator(request: ServiceRequest, _: actix_web_httpauth::extractor
         .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
 
     // query redis using the `session_id` from Bearer as key
-    let result: Option<String> = con.get(session_id).await
+    let result: Option<String> = con.get(&session_id).await
         .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?;
 
-    if let None = result {
-        return Err(actix_web::error::ErrorUnauthorized("Bearer token is invalid or has expired."));
+    let session: Session = match result {
+        // sessions that can't be parsed were created by an older version, they must authenticate again
+        Some(session) => serde_json::from_str(&session)
+            .map_err(|_| actix_web::error::ErrorUnauthorized("Bearer token is invalid or has expired."))?,
+        None => return Err(actix_web::error::ErrorUnauthorized("Bearer token is invalid or has expired.")),
+    };
+    if let Some(expires_at) = session.api_key_expires_at.filter(|expires_at| *expires_at <= Utc::now().naive_utc()) {
+        return Err(ApiKeyError::expired(expires_at).into());
     }
+    // the `X-Forwarded-For` of a trusted proxy only, any client can set it
+    if (!ip_allowlist::is_allowed(&session.allowed_ips, client_ip(request.request()))){
+        return Err(actix_web::error::ErrorForbidden("The API key can't be used from this IP."));
+    }
+    request.extensions_mut().insert(session);
 
     return Ok(request);
 }
 
 
-#[tracing::instrument(name = "Authenticate", skip(request, redis, api_key))]
+#[tracing::instrument(name = "Authenticate", skip(http_request, request, redis, api_key, pool, lockout_settings, session_settings))]
 // when sending a request to any route under auth middleware send a dummy bearer authentication token
 #[post("/auth")]
-pub async fn authenticate(request: web::Json<ApiKeyRequest>, redis: Data<redis::Client>, api_key: Data<ApiKey>) -> Result<HttpResponse, actix_web::Error> {
+pub async fn authenticate(http_request: HttpRequest, request: web::Json<ApiKeyRequest>, redis: Data<redis::Client>, api_key: Data<ApiKey>, pool: Data<MySqlPool>, lockout_settings: Data<AuthLockoutSettings>, session_settings: Data<Reloadable<SessionSettings>>) -> Result<HttpResponse, actix_web::Error> {
+    // only the prefix of the key is recorded in the audit log
+    let key_identifier = format!("api_key:{}", api_key_hash::key_prefix(&request.api_key));
+    // the failures count for the issued keys sharing the prefix, a random guess is for none of them and only counts for the IP
+    let targets: Vec<String> = api_key_service::find_ids_by_prefix(&request.api_key, &pool).await?
+        .into_iter()
+        .map(lockout_api_key)
+        .collect();
+    let lockout_identifiers = lockout::identifiers(&http_request, &targets);
+    let audit = |outcome| AuthAuditInsert::new(&http_request, AuthEvent::Authenticate, outcome).identifier(&key_identifier);
+    if let Err(error) = lockout::check(&redis, &lockout_settings, &lockout_identifiers).await {
+        if (error.as_error::<lockout::LockedOut>().is_some()){
+            audit_log_service::record(audit(AuthOutcome::LockedOut), &pool).await;
+        }
+        return Err(error);
+    }
+
+    // the configured key is always accepted as `admin` with every scope, the others are the ones issued on `/admin/api-keys`
+    let session = if (api_key_hash::verify_configured_api_key(api_key.0.expose_secret(), &request.api_key)){
+        Session { role: Role::Admin, scopes: Scope::ALL.to_vec(), api_key_id: None, api_key_expires_at: None, allowed_ips: vec![], user_id: None }
+    } else {
+        match api_key_service::find_active(&request.api_key, &pool).await {
+            Ok(Some(issued_api_key)) => Session {
+                role: issued_api_key.role.parse().unwrap_or(Role::Readonly),
+                scopes: Scope::parse_list(&issued_api_key.scopes),
+                api_key_id: Some(issued_api_key.id),
+                api_key_expires_at: issued_api_key.expires_at,
+                allowed_ips: issued_api_key.allowed_ips(),
+                user_id: None,
+            },
+            // the key is right, it doesn't count towards the lockout
+            Err(error @ ApiKeyError::ExpiredError(_)) => {
+                audit_log_service::record(audit(AuthOutcome::Failure), &pool).await;
+                return Err(error.into());
+            },
+            Err(error) => return Err(error.into()),
+            Ok(None) => {
+                audit_log_service::record(audit(AuthOutcome::Failure), &pool).await;
+                lockout::record_failure(&redis, &lockout_settings, &lockout_identifiers).await?;
+                return Err(actix_web::error::ErrorUnauthorized("Request token is invalid"));
+            },
+        }
+    };
+    if let Some(api_key_id) = session.api_key_id {
+        lockout::reset(&redis, &lockout_settings, &lockout_api_key(api_key_id)).await?;
+    }
+    if (!ip_allowlist::is_allowed(&session.allowed_ips, client_ip(&http_request))){
+        audit_log_service::record(audit(AuthOutcome::Failure).api_key_id(session.api_key_id), &pool).await;
+        return Err(actix_web::error::ErrorForbidden("The API key can't be used from this IP."));
+    }
+    let tokens = create_session(&redis, &session_settings.get(), &session).await?;
+    audit_log_service::record(audit(AuthOutcome::Success).api_key_id(session.api_key_id), &pool).await;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, tokens)));
+}
+
+#[tracing::instrument(name = "Login", skip(http_request, request, redis, pool, lockout_settings, session_settings), fields(username = %request.username))]
+// same as `/auth`, for the users instead of the systems integrating with the API
+#[post("/login")]
+pub async fn login(http_request: HttpRequest, request: web::Json<LoginRequest>, redis: Data<redis::Client>, pool: Data<MySqlPool>, lockout_settings: Data<AuthLockoutSettings>, session_settings: Data<Reloadable<SessionSettings>>) -> Result<HttpResponse, actix_web::Error> {
+    let request = request.into_inner();
+    let user_identifier = format!("user:{}", request.username.trim());
+    // the failures count for the user the username is of, not for the username as typed, unknown usernames only count for the IP
+    let targets: Vec<String> = user_service::find_by_username(&request.username, &pool).await?
+        .map(|user| lockout_user(user.id))
+        .into_iter()
+        .collect();
+    let lockout_identifiers = lockout::identifiers(&http_request, &targets);
+    let audit = |outcome| AuthAuditInsert::new(&http_request, AuthEvent::Login, outcome).identifier(&user_identifier);
+    if let Err(error) = lockout::check(&redis, &lockout_settings, &lockout_identifiers).await {
+        if (error.as_error::<lockout::LockedOut>().is_some()){
+            audit_log_service::record(audit(AuthOutcome::LockedOut), &pool).await;
+        }
+        return Err(error);
+    }
 
-    let api_key = api_key.0.expose_secret().to_string();
-    if (request.api_key != api_key){
-        return Err(actix_web::error::ErrorUnauthorized("Request token is invalid"));
+    let user = match user_service::verify_credentials(&request.username, request.password, &pool).await {
+        Ok(user) => user,
+        Err(UserError::InvalidCredentialsError) => {
+            audit_log_service::record(audit(AuthOutcome::Failure), &pool).await;
+            lockout::record_failure(&redis, &lockout_settings, &lockout_identifiers).await?;
+            return Err(UserError::InvalidCredentialsError.into());
+        },
+        Err(error) => return Err(error.into()),
+    };
+    if (user.totp_enabled){
+        let verified = match &request.totp_code {
+            Some(code) => user_service::verify_totp(&user, code, &pool).await,
+            None => Err(UserError::InvalidTotpCodeError),
+        };
+        if let Err(error) = verified {
+            audit_log_service::record(audit(AuthOutcome::Failure), &pool).await;
+            lockout::record_failure(&redis, &lockout_settings, &lockout_identifiers).await?;
+            return Err(error.into());
+        }
     }
+    lockout::reset(&redis, &lockout_settings, &lockout_user(user.id)).await?;
+
+    // users are only limited by their role
+    let role = user_role(&user, &session_settings.get());
+    let session = Session { role, scopes: Scope::ALL.to_vec(), api_key_id: None, api_key_expires_at: None, allowed_ips: vec![], user_id: Some(user.id) };
+    let tokens = create_session(&redis, &session_settings.get(), &session).await?;
+    audit_log_service::record(audit(AuthOutcome::Success), &pool).await;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, tokens)));
+}
+
+#[tracing::instrument(name = "Refresh token", skip(http_request, request, redis, pool, session_settings))]
+// the refresh token can only be used once, a new one is returned with the new Bearer
+#[post("/token/refresh")]
+pub async fn refresh_session(http_request: HttpRequest, request: web::Json<RefreshTokenRequest>, redis: Data<redis::Client>, pool: Data<MySqlPool>, session_settings: Data<Reloadable<SessionSettings>>) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+
+    // GETDEL so the same token can't be used twice by concurrent requests
+    let key = refresh_token_key(request.refresh_token.expose_secret());
+    let (remaining_seconds, session): (i64, Option<String>) = redis::pipe()
+        .atomic()
+        .ttl(&key)
+        .cmd("GETDEL").arg(&key)
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?;
+
+    let session: Session = session
+        .and_then(|session| serde_json::from_str(&session).ok())
+        .filter(|_| remaining_seconds > 0)
+        .ok_or(actix_web::error::ErrorUnauthorized("Refresh token is invalid or has expired."))?;
+
+    let settings = session_settings.get();
+    let session = reload_session(session, &pool, &settings).await?;
+    // the new refresh token expires with the one it replaces, the session can't be refreshed forever
+    let remaining_seconds = remaining_seconds as u64;
+    let settings = SessionSettings {
+        expiration_seconds: settings.expiration_seconds.min(remaining_seconds),
+        refresh_token_expiration_seconds: remaining_seconds,
+        ..settings
+    };
+    let tokens = create_session(&redis, &settings, &session).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, tokens)));
+}
+
+/// The `session` with the current role, scopes and IP allowlist of its API key or user,
+/// `Unauthorized` when the key was revoked or expired, or the user deleted, since it was created.
+async fn reload_session(session: Session, pool: &MySqlPool, settings: &SessionSettings) -> Result<Session, actix_web::Error> {
+    if let Some(api_key_id) = session.api_key_id {
+        let issued_api_key = api_key_service::find_active_by_id(api_key_id, pool).await?
+            .ok_or(actix_web::error::ErrorUnauthorized("Refresh token is invalid or has expired."))?;
+        return Ok(Session {
+            role: issued_api_key.role.parse().unwrap_or(Role::Readonly),
+            scopes: Scope::parse_list(&issued_api_key.scopes),
+            api_key_expires_at: issued_api_key.expires_at,
+            allowed_ips: issued_api_key.allowed_ips(),
+            ..session
+        });
+    }
+    if let Some(user_id) = session.user_id {
+        let user = user_service::find_by_id(user_id, pool).await?
+            .ok_or(actix_web::error::ErrorUnauthorized("Refresh token is invalid or has expired."))?;
+        return Ok(Session { role: user_role(&user, settings), ..session });
+    }
+    // the configured key and the OIDC logins have nothing stored to check again
+    return Ok(session);
+}
+
+fn user_role(user: &User, settings: &SessionSettings) -> Role {
+    let role = user.role.parse().unwrap_or(Role::Readonly);
+    if (role == Role::Admin && !user.totp_enabled && settings.admin_requires_totp){
+        // enough to enroll on `/account/totp` and log in again
+        return Role::Readonly;
+    }
+    return role;
+}
+
+#[tracing::instrument(name = "Revoke token", skip(request, redis))]
+// deleting the token from redis is enough for the validator (or `/token/refresh`) to reject it from now on
+#[post("/token/revoke")]
+pub async fn revoke_token(request: web::Json<RevokeTokenRequest>, redis: Data<redis::Client>) -> Result<HttpResponse, actix_web::Error> {
+    let token = request.token.expose_secret();
+    let key = if (token.starts_with("Bearer ")){
+        session_id_from_bearer(token)?
+    } else {
+        refresh_token_key(token)
+    };
+
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+    conn.del::<_, ()>(key)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to revoke token: {}.", e)))?;
+
+    // unknown or already expired tokens are not an error, the result is the same
+    return Ok(HttpResponse::NoContent().finish());
+}
+
+#[tracing::instrument(name = "Logout", skip(http_request, request, redis, pool))]
+// ends the session of the `Authorization` header, and its refresh token when sent
+#[post("/logout")]
+pub async fn logout(http_request: HttpRequest, request: Option<web::Json<LogoutRequest>>, redis: Data<redis::Client>, pool: Data<MySqlPool>) -> Result<HttpResponse, actix_web::Error> {
+    let bearer = http_request.headers().get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .ok_or(actix_web::error::ErrorUnauthorized("`Authorization` header is missing."))?;
+
+    let session_id = session_id_from_bearer(bearer)?;
+    let mut keys = vec![session_id.clone()];
+    if let Some(refresh_token) = request.and_then(|request| request.into_inner().refresh_token) {
+        keys.push(refresh_token_key(refresh_token.expose_secret()));
+    }
+
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+    // read before deleting it, only to know whose session ended for the audit log
+    let session: Option<Session> = conn.get::<_, Option<String>>(&session_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?
+        .and_then(|session| serde_json::from_str(&session).ok());
+    conn.del::<_, ()>(keys)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to delete session: {}.", e)))?;
+
+    let outcome = if (session.is_some()) { AuthOutcome::Success } else { AuthOutcome::Failure };
+    let mut entry = AuthAuditInsert::new(&http_request, AuthEvent::Logout, outcome)
+        .api_key_id(session.as_ref().and_then(|session| session.api_key_id));
+    if let Some(user_id) = session.as_ref().and_then(|session| session.user_id) {
+        entry = entry.identifier(&format!("user_id:{}", user_id));
+    }
+    audit_log_service::record(entry, &pool).await;
+
+    return Ok(HttpResponse::NoContent().finish());
+}
+
+/// Decode the `Bearer <base64 of session_id:session_token>` value sent in the `Authorization` header.
+fn session_id_from_bearer(bearer: &str) -> Result<String, actix_web::Error> {
+    // Decode the `Authorization` header value from base64
+    let decoded = base64::decode(bearer.replace("Bearer ", ""))
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to decode base64 header: {}.", e)))?;
+
+    let bearer = String::from_utf8(decoded)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to parse decoded base64 to string: {}.", e)))?;
+
+    // split Bearer token and get the first part, that contains the session id
+    return match bearer.split(':').next() {
+        Some(session_id) => Ok(session_id.to_string()),
+        None => Err(actix_web::error::ErrorBadRequest("Bearer header is invalid.")),
+    };
+}
+
+fn lockout_api_key(api_key_id: i32) -> String {
+    return format!("api_key_id:{}", api_key_id);
+}
+
+fn lockout_user(user_id: i32) -> String {
+    return format!("user_id:{}", user_id);
+}
+
+pub fn refresh_token_key(refresh_token: &str) -> String {
+    return format!("refresh_token:{}", refresh_token);
+}
+
+/// Store the session and its refresh token on redis.
+pub async fn create_session(redis: &redis::Client, settings: &SessionSettings, session: &Session) -> Result<AuthTokens, actix_web::Error> {
+    let session_json = serde_json::to_string(session)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to serialize session: {}.", e)))?;
 
     let mut conn = redis
         .get_async_connection()
@@ -92,22 +388,26 @@ pub async fn authenticate(request: web::Json<ApiKeyRequest>, redis: Data<redis::
         .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
 
     let session_id = Uuid::new_v4();
-    // we are only using the session id for its `key`, the `value` actually is not being used
-    // so lets insert it empty to save some memory.
+    // the session id is the only part of the Bearer that is checked, so the token is sent empty
     let session_token = "".to_string();
     
-    // 1 hour
-    let expiration = 1 * 60 * 60;
-    // insert on redis the session as session_id = session_token
-    conn.set_ex(session_id.to_string(), session_token.to_string(), expiration)
+    // insert on redis the session as session_id = session, so the validator knows what the session is allowed to do
+    conn.set_ex::<_, _, ()>(session_id.to_string(), &session_json, settings.expiration_seconds as usize)
         .await
         .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to insert session token: {}.", e)))?;
 
+    // the refresh token keeps a copy of the session, to create the next one with the same permissions
+    let refresh_token = Uuid::new_v4().simple().to_string();
+    conn.set_ex::<_, _, ()>(refresh_token_key(&refresh_token), &session_json, settings.refresh_token_expiration_seconds as usize)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to insert refresh token: {}.", e)))?;
+    // so it can be listed and ended on `/admin/sessions`
+    sessions::index(&mut conn, settings, &session_id.to_string(), &refresh_token, session).await?;
 
-    let bearer_base64 = base64::encode(format!("{}:{}", session_id.to_string(), session_token.to_string()));
-    let bearer = format!("Bearer {}", bearer_base64);
-
-    // request.extensions_mut().insert(Bearer { token: String::from(request_token) });
-    return Ok(HttpResponse::Ok().json(bearer));
+    let bearer_base64 = base64::encode(format!("{}:{}", session_id, session_token));
+    return Ok(AuthTokens {
+        bearer: format!("Bearer {}", bearer_base64),
+        refresh_token,
+        expires_in: settings.expiration_seconds,
+    });
 }
-
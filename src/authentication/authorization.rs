@@ -0,0 +1,156 @@
+use super::role::Role;
+use crate::api_key::ip_allowlist::Cidr;
+use super::scope::Scope;
+use actix_web::{
+    Error, HttpMessage,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+};
+use chrono::NaiveDateTime;
+use serde::{Serialize, Deserialize};
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+
+/// What the session is allowed to do, stored on redis by `/auth`
+/// and inserted in the request extensions by the `validator`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub role: Role,
+    pub scopes: Vec<Scope>,
+    // the issued API key used on `/auth`, `None` for the configured key and the logins
+    #[serde(default)]
+    pub api_key_id: Option<i32>,
+    // checked by the `validator` on every request, so the sessions don't outlive their key
+    #[serde(default)]
+    pub api_key_expires_at: Option<NaiveDateTime>,
+    // IP allowlist of the API key, checked by the `validator` on every request
+    #[serde(default)]
+    pub allowed_ips: Vec<Cidr>,
+    // the user of `/login`, `None` for the API keys and the OIDC logins
+    #[serde(default)]
+    pub user_id: Option<i32>,
+}
+
+/// Minimum role and the scope needed to call a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permission {
+    pub role: Role,
+    pub scope: Scope,
+}
+
+impl Permission {
+    pub const ADMIN: Permission = Permission { role: Role::Admin, scope: Scope::Admin };
+    // the imports are followed on `/jobs` by who can start them
+    pub const COUPON_WRITE: Permission = Permission { role: Role::Editor, scope: Scope::CouponWrite };
+
+    /// Reading, verifying and applying coupons is open to every role, changing them needs at least `editor`.
+    pub fn for_coupon_route(method: &Method, path: &str) -> Permission {
+        let scope = Scope::for_coupon_route(method, path);
+        let role = if (method == Method::GET || method == Method::HEAD || scope == Scope::CouponRedeem){
+            Role::Readonly
+        } else {
+            Role::Editor
+        };
+        return Permission { role, scope };
+    }
+}
+
+impl Session {
+    pub fn authorize(&self, permission: Permission) -> Result<(), String> {
+        if (self.role < permission.role){
+            return Err(format!("The `{}` role is required.", permission.role.as_str()));
+        }
+        if (!self.scopes.contains(&permission.scope)){
+            return Err(format!("The `{}` scope is required.", permission.scope.as_str()));
+        }
+        return Ok(());
+    }
+}
+
+/// Reject with `403` the requests whose session doesn't have the role or scope required by the route.
+/// Must be wrapped inside the authentication middleware, which is the one inserting the `Session`.
+#[derive(Clone, Copy)]
+pub struct Authorize {
+    required: fn(&ServiceRequest) -> Permission,
+}
+
+impl Authorize {
+    pub fn new(required: fn(&ServiceRequest) -> Permission) -> Self {
+        return Self { required };
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Authorize
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthorizeMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(AuthorizeMiddleware { service: Rc::new(service), required: self.required }));
+    }
+}
+
+pub struct AuthorizeMiddleware<S> {
+    service: Rc<S>,
+    required: fn(&ServiceRequest) -> Permission,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthorizeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let required = (self.required)(&request);
+        let authorized = match request.extensions().get::<Session>() {
+            Some(session) => session.authorize(required),
+            None => Err("The request has no session.".to_string()),
+        };
+
+        return Box::pin(async move {
+            authorized.map_err(actix_web::error::ErrorForbidden)?;
+            return service.call(request).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Session};
+    use crate::authentication::{Role, Scope};
+    use actix_web::http::Method;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn session_needs_both_the_role_and_the_scope(){
+        let readonly = Session { role: Role::Readonly, scopes: Scope::ALL.to_vec(), api_key_id: None, api_key_expires_at: None, allowed_ips: vec![], user_id: None };
+        assert_ok!(readonly.authorize(Permission::for_coupon_route(&Method::GET, "/coupon/CODE")));
+        assert_err!(readonly.authorize(Permission::for_coupon_route(&Method::DELETE, "/coupon/CODE")));
+        assert_ok!(readonly.authorize(Permission::for_coupon_route(&Method::POST, "/coupon/apply")));
+        assert_err!(readonly.authorize(Permission::ADMIN));
+
+        let editor = Session { role: Role::Editor, scopes: vec![Scope::CouponRead], api_key_id: None, api_key_expires_at: None, allowed_ips: vec![], user_id: None };
+        assert_ok!(editor.authorize(Permission::for_coupon_route(&Method::GET, "/coupon/CODE")));
+        assert_err!(editor.authorize(Permission::for_coupon_route(&Method::DELETE, "/coupon/CODE")));
+
+        let admin = Session { role: Role::Admin, scopes: Scope::ALL.to_vec(), api_key_id: None, api_key_expires_at: None, allowed_ips: vec![], user_id: None };
+        assert_ok!(admin.authorize(Permission::ADMIN));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::client_ip::client_ip;
+use crate::configuration::AuthLockoutSettings;
+use actix_web::{
+    HttpRequest, HttpResponse, ResponseError,
+    http::{StatusCode, header::RETRY_AFTER},
+};
+use redis::AsyncCommands;
+
+
+/// Who is trying to authenticate, every failed attempt counts for all of them.
+/// The IP stops a client guessing many keys, the `targets` (the API keys or the user the attempt is for)
+/// stop many clients guessing the same one.
+pub fn identifiers(http_request: &HttpRequest, targets: &[String]) -> Vec<String> {
+    let ip = client_ip(http_request).map(|ip| ip.to_string()).unwrap_or_else(|| "unknown".to_string());
+    let mut identifiers = vec![format!("ip:{}", ip)];
+    identifiers.extend(targets.iter().cloned());
+    return identifiers;
+}
+
+/// Reject with `429` while any of the `identifiers` is locked out.
+pub async fn check(redis: &redis::Client, settings: &AuthLockoutSettings, identifiers: &[String]) -> Result<(), actix_web::Error> {
+    if (!settings.enabled){
+        return Ok(());
+    }
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+
+    for identifier in identifiers {
+        // `-2` when the key doesn't exist
+        let ttl: i64 = conn.ttl(lockout_key(identifier))
+            .await
+            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?;
+        if (ttl > 0){
+            return Err(LockedOut { retry_after: ttl as u64 }.into());
+        }
+    }
+    return Ok(());
+}
+
+/// Count a failed attempt, locking out the identifiers that reached `max_failed_attempts`.
+/// Each failure after that doubles the lockout, up to `max_lockout_seconds`.
+pub async fn record_failure(redis: &redis::Client, settings: &AuthLockoutSettings, identifiers: &[String]) -> Result<(), actix_web::Error> {
+    if (!settings.enabled){
+        return Ok(());
+    }
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+
+    for identifier in identifiers {
+        let failures: u32 = conn.incr(failures_key(identifier), 1)
+            .await
+            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to count failed attempt: {}.", e)))?;
+        // the count is forgotten after a while without failures, it must outlive the longest lockout
+        conn.expire::<_, ()>(failures_key(identifier), settings.max_lockout_seconds as usize)
+            .await
+            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to count failed attempt: {}.", e)))?;
+
+        if let Some(lockout_seconds) = lockout_seconds(settings, failures) {
+            conn.set_ex::<_, _, ()>(lockout_key(identifier), failures, lockout_seconds as usize)
+                .await
+                .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to insert lockout: {}.", e)))?;
+            tracing::warn!("`{}` locked out for {} seconds after {} failed authentication attempts.", identifier, lockout_seconds, failures);
+        }
+    }
+    return Ok(());
+}
+
+/// Forget the failed attempts of `identifier`, after it authenticated.
+pub async fn reset(redis: &redis::Client, settings: &AuthLockoutSettings, identifier: &str) -> Result<(), actix_web::Error> {
+    if (!settings.enabled){
+        return Ok(());
+    }
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+    conn.del::<_, ()>(failures_key(identifier))
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to reset failed attempts: {}.", e)))?;
+    return Ok(());
+}
+
+fn lockout_seconds(settings: &AuthLockoutSettings, failures: u32) -> Option<u64> {
+    if (failures < settings.max_failed_attempts.max(1)){
+        return None;
+    }
+    let doublings = (failures - settings.max_failed_attempts.max(1)).min(32);
+    return Some(settings.lockout_seconds.saturating_mul(1u64 << doublings).min(settings.max_lockout_seconds));
+}
+
+fn failures_key(identifier: &str) -> String {
+    return format!("auth_failures:{}", identifier);
+}
+
+fn lockout_key(identifier: &str) -> String {
+    return format!("auth_lockout:{}", identifier);
+}
+
+#[derive(Debug)]
+pub struct LockedOut {
+    // seconds until the lockout ends
+    pub retry_after: u64,
+}
+
+impl std::fmt::Display for LockedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "Too many failed authentication attempts, retry in {} seconds.", self.retry_after);
+    }
+}
+
+impl ResponseError for LockedOut {
+    fn status_code(&self) -> StatusCode {
+        return StatusCode::TOO_MANY_REQUESTS;
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        return HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, self.retry_after))
+            .body(self.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lockout_seconds;
+    use crate::configuration::AuthLockoutSettings;
+
+    #[test]
+    fn lockout_doubles_after_each_failure_up_to_the_maximum(){
+        let settings = AuthLockoutSettings { enabled: true, max_failed_attempts: 3, lockout_seconds: 60, max_lockout_seconds: 300 };
+
+        assert_eq!(lockout_seconds(&settings, 2), None);
+        assert_eq!(lockout_seconds(&settings, 3), Some(60));
+        assert_eq!(lockout_seconds(&settings, 4), Some(120));
+        assert_eq!(lockout_seconds(&settings, 5), Some(240));
+        assert_eq!(lockout_seconds(&settings, 6), Some(300));
+        assert_eq!(lockout_seconds(&settings, 100), Some(300));
+    }
+}
@@ -0,0 +1,63 @@
+use super::{request_signing, validator};
+use actix_web::{
+    Error,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+
+/// Authenticate the request either with the `Bearer` of a session (see the `validator`)
+/// or, when it has the `X-Signature` header, with the signature of an API key (see `request_signing`).
+#[derive(Clone, Copy, Default)]
+pub struct Authenticate;
+
+impl<S, B> Transform<S, ServiceRequest> for Authenticate
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthenticateMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(AuthenticateMiddleware { service: Rc::new(service) }));
+    }
+}
+
+pub struct AuthenticateMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticateMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        return Box::pin(async move {
+            let request = if (request_signing::is_signed(&request)){
+                request_signing::validator(request).await?
+            } else {
+                // rejects the requests without a `Bearer` with `401`
+                let bearer = request.extract::<BearerAuth>().await?;
+                validator(request, bearer).await?
+            };
+            return service.call(request).await;
+        });
+    }
+}
@@ -1,3 +1,17 @@
 pub mod auth;
+pub mod authorization;
+pub mod lockout;
+pub mod middleware;
+pub mod oidc;
+pub mod request_signing;
+pub mod role;
+pub mod scope;
+pub mod sessions;
 
 pub use auth::*;
+pub use authorization::*;
+pub use middleware::*;
+pub use oidc::*;
+pub use role::*;
+pub use scope::*;
+pub use sessions::*;
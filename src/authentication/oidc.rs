@@ -0,0 +1,350 @@
+use super::{create_session, Scope, Session};
+use crate::configuration::{OidcSettings, Reloadable, SessionSettings};
+use crate::envelope::Envelope;
+use actix_web::{
+    web, get, HttpRequest, HttpResponse,
+    http::header::LOCATION,
+    web::Data,
+};
+use jsonwebtoken::{
+    decode, decode_header, Algorithm, DecodingKey, Validation,
+    jwk::{AlgorithmParameters, EllipticCurve, EllipticCurveKeyParameters, Jwk, JwkSet},
+};
+use moka::sync::Cache;
+use once_cell::sync::Lazy;
+use redis::AsyncCommands;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+
+// time the user has to login in the provider
+const STATE_EXPIRATION_SECONDS: usize = 10 * 60;
+
+// the asymmetric algorithms, a token signed with a shared secret (or with none) is never accepted
+const ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256, Algorithm::RS384, Algorithm::RS512,
+    Algorithm::PS256, Algorithm::PS384, Algorithm::PS512,
+    Algorithm::ES256, Algorithm::ES384, Algorithm::EdDSA,
+];
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// the provider configuration and keys rarely change, a key rotation is seen once they expire
+const PROVIDER_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+// shared by the logins, so a slow provider can't hold the requests
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    return reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+});
+// by issuer URL
+static PROVIDER_METADATA: Lazy<Cache<String, ProviderMetadata>> = Lazy::new(provider_cache);
+// by `jwks_uri`
+static PROVIDER_KEYS: Lazy<Cache<String, JwkSet>> = Lazy::new(provider_cache);
+
+#[derive(Deserialize, Debug, Clone)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CallbackQuery {
+    pub code: String,
+    pub state: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: Option<bool>,
+    // not standard, sent by Keycloak, Okta and others once configured
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[tracing::instrument(name = "OIDC login", skip(settings, redis))]
+// redirect to the provider, which redirects back to `/auth/oidc/callback` after the login
+#[get("/auth/oidc/login")]
+pub async fn oidc_login(settings: Data<Option<OidcSettings>>, redis: Data<redis::Client>) -> Result<HttpResponse, actix_web::Error> {
+    let settings = get_settings(&settings)?;
+    let metadata = discover(settings).await?;
+
+    // `state` protects the callback from CSRF, `nonce` ties the ID token to this login
+    let state = Uuid::new_v4().simple().to_string();
+    let nonce = Uuid::new_v4().simple().to_string();
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+    conn.set_ex::<_, _, ()>(state_key(&state), &nonce, STATE_EXPIRATION_SECONDS)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to insert OIDC state: {}.", e)))?;
+
+    let mut authorization_url = url::Url::parse(&metadata.authorization_endpoint)
+        .map_err(|e| actix_web::error::ErrorBadGateway(format!("Invalid OIDC authorization endpoint: {}.", e)))?;
+    authorization_url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &settings.client_id)
+        .append_pair("redirect_uri", &settings.redirect_url)
+        .append_pair("scope", "openid email")
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce);
+
+    return Ok(HttpResponse::Found().insert_header((LOCATION, authorization_url.to_string())).finish());
+}
+
+#[tracing::instrument(name = "OIDC callback", skip(http_request, query, settings, redis, session_settings))]
+// exchange the authorization code for the ID token and, when valid, create a session like `/auth` does
+#[get("/auth/oidc/callback")]
+pub async fn oidc_callback(http_request: HttpRequest, query: web::Query<CallbackQuery>, settings: Data<Option<OidcSettings>>, redis: Data<redis::Client>, session_settings: Data<Reloadable<SessionSettings>>) -> Result<HttpResponse, actix_web::Error> {
+    let settings = get_settings(&settings)?;
+
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+    // GETDEL so each state can only be used once
+    let nonce: Option<String> = redis::cmd("GETDEL")
+        .arg(state_key(&query.state))
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?;
+    let nonce = nonce.ok_or(actix_web::error::ErrorUnauthorized("OIDC state is invalid or has expired."))?;
+
+    let metadata = discover(settings).await?;
+    let tokens: TokenResponse = CLIENT
+        .post(&metadata.token_endpoint)
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", query.code.as_str()),
+            ("redirect_uri", settings.redirect_url.as_str()),
+            ("client_id", settings.client_id.as_str()),
+            ("client_secret", settings.client_secret.expose_secret().as_str()),
+        ])
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| actix_web::error::ErrorBadGateway(format!("Failed to exchange the OIDC authorization code: {}.", e)))?
+        .json()
+        .await
+        .map_err(|e| actix_web::error::ErrorBadGateway(format!("Invalid OIDC token response: {}.", e)))?;
+
+    let header = decode_header(&tokens.id_token)
+        .map_err(|e| actix_web::error::ErrorUnauthorized(format!("Invalid ID token: {}.", e)))?;
+    let kid = header.kid.as_ref()
+        .ok_or(actix_web::error::ErrorUnauthorized("ID token was not signed by a key of the provider."))?;
+    let mut jwks = provider_keys(&metadata.jwks_uri).await?;
+    // signed by a key added since they were cached
+    if (jwks.find(kid).is_none()){
+        PROVIDER_KEYS.invalidate(&metadata.jwks_uri);
+        jwks = provider_keys(&metadata.jwks_uri).await?;
+    }
+    let jwk = jwks.find(kid)
+        .ok_or(actix_web::error::ErrorUnauthorized("ID token was not signed by a key of the provider."))?;
+    let key = DecodingKey::from_jwk(jwk)
+        .map_err(|e| actix_web::error::ErrorUnauthorized(format!("Invalid OIDC provider key: {}.", e)))?;
+    // the algorithm of the provider key, the one of the header is chosen by whoever sent the token
+    let algorithm = key_algorithm(jwk).map_err(actix_web::error::ErrorUnauthorized)?;
+    if (header.alg != algorithm){
+        return Err(actix_web::error::ErrorUnauthorized("ID token algorithm doesn't match the provider key."));
+    }
+
+    let claims = validate_id_token(&tokens.id_token, &key, algorithm, &metadata.issuer, &settings.client_id, &nonce)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+    let account = claims.email.as_deref().unwrap_or(&claims.sub);
+    if (!is_allowed(settings, &claims)){
+        tracing::warn!("OIDC login of `{}` rejected, it isn't in the allowed subjects, emails or groups.", account);
+        return Err(actix_web::error::ErrorForbidden("The account isn't allowed to log in."));
+    }
+    tracing::info!("OIDC login of `{}`.", account);
+
+    let session = Session { role: settings.role, scopes: Scope::ALL.to_vec(), api_key_id: None, api_key_expires_at: None, allowed_ips: vec![], user_id: None };
+    let tokens = create_session(&redis, &session_settings.get(), &session).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, tokens)));
+}
+
+/// Check the signature, issuer, audience, expiration and nonce of the ID token.
+pub fn validate_id_token(id_token: &str, key: &DecodingKey, algorithm: Algorithm, issuer: &str, client_id: &str, nonce: &str) -> Result<IdTokenClaims, String> {
+    let mut validation = Validation::new(algorithm);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[client_id]);
+
+    let claims = decode::<IdTokenClaims>(id_token, key, &validation)
+        .map_err(|e| format!("Invalid ID token: {}.", e))?
+        .claims;
+    if (claims.nonce.as_deref() != Some(nonce)){
+        return Err("ID token nonce doesn't match the login.".to_string());
+    }
+    return Ok(claims);
+}
+
+/// The algorithm of the ID tokens signed with `jwk`, its `alg` or the usual one of its key type.
+fn key_algorithm(jwk: &Jwk) -> Result<Algorithm, String> {
+    let algorithm = match (jwk.common.algorithm, &jwk.algorithm) {
+        (Some(algorithm), _) => algorithm,
+        (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+        (None, AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters { curve: EllipticCurve::P256, .. })) => Algorithm::ES256,
+        (None, AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters { curve: EllipticCurve::P384, .. })) => Algorithm::ES384,
+        (None, AlgorithmParameters::OctetKeyPair(_)) => Algorithm::EdDSA,
+        _ => return Err("The OIDC provider key has no supported algorithm.".to_string()),
+    };
+    if (!ALLOWED_ALGORITHMS.contains(&algorithm)){
+        return Err(format!("ID tokens signed with `{:?}` are not accepted.", algorithm));
+    }
+    return Ok(algorithm);
+}
+
+/// Whether the account of the ID token is one of the configured `allowed_subjects`, `allowed_emails` or `allowed_groups`.
+/// The email only counts once the provider verified it.
+fn is_allowed(settings: &OidcSettings, claims: &IdTokenClaims) -> bool {
+    let verified_email = claims.email.as_deref().filter(|_| claims.email_verified == Some(true));
+    return settings.allowed_subjects.contains(&claims.sub)
+        || verified_email.map_or(false, |email| settings.allowed_emails.iter().any(|allowed| allowed.eq_ignore_ascii_case(email)))
+        || claims.groups.iter().any(|group| settings.allowed_groups.contains(group));
+}
+
+fn get_settings(settings: &Option<OidcSettings>) -> Result<&OidcSettings, actix_web::Error> {
+    return settings.as_ref().ok_or(actix_web::error::ErrorNotFound("OIDC login is not configured."));
+}
+
+fn provider_cache<V: Clone + Send + Sync + 'static>() -> Cache<String, V> {
+    return Cache::builder()
+        .max_capacity(16)
+        .time_to_live(PROVIDER_CACHE_TTL)
+        .build();
+}
+
+async fn discover(settings: &OidcSettings) -> Result<ProviderMetadata, actix_web::Error> {
+    if let Some(metadata) = PROVIDER_METADATA.get(&settings.issuer_url) {
+        return Ok(metadata);
+    }
+    let url = format!("{}/.well-known/openid-configuration", settings.issuer_url.trim_end_matches('/'));
+    let metadata: ProviderMetadata = CLIENT
+        .get(&url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| actix_web::error::ErrorBadGateway(format!("Failed to get the OIDC provider configuration: {}.", e)))?
+        .json()
+        .await
+        .map_err(|e| actix_web::error::ErrorBadGateway(format!("Invalid OIDC provider configuration: {}.", e)))?;
+    PROVIDER_METADATA.insert(settings.issuer_url.clone(), metadata.clone());
+    return Ok(metadata);
+}
+
+async fn provider_keys(jwks_uri: &str) -> Result<JwkSet, actix_web::Error> {
+    if let Some(jwks) = PROVIDER_KEYS.get(jwks_uri) {
+        return Ok(jwks);
+    }
+    let jwks: JwkSet = CLIENT
+        .get(jwks_uri)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| actix_web::error::ErrorBadGateway(format!("Failed to get the OIDC provider keys: {}.", e)))?
+        .json()
+        .await
+        .map_err(|e| actix_web::error::ErrorBadGateway(format!("Invalid OIDC provider keys: {}.", e)))?;
+    PROVIDER_KEYS.insert(jwks_uri.to_string(), jwks.clone());
+    return Ok(jwks);
+}
+
+fn state_key(state: &str) -> String {
+    return format!("oidc_state:{}", state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_allowed, key_algorithm, validate_id_token, IdTokenClaims};
+    use crate::authentication::Role;
+    use crate::configuration::OidcSettings;
+    use claim::{assert_err, assert_ok};
+    use jsonwebtoken::{encode, jwk::Jwk, Algorithm, DecodingKey, EncodingKey, Header};
+    use secrecy::Secret;
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"secret";
+
+    fn id_token(issuer: &str, audience: &str, nonce: &str, exp_offset: i64) -> String {
+        let exp = chrono::Utc::now().timestamp() + exp_offset;
+        let claims = json!({"sub": "user", "iss": issuer, "aud": audience, "nonce": nonce, "exp": exp});
+        return encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(SECRET)).unwrap();
+    }
+
+    fn validate(id_token: &str) -> Result<super::IdTokenClaims, String> {
+        return validate_id_token(id_token, &DecodingKey::from_secret(SECRET), Algorithm::HS256, "https://issuer", "client", "nonce");
+    }
+
+    #[test]
+    fn valid_id_token_is_accepted(){
+        let claims = assert_ok!(validate(&id_token("https://issuer", "client", "nonce", 60)));
+        assert_eq!(claims.sub, "user");
+    }
+
+    #[test]
+    fn id_token_with_wrong_claims_is_rejected(){
+        assert_err!(validate(&id_token("https://other", "client", "nonce", 60)));
+        assert_err!(validate(&id_token("https://issuer", "other", "nonce", 60)));
+        assert_err!(validate(&id_token("https://issuer", "client", "other", 60)));
+        assert_err!(validate(&id_token("https://issuer", "client", "nonce", -120)));
+    }
+
+    #[test]
+    fn algorithm_comes_from_the_provider_key(){
+        let jwk = |value| serde_json::from_value::<Jwk>(value).unwrap();
+
+        let rsa = jwk(json!({"kty": "RSA", "kid": "1", "n": "AQAB", "e": "AQAB"}));
+        assert_eq!(assert_ok!(key_algorithm(&rsa)), Algorithm::RS256);
+        let ps256 = jwk(json!({"kty": "RSA", "kid": "1", "alg": "PS256", "n": "AQAB", "e": "AQAB"}));
+        assert_eq!(assert_ok!(key_algorithm(&ps256)), Algorithm::PS256);
+        let ec = jwk(json!({"kty": "EC", "kid": "1", "crv": "P-256", "x": "AQAB", "y": "AQAB"}));
+        assert_eq!(assert_ok!(key_algorithm(&ec)), Algorithm::ES256);
+        // a shared secret would let anyone knowing it sign tokens
+        let secret = jwk(json!({"kty": "oct", "kid": "1", "alg": "HS256", "k": "c2VjcmV0"}));
+        assert_err!(key_algorithm(&secret));
+    }
+
+    #[test]
+    fn only_the_allowed_accounts_can_log_in(){
+        let settings = OidcSettings {
+            issuer_url: "https://issuer".to_string(),
+            client_id: "client".to_string(),
+            client_secret: Secret::new("secret".to_string()),
+            redirect_url: "https://api/auth/oidc/callback".to_string(),
+            role: Role::Readonly,
+            allowed_subjects: vec!["admin-sub".to_string()],
+            allowed_emails: vec!["jane@example.com".to_string()],
+            allowed_groups: vec!["coupon-editors".to_string()],
+        };
+        let claims = |sub: &str, email: Option<&str>, email_verified: bool, groups: &[&str]| IdTokenClaims {
+            sub: sub.to_string(),
+            nonce: None,
+            email: email.map(|email| email.to_string()),
+            email_verified: Some(email_verified),
+            groups: groups.iter().map(|group| group.to_string()).collect(),
+        };
+
+        assert!(is_allowed(&settings, &claims("admin-sub", None, false, &[])));
+        assert!(is_allowed(&settings, &claims("other", Some("Jane@example.com"), true, &[])));
+        assert!(is_allowed(&settings, &claims("other", None, false, &["staff", "coupon-editors"])));
+        assert!(!is_allowed(&settings, &claims("other", Some("jane@example.com"), false, &[])));
+        assert!(!is_allowed(&settings, &claims("other", Some("john@example.com"), true, &["staff"])));
+        assert!(!is_allowed(&OidcSettings { allowed_subjects: vec![], allowed_emails: vec![], allowed_groups: vec![], ..settings.clone() }, &claims("admin-sub", None, false, &[])));
+    }
+}
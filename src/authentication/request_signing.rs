@@ -0,0 +1,167 @@
+use super::{Role, Scope, Session};
+use crate::api_key::{api_key_repository, api_key_service, ip_allowlist};
+use crate::client_ip::client_ip;
+use crate::configuration::RequestSigningSettings;
+use actix_web::{
+    HttpMessage,
+    dev::ServiceRequest,
+    http::Method,
+    web::{Bytes, Data},
+};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use sqlx::MySqlPool;
+
+
+pub const KEY_ID_HEADER: &str = "X-Api-Key-Id";
+pub const TIMESTAMP_HEADER: &str = "X-Timestamp";
+pub const NONCE_HEADER: &str = "X-Nonce";
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+/// Signed requests are authenticated with the signing secret of an API key instead of a session,
+/// for the server-to-server callers that don't want to keep a `Bearer` around.
+pub fn is_signed(request: &ServiceRequest) -> bool {
+    return request.headers().contains_key(SIGNATURE_HEADER);
+}
+
+/// HMAC-SHA256 of the request with the signing secret, in the `sha256=<hex>` format (as the webhooks are signed).
+/// The signed string is the method, path with the query, timestamp, nonce and the hex SHA-256 of the body, separated by `\n`.
+pub fn sign(secret: &str, method: &Method, path_and_query: &str, timestamp: i64, nonce: &str, body: &[u8]) -> String {
+    let mac = signature_mac(secret, method, path_and_query, timestamp, nonce, body);
+    return format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+}
+
+/// Constant-time comparison, so the response time doesn't tell how much of the signature matched.
+pub fn verify(secret: &str, method: &Method, path_and_query: &str, timestamp: i64, nonce: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match signature.strip_prefix("sha256=").map(hex::decode) {
+        Some(Ok(signature)) => signature,
+        _ => return false,
+    };
+    return signature_mac(secret, method, path_and_query, timestamp, nonce, body).verify_slice(&signature).is_ok();
+}
+
+fn signature_mac(secret: &str, method: &Method, path_and_query: &str, timestamp: i64, nonce: &str, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC can take a key of any size");
+    let body_hash = hex::encode(Sha256::digest(body));
+    mac.update(format!("{}\n{}\n{}\n{}\n{}", method, path_and_query, timestamp, nonce, body_hash).as_bytes());
+    return mac;
+}
+
+#[tracing::instrument(name = "Request signature validator", skip(request))]
+// same as the `validator`, inserting the session of the API key that signed the request
+pub async fn validator(mut request: ServiceRequest) -> Result<ServiceRequest, actix_web::Error> {
+    let header = |name: &str| request.headers().get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+        .ok_or(actix_web::error::ErrorUnauthorized(format!("`{}` header is missing or invalid.", name)));
+    let key_id: i32 = header(KEY_ID_HEADER)?.parse()
+        .map_err(|_| actix_web::error::ErrorUnauthorized(format!("`{}` header is invalid.", KEY_ID_HEADER)))?;
+    let timestamp: i64 = header(TIMESTAMP_HEADER)?.parse()
+        .map_err(|_| actix_web::error::ErrorUnauthorized(format!("`{}` header is invalid.", TIMESTAMP_HEADER)))?;
+    let nonce = header(NONCE_HEADER)?;
+    let signature = header(SIGNATURE_HEADER)?;
+
+    let settings = request.app_data::<Data<RequestSigningSettings>>()
+        .ok_or(actix_web::error::ErrorInternalServerError("Failed to get request signing settings from app data."))?
+        .clone();
+    // `abs_diff` can't overflow, whatever the timestamp sent
+    if (chrono::Utc::now().timestamp().abs_diff(timestamp) > settings.max_clock_skew_seconds){
+        return Err(actix_web::error::ErrorUnauthorized("Request timestamp is too far from the current time."));
+    }
+
+    let pool = request.app_data::<Data<MySqlPool>>()
+        .ok_or(actix_web::error::ErrorInternalServerError("Failed to get `pool` data from app data."))?
+        .clone();
+    let api_key = api_key_repository::get_by_id(key_id, &pool).await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get API key: {}.", e)))?
+        .filter(|api_key| api_key.date_revoked.is_none())
+        .ok_or(actix_web::error::ErrorUnauthorized("Request signature is invalid."))?;
+    let cipher = settings.cipher()
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or(actix_web::error::ErrorUnauthorized("Request signing is not enabled."))?;
+    // the ones still in plain text are encrypted on startup
+    let signing_secret = api_key.signing_secret.as_deref()
+        .and_then(|secret| cipher.decrypt(secret))
+        .ok_or(actix_web::error::ErrorUnauthorized("Request signature is invalid."))?;
+
+    // the body can only be read once, it is put back for the handler
+    let body = request.extract::<Bytes>().await?;
+    request.set_payload(bytes_to_payload(body.clone()));
+
+    let path_and_query = request.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/").to_string();
+    if (!verify(&signing_secret, request.method(), &path_and_query, timestamp, &nonce, &body, &signature)){
+        return Err(actix_web::error::ErrorUnauthorized("Request signature is invalid."));
+    }
+    // after the signature, so only the owner of the key learns it expired
+    api_key_service::check_not_expired(&api_key)?;
+
+    // only checked once the signature is valid, so it can't be used to fill the cache of another key
+    let redis = request.app_data::<Data<redis::Client>>()
+        .ok_or(actix_web::error::ErrorInternalServerError("Failed to get `redis` data from app data."))?
+        .clone();
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+    // a nonce older than the allowed clock skew is rejected by its timestamp, so it doesn't have to be kept longer
+    let first_use: bool = redis::cmd("SET")
+        .arg(nonce_key(key_id, &nonce))
+        .arg(timestamp)
+        .arg("NX")
+        .arg("EX")
+        .arg(settings.max_clock_skew_seconds.saturating_mul(2).max(1))
+        .query_async::<_, Option<String>>(&mut conn)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?
+        .is_some();
+    if (!first_use){
+        return Err(actix_web::error::ErrorUnauthorized("Request nonce was already used."));
+    }
+
+    let session = Session {
+        role: api_key.role.parse().unwrap_or(Role::Readonly),
+        scopes: Scope::parse_list(&api_key.scopes),
+        api_key_id: Some(api_key.id),
+        api_key_expires_at: api_key.expires_at,
+        allowed_ips: api_key.allowed_ips(),
+        user_id: None,
+    };
+    if (!ip_allowlist::is_allowed(&session.allowed_ips, client_ip(request.request()))){
+        return Err(actix_web::error::ErrorForbidden("The API key can't be used from this IP."));
+    }
+    request.extensions_mut().insert(session);
+
+    return Ok(request);
+}
+
+fn nonce_key(key_id: i32, nonce: &str) -> String {
+    return format!("request_nonce:{}:{}", key_id, nonce);
+}
+
+fn bytes_to_payload(body: Bytes) -> actix_web::dev::Payload {
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body);
+    return actix_web::dev::Payload::from(payload);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, verify};
+    use actix_web::http::Method;
+
+    #[test]
+    fn signature_covers_the_whole_request(){
+        let signature = sign("secret", &Method::POST, "/coupon", 1000, "nonce", b"{}");
+        assert!(signature.starts_with("sha256="));
+        assert!(verify("secret", &Method::POST, "/coupon", 1000, "nonce", b"{}", &signature));
+
+        assert!(!verify("other secret", &Method::POST, "/coupon", 1000, "nonce", b"{}", &signature));
+        assert!(!verify("secret", &Method::PUT, "/coupon", 1000, "nonce", b"{}", &signature));
+        assert!(!verify("secret", &Method::POST, "/coupon?page=2", 1000, "nonce", b"{}", &signature));
+        assert!(!verify("secret", &Method::POST, "/coupon", 1001, "nonce", b"{}", &signature));
+        assert!(!verify("secret", &Method::POST, "/coupon", 1000, "other", b"{}", &signature));
+        assert!(!verify("secret", &Method::POST, "/coupon", 1000, "nonce", b"{\"a\":1}", &signature));
+        assert!(!verify("secret", &Method::POST, "/coupon", 1000, "nonce", b"{}", "not hex"));
+    }
+}
@@ -0,0 +1,34 @@
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+
+
+/// Role of an API key, each role can do everything the previous ones can.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Readonly,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Readonly, Role::Editor, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Role::Readonly => "readonly",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        };
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        return Role::ALL.iter()
+            .find(|candidate| candidate.as_str() == role)
+            .copied()
+            .ok_or(format!("Invalid role `{}`.", role));
+    }
+}
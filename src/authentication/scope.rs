@@ -0,0 +1,93 @@
+use actix_web::http::Method;
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+
+
+/// Permissions attached to an API key, every authenticated route requires one of them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    #[serde(rename = "coupon:read")]
+    CouponRead,
+    #[serde(rename = "coupon:write")]
+    CouponWrite,
+    #[serde(rename = "coupon:redeem")]
+    CouponRedeem,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [Scope::CouponRead, Scope::CouponWrite, Scope::CouponRedeem, Scope::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Scope::CouponRead => "coupon:read",
+            Scope::CouponWrite => "coupon:write",
+            Scope::CouponRedeem => "coupon:redeem",
+            Scope::Admin => "admin",
+        };
+    }
+
+    /// Parse a comma separated list of scopes, as stored in the database and in the sessions.
+    pub fn parse_list(scopes: &str) -> Vec<Scope> {
+        return scopes
+            .split(',')
+            .flat_map(|scope| scope.trim().parse::<Scope>().ok())
+            .collect();
+    }
+
+    pub fn join(scopes: &[Scope]) -> String {
+        let mut scopes: Vec<&str> = scopes.iter().map(|scope| scope.as_str()).collect();
+        scopes.sort();
+        scopes.dedup();
+        return scopes.join(",");
+    }
+
+    /// Scope needed to call a `/coupon` route.
+    /// Verifying coupons or applying them to a cart only needs `coupon:redeem`, so a storefront key doesn't have to read or change the others.
+    pub fn for_coupon_route(method: &Method, path: &str) -> Scope {
+        if (((method == Method::GET || method == Method::HEAD) && path.starts_with("/coupon/verify/")) || (method == Method::POST && (path.ends_with("/coupon/apply") || path.ends_with("/coupon/validate/bulk")))){
+            return Scope::CouponRedeem;
+        }
+        if (method == Method::GET || method == Method::HEAD){
+            return Scope::CouponRead;
+        }
+        return Scope::CouponWrite;
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        return Scope::ALL.iter()
+            .find(|candidate| candidate.as_str() == scope)
+            .copied()
+            .ok_or(format!("Invalid scope `{}`.", scope));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scope;
+    use actix_web::http::Method;
+
+    #[test]
+    fn scope_list_roundtrips_ignoring_unknown_scopes(){
+        let scopes = Scope::parse_list("coupon:redeem,unknown,admin");
+        assert_eq!(scopes, vec![Scope::CouponRedeem, Scope::Admin]);
+        assert_eq!(Scope::join(&scopes), "admin,coupon:redeem");
+    }
+
+    #[test]
+    fn coupon_routes_require_the_matching_scope(){
+        assert_eq!(Scope::for_coupon_route(&Method::GET, "/coupon/verify/CODE"), Scope::CouponRedeem);
+        assert_eq!(Scope::for_coupon_route(&Method::DELETE, "/coupon/verify/CODE"), Scope::CouponWrite);
+        assert_eq!(Scope::for_coupon_route(&Method::POST, "/coupon/apply"), Scope::CouponRedeem);
+        assert_eq!(Scope::for_coupon_route(&Method::POST, "/coupon/validate/bulk"), Scope::CouponRedeem);
+        assert_eq!(Scope::for_coupon_route(&Method::POST, "/coupon/import"), Scope::CouponWrite);
+        assert_eq!(Scope::for_coupon_route(&Method::GET, "/coupon/CODE"), Scope::CouponRead);
+        assert_eq!(Scope::for_coupon_route(&Method::HEAD, "/coupon/code/CODE"), Scope::CouponRead);
+        assert_eq!(Scope::for_coupon_route(&Method::DELETE, "/coupon/CODE"), Scope::CouponWrite);
+        assert_eq!(Scope::for_coupon_route(&Method::POST, "/coupon"), Scope::CouponWrite);
+    }
+}
@@ -0,0 +1,162 @@
+use super::{refresh_token_key, Role, Scope, Session};
+use crate::configuration::SessionSettings;
+use crate::envelope::Envelope;
+use actix_web::{
+    web, get, delete, HttpRequest, HttpResponse,
+    web::Data,
+};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+use uuid::Uuid;
+
+
+// sorted set of the ids of the issued sessions, scored by when their refresh token expires
+const SESSIONS_KEY: &str = "sessions";
+
+/// What is stored for each issued session, so it can be listed and ended by `/admin/sessions`.
+/// The `session_id` of the `Bearer` is never returned, the session is identified by a separate `id`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SessionIndex {
+    session_id: String,
+    refresh_token: String,
+    session: Session,
+    expires_at: i64,
+    refresh_expires_at: i64,
+}
+
+/// Returned by `/admin/sessions`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActiveSession {
+    pub id: String,
+    pub role: Role,
+    pub scopes: Vec<Scope>,
+    pub api_key_id: Option<i32>,
+    pub user_id: Option<i32>,
+    // when the `Bearer` expires, the session can still be refreshed until `refresh_expires_at`
+    pub expires_at: Option<NaiveDateTime>,
+    pub refresh_expires_at: Option<NaiveDateTime>,
+}
+
+/// Keep track of the session created by `create_session`.
+pub async fn index(conn: &mut redis::aio::Connection, settings: &SessionSettings, session_id: &str, refresh_token: &str, session: &Session) -> Result<(), actix_web::Error> {
+    let now = chrono::Utc::now().timestamp();
+    let index = SessionIndex {
+        session_id: session_id.to_string(),
+        refresh_token: refresh_token.to_string(),
+        session: session.clone(),
+        expires_at: now.saturating_add(settings.expiration_seconds as i64),
+        refresh_expires_at: now.saturating_add(settings.refresh_token_expiration_seconds as i64),
+    };
+    let index_json = serde_json::to_string(&index)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to serialize session: {}.", e)))?;
+
+    let id = Uuid::new_v4().simple().to_string();
+    let lifetime_seconds = settings.expiration_seconds.max(settings.refresh_token_expiration_seconds);
+    conn.set_ex::<_, _, ()>(index_key(&id), index_json, lifetime_seconds as usize)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to insert session index: {}.", e)))?;
+    conn.zadd::<_, _, _, ()>(SESSIONS_KEY, &id, index.expires_at.max(index.refresh_expires_at))
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to insert session index: {}.", e)))?;
+    return Ok(());
+}
+
+#[tracing::instrument(name = "Get all sessions", skip(http_request, redis))]
+// sessions that ended (logout, revoked or refreshed and expired) are left out
+#[get("/sessions")]
+pub async fn get_all_sessions(http_request: HttpRequest, redis: Data<redis::Client>) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+
+    // the expired ids are removed here, there is nothing else to clean the set
+    conn.zrembyscore::<_, _, _, ()>(SESSIONS_KEY, "-inf", chrono::Utc::now().timestamp())
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?;
+    let ids: Vec<String> = conn.zrange(SESSIONS_KEY, 0, -1)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?;
+
+    let mut sessions = vec![];
+    for id in ids {
+        let index = match get_index(&mut conn, &id).await? {
+            Some(index) => index,
+            None => continue,
+        };
+        // both the `Bearer` and the refresh token are gone when the session ended
+        let active: bool = redis::pipe()
+            .exists(&index.session_id)
+            .exists(refresh_token_key(&index.refresh_token))
+            .query_async::<_, (bool, bool)>(&mut conn)
+            .await
+            .map(|(bearer, refresh_token)| bearer || refresh_token)
+            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?;
+        if (!active){
+            continue;
+        }
+        sessions.push(ActiveSession {
+            id,
+            role: index.session.role,
+            scopes: index.session.scopes,
+            api_key_id: index.session.api_key_id,
+            user_id: index.session.user_id,
+            expires_at: NaiveDateTime::from_timestamp_opt(index.expires_at, 0),
+            refresh_expires_at: NaiveDateTime::from_timestamp_opt(index.refresh_expires_at, 0),
+        });
+    }
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, sessions)));
+}
+
+#[tracing::instrument(name = "Delete session", skip(redis))]
+// ends the `Bearer` and the refresh token of the session, a refresh token already exchanged belongs to the next session
+#[delete("/sessions/{id}")]
+pub async fn delete_session(param: web::Path<String>, redis: Data<redis::Client>) -> Result<HttpResponse, actix_web::Error> {
+    let id = param.into_inner();
+    let mut conn = redis
+        .get_async_connection()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to get `redis` connection: {}.", e)))?;
+
+    let index = get_index(&mut conn, &id).await?
+        .ok_or(actix_web::error::ErrorNotFound(format!("Session with id `{}` not found.", id)))?;
+    conn.del::<_, ()>(vec![index.session_id, refresh_token_key(&index.refresh_token), index_key(&id)])
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to delete session: {}.", e)))?;
+    conn.zrem::<_, _, ()>(SESSIONS_KEY, &id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to delete session: {}.", e)))?;
+
+    return Ok(HttpResponse::NoContent().finish());
+}
+
+/// End the sessions for which `ends` is true and their refresh tokens, e.g. the ones of a revoked API key or of a deleted user.
+pub async fn end_sessions(redis: &redis::Client, ends: impl Fn(&Session) -> bool) -> Result<(), redis::RedisError> {
+    let mut conn = redis.get_async_connection().await?;
+    let ids: Vec<String> = conn.zrange(SESSIONS_KEY, 0, -1).await?;
+    for id in ids {
+        let index: Option<SessionIndex> = conn.get::<_, Option<String>>(index_key(&id))
+            .await?
+            .and_then(|index| serde_json::from_str(&index).ok());
+        let index = match index {
+            Some(index) if (ends(&index.session)) => index,
+            _ => continue,
+        };
+        conn.del::<_, ()>(vec![index.session_id, refresh_token_key(&index.refresh_token), index_key(&id)]).await?;
+        conn.zrem::<_, _, ()>(SESSIONS_KEY, &id).await?;
+    }
+    return Ok(());
+}
+
+async fn get_index(conn: &mut redis::aio::Connection, id: &str) -> Result<Option<SessionIndex>, actix_web::Error> {
+    let index: Option<String> = conn.get(index_key(id))
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to query `redis`: {}.", e)))?;
+    // an index that can't be parsed was created by an older version, it is left to expire
+    return Ok(index.and_then(|index| serde_json::from_str(&index).ok()));
+}
+
+fn index_key(id: &str) -> String {
+    return format!("session_index:{}", id);
+}
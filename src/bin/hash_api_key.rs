@@ -0,0 +1,15 @@
+//! Print the hash of an API key, to be used as `application.api_key` instead of the key itself.
+//!
+//! cargo run --bin hash_api_key -- <api_key>
+use coupon_api::api_key::api_key_hash::ApiKeyHash;
+
+fn main() {
+    let api_key = match std::env::args().nth(1) {
+        Some(api_key) => api_key,
+        None => {
+            eprintln!("Usage: hash_api_key <api_key>");
+            std::process::exit(1);
+        }
+    };
+    println!("{}", ApiKeyHash::new(&api_key));
+}
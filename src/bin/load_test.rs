@@ -0,0 +1,166 @@
+//! Drive concurrent coupon verifications and lookups against the API, started on a test database,
+//! and report the throughput and latency percentiles.
+//!
+//! cargo run --release --bin load_test -- --concurrency 64 --duration-seconds 30
+#![allow(unused_parens)]
+#![allow(clippy::needless_return)]
+
+use clap::Parser;
+use coupon_api::{
+    authentication::{AuthTokens, Role, Scope},
+    api_key::ApiKeyCreateRequest,
+    configuration::{get_configuration, Reloadable, SeedSettings, Settings},
+    coupon::CouponInsertRequest,
+    envelope::Envelope,
+    metrics::Metrics,
+    migrations,
+    seed,
+    startup::{get_connection_pool, get_coupon_store, Application},
+    telemetry::PoolMetrics,
+};
+use rand::Rng;
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use sqlx::{Connection, Executor, MySqlConnection};
+use std::time::{Duration, Instant};
+
+/// Load test of the coupon verification and lookup, on the `database.test_database_name` database (dropped and created again).
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Requests sent at the same time
+    #[arg(long, default_value_t = 32)]
+    concurrency: usize,
+    /// How long the requests are sent for
+    #[arg(long, default_value_t = 30)]
+    duration_seconds: u64,
+    /// Coupons inserted before the test, each request picks one at random
+    #[arg(long, default_value_t = 1000)]
+    coupons: usize,
+    /// Share of the requests that verify the coupon (`GET /coupon/verify/{code}`), the others look it up (`GET /coupon/{code}`)
+    #[arg(long, default_value_t = 80)]
+    verify_percent: u8,
+}
+
+#[derive(Default)]
+struct Report {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+    if (cli.verify_percent > 100){
+        anyhow::bail!("`--verify-percent` must be between 0 and 100.");
+    }
+
+    let mut configuration = get_configuration().await?;
+    // a random port, and nothing limiting the traffic of a single client
+    configuration.application.port = 0;
+    configuration.rate_limit.enabled = false;
+    configuration.auth_lockout.enabled = false;
+    let api_key = prepare_database(&configuration, cli.coupons).await?;
+
+    let application = Application::build(configuration.clone(), true).await?;
+    let address = format!("http://127.0.0.1:{}", application.port());
+    drop(tokio::spawn(application.run_until_stopped()));
+    let client = authenticated_client(&address, &api_key).await?;
+
+    println!("Sending requests for {} seconds, {} at a time...", cli.duration_seconds, cli.concurrency);
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(cli.duration_seconds);
+    let workers: Vec<_> = (0..cli.concurrency)
+        .map(|_| tokio::spawn(send_requests(client.clone(), address.clone(), cli.coupons, cli.verify_percent, deadline)))
+        .collect();
+    let mut report = Report::default();
+    for worker in workers {
+        let worker_report = worker.await?;
+        report.latencies.extend(worker_report.latencies);
+        report.errors += worker_report.errors;
+    }
+    print_report(report, started.elapsed());
+    return Ok(());
+}
+
+/// Create the test database with `coupons` coupons, `LOAD0` to `LOAD<coupons - 1>`, and return a new API key.
+async fn prepare_database(configuration: &Settings, coupons: usize) -> Result<String, anyhow::Error> {
+    let database_name = &configuration.database.test_database_name;
+    // same safety as the integration tests, the database is dropped
+    if (!database_name.contains("TEST")){
+        anyhow::bail!("`database.test_database_name` must contain `TEST`, the database is dropped.");
+    }
+    let mut connection = MySqlConnection::connect_with(&configuration.database.without_db()).await?;
+    connection.execute(format!("DROP DATABASE IF EXISTS {};", database_name).as_str()).await?;
+    connection.execute(format!("CREATE DATABASE {};", database_name).as_str()).await?;
+
+    let pool = get_connection_pool(&configuration.database, true);
+    migrations::run(&pool).await?;
+    let store = get_coupon_store(configuration, &Reloadable::new(configuration.cache.clone()), &pool, PoolMetrics::new("mysql"), &Metrics::new()).await?;
+    let expiration_date = Some(chrono::Utc::now().naive_utc() + chrono::Duration::days(30));
+    let settings = SeedSettings {
+        coupons: (0..coupons)
+            .map(|i| CouponInsertRequest { code: format!("LOAD{}", i), discount: 10, active: true, max_usage_count: None, expiration_date })
+            .collect(),
+        api_key: Some(ApiKeyCreateRequest {
+            name: "load-test".to_string(),
+            role: Role::Readonly,
+            scopes: vec![Scope::CouponRead, Scope::CouponRedeem],
+            allowed_ips: Vec::new(),
+            expires_at: None,
+        }),
+    };
+    let report = seed::seed(&settings, &configuration.request_signing, store.as_ref(), &pool).await?;
+    return report.api_key.and_then(|api_key| api_key.api_key)
+        .ok_or_else(|| anyhow::anyhow!("The API key was not issued."));
+}
+
+async fn authenticated_client(address: &str, api_key: &str) -> Result<reqwest::Client, anyhow::Error> {
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", address))
+        .json(&serde_json::json!({"api_key": api_key}))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, tokens.data.bearer.parse()?);
+    return Ok(reqwest::Client::builder().default_headers(headers).build()?);
+}
+
+async fn send_requests(client: reqwest::Client, address: String, coupons: usize, verify_percent: u8, deadline: Instant) -> Report {
+    let mut report = Report::default();
+    while (Instant::now() < deadline) {
+        let (code, verify) = {
+            let mut rng = rand::thread_rng();
+            (rng.gen_range(0..coupons.max(1)), rng.gen_range(0..100) < verify_percent)
+        };
+        let url = match verify {
+            true => format!("{}/coupon/verify/LOAD{}", address, code),
+            false => format!("{}/coupon/LOAD{}", address, code),
+        };
+        let sent = Instant::now();
+        // the body is read too, so the latency includes it
+        let succeeded = match client.get(url).send().await {
+            Ok(response) => response.status().is_success() && response.bytes().await.is_ok(),
+            Err(_) => false,
+        };
+        report.latencies.push(sent.elapsed());
+        if (!succeeded){
+            report.errors += 1;
+        }
+    }
+    return report;
+}
+
+fn print_report(mut report: Report, elapsed: Duration) {
+    let requests = report.latencies.len();
+    if (requests == 0){
+        println!("No request was sent.");
+        return;
+    }
+    report.latencies.sort();
+    let percentile = |p: usize| report.latencies[(requests * p / 100).min(requests - 1)];
+    println!("Requests:   {} ({} failed)", requests, report.errors);
+    println!("Throughput: {:.1} requests/s", requests as f64 / elapsed.as_secs_f64());
+    println!("Latency:    p50 {:?}, p90 {:?}, p99 {:?}, max {:?}", percentile(50), percentile(90), percentile(99), report.latencies[requests - 1]);
+}
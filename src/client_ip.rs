@@ -0,0 +1,62 @@
+use crate::api_key::ip_allowlist::Cidr;
+use actix_web::{HttpRequest, web::Data};
+use std::net::IpAddr;
+
+
+/// The reverse proxies whose `X-Forwarded-For` is trusted, see `application.trusted_proxies`.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<Cidr>);
+
+/// The IP of the client of `request`.
+/// The peer address, unless it is a trusted proxy: any client can set `X-Forwarded-For`, so it is only read then.
+pub fn client_ip(request: &HttpRequest) -> Option<IpAddr> {
+    let peer = request.peer_addr().map(|address| address.ip())?;
+    let trusted_proxies = match request.app_data::<Data<TrustedProxies>>() {
+        Some(trusted_proxies) => trusted_proxies,
+        None => return Some(peer),
+    };
+    let forwarded_for = request.headers().get("X-Forwarded-For").and_then(|value| value.to_str().ok());
+    return Some(forwarded_client(peer, forwarded_for, &trusted_proxies.0));
+}
+
+// each proxy appends the address it got the request from, so the client is the last one that isn't a trusted proxy
+fn forwarded_client(peer: IpAddr, forwarded_for: Option<&str>, trusted_proxies: &[Cidr]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|range| range.contains(ip));
+    if (!is_trusted(&peer)){
+        return peer;
+    }
+    let mut client = peer;
+    for address in forwarded_for.unwrap_or("").rsplit(',') {
+        client = match address.trim().parse::<IpAddr>() {
+            Ok(ip) => ip,
+            // anything before an address that can't be parsed was set by the client
+            Err(_) => return client,
+        };
+        if (!is_trusted(&client)){
+            return client;
+        }
+    }
+    return client;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::forwarded_client;
+    use crate::api_key::ip_allowlist::Cidr;
+    use std::net::IpAddr;
+
+    #[test]
+    fn forwarded_for_is_only_read_from_trusted_proxies(){
+        let trusted_proxies: Vec<Cidr> = vec!["10.0.0.0/8".parse().unwrap()];
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+        // not a trusted proxy, the header was set by the client itself
+        assert_eq!(forwarded_client(ip("203.0.113.7"), Some("198.51.100.1"), &trusted_proxies), ip("203.0.113.7"));
+        assert_eq!(forwarded_client(ip("10.0.0.1"), Some("198.51.100.1"), &trusted_proxies), ip("198.51.100.1"));
+        // the client can't skip the proxy by sending its own `X-Forwarded-For`
+        assert_eq!(forwarded_client(ip("10.0.0.1"), Some("192.0.2.1, 198.51.100.1, 10.0.0.2"), &trusted_proxies), ip("198.51.100.1"));
+        assert_eq!(forwarded_client(ip("10.0.0.1"), Some("unknown, 10.0.0.2"), &trusted_proxies), ip("10.0.0.2"));
+        assert_eq!(forwarded_client(ip("10.0.0.1"), None, &trusted_proxies), ip("10.0.0.1"));
+        assert_eq!(forwarded_client(ip("10.0.0.1"), Some("198.51.100.1"), &[]), ip("10.0.0.1"));
+    }
+}
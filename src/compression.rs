@@ -0,0 +1,106 @@
+use crate::configuration::CompressionEncoding;
+use actix_web::{
+    Error,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderValue, ACCEPT_ENCODING},
+};
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+
+/// Removes from the `Accept-Encoding` of the requests the encodings not in `compression.encodings`,
+/// so the `Compress` middleware it wraps only picks among the configured ones.
+#[derive(Clone)]
+pub struct AcceptedEncodings {
+    encodings: Vec<CompressionEncoding>,
+}
+
+impl AcceptedEncodings {
+    pub fn new(encodings: Vec<CompressionEncoding>) -> Self {
+        return Self { encodings };
+    }
+}
+
+// the `identity` and the configured encodings of `header`, with their weights, `None` when none is left
+fn filter(header: &str, encodings: &[CompressionEncoding]) -> Option<String> {
+    let accepted: Vec<&str> = header.split(',')
+        .map(|encoding| encoding.trim())
+        .filter(|encoding| {
+            let name = encoding.split(';').next().unwrap_or_default().trim();
+            return name.eq_ignore_ascii_case("identity") || encodings.iter().any(|allowed| name.eq_ignore_ascii_case(allowed.as_str()));
+        })
+        .collect();
+    if (accepted.is_empty()){
+        return None;
+    }
+    return Some(accepted.join(", "));
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AcceptedEncodings
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AcceptedEncodingsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(AcceptedEncodingsMiddleware { service: Rc::new(service), encodings: self.encodings.clone() }));
+    }
+}
+
+pub struct AcceptedEncodingsMiddleware<S> {
+    service: Rc<S>,
+    encodings: Vec<CompressionEncoding>,
+}
+
+impl<S, B> Service<ServiceRequest> for AcceptedEncodingsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = S::Future;
+
+    forward_ready!(service);
+
+    fn call(&self, mut request: ServiceRequest) -> Self::Future {
+        let header = request.headers().get(ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| filter(value, &self.encodings));
+        match header {
+            Some(Some(accepted)) => {
+                if let Ok(value) = HeaderValue::from_str(&accepted) {
+                    request.headers_mut().insert(ACCEPT_ENCODING, value);
+                }
+            },
+            // no configured encoding accepted, or an unreadable header: sent uncompressed
+            _ => {
+                request.headers_mut().remove(ACCEPT_ENCODING);
+            },
+        }
+        return self.service.call(request);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::filter;
+    use crate::configuration::CompressionEncoding;
+    use claim::{assert_none, assert_some_eq};
+
+    #[test]
+    fn only_the_configured_encodings_are_kept(){
+        let encodings = [CompressionEncoding::Gzip];
+
+        assert_some_eq!(filter("br;q=1.0, gzip;q=0.8, zstd", &encodings), "gzip;q=0.8");
+        assert_some_eq!(filter("GZIP, identity", &encodings), "GZIP, identity");
+        assert_none!(filter("br, deflate", &encodings));
+    }
+}
@@ -0,0 +1,151 @@
+use super::model::{CouponError, PageMeta};
+use crate::envelope::{Envelope, Meta};
+use actix_web::{
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+    http::header::{self, Accept, Header},
+    web::Bytes,
+};
+use anyhow::anyhow;
+use futures_util::{future, stream, Stream, StreamExt};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+
+/// If the client prefers XML over JSON in the `Accept` header.
+/// JSON is the default for missing, wildcard or unknown media types.
+pub fn prefers_xml(request: &HttpRequest) -> bool {
+    let accept = match Accept::parse(request) {
+        Ok(accept) => accept,
+        Err(_) => return false,
+    };
+
+    for mime in accept.ranked() {
+        match mime.essence_str() {
+            "application/xml" | "text/xml" => return true,
+            "application/json" | "*/*" => return false,
+            _ => {},
+        }
+    }
+    return false;
+}
+
+/// Wrap `data` in the response `Envelope` and serialize it as XML or JSON, according to the `Accept` header.
+pub fn respond<T: Serialize>(request: &HttpRequest, response: HttpResponseBuilder, data: T) -> Result<HttpResponse, CouponError> {
+    return respond_envelope(request, response, &Envelope::new(request, data));
+}
+
+/// Same as `respond()` for a list: in XML `data` has one `xml_item` element per item.
+pub fn respond_list<T: Serialize>(request: &HttpRequest, response: HttpResponseBuilder, items: &[T], xml_item: &str, pagination: Option<PageMeta>) -> Result<HttpResponse, CouponError> {
+    let mut meta = Meta::new(request);
+    meta.pagination = pagination;
+    if (!prefers_xml(request)){
+        return respond_envelope(request, response, &Envelope { data: items, meta });
+    }
+    // a sequence as a map value is serialized as one element (named with the key) per item
+    return respond_envelope(request, response, &Envelope { data: BTreeMap::from([(xml_item, items)]), meta });
+}
+
+/// Same as `respond_list()` in JSON, with the items serialized and sent while they are read instead of buffered.
+/// The status is sent before the items are read: on an error the response is cut short, so the client fails to parse it.
+pub fn stream_list<T, S>(request: &HttpRequest, mut response: HttpResponseBuilder, items: S) -> HttpResponse
+where
+    T: Serialize,
+    S: Stream<Item = Result<T, CouponError>> + 'static,
+{
+    let meta = serde_json::to_string(&Meta::new(request)).unwrap_or_else(|_| "{}".to_string());
+    let items = items.enumerate().map(|(index, item)| {
+        let json = item.and_then(|item| serde_json::to_vec(&item)
+            .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to serialize JSON response: {}.", e)))))
+            .map_err(|error| {
+                tracing::error!("Failed to stream the response, it is cut short: {:?}", error);
+                error
+            })?;
+        // the items of the `data` array are separated by commas
+        let mut chunk = Vec::with_capacity(json.len() + 1);
+        if (index > 0){
+            chunk.push(b',');
+        }
+        chunk.extend(json);
+        return Ok::<Bytes, CouponError>(Bytes::from(chunk));
+    });
+    let body = stream::once(future::ready(Ok(Bytes::from_static(b"{\"data\":["))))
+        .chain(items)
+        .chain(stream::once(future::ready(Ok(Bytes::from(format!("],\"meta\":{}}}", meta))))));
+    return response.content_type("application/json").streaming(body);
+}
+
+fn respond_envelope<T: Serialize>(request: &HttpRequest, mut response: HttpResponseBuilder, envelope: &Envelope<T>) -> Result<HttpResponse, CouponError> {
+    if (!prefers_xml(request)){
+        return Ok(response.json(envelope));
+    }
+
+    let xml = quick_xml::se::to_string_with_root("response", envelope)
+        .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to serialize XML response: {}.", e))))?;
+    return Ok(response
+        .insert_header((header::CONTENT_TYPE, "application/xml"))
+        .body(xml));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prefers_xml, respond_list, stream_list};
+    use crate::coupon::model::{CouponError, CouponLinks, CouponResponse};
+    use actix_web::{HttpResponse, body::{self, MessageBody}, test::TestRequest};
+    use futures_util::stream;
+
+    #[test]
+    fn xml_is_used_only_when_preferred_over_json(){
+        let request = TestRequest::default().insert_header(("Accept", "application/xml")).to_http_request();
+        assert!(prefers_xml(&request));
+
+        let request = TestRequest::default().insert_header(("Accept", "application/json;q=0.5, text/xml")).to_http_request();
+        assert!(prefers_xml(&request));
+
+        let request = TestRequest::default().insert_header(("Accept", "application/json, application/xml;q=0.9")).to_http_request();
+        assert!(!prefers_xml(&request));
+
+        let request = TestRequest::default().insert_header(("Accept", "*/*")).to_http_request();
+        assert!(!prefers_xml(&request));
+
+        let request = TestRequest::default().to_http_request();
+        assert!(!prefers_xml(&request));
+    }
+
+    #[test]
+    fn list_is_serialized_as_one_xml_element_per_item(){
+        let request = TestRequest::default().insert_header(("Accept", "application/xml")).to_http_request();
+        let coupon = CouponResponse {
+            id: 1,
+            code: "CODE".to_string(),
+            discount: 10,
+            active: true,
+            max_usage_count: None,
+            expiration_date: None,
+            date_created: None,
+            date_updated: None,
+            version: 1,
+            links: CouponLinks::new(1),
+        };
+
+        let response = respond_list(&request, HttpResponse::Ok(), &[coupon.clone(), coupon], "coupon", None).unwrap();
+
+        assert_eq!(response.headers().get("Content-Type").unwrap(), "application/xml");
+        let body = response.into_body().try_into_bytes().unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with("<response><data><coupon><id>1</id><code>CODE</code>"), "{}", body);
+        assert_eq!(body.matches("<coupon>").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn streamed_lists_are_the_same_json_envelope(){
+        let request = TestRequest::default().to_http_request();
+        let items: Vec<Result<i32, CouponError>> = vec![Ok(1), Ok(2), Ok(3)];
+
+        let response = stream_list(&request, HttpResponse::Ok(), stream::iter(items));
+
+        let body = body::to_bytes(response.into_body()).await.unwrap();
+        let envelope: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(envelope["data"], serde_json::json!([1, 2, 3]));
+        assert!(envelope["meta"].is_object());
+    }
+}
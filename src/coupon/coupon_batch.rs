@@ -0,0 +1,194 @@
+use super::model::{
+    BatchOperation, BatchOperationResult, CouponError, CouponInsertRequest, CouponPatchRequest, CouponUpdateRequest, JsonPatchOperation,
+    CouponChangesQuery, CouponFilter, CouponPagination,
+};
+use super::{coupon_service, coupon_store::CouponStore};
+use crate::authentication::{Permission, Session};
+use crate::feature_flags::FeatureFlags;
+use crate::rate_limit::ClientBucket;
+use actix_web::{
+    ResponseError,
+    http::{Method, StatusCode},
+    web,
+};
+use anyhow::anyhow;
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use sqlx::MySqlPool;
+
+
+/// `application.max_batch_operations`, the larger batches are rejected.
+pub struct MaxBatchOperations(pub usize);
+
+/// Execute the operations sequentially, in the order they were sent.
+/// A failing operation does not stop the next ones, each one gets its own status and body.
+/// Each operation needs the same role and scope as the route it calls, and counts as a request for the rate limit:
+/// the request itself is the first one, the others take a token from the `bucket` of the client, if it is limited.
+pub async fn execute(operations: Vec<BatchOperation>, max_operations: usize, session: &Session, bucket: Option<&ClientBucket>, flags: &FeatureFlags, store: &dyn CouponStore, pool: &MySqlPool) -> Result<Vec<BatchOperationResult>, CouponError> {
+    if (operations.len() > max_operations){
+        return Err(CouponError::ValidationError(format!("A batch can have up to {} operations, it has {}.", max_operations, operations.len())));
+    }
+
+    let mut results = Vec::with_capacity(operations.len());
+    for (index, operation) in operations.into_iter().enumerate() {
+        let rate_limited = match bucket {
+            Some(bucket) if (index > 0) => bucket.take().err(),
+            _ => None,
+        };
+        let (status, body) = match rate_limited {
+            Some(exceeded) => (exceeded.status_code(), json!(exceeded.to_string())),
+            None => match execute_operation(&operation, session, flags, store, pool).await {
+                Ok(result) => result,
+                Err(error) => (error.status_code(), json!(error.to_string())),
+            },
+        };
+        results.push(BatchOperationResult {
+            method: operation.method,
+            path: operation.path,
+            status: status.as_u16(),
+            body,
+        });
+    }
+    return Ok(results);
+}
+
+async fn execute_operation(operation: &BatchOperation, session: &Session, flags: &FeatureFlags, store: &dyn CouponStore, pool: &MySqlPool) -> Result<(StatusCode, Value), CouponError> {
+    let method = Method::from_bytes(operation.method.to_uppercase().as_bytes())
+        .map_err(|_| CouponError::ValidationError(format!("Invalid method `{}`.", operation.method)))?;
+    let (path, query) = operation.path.split_once('?').unwrap_or((operation.path.as_str(), ""));
+
+    // only the `/coupon` routes can be used in a batch
+    let segments = coupon_route_segments(path)
+        .ok_or(CouponError::NotFoundError(anyhow!(format!("Path `{}` not found.", operation.path))))?;
+    // authorized as the route it is dispatched to, not as the path as sent
+    let route = format!("/coupon{}", segments.iter().map(|segment| format!("/{}", segment)).collect::<String>());
+    session.authorize(Permission::for_coupon_route(&method, &route))
+        .map_err(CouponError::ForbiddenError)?;
+    // decoded like the router decodes the path params, once split so an encoded `/` stays in its segment
+    let segments = decode_segments(&segments)
+        .ok_or(CouponError::ValidationError(format!("Invalid path `{}`, it is not UTF-8 once decoded.", operation.path)))?;
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    // the same routes as the `/coupon` scope, `count`, `changes` and `events` before the coupons
+    return match (method, segments.as_slice()) {
+        (Method::GET, []) => {
+            let filter: CouponFilter = parse_query(query)?;
+            let pagination: CouponPagination = parse_query(query)?;
+            // the whole list would be buffered in the response of the batch
+            if (pagination.limit.is_none()){
+                return Err(CouponError::ValidationError("`GET /coupon` needs a `limit` in a batch, e.g. `/coupon?limit=100`.".to_string()));
+            }
+            let page = coupon_service::get_page(&filter, &pagination, store).await?;
+            Ok((StatusCode::OK, json!(page)))
+        },
+        (Method::GET, ["count"]) => {
+            let filter: CouponFilter = parse_query(query)?;
+            let count = coupon_service::count(&filter, store).await?;
+            Ok((StatusCode::OK, json!(count)))
+        },
+        (Method::GET, ["changes"]) => {
+            let changes_query: CouponChangesQuery = parse_query(query)?;
+            let changes = coupon_service::get_changes(&changes_query, store).await?;
+            Ok((StatusCode::OK, json!(changes)))
+        },
+        (Method::GET, ["events"]) => Err(CouponError::ValidationError("`GET /coupon/events` is a stream, it can't be sent in a batch.".to_string())),
+        (Method::GET, ["verify", id_or_code]) => {
+            let valid_coupon = coupon_service::is_valid(id_or_code.to_string(), flags, store).await?;
+            Ok((StatusCode::OK, json!(valid_coupon)))
+        },
+        (Method::GET, [id_or_code]) => {
+            let coupon = coupon_service::get_by_id_or_code(id_or_code.to_string(), store).await?;
+            Ok((StatusCode::OK, json!(coupon)))
+        },
+        (Method::HEAD, ["code", code]) => {
+            let exists = coupon_service::exists_by_code(code.to_string(), store).await?;
+            Ok((if (exists) { StatusCode::OK } else { StatusCode::NOT_FOUND }, Value::Null))
+        },
+        (Method::POST, []) => {
+            let request: CouponInsertRequest = parse_body(operation)?;
+            let coupon = coupon_service::insert(request, store, pool).await?;
+            Ok((StatusCode::CREATED, json!(coupon)))
+        },
+        (Method::PUT, ["code", code]) => {
+            let request: CouponUpdateRequest = parse_body(operation)?;
+            let (coupon, created) = coupon_service::upsert(code.to_string(), request, store, pool).await?;
+            Ok((if (created) { StatusCode::CREATED } else { StatusCode::OK }, json!(coupon)))
+        },
+        (Method::PUT, [id_or_code]) => {
+            let request: CouponUpdateRequest = parse_body(operation)?;
+            coupon_service::update(id_or_code.to_string(), request, store, pool).await?;
+            Ok((StatusCode::OK, Value::Null))
+        },
+        // a JSON Patch is an array of operations, a JSON Merge Patch an object
+        (Method::PATCH, [id_or_code]) if matches!(operation.body, Some(Value::Array(_))) => {
+            let operations: Vec<JsonPatchOperation> = parse_body(operation)?;
+            let coupon = coupon_service::json_patch(id_or_code.to_string(), operations, None, store, pool).await?;
+            Ok((StatusCode::OK, json!(coupon)))
+        },
+        (Method::PATCH, [id_or_code]) => {
+            let request: CouponPatchRequest = parse_body(operation)?;
+            let coupon = coupon_service::patch(id_or_code.to_string(), request, store, pool).await?;
+            Ok((StatusCode::OK, json!(coupon)))
+        },
+        (Method::DELETE, [id_or_code]) => {
+            coupon_service::delete(id_or_code.to_string(), store, pool).await?;
+            Ok((StatusCode::NO_CONTENT, Value::Null))
+        },
+        _ => Err(CouponError::NotFoundError(anyhow!(format!("Route `{} {}` not found.", operation.method, operation.path)))),
+    };
+}
+
+/// The segments of a `/coupon` path after `/coupon`, without the empty ones (e.g. `/coupon//CODE/` is `["CODE"]`).
+/// `None` for the other paths, including `/couponX`.
+fn coupon_route_segments(path: &str) -> Option<Vec<&str>> {
+    if (!path.starts_with('/')){
+        return None;
+    }
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    if (segments.next() != Some("coupon")){
+        return None;
+    }
+    return Some(segments.collect());
+}
+
+/// `None` when a segment is not UTF-8 once decoded.
+fn decode_segments(segments: &[&str]) -> Option<Vec<String>> {
+    return segments.iter()
+        .map(|segment| percent_decode_str(segment).decode_utf8().ok().map(|segment| segment.into_owned()))
+        .collect();
+}
+
+fn parse_query<T: serde::de::DeserializeOwned>(query: &str) -> Result<T, CouponError> {
+    return web::Query::<T>::from_query(query)
+        .map(web::Query::into_inner)
+        .map_err(|e| CouponError::ValidationError(format!("Invalid query: {}.", e)));
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(operation: &BatchOperation) -> Result<T, CouponError> {
+    let body = operation.body.clone().unwrap_or(Value::Null);
+    return serde_json::from_value(body)
+        .map_err(|e| CouponError::ValidationError(format!("Invalid body: {}.", e)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{coupon_route_segments, decode_segments};
+
+    #[test]
+    fn only_coupon_paths_are_dispatched(){
+        assert_eq!(coupon_route_segments("/coupon"), Some(vec![]));
+        assert_eq!(coupon_route_segments("/coupon/"), Some(vec![]));
+        assert_eq!(coupon_route_segments("/coupon/verify/CODE"), Some(vec!["verify", "CODE"]));
+        assert_eq!(coupon_route_segments("/coupon//verify/"), Some(vec!["verify"]));
+        assert_eq!(coupon_route_segments("/couponX/CODE"), None);
+        assert_eq!(coupon_route_segments("/admin/users"), None);
+        assert_eq!(coupon_route_segments("coupon/CODE"), None);
+    }
+
+    #[test]
+    fn segments_are_percent_decoded(){
+        assert_eq!(decode_segments(&["code", "SUMMER%2010"]), Some(vec!["code".to_string(), "SUMMER 10".to_string()]));
+        assert_eq!(decode_segments(&["A%2FB"]), Some(vec!["A/B".to_string()]));
+        assert_eq!(decode_segments(&["%FF"]), None);
+    }
+}
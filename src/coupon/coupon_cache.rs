@@ -0,0 +1,478 @@
+use super::model::{ChangesSince, Coupon, CouponChangeRecord, CouponCount, CouponFilter, CouponInsert, CouponPatch, CouponUpdate, Cursor};
+use super::coupon_store::{CouponStore, CouponStream, CouponTransaction};
+use crate::configuration::{CacheSettings, Reloadable, RetrySettings};
+use crate::events::PendingEvent;
+use crate::webhook::model::WebhookEvent;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use moka::sync::Cache;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+
+// the codes evicted by an instance, published as a JSON array for the other instances to evict them too
+const INVALIDATION_CHANNEL: &str = "coupon-cache:invalidate";
+
+/// Where `CachedCouponStore` keeps the coupons, by code.
+/// The failures of the cache are logged and the coupons read from the store, they never fail a request.
+#[async_trait]
+pub trait CouponCache: Send + Sync {
+    async fn get(&self, code: &str) -> Option<Coupon>;
+
+    async fn insert(&self, coupon: &Coupon);
+
+    /// Evict the coupons, from the cache of every instance when it is shared.
+    async fn invalidate(&self, codes: &[String]);
+}
+
+/// In the memory of the instance, the coupons changed by the other instances are only seen once they expire.
+/// `ttl_seconds` is read on each lookup, so `/admin/config/reload` applies it to the coupons already cached,
+/// `0` stops using the cache until it is set again.
+#[derive(Clone)]
+pub struct MemoryCouponCache {
+    // with the time they were cached
+    coupons: Cache<String, (Coupon, Instant)>,
+    settings: Reloadable<CacheSettings>,
+}
+
+impl MemoryCouponCache {
+    pub fn new(settings: Reloadable<CacheSettings>) -> Self {
+        return Self {
+            coupons: Cache::builder()
+                .max_capacity(settings.get().max_entries)
+                .build(),
+            settings,
+        };
+    }
+
+    fn ttl(&self) -> Duration {
+        return Duration::from_secs(self.settings.get().ttl_seconds);
+    }
+}
+
+#[async_trait]
+impl CouponCache for MemoryCouponCache {
+    async fn get(&self, code: &str) -> Option<Coupon> {
+        let (coupon, cached_at) = self.coupons.get(code)?;
+        if (cached_at.elapsed() >= self.ttl()){
+            self.coupons.invalidate(code);
+            return None;
+        }
+        return Some(coupon);
+    }
+
+    async fn insert(&self, coupon: &Coupon) {
+        if (self.ttl().is_zero()){
+            return;
+        }
+        self.coupons.insert(coupon.code.clone(), (coupon.clone(), Instant::now()));
+    }
+
+    async fn invalidate(&self, codes: &[String]) {
+        for code in codes {
+            self.coupons.invalidate(code);
+        }
+    }
+}
+
+/// Shared by the instances in Redis (`redis_uri`), with a copy in the memory of each instance so the hot coupons
+/// don't cost a round trip. The evicted codes are published on `INVALIDATION_CHANNEL`, and `subscribe` evicts them
+/// from the memory of every instance.
+pub struct RedisCouponCache {
+    client: redis::Client,
+    // connected on the first use, so the server starts without Redis
+    connection: OnceCell<ConnectionManager>,
+    memory: MemoryCouponCache,
+    settings: Reloadable<CacheSettings>,
+}
+
+impl RedisCouponCache {
+    pub fn new(client: redis::Client, settings: Reloadable<CacheSettings>) -> Self {
+        return Self {
+            client,
+            connection: OnceCell::new(),
+            memory: MemoryCouponCache::new(settings.clone()),
+            settings,
+        };
+    }
+
+    /// Evict from the memory the codes published by any instance, for as long as the server runs.
+    /// The whole memory is evicted when the subscription is lost, the invalidations published meanwhile are missed.
+    pub fn subscribe(&self) {
+        let client = self.client.clone();
+        let memory = self.memory.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(error) = listen(&client, &memory).await {
+                    tracing::warn!("Lost the subscription to the coupon cache invalidations, retrying: {}", error);
+                }
+                memory.coupons.invalidate_all();
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager, redis::RedisError> {
+        let connection = self.connection.get_or_try_init(|| ConnectionManager::new(self.client.clone())).await?;
+        return Ok(connection.clone());
+    }
+}
+
+async fn listen(client: &redis::Client, memory: &MemoryCouponCache) -> Result<(), redis::RedisError> {
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(INVALIDATION_CHANNEL).await?;
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+        match serde_json::from_str::<Vec<String>>(&payload) {
+            Ok(codes) => memory.invalidate(&codes).await,
+            Err(error) => tracing::warn!("Invalid coupon cache invalidation {:?}: {}", payload, error),
+        }
+    }
+    return Ok(());
+}
+
+fn redis_key(code: &str) -> String {
+    return format!("coupon:code:{}", code);
+}
+
+#[async_trait]
+impl CouponCache for RedisCouponCache {
+    async fn get(&self, code: &str) -> Option<Coupon> {
+        if let Some(coupon) = self.memory.get(code).await {
+            return Some(coupon);
+        }
+        // disabled by `/admin/config/reload`
+        if (self.settings.get().ttl_seconds == 0){
+            return None;
+        }
+        let json: Option<String> = match self.connection().await {
+            Ok(mut conn) => match conn.get(redis_key(code)).await {
+                Ok(json) => json,
+                Err(error) => {
+                    tracing::warn!("Failed to read the coupon cache: {}", error);
+                    return None;
+                },
+            },
+            Err(error) => {
+                tracing::warn!("Failed to connect to the coupon cache: {}", error);
+                return None;
+            },
+        };
+        let coupon = json.and_then(|json| serde_json::from_str::<Coupon>(&json).ok());
+        if let Some(coupon) = &coupon {
+            self.memory.insert(coupon).await;
+        }
+        return coupon;
+    }
+
+    async fn insert(&self, coupon: &Coupon) {
+        let ttl_seconds = self.settings.get().ttl_seconds as usize;
+        if (ttl_seconds == 0){
+            return;
+        }
+        self.memory.insert(coupon).await;
+        let json = match serde_json::to_string(coupon) {
+            Ok(json) => json,
+            Err(_) => return,
+        };
+        let result: Result<(), redis::RedisError> = match self.connection().await {
+            Ok(mut conn) => conn.set_ex(redis_key(&coupon.code), json, ttl_seconds).await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            tracing::warn!("Failed to write the coupon cache: {}", error);
+        }
+    }
+
+    async fn invalidate(&self, codes: &[String]) {
+        self.memory.invalidate(codes).await;
+        let keys: Vec<String> = codes.iter().map(|code| redis_key(code)).collect();
+        let message = serde_json::to_string(codes).unwrap_or_default();
+        let result: Result<(), redis::RedisError> = match self.connection().await {
+            Ok(mut conn) => redis::pipe()
+                .del(keys).ignore()
+                .publish(INVALIDATION_CHANNEL, message).ignore()
+                .query_async(&mut conn)
+                .await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            // the other instances keep serving the old coupons until they expire
+            tracing::error!("Failed to invalidate the coupon cache: {}", error);
+        }
+    }
+}
+
+/// Keeps the coupons found by `get_by_code`, the hottest path, in a `CouponCache` for `cache.ttl_seconds`.
+/// The coupons changed by a transaction are evicted once it is committed, and again after `evict_again_after`
+/// when the store reads from a replica: a read of the replica before it caught up would cache the old coupon again.
+pub struct CachedCouponStore {
+    store: Arc<dyn CouponStore>,
+    cache: Arc<dyn CouponCache>,
+    evict_again_after: Option<Duration>,
+}
+
+impl CachedCouponStore {
+    pub fn new(store: Arc<dyn CouponStore>, cache: Arc<dyn CouponCache>, evict_again_after: Option<Duration>) -> Self {
+        return Self { store, cache, evict_again_after };
+    }
+}
+
+async fn evict(cache: &Arc<dyn CouponCache>, codes: Vec<String>, evict_again_after: Option<Duration>) {
+    cache.invalidate(&codes).await;
+    if let Some(delay) = evict_again_after {
+        let cache = cache.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            cache.invalidate(&codes).await;
+        });
+    }
+}
+
+#[async_trait]
+impl CouponStore for CachedCouponStore {
+    async fn get_all(&self, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error> {
+        return self.store.get_all(filter).await;
+    }
+
+    async fn stream_all(&self, filter: &CouponFilter) -> Result<CouponStream, sqlx::Error> {
+        return self.store.stream_all(filter).await;
+    }
+
+    async fn get_page(&self, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error> {
+        return self.store.get_page(filter, cursor, limit).await;
+    }
+
+    async fn count(&self, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error> {
+        return self.store.count(filter).await;
+    }
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        return self.store.get_by_id(id).await;
+    }
+
+    async fn get_by_code(&self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        if let Some(coupon) = self.cache.get(code).await {
+            return Ok(Some(coupon));
+        }
+        let coupon = self.store.get_by_code(code).await?;
+        // the unknown codes are not kept, so an inserted coupon is found right away
+        if let Some(coupon) = &coupon {
+            self.cache.insert(coupon).await;
+        }
+        return Ok(coupon);
+    }
+
+    async fn get_by_codes(&self, codes: &[String]) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut coupons = Vec::new();
+        let mut missing = Vec::new();
+        for code in codes {
+            match self.cache.get(code).await {
+                Some(coupon) => coupons.push(coupon),
+                None => missing.push(code.clone()),
+            }
+        }
+        // a single query for the ones not cached
+        for coupon in self.store.get_by_codes(&missing).await? {
+            self.cache.insert(&coupon).await;
+            coupons.push(coupon);
+        }
+        return Ok(coupons);
+    }
+
+    async fn exists_by_code(&self, code: &String) -> Result<bool, sqlx::Error> {
+        if (self.cache.get(code).await.is_some()){
+            return Ok(true);
+        }
+        return self.store.exists_by_code(code).await;
+    }
+
+    async fn get_changes(&self, since: ChangesSince, settle_seconds: u32, limit: u32) -> Result<Option<Vec<CouponChangeRecord>>, sqlx::Error> {
+        return self.store.get_changes(since, settle_seconds, limit).await;
+    }
+
+    async fn begin(&self) -> Result<Box<dyn CouponTransaction>, sqlx::Error> {
+        let transaction = self.store.begin().await?;
+        return Ok(Box::new(CachedCouponTransaction { transaction, cache: self.cache.clone(), evict_again_after: self.evict_again_after, codes: HashMap::new(), changed_codes: vec![] }));
+    }
+
+    async fn evict_cached(&self, codes: &[String]) {
+        evict(&self.cache, codes.to_vec(), self.evict_again_after).await;
+    }
+
+    fn retry_settings(&self) -> &RetrySettings {
+        return self.store.retry_settings();
+    }
+}
+
+/// Evicts the coupons it changed once committed: evicted earlier, a concurrent read could cache them again before the commit.
+pub struct CachedCouponTransaction {
+    transaction: Box<dyn CouponTransaction>,
+    cache: Arc<dyn CouponCache>,
+    evict_again_after: Option<Duration>,
+    // codes of the coupons read, by id, to evict the ones changed by id
+    codes: HashMap<i32, String>,
+    changed_codes: Vec<String>,
+}
+
+impl CachedCouponTransaction {
+    fn remember(&mut self, coupon: &Option<Coupon>) {
+        if let Some(coupon) = coupon {
+            self.codes.insert(coupon.id, coupon.code.clone());
+        }
+    }
+
+    async fn changed_id(&mut self, id: i32) -> Result<(), sqlx::Error> {
+        // `coupon_service` reads the coupons before changing them, the code is only queried here otherwise
+        if (!self.codes.contains_key(&id)){
+            let coupon = self.transaction.get_by_id(id).await?;
+            self.remember(&coupon);
+        }
+        if let Some(code) = self.codes.get(&id) {
+            self.changed_codes.push(code.clone());
+        }
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl CouponTransaction for CachedCouponTransaction {
+    async fn insert(&mut self, coupon: CouponInsert) -> Result<u64, sqlx::Error> {
+        return self.transaction.insert(coupon).await;
+    }
+
+    async fn upsert(&mut self, code: &String, coupon: CouponUpdate) -> Result<bool, sqlx::Error> {
+        self.changed_codes.push(code.clone());
+        return self.transaction.upsert(code, coupon).await;
+    }
+
+    async fn update(&mut self, id: i32, coupon: CouponUpdate, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+        self.changed_id(id).await?;
+        return self.transaction.update(id, coupon, expected_version).await;
+    }
+
+    async fn patch(&mut self, id: i32, patch: &CouponPatch, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+        self.changed_id(id).await?;
+        return self.transaction.patch(id, patch, expected_version).await;
+    }
+
+    async fn get_by_id(&mut self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        let coupon = self.transaction.get_by_id(id).await?;
+        self.remember(&coupon);
+        return Ok(coupon);
+    }
+
+    async fn get_by_code(&mut self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        let coupon = self.transaction.get_by_code(code).await?;
+        self.remember(&coupon);
+        return Ok(coupon);
+    }
+
+    async fn delete_by_id(&mut self, id: i32) -> Result<bool, sqlx::Error> {
+        self.changed_id(id).await?;
+        return self.transaction.delete_by_id(id).await;
+    }
+
+    async fn delete_by_code(&mut self, code: &String) -> Result<bool, sqlx::Error> {
+        self.changed_codes.push(code.clone());
+        return self.transaction.delete_by_code(code).await;
+    }
+
+    async fn add_event(&mut self, event: &PendingEvent) -> Result<(), sqlx::Error> {
+        return self.transaction.add_event(event).await;
+    }
+
+    async fn add_webhook_deliveries(&mut self, event: WebhookEvent, body: &str) -> Result<bool, sqlx::Error> {
+        return self.transaction.add_webhook_deliveries(event, body).await;
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error> {
+        let transaction = *self;
+        transaction.transaction.commit().await?;
+        if (!transaction.changed_codes.is_empty()){
+            evict(&transaction.cache, transaction.changed_codes, transaction.evict_again_after).await;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachedCouponStore, CouponCache, MemoryCouponCache};
+    use crate::configuration::{CacheSettings, Reloadable};
+    use crate::coupon::coupon_repository_memory::InMemoryCouponStore;
+    use crate::coupon::coupon_store::CouponStore;
+    use crate::coupon::model::{CouponDiscount, CouponInsert, CouponUpdate};
+    use claim::{assert_none, assert_ok, assert_some};
+    use std::sync::Arc;
+
+    fn coupon_update(discount: i32) -> CouponUpdate {
+        return CouponUpdate { discount: CouponDiscount::parse(discount).unwrap(), active: true, max_usage_count: None, expiration_date: None };
+    }
+
+    #[tokio::test]
+    async fn updated_coupons_are_evicted_on_commit(){
+        let cache = MemoryCouponCache::new(Reloadable::new(CacheSettings { ttl_seconds: 60, max_entries: 100, ..CacheSettings::default() }));
+        let store = CachedCouponStore::new(Arc::new(InMemoryCouponStore::new()), Arc::new(cache), None);
+        let mut transaction = assert_ok!(store.begin().await);
+        let coupon = CouponInsert { code: "CACHED".to_string(), discount: CouponDiscount::parse(10).unwrap(), active: true, max_usage_count: None, expiration_date: None };
+        let id = assert_ok!(transaction.insert(coupon).await);
+        assert_ok!(transaction.commit().await);
+        let cached = assert_some!(assert_ok!(store.get_by_code(&"CACHED".to_string()).await));
+        assert_eq!(cached.discount, 10);
+
+        // by id, without reading the coupon first
+        let mut transaction = assert_ok!(store.begin().await);
+        assert_ok!(transaction.update(id as i32, coupon_update(20), None).await);
+        assert_ok!(transaction.commit().await);
+
+        let coupon = assert_some!(assert_ok!(store.get_by_code(&"CACHED".to_string()).await));
+        assert_eq!(coupon.discount, 20);
+    }
+
+    #[tokio::test]
+    async fn coupons_changed_outside_of_the_store_are_evicted(){
+        let cache = MemoryCouponCache::new(Reloadable::new(CacheSettings { ttl_seconds: 60, max_entries: 100, ..CacheSettings::default() }));
+        let inner = Arc::new(InMemoryCouponStore::new());
+        let store = CachedCouponStore::new(inner.clone(), Arc::new(cache), None);
+        let mut transaction = assert_ok!(store.begin().await);
+        let coupon = CouponInsert { code: "ARCHIVED".to_string(), discount: CouponDiscount::parse(10).unwrap(), active: true, max_usage_count: None, expiration_date: None };
+        assert_ok!(transaction.insert(coupon).await);
+        assert_ok!(transaction.commit().await);
+        assert_some!(assert_ok!(store.get_by_code(&"ARCHIVED".to_string()).await));
+
+        // e.g. by `coupon_archive_repository`, which doesn't go through the store
+        let mut transaction = assert_ok!(inner.begin().await);
+        assert_ok!(transaction.delete_by_code(&"ARCHIVED".to_string()).await);
+        assert_ok!(transaction.commit().await);
+        assert_some!(assert_ok!(store.get_by_code(&"ARCHIVED".to_string()).await));
+        store.evict_cached(&["ARCHIVED".to_string()]).await;
+
+        assert_none!(assert_ok!(store.get_by_code(&"ARCHIVED".to_string()).await));
+    }
+
+    #[tokio::test]
+    async fn reloaded_ttl_applies_to_the_cached_coupons(){
+        let settings = Reloadable::new(CacheSettings { ttl_seconds: 60, max_entries: 100, ..CacheSettings::default() });
+        let cache = MemoryCouponCache::new(settings.clone());
+        let store = InMemoryCouponStore::new();
+        let mut transaction = assert_ok!(store.begin().await);
+        let coupon = CouponInsert { code: "CACHED".to_string(), discount: CouponDiscount::parse(10).unwrap(), active: true, max_usage_count: None, expiration_date: None };
+        assert_ok!(transaction.insert(coupon).await);
+        assert_ok!(transaction.commit().await);
+        let coupon = assert_some!(assert_ok!(store.get_by_code(&"CACHED".to_string()).await));
+        cache.insert(&coupon).await;
+        assert_some!(cache.get("CACHED").await);
+
+        settings.set(CacheSettings { ttl_seconds: 0, max_entries: 100, ..CacheSettings::default() });
+
+        assert_none!(cache.get("CACHED").await);
+        cache.insert(&coupon).await;
+        settings.set(CacheSettings { ttl_seconds: 60, max_entries: 100, ..CacheSettings::default() });
+        assert_none!(cache.get("CACHED").await);
+    }
+}
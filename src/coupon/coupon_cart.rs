@@ -0,0 +1,165 @@
+use super::model::{
+    AppliedCoupon, CartApplyRequest, CartApplyResponse, CartLine, CouponError, CouponResponse, RejectedCoupon,
+};
+use super::{coupon_service, coupon_store::CouponStore};
+use crate::feature_flags::FeatureFlags;
+
+
+/// Apply the codes to the cart, each one that passes the rules of `coupon_service::is_valid`.
+/// The percentages are applied one after the other on what is left of every item, the shipping is not discounted.
+/// The codes that are unknown, not valid or sent twice are listed as rejected instead of failing the request.
+pub async fn apply(request: CartApplyRequest, flags: &FeatureFlags, store: &dyn CouponStore) -> Result<CartApplyResponse, CouponError> {
+    request.validate().map_err(CouponError::ValidationError)?;
+
+    let mut coupons: Vec<CouponResponse> = Vec::new();
+    let mut rejected = Vec::new();
+    for code in &request.codes {
+        if (coupons.iter().any(|coupon| coupon.code.eq_ignore_ascii_case(code))){
+            rejected.push(RejectedCoupon { code: code.clone(), reason: "Coupon was already applied.".to_string() });
+            continue;
+        }
+        let coupon = match coupon_service::get_by_code(code.clone(), store).await {
+            Ok(coupon) => coupon,
+            Err(CouponError::NotFoundError(_)) => {
+                rejected.push(RejectedCoupon { code: code.clone(), reason: "Coupon not found.".to_string() });
+                continue;
+            },
+            Err(error) => return Err(error),
+        };
+        match coupon_service::check_validity(&coupon, flags) {
+            Ok(()) => coupons.push(coupon),
+            Err(reason) => rejected.push(RejectedCoupon { code: code.clone(), reason }),
+        }
+    }
+
+    let mut response = price(&request, &coupons);
+    response.rejected = rejected;
+    return Ok(response);
+}
+
+fn price(request: &CartApplyRequest, coupons: &[CouponResponse]) -> CartApplyResponse {
+    let mut applied: Vec<AppliedCoupon> = coupons.iter()
+        .map(|coupon| AppliedCoupon { code: coupon.code.clone(), discount: coupon.discount, amount: 0 })
+        .collect();
+
+    let items: Vec<CartLine> = request.items.iter().map(|item| {
+        // `validate()` made sure it doesn't overflow
+        let subtotal = item.unit_price * i64::from(item.quantity);
+        let mut total = subtotal;
+        for coupon in applied.iter_mut() {
+            // rounded down, in favor of the store
+            let amount = (i128::from(total) * i128::from(coupon.discount) / 100) as i64;
+            coupon.amount += amount;
+            total -= amount;
+        }
+        return CartLine { sku: item.sku.clone(), quantity: item.quantity, subtotal, discount: subtotal - total, total };
+    }).collect();
+
+    let subtotal = items.iter().map(|line| line.subtotal).sum();
+    let discount = items.iter().map(|line| line.discount).sum();
+    let total = items.iter().map(|line| line.total).sum::<i64>() + request.shipping;
+    return CartApplyResponse {
+        currency: request.currency.clone(),
+        items,
+        applied,
+        rejected: Vec::new(),
+        subtotal,
+        discount,
+        shipping: request.shipping,
+        total,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, price};
+    use crate::coupon::coupon_repository_memory::InMemoryCouponStore;
+    use crate::coupon::coupon_store::CouponStore;
+    use crate::coupon::{CartApplyRequest, CartItem, CouponDiscount, CouponInsert, CouponLinks, CouponResponse};
+    use crate::feature_flags::FeatureFlags;
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use std::collections::BTreeMap;
+
+    fn cart(codes: &[&str]) -> CartApplyRequest {
+        return CartApplyRequest {
+            items: vec![
+                CartItem { sku: "SHIRT".to_string(), quantity: 2, unit_price: 5000 },
+                CartItem { sku: "SOCKS".to_string(), quantity: 1, unit_price: 999 },
+            ],
+            shipping: 1500,
+            currency: "BRL".to_string(),
+            codes: codes.iter().map(|code| code.to_string()).collect(),
+        };
+    }
+
+    fn coupon(code: &str, discount: i32) -> CouponResponse {
+        return CouponResponse {
+            id: 1,
+            code: code.to_string(),
+            discount,
+            active: true,
+            max_usage_count: None,
+            expiration_date: None,
+            date_created: None,
+            date_updated: None,
+            version: 1,
+            links: CouponLinks::new(1),
+        };
+    }
+
+    #[test]
+    fn the_discounts_are_applied_one_after_the_other_on_every_item(){
+        let response = price(&cart(&["TEN", "TWENTY"]), &[coupon("TEN", 10), coupon("TWENTY", 20)]);
+
+        // 10000 - 10% = 9000, - 20% = 7200
+        assert_eq!((response.items[0].subtotal, response.items[0].discount, response.items[0].total), (10000, 2800, 7200));
+        // 999 - 99 = 900, - 180 = 720
+        assert_eq!((response.items[1].subtotal, response.items[1].discount, response.items[1].total), (999, 279, 720));
+        assert_eq!((response.applied[0].amount, response.applied[1].amount), (1000 + 99, 1800 + 180));
+        assert_eq!((response.subtotal, response.discount, response.shipping, response.total), (10999, 3079, 1500, 7920 + 1500));
+    }
+
+    #[test]
+    fn invalid_carts_are_rejected(){
+        assert_ok!(cart(&["TEN"]).validate());
+        assert_err!(cart(&[]).validate());
+        assert_err!(CartApplyRequest { items: vec![], ..cart(&["TEN"]) }.validate());
+        assert_err!(CartApplyRequest { shipping: -1, ..cart(&["TEN"]) }.validate());
+        assert_err!(CartApplyRequest { currency: "real".to_string(), ..cart(&["TEN"]) }.validate());
+        let mut zero_quantity = cart(&["TEN"]);
+        zero_quantity.items[0].quantity = 0;
+        assert_err!(zero_quantity.validate());
+        let mut too_large = cart(&["TEN"]);
+        too_large.items[0].unit_price = i64::MAX;
+        assert_err!(too_large.validate());
+    }
+
+    #[tokio::test]
+    async fn unknown_invalid_and_repeated_codes_are_rejected(){
+        let store = InMemoryCouponStore::new();
+        let mut transaction = assert_ok!(store.begin().await);
+        for (code, active) in [("TEN", true), ("INACTIVE", false)] {
+            assert_ok!(transaction.insert(CouponInsert {
+                code: code.to_string(),
+                discount: CouponDiscount::parse(10).unwrap(),
+                active,
+                max_usage_count: None,
+                expiration_date: Some(Utc::now().naive_utc() + Duration::days(1)),
+            }).await);
+        }
+        assert_ok!(transaction.commit().await);
+        let flags = FeatureFlags::new(BTreeMap::new());
+
+        let response = assert_ok!(apply(cart(&["TEN", "ten", "INACTIVE", "UNKNOWN"]), &flags, &store).await);
+
+        assert_eq!(response.applied.iter().map(|coupon| coupon.code.as_str()).collect::<Vec<_>>(), vec!["TEN"]);
+        let rejected: Vec<(&str, &str)> = response.rejected.iter().map(|coupon| (coupon.code.as_str(), coupon.reason.as_str())).collect();
+        assert_eq!(rejected, vec![
+            ("ten", "Coupon was already applied."),
+            ("INACTIVE", "Coupon is not active."),
+            ("UNKNOWN", "Coupon not found."),
+        ]);
+        assert_eq!(response.total, 10999 - 1099 + 1500);
+    }
+}
@@ -1,52 +1,192 @@
-use super::model::{CouponInsertRequest, CouponError, CouponUpdateRequest};
-use super::coupon_service;
+use super::model::{
+    BatchOperation, BulkValidationRequest, CartApplyRequest, CouponChangesQuery, CouponInsertRequest, CouponPatchRequest, JsonPatchOperation, CouponError, CouponUpdateRequest, CouponFilter, CouponPagination,
+    TOTAL_COUNT_HEADER,
+};
+use super::{content_negotiation, coupon_batch::{self, MaxBatchOperations}, coupon_cart, coupon_service, coupon_store::CouponStore};
+use crate::authentication::Session;
+use crate::events;
+use crate::feature_flags::FeatureFlags;
+use crate::rate_limit::ClientBucket;
 use actix_web::{
-    web, get, post, put, delete, HttpResponse, Responder,
+    web, get, head, post, put, patch, delete, HttpMessage, HttpRequest, HttpResponse,
+    http::header::{self, ETag, EntityTag, Header, IfMatch},
     web::Data,
 };
 use sqlx::MySqlPool;
 
 
-#[tracing::instrument( name = "Get all coupons", skip(pool) )]
+#[tracing::instrument( name = "Get all coupons", skip(store, http_request) )]
 #[get("")]
-pub async fn get_all_coupons(pool: Data::<MySqlPool>) -> Result<impl Responder, CouponError> {
-    let coupons = coupon_service::get_all(&pool).await?;
-    return Ok(web::Json(coupons));
+pub async fn get_all_coupons(http_request: HttpRequest, filter: web::Query<CouponFilter>, pagination: web::Query<CouponPagination>, store: Data<dyn CouponStore>) -> Result<HttpResponse, CouponError> {
+    let mut response = HttpResponse::Ok();
+    // keyset pagination is opt-in with the `limit` or `cursor` query params
+    if (pagination.is_requested()){
+        let page = coupon_service::get_page(&filter, &pagination, store.get_ref()).await?;
+        // in the headers too, for the generic clients
+        response
+            .insert_header((TOTAL_COUNT_HEADER, page.pagination.total.to_string()))
+            .insert_header((header::LINK, page.pagination.links.to_header()));
+        return content_negotiation::respond_list(&http_request, response, &page.coupons, "coupon", Some(page.pagination));
+    }
+    if (content_negotiation::prefers_xml(&http_request)){
+        let coupons = coupon_service::get_all(&filter, store.get_ref()).await?;
+        response.insert_header((TOTAL_COUNT_HEADER, coupons.len().to_string()));
+        return content_negotiation::respond_list(&http_request, response, &coupons, "coupon", None);
+    }
+    // the whole table can be large, it is sent while it is read instead of buffered, so it is counted first
+    let total = coupon_service::count(&filter, store.get_ref()).await?.total;
+    response.insert_header((TOTAL_COUNT_HEADER, total.to_string()));
+    let coupons = coupon_service::stream_all(&filter, store.get_ref()).await?;
+    return Ok(content_negotiation::stream_list(&http_request, response, coupons));
+}
+
+#[tracing::instrument( name = "Count coupons", skip(store, http_request) )]
+#[get("/count")]
+pub async fn count_coupons(http_request: HttpRequest, filter: web::Query<CouponFilter>, store: Data<dyn CouponStore>) -> Result<HttpResponse, CouponError> {
+    let count = coupon_service::count(&filter, store.get_ref()).await?;
+    return content_negotiation::respond(&http_request, HttpResponse::Ok(), count);
 }
 
-#[tracing::instrument( name = "Get coupon", skip(pool) )]
+#[tracing::instrument( name = "Get coupon changes", skip(store, http_request) )]
+#[get("/changes")]
+pub async fn get_coupon_changes(http_request: HttpRequest, query: web::Query<CouponChangesQuery>, store: Data<dyn CouponStore>) -> Result<HttpResponse, CouponError> {
+    let changes = coupon_service::get_changes(&query, store.get_ref()).await?;
+    return content_negotiation::respond(&http_request, HttpResponse::Ok(), changes);
+}
+
+#[tracing::instrument( name = "Stream coupon events" )]
+#[get("/events")]
+pub async fn stream_coupon_events() -> HttpResponse {
+    return HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        // not compressed nor buffered by a proxy, so every event reaches the client right away
+        .insert_header(header::ContentEncoding::Identity)
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(events::stream::subscribe());
+}
+
+#[tracing::instrument( name = "Get coupon", skip(store, http_request) )]
 #[get("/{id_or_code}")]
-pub async fn get_coupon(param: web::Path<String>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
-    let coupon = coupon_service::get_by_id_or_code(param.into_inner(), &pool).await?;
-    return Ok(HttpResponse::Ok().json(coupon));
+pub async fn get_coupon(http_request: HttpRequest, param: web::Path<String>, store: Data<dyn CouponStore>) -> Result<HttpResponse, CouponError> {
+    let coupon = coupon_service::get_by_id_or_code(param.into_inner(), store.get_ref()).await?;
+    // the version, to send back in `If-Match` when updating the coupon
+    let etag = ETag(EntityTag::new_strong(coupon.version.to_string()));
+    return content_negotiation::respond(&http_request, HttpResponse::Ok().insert_header(etag).take(), coupon);
+}
+
+#[tracing::instrument( name = "Head coupon by code", skip(store) )]
+#[head("/code/{code}")]
+pub async fn coupon_exists(param: web::Path<String>, store: Data<dyn CouponStore>) -> Result<HttpResponse, CouponError> {
+    // no body is sent on both cases, the status code is enough to know if the coupon exists
+    if (coupon_service::exists_by_code(param.into_inner(), store.get_ref()).await?){
+        return Ok(HttpResponse::Ok().finish());
+    }
+    return Ok(HttpResponse::NotFound().finish());
 }
 
-#[tracing::instrument( name = "Put coupon", skip(pool) )]
+#[tracing::instrument( name = "Put coupon", skip(store, pool, http_request) )]
 #[put("/{id_or_code}")]
-pub async fn update_coupon(params: web::Path<String>, request: web::Json<CouponUpdateRequest>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
-    coupon_service::update(params.into_inner(), request.0, &pool).await?;
+pub async fn update_coupon(http_request: HttpRequest, params: web::Path<String>, request: web::Json<CouponUpdateRequest>, store: Data<dyn CouponStore>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
+    let mut request = request.0;
+    // `If-Match` takes precedence over the `version` of the body
+    if let Some(version) = if_match_version(&http_request)? {
+        request.version = Some(version);
+    }
+    coupon_service::update(params.into_inner(), request, store.get_ref(), &pool).await?;
     return Ok(HttpResponse::Ok().finish());
 }
 
-#[tracing::instrument( name = "Delete coupon", skip(pool) )]
+// the version of `If-Match: "<version>"`, `None` when the header is missing or `*`
+fn if_match_version(http_request: &HttpRequest) -> Result<Option<i32>, CouponError> {
+    if (!http_request.headers().contains_key(header::IF_MATCH)){
+        return Ok(None);
+    }
+    let invalid = || CouponError::ValidationError("`If-Match` must be the `ETag` of the coupon, e.g. `\"1\"`.".to_string());
+    return match IfMatch::parse(http_request).map_err(|_| invalid())? {
+        IfMatch::Any => Ok(None),
+        IfMatch::Items(tags) if tags.len() == 1 => tags[0].tag().parse::<i32>().map(Some).map_err(|_| invalid()),
+        IfMatch::Items(_) => Err(invalid()),
+    };
+}
+
+#[tracing::instrument( name = "Patch coupon", skip(store, pool, http_request, body) )]
+#[patch("/{id_or_code}")]
+pub async fn patch_coupon(http_request: HttpRequest, params: web::Path<String>, body: web::Bytes, store: Data<dyn CouponStore>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
+    let version = if_match_version(&http_request)?;
+    // a JSON Patch (RFC 6902) with its content type, a JSON Merge Patch (RFC 7386) otherwise
+    let coupon = if (http_request.content_type() == "application/json-patch+json"){
+        let operations: Vec<JsonPatchOperation> = parse_body(&body)?;
+        coupon_service::json_patch(params.into_inner(), operations, version, store.get_ref(), &pool).await?
+    } else {
+        let mut request: CouponPatchRequest = parse_body(&body)?;
+        // `If-Match` takes precedence over the `version` of the body
+        if let Some(version) = version {
+            request.version = Some(version);
+        }
+        coupon_service::patch(params.into_inner(), request, store.get_ref(), &pool).await?
+    };
+    let etag = ETag(EntityTag::new_strong(coupon.version.to_string()));
+    return content_negotiation::respond(&http_request, HttpResponse::Ok().insert_header(etag).take(), coupon);
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, CouponError> {
+    return serde_json::from_slice(body)
+        .map_err(|e| CouponError::ValidationError(format!("Invalid body: {}.", e)));
+}
+
+#[tracing::instrument( name = "Upsert coupon by code", skip(store, pool, http_request) )]
+#[put("/code/{code}")]
+pub async fn upsert_coupon(http_request: HttpRequest, params: web::Path<String>, request: web::Json<CouponUpdateRequest>, store: Data<dyn CouponStore>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
+    let (coupon, created) = coupon_service::upsert(params.into_inner(), request.0, store.get_ref(), &pool).await?;
+    if (created){
+        return content_negotiation::respond(&http_request, HttpResponse::Created(), coupon);
+    }
+    return content_negotiation::respond(&http_request, HttpResponse::Ok(), coupon);
+}
+
+#[tracing::instrument( name = "Delete coupon", skip(store, pool) )]
 #[delete("/{id_or_code}")]
-pub async fn delete_coupon(param: web::Path<String>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
-    coupon_service::delete(param.into_inner(), &pool).await?;
+pub async fn delete_coupon(param: web::Path<String>, store: Data<dyn CouponStore>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
+    coupon_service::delete(param.into_inner(), store.get_ref(), &pool).await?;
     return Ok(HttpResponse::NoContent().finish());
 }
 
-#[tracing::instrument( name = "Post coupon", skip(pool) )]
+#[tracing::instrument( name = "Post coupon", skip(store, pool, http_request) )]
 #[post("")]
-pub async fn add_coupon(request: web::Json<CouponInsertRequest>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
-    let coupon = coupon_service::insert(request.0, &pool).await?;
-    return Ok(HttpResponse::Created().json(coupon));
+pub async fn add_coupon(http_request: HttpRequest, request: web::Json<CouponInsertRequest>, store: Data<dyn CouponStore>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
+    let coupon = coupon_service::insert(request.0, store.get_ref(), &pool).await?;
+    return content_negotiation::respond(&http_request, HttpResponse::Created(), coupon);
 }
 
-#[tracing::instrument( name = "Verify coupon", skip(pool) )]
+#[tracing::instrument( name = "Verify coupon", skip(store, http_request, flags) )]
 #[get("/verify/{id_or_code}")]
-pub async fn verify_coupon(param: web::Path<String>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
-    let valid_coupon = coupon_service::is_valid(param.into_inner(), &pool).await?;
-    return Ok(HttpResponse::Ok().body(valid_coupon.to_string()));
+pub async fn verify_coupon(http_request: HttpRequest, param: web::Path<String>, flags: Data<FeatureFlags>, store: Data<dyn CouponStore>) -> Result<HttpResponse, CouponError> {
+    let valid_coupon = coupon_service::is_valid(param.into_inner(), &flags, store.get_ref()).await?;
+    return content_negotiation::respond(&http_request, HttpResponse::Ok(), valid_coupon);
 }
 
+#[tracing::instrument( name = "Validate coupon codes in bulk", skip(store, http_request, flags, request) )]
+#[post("/validate/bulk")]
+pub async fn validate_coupons(http_request: HttpRequest, request: web::Json<BulkValidationRequest>, flags: Data<FeatureFlags>, store: Data<dyn CouponStore>) -> Result<HttpResponse, CouponError> {
+    let validations = coupon_service::validate_codes(request.0, &flags, store.get_ref()).await?;
+    return content_negotiation::respond(&http_request, HttpResponse::Ok(), validations);
+}
+
+#[tracing::instrument( name = "Apply coupons to a cart", skip(store, http_request, flags, request) )]
+#[post("/apply")]
+pub async fn apply_coupons(http_request: HttpRequest, request: web::Json<CartApplyRequest>, flags: Data<FeatureFlags>, store: Data<dyn CouponStore>) -> Result<HttpResponse, CouponError> {
+    let cart = coupon_cart::apply(request.0, &flags, store.get_ref()).await?;
+    return content_negotiation::respond(&http_request, HttpResponse::Ok(), cart);
+}
 
+#[tracing::instrument( name = "Batch coupon operations", skip(store, pool, http_request, flags, max_operations) )]
+#[post("")]
+pub async fn batch_coupons(http_request: HttpRequest, request: web::Json<Vec<BatchOperation>>, max_operations: Data<MaxBatchOperations>, flags: Data<FeatureFlags>, store: Data<dyn CouponStore>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponError> {
+    let session = http_request.extensions().get::<Session>().cloned()
+        .ok_or(CouponError::ForbiddenError("The request has no session.".to_string()))?;
+    // not set when the rate limit is disabled
+    let bucket = http_request.extensions().get::<ClientBucket>().cloned();
+    let results = coupon_batch::execute(request.0, max_operations.0, &session, bucket.as_ref(), &flags, store.get_ref(), &pool).await?;
+    return content_negotiation::respond_list(&http_request, HttpResponse::Ok(), &results, "operation", None);
+}
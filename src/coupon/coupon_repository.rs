@@ -1,9 +1,11 @@
-use super::model::{Coupon, CouponInsert, CouponUpdate};
-use sqlx::{MySqlPool, query, query_as};
-use sqlx::types::chrono::{NaiveDateTime};
+use super::model::{ChangesSince, Coupon, CouponChangeRecord, CouponCount, CouponFilter, CouponInsert, CouponPatch, CouponUpdate, Cursor};
+use futures_util::TryStreamExt;
+use sqlx::{MySqlConnection, query};
+use sqlx::types::chrono::NaiveDateTime;
+use tokio::sync::mpsc::Sender;
 
 
-pub async fn insert(coupon: CouponInsert, pool: &MySqlPool) -> Result<u64, sqlx::Error> {
+pub async fn insert(coupon: CouponInsert, conn: &mut MySqlConnection) -> Result<u64, sqlx::Error> {
     let result = query!(
         r#"
             INSERT INTO coupon 
@@ -17,7 +19,7 @@ pub async fn insert(coupon: CouponInsert, pool: &MySqlPool) -> Result<u64, sqlx:
         coupon.max_usage_count,
         coupon.expiration_date,
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await
     .map_err(|error| {
         tracing::error!("Failed to execute insert query: {:?}", error);
@@ -26,45 +28,144 @@ pub async fn insert(coupon: CouponInsert, pool: &MySqlPool) -> Result<u64, sqlx:
     return Ok(result.last_insert_id());
 }
 
-pub async fn update(id: i32, coupon: CouponUpdate, pool: &MySqlPool) -> Result<(), sqlx::Error> {
-    query!(
+/// Insert the coupon, or fully replace it if a coupon with the same `code` already exists.
+/// Returns the affected rows count: `1` when inserted and `2` when updated.
+pub async fn upsert(code: &String, coupon: CouponUpdate, conn: &mut MySqlConnection) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+            INSERT INTO coupon
+            (code, discount, active, max_usage_count, expiration_date)
+            VALUES
+            (?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE
+            discount = VALUES(discount),
+            active = VALUES(active),
+            max_usage_count = VALUES(max_usage_count),
+            expiration_date = VALUES(expiration_date),
+            version = version + 1
+        "#)
+    .bind(code)
+    .bind(coupon.discount.as_ref())
+    .bind(coupon.active)
+    .bind(coupon.max_usage_count)
+    .bind(coupon.expiration_date)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute upsert query: {:?}", error);
+        error
+    })?;
+    return Ok(result.rows_affected());
+}
+
+/// Update the coupon if its `version` is still `expected_version` (any version when `None`), bumping the version.
+/// Returns if the coupon was updated.
+pub async fn update(id: i32, coupon: CouponUpdate, expected_version: Option<i32>, conn: &mut MySqlConnection) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
         r#"
             UPDATE coupon SET
             discount = ?,
             active = ?,
             max_usage_count = ?,
-            expiration_date = ?
+            expiration_date = ?,
+            version = version + 1
             WHERE id = ?
-        "#,
-        coupon.discount.as_ref(),
-        coupon.active,
-        coupon.max_usage_count,
-        coupon.expiration_date,
-        id
-    )
-    .execute(pool)
+            AND (? IS NULL OR version = ?)
+        "#)
+    .bind(coupon.discount.as_ref())
+    .bind(coupon.active)
+    .bind(coupon.max_usage_count)
+    .bind(coupon.expiration_date)
+    .bind(id)
+    .bind(expected_version)
+    .bind(expected_version)
+    .execute(&mut *conn)
     .await
     .map_err(|error| {
         tracing::error!("Failed to execute update query: {:?}", error);
         error
     })?;
 
-    return Ok(());
+    // the version always changes, so an updated coupon is always an affected row
+    return Ok(result.rows_affected() == 1);
+}
+
+/// Same as `update`, only setting the fields of the patch.
+pub async fn patch(id: i32, patch: &CouponPatch, expected_version: Option<i32>, conn: &mut MySqlConnection) -> Result<bool, sqlx::Error> {
+    let mut assignments = Vec::new();
+    if (patch.discount.is_some()){
+        assignments.push("discount = ?");
+    }
+    if (patch.active.is_some()){
+        assignments.push("active = ?");
+    }
+    if (patch.max_usage_count.is_some()){
+        assignments.push("max_usage_count = ?");
+    }
+    if (patch.expiration_date.is_some()){
+        assignments.push("expiration_date = ?");
+    }
+    assignments.push("version = version + 1");
+    let sql = format!("UPDATE coupon SET {} WHERE id = ? AND (? IS NULL OR version = ?)", assignments.join(", "));
+
+    // bound in the order of the assignments
+    let mut query = sqlx::query(&sql);
+    if let Some(discount) = &patch.discount {
+        query = query.bind(*discount.as_ref());
+    }
+    if let Some(active) = patch.active {
+        query = query.bind(active);
+    }
+    if let Some(max_usage_count) = patch.max_usage_count {
+        query = query.bind(max_usage_count);
+    }
+    if let Some(expiration_date) = patch.expiration_date {
+        query = query.bind(expiration_date);
+    }
+    let result = query
+    .bind(id)
+    .bind(expected_version)
+    .bind(expected_version)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute patch query: {:?}", error);
+        error
+    })?;
+
+    return Ok(result.rows_affected() == 1);
 }
 
 
-pub async fn get_all(pool: &MySqlPool) -> Result<Vec<Coupon>, sqlx::Error> {
-    let coupons = query_as!(Coupon,
-        r#"SELECT id
+// `?` placeholders are bound with the `CouponFilter` values by `bind_filter()`
+const FILTER_WHERE_CLAUSE: &str = r#"
+        WHERE (? IS NULL OR active = ?)
+        AND (? IS NULL OR (expiration_date IS NOT NULL AND expiration_date < NOW()) = ?)
+"#;
+
+fn bind_filter<'q, O>(query: sqlx::query::QueryAs<'q, sqlx::MySql, O, sqlx::mysql::MySqlArguments>, filter: &CouponFilter)
+    -> sqlx::query::QueryAs<'q, sqlx::MySql, O, sqlx::mysql::MySqlArguments> {
+    return query
+        .bind(filter.active)
+        .bind(filter.active)
+        .bind(filter.expired)
+        .bind(filter.expired);
+}
+
+pub async fn get_all(filter: &CouponFilter, conn: &mut MySqlConnection) -> Result<Vec<Coupon>, sqlx::Error> {
+    let sql = format!(r#"SELECT id
         , code
-        , discount 
+        , discount
         , max_usage_count
-        , active as `active: bool`
-        , expiration_date as `expiration_date: NaiveDateTime`
-        , date_created as `date_created: Option<NaiveDateTime>`
-        , date_updated as `date_updated: NaiveDateTime`
-        FROM coupon"#)
-    .fetch_all(pool)
+        , active
+        , version
+        , expiration_date
+        , date_created
+        , date_updated
+        FROM coupon {}"#, FILTER_WHERE_CLAUSE);
+
+    let coupons = bind_filter(sqlx::query_as::<_, Coupon>(&sql), filter)
+    .fetch_all(&mut *conn)
     .await
     .map_err(|error| {
         tracing::error!("Failed to execute select query: {:?}", error);
@@ -74,13 +175,86 @@ pub async fn get_all(pool: &MySqlPool) -> Result<Vec<Coupon>, sqlx::Error> {
    return Ok(coupons);
 }
 
+/// Send the coupons to `sender` while they are read, until it is closed (e.g. the client disconnected).
+pub async fn stream_all(filter: &CouponFilter, sender: &Sender<Result<Coupon, sqlx::Error>>, conn: &mut MySqlConnection) -> Result<(), sqlx::Error> {
+    let sql = format!("{} {}", COUPON_SELECT, FILTER_WHERE_CLAUSE);
+    let mut coupons = bind_filter(sqlx::query_as::<_, Coupon>(&sql), filter).fetch(&mut *conn);
+    while let Some(coupon) = coupons.try_next().await
+        .map_err(|error| {
+            tracing::error!("Failed to execute select query: {:?}", error);
+            error
+        })? {
+        if (sender.send(Ok(coupon)).await.is_err()){
+            return Ok(());
+        }
+    }
+    return Ok(());
+}
+
+/// Keyset pagination: returns up to `limit` coupons after (ascending `id`) or before (descending `id`) the cursor.
+pub async fn get_page(filter: &CouponFilter, cursor: Option<Cursor>, limit: u32, conn: &mut MySqlConnection) -> Result<Vec<Coupon>, sqlx::Error> {
+    let (position, order, id) = match cursor {
+        None => ("", "ASC", None),
+        Some(Cursor::After(id)) => ("AND id > ?", "ASC", Some(id)),
+        Some(Cursor::Before(id)) => ("AND id < ?", "DESC", Some(id)),
+    };
+    let sql = format!(r#"SELECT id
+        , code
+        , discount
+        , max_usage_count
+        , active
+        , version
+        , expiration_date
+        , date_created
+        , date_updated
+        FROM coupon {}
+        {}
+        ORDER BY id {}
+        LIMIT ?"#, FILTER_WHERE_CLAUSE, position, order);
+
+    let mut query = bind_filter(sqlx::query_as::<_, Coupon>(&sql), filter);
+    if let Some(id) = id {
+        query = query.bind(id);
+    }
+    let coupons = query
+    .bind(limit)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+   return Ok(coupons);
+}
+
+pub async fn count(filter: &CouponFilter, conn: &mut MySqlConnection) -> Result<CouponCount, sqlx::Error> {
+    let sql = format!(r#"SELECT COUNT(*) as total
+        , CAST(COALESCE(SUM(active), 0) AS SIGNED) as active
+        , CAST(COALESCE(SUM(expiration_date < NOW()), 0) AS SIGNED) as expired
+        , CAST(COALESCE(SUM(
+            IF(active AND (expiration_date IS NULL OR expiration_date >= NOW()), max_usage_count, 0)
+        ), 0) AS SIGNED) as remaining_usages
+        FROM coupon {}"#, FILTER_WHERE_CLAUSE);
+
+    let count = bind_filter(sqlx::query_as::<_, CouponCount>(&sql), filter)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute count query: {:?}", error);
+        error
+    })?;
+
+   return Ok(count);
+}
+
 pub enum Fields {
     Id(i32),
     Code(String),
     None,
 }
 
-pub async fn get_by_field(field: Fields, pool: &MySqlPool) -> Result<Option<Coupon>, sqlx::Error> {
+pub async fn get_by_field(field: Fields, conn: &mut MySqlConnection) -> Result<Option<Coupon>, sqlx::Error> {
     let field_name: &str;
     let field_value: String;
     match field {
@@ -97,19 +271,11 @@ pub async fn get_by_field(field: Fields, pool: &MySqlPool) -> Result<Option<Coup
         }
     }
 
-    let coupon = query_as!(Coupon, 
-        r#"SELECT id
-        , code
-        , discount 
-        , max_usage_count
-        , active as `active: bool`
-        , expiration_date as `expiration_date: NaiveDateTime`
-        , date_created as `date_created: Option<NaiveDateTime>`
-        , date_updated as `date_updated: NaiveDateTime`
-        FROM coupon WHERE ? = ?
-        "#, field_name, field_value
-    )
-    .fetch_optional(pool)
+    // the field name comes from `Fields`, it is never user input
+    let sql = format!("{} WHERE {} = ?", COUPON_SELECT, field_name);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(field_value)
+    .fetch_optional(&mut *conn)
     .await
     .map_err(|error| {
         tracing::error!("Failed to execute select query: {:?}", error);
@@ -120,20 +286,11 @@ pub async fn get_by_field(field: Fields, pool: &MySqlPool) -> Result<Option<Coup
 
 }
 
-pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<Option<Coupon>, sqlx::Error> {
-    let coupon = query_as!(Coupon, 
-        r#"SELECT id
-        , code
-        , discount 
-        , max_usage_count
-        , active as `active: bool`
-        , expiration_date as `expiration_date: NaiveDateTime`
-        , date_created as `date_created: Option<NaiveDateTime>`
-        , date_updated as `date_updated: NaiveDateTime`
-        FROM coupon WHERE id = ?
-        "#, id
-    )
-    .fetch_optional(pool)
+pub async fn get_by_id(id: i32, conn: &mut MySqlConnection) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("{} WHERE id = ?", COUPON_SELECT);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(id)
+    .fetch_optional(&mut *conn)
     .await
     .map_err(|error| {
         tracing::error!("Failed to execute select query: {:?}", error);
@@ -143,57 +300,198 @@ pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<Option<Coupon>, sqlx
     return Ok(coupon);
 }
 
-pub async fn get_by_code(code: &String, pool: &MySqlPool) -> Result<Option<Coupon>, sqlx::Error> {
-    let coupon = query_as!(Coupon, 
-        r#"SELECT id
+pub async fn get_by_code(code: &String, conn: &mut MySqlConnection) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("{} WHERE code = ?", COUPON_SELECT);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupon);
+}
+
+/// The active coupons expiring between `from` and `until`, the first to expire first.
+pub async fn get_expiring(from: NaiveDateTime, until: NaiveDateTime, conn: &mut MySqlConnection) -> Result<Vec<Coupon>, sqlx::Error> {
+    let sql = format!("{} WHERE active = TRUE AND expiration_date >= ? AND expiration_date < ? ORDER BY expiration_date, id", COUPON_SELECT);
+    let coupons = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(from)
+    .bind(until)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupons);
+}
+
+/// Up to `limit` writes of the `coupon_changes` log after `since`, oldest first, each with the coupon as it is now.
+/// The writes of the last `settle_seconds` are left for the next call, so the transactions still running
+/// when it is made don't commit a write before the cursor it returned.
+pub async fn get_changes(since: ChangesSince, settle_seconds: u32, limit: u32, conn: &mut MySqlConnection) -> Result<Vec<CouponChangeRecord>, sqlx::Error> {
+    let condition = match since {
+        ChangesSince::Start => "TRUE",
+        ChangesSince::Cursor(_) => "seq > ?",
+        ChangesSince::Time(_) => "changed_at > ?",
+    };
+    let sql = format!(
+        "SELECT seq, coupon_id, code, changed_at FROM coupon_changes WHERE {} AND changed_at < NOW() - INTERVAL ? SECOND ORDER BY seq LIMIT ?",
+        condition
+    );
+    let mut changes_query = sqlx::query_as::<_, (i64, i32, String, NaiveDateTime)>(&sql);
+    changes_query = match since {
+        ChangesSince::Start => changes_query,
+        ChangesSince::Cursor(seq) => changes_query.bind(seq),
+        ChangesSince::Time(time) => changes_query.bind(time),
+    };
+    let changes = changes_query
+    .bind(settle_seconds)
+    .bind(limit)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select changes query: {:?}", error);
+        error
+    })?;
+    if (changes.is_empty()){
+        return Ok(Vec::new());
+    }
+
+    let mut ids: Vec<i32> = changes.iter().map(|(_, coupon_id, _, _)| *coupon_id).collect();
+    ids.sort();
+    ids.dedup();
+    let sql = format!("{} WHERE id IN ({})", COUPON_SELECT, vec!["?"; ids.len()].join(", "));
+    let mut coupons_query = sqlx::query_as::<_, Coupon>(&sql);
+    for id in &ids {
+        coupons_query = coupons_query.bind(id);
+    }
+    let coupons = coupons_query
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(changes.into_iter().map(|(seq, coupon_id, code, changed_at)| CouponChangeRecord {
+        seq,
+        coupon_id,
+        code,
+        changed_at,
+        coupon: coupons.iter().find(|coupon| coupon.id == coupon_id).cloned(),
+    }).collect());
+}
+
+/// Same as `get_by_id`, locking the coupon until the end of the transaction of `conn`.
+pub async fn get_by_id_for_update(id: i32, conn: &mut MySqlConnection) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("{} WHERE id = ? FOR UPDATE", COUPON_SELECT);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupon);
+}
+
+/// Same as `get_by_code`, locking the coupon until the end of the transaction of `conn`.
+pub async fn get_by_code_for_update(code: &String, conn: &mut MySqlConnection) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("{} WHERE code = ? FOR UPDATE", COUPON_SELECT);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupon);
+}
+
+const COUPON_SELECT: &str = r#"SELECT id
         , code
-        , discount 
+        , discount
         , max_usage_count
-        , active as `active: bool`
-        , expiration_date as `expiration_date: NaiveDateTime`
-        , date_created as `date_created: Option<NaiveDateTime>`
-        , date_updated as `date_updated: NaiveDateTime`
-        FROM coupon WHERE code = ?
-        "#, code
-    )
-    .fetch_optional(pool)
+        , active
+        , version
+        , expiration_date
+        , date_created
+        , date_updated
+        FROM coupon"#;
+
+/// The coupons of the codes found, in a single query.
+pub async fn get_by_codes(codes: &[String], conn: &mut MySqlConnection) -> Result<Vec<Coupon>, sqlx::Error> {
+    if (codes.is_empty()){
+        return Ok(Vec::new());
+    }
+    let sql = format!("{} WHERE code IN ({})", COUPON_SELECT, vec!["?"; codes.len()].join(", "));
+    let mut query = sqlx::query_as::<_, Coupon>(&sql);
+    for code in codes {
+        query = query.bind(code);
+    }
+    let coupons = query
+    .fetch_all(&mut *conn)
     .await
     .map_err(|error| {
         tracing::error!("Failed to execute select query: {:?}", error);
         error
     })?;
 
-    return Ok(coupon);
+    return Ok(coupons);
+}
+
+pub async fn exists_by_code(code: &String, conn: &mut MySqlConnection) -> Result<bool, sqlx::Error> {
+    let exists: Option<i32> = sqlx::query_scalar("SELECT 1 FROM coupon WHERE code = ?")
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(exists.is_some());
 }
 
-pub async fn delete_by_id(id: i32, pool: &MySqlPool) -> Result<(), sqlx::Error> {
-    query!( 
+/// Returns the affected rows count, `0` when there was no such coupon.
+pub async fn delete_by_id(id: i32, conn: &mut MySqlConnection) -> Result<u64, sqlx::Error> {
+    let result = query!( 
         r#"DELETE FROM coupon
             WHERE id = ?
         "#, id
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await
     .map_err(|error| {
         tracing::error!("Failed to execute delete query: {:?}", error);
         error
     })?;
 
-    return Ok(());
+    return Ok(result.rows_affected());
 }
 
-pub async fn delete_by_code(code: &String, pool: &MySqlPool) -> Result<(), sqlx::Error> {
-    query!( 
+/// Returns the affected rows count, `0` when there was no such coupon.
+pub async fn delete_by_code(code: &String, conn: &mut MySqlConnection) -> Result<u64, sqlx::Error> {
+    let result = query!( 
         r#"DELETE FROM coupon
             WHERE code = ?
         "#, code
     )
-    .execute(pool)
+    .execute(&mut *conn)
     .await
     .map_err(|error| {
         tracing::error!("Failed to execute delete query: {:?}", error);
         error
     })?;
 
-    return Ok(());
+    return Ok(result.rows_affected());
 }
\ No newline at end of file
@@ -0,0 +1,211 @@
+use super::model::{Coupon, CouponCount, CouponFilter, CouponInsert, CouponUpdate, Cursor};
+use super::coupon_store::{CouponStore, CouponTransaction};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::error::DatabaseError;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+
+/// Coupons kept in memory, e.g. to unit test `coupon_service` without a database.
+/// A transaction works on a copy of the coupons, which replaces them on `commit()`:
+/// the transactions are not isolated from each other, the last one committed wins.
+#[derive(Clone, Default)]
+pub struct InMemoryCouponStore {
+    coupons: Arc<Mutex<Coupons>>,
+}
+
+#[derive(Clone, Default)]
+struct Coupons {
+    by_id: BTreeMap<i32, Coupon>,
+    last_id: i32,
+}
+
+impl InMemoryCouponStore {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    fn snapshot(&self) -> Coupons {
+        return self.coupons.lock().unwrap().clone();
+    }
+}
+
+impl Coupons {
+    fn filtered<'a>(&'a self, filter: &'a CouponFilter) -> impl DoubleEndedIterator<Item = &'a Coupon> {
+        let now = Utc::now().naive_utc();
+        return self.by_id.values()
+            .filter(move |coupon| filter.active.map_or(true, |active| coupon.active == active))
+            .filter(move |coupon| filter.expired.map_or(true, |expired| is_expired(coupon, now) == expired));
+    }
+
+    fn by_code(&self, code: &String) -> Option<&Coupon> {
+        return self.by_id.values().find(|coupon| &coupon.code == code);
+    }
+}
+
+// the error of the unique index on `code`, with the SQLSTATE of Postgres so `is_unique_violation` knows it
+#[derive(thiserror::Error, Debug)]
+#[error("Duplicate coupon code `{0}`.")]
+struct DuplicateCode(String);
+
+impl DatabaseError for DuplicateCode {
+    fn message(&self) -> &str {
+        return "Duplicate coupon code.";
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        return Some(Cow::Borrowed("23505"));
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        return self;
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        return self;
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        return self;
+    }
+}
+
+fn is_expired(coupon: &Coupon, now: NaiveDateTime) -> bool {
+    return coupon.expiration_date.map_or(false, |expiration_date| expiration_date < now);
+}
+
+#[async_trait]
+impl CouponStore for InMemoryCouponStore {
+    async fn get_all(&self, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error> {
+        return Ok(self.snapshot().filtered(filter).cloned().collect());
+    }
+
+    async fn get_page(&self, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error> {
+        let coupons = self.snapshot();
+        let page: Vec<Coupon> = match cursor {
+            None => coupons.filtered(filter).take(limit as usize).cloned().collect(),
+            Some(Cursor::After(id)) => coupons.filtered(filter).filter(|coupon| coupon.id > id).take(limit as usize).cloned().collect(),
+            Some(Cursor::Before(id)) => coupons.filtered(filter).rev().filter(|coupon| coupon.id < id).take(limit as usize).cloned().collect(),
+        };
+        return Ok(page);
+    }
+
+    async fn count(&self, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error> {
+        let now = Utc::now().naive_utc();
+        let coupons = self.snapshot();
+        let mut count = CouponCount { total: 0, active: 0, expired: 0, remaining_usages: 0 };
+        for coupon in coupons.filtered(filter) {
+            count.total += 1;
+            if (coupon.active){
+                count.active += 1;
+            }
+            if (is_expired(coupon, now)){
+                count.expired += 1;
+            } else if (coupon.active){
+                count.remaining_usages += i64::from(coupon.max_usage_count.unwrap_or(0));
+            }
+        }
+        return Ok(count);
+    }
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        return Ok(self.snapshot().by_id.get(&id).cloned());
+    }
+
+    async fn get_by_code(&self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        return Ok(self.snapshot().by_code(code).cloned());
+    }
+
+    async fn exists_by_code(&self, code: &String) -> Result<bool, sqlx::Error> {
+        return Ok(self.snapshot().by_code(code).is_some());
+    }
+
+    async fn begin(&self) -> Result<Box<dyn CouponTransaction>, sqlx::Error> {
+        return Ok(Box::new(InMemoryCouponTransaction { store: self.clone(), coupons: self.snapshot() }));
+    }
+}
+
+pub struct InMemoryCouponTransaction {
+    store: InMemoryCouponStore,
+    coupons: Coupons,
+}
+
+impl InMemoryCouponTransaction {
+    fn apply(&mut self, id: i32, coupon: CouponUpdate) {
+        if let Some(existing) = self.coupons.by_id.get_mut(&id) {
+            existing.discount = *coupon.discount.as_ref();
+            existing.active = coupon.active;
+            existing.max_usage_count = coupon.max_usage_count;
+            existing.expiration_date = coupon.expiration_date;
+            existing.version += 1;
+            existing.date_updated = Some(Utc::now().naive_utc());
+        }
+    }
+}
+
+#[async_trait]
+impl CouponTransaction for InMemoryCouponTransaction {
+    async fn insert(&mut self, coupon: CouponInsert) -> Result<u64, sqlx::Error> {
+        // the unique index on `code` of the databases
+        if (self.coupons.by_code(&coupon.code).is_some()){
+            return Err(sqlx::Error::Database(Box::new(DuplicateCode(coupon.code))));
+        }
+        self.coupons.last_id += 1;
+        let id = self.coupons.last_id;
+        self.coupons.by_id.insert(id, Coupon::inserted(id, coupon, Utc::now().naive_utc()));
+        return Ok(id as u64);
+    }
+
+    async fn upsert(&mut self, code: &String, coupon: CouponUpdate) -> Result<bool, sqlx::Error> {
+        if let Some(id) = self.coupons.by_code(code).map(|existing| existing.id) {
+            self.apply(id, coupon);
+            return Ok(false);
+        }
+        self.insert(CouponInsert {
+            code: code.clone(),
+            discount: coupon.discount,
+            active: coupon.active,
+            max_usage_count: coupon.max_usage_count,
+            expiration_date: coupon.expiration_date,
+        }).await?;
+        return Ok(true);
+    }
+
+    async fn update(&mut self, id: i32, coupon: CouponUpdate, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+        let version = match self.coupons.by_id.get(&id) {
+            Some(existing) => existing.version,
+            None => return Ok(false),
+        };
+        if (expected_version.map_or(false, |expected_version| expected_version != version)){
+            return Ok(false);
+        }
+        self.apply(id, coupon);
+        return Ok(true);
+    }
+
+    async fn get_by_id(&mut self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        return Ok(self.coupons.by_id.get(&id).cloned());
+    }
+
+    async fn get_by_code(&mut self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        return Ok(self.coupons.by_code(code).cloned());
+    }
+
+    async fn delete_by_id(&mut self, id: i32) -> Result<bool, sqlx::Error> {
+        return Ok(self.coupons.by_id.remove(&id).is_some());
+    }
+
+    async fn delete_by_code(&mut self, code: &String) -> Result<bool, sqlx::Error> {
+        let count = self.coupons.by_id.len();
+        self.coupons.by_id.retain(|_, coupon| &coupon.code != code);
+        return Ok(self.coupons.by_id.len() < count);
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error> {
+        let transaction = *self;
+        *transaction.store.coupons.lock().unwrap() = transaction.coupons;
+        return Ok(());
+    }
+}
@@ -0,0 +1,378 @@
+use super::model::{Coupon, CouponCount, CouponFilter, CouponInsert, CouponUpdate, Cursor};
+use super::coupon_store::{CouponStore, CouponTransaction};
+use crate::telemetry::PoolMetrics;
+use async_trait::async_trait;
+use sqlx::{PgConnection, Postgres, PgPool, Transaction};
+use std::sync::Arc;
+
+
+/// Coupons stored in Postgres, with the schema of `migrations_postgres`.
+/// Same queries as `coupon_repository`, without the compile-time checked macros that need a MySQL database.
+pub struct PostgresCouponStore {
+    pool: PgPool,
+    metrics: Arc<PoolMetrics>,
+}
+
+impl PostgresCouponStore {
+    pub fn new(pool: PgPool, metrics: Arc<PoolMetrics>) -> Self {
+        return Self { pool, metrics };
+    }
+}
+
+const COUPON_COLUMNS: &str = r#"id
+        , code
+        , discount
+        , max_usage_count
+        , active
+        , version
+        , expiration_date
+        , date_created
+        , date_updated"#;
+
+// `$1` and `$2` are bound with the `CouponFilter` values by `bind_filter()`
+const FILTER_WHERE_CLAUSE: &str = r#"
+        WHERE ($1::BOOLEAN IS NULL OR active = $1)
+        AND ($2::BOOLEAN IS NULL OR (expiration_date IS NOT NULL AND expiration_date < LOCALTIMESTAMP) = $2)
+"#;
+
+fn bind_filter<'q, O>(query: sqlx::query::QueryAs<'q, sqlx::Postgres, O, sqlx::postgres::PgArguments>, filter: &CouponFilter)
+    -> sqlx::query::QueryAs<'q, sqlx::Postgres, O, sqlx::postgres::PgArguments> {
+    return query
+        .bind(filter.active)
+        .bind(filter.expired);
+}
+
+async fn insert(conn: &mut PgConnection, coupon: CouponInsert) -> Result<u64, sqlx::Error> {
+    let id: i32 = sqlx::query_scalar(
+        r#"
+            INSERT INTO coupon
+            (code, discount, active, max_usage_count, expiration_date)
+            VALUES
+            ($1, $2, $3, $4, $5)
+            RETURNING id
+        "#)
+    .bind(&coupon.code)
+    .bind(coupon.discount.as_ref())
+    .bind(coupon.active)
+    .bind(coupon.max_usage_count)
+    .bind(coupon.expiration_date)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(id as u64);
+}
+
+async fn upsert(conn: &mut PgConnection, code: &String, coupon: CouponUpdate) -> Result<bool, sqlx::Error> {
+    // `xmax` is only set on the rows that were updated
+    let inserted: bool = sqlx::query_scalar(
+        r#"
+            INSERT INTO coupon
+            (code, discount, active, max_usage_count, expiration_date)
+            VALUES
+            ($1, $2, $3, $4, $5)
+            ON CONFLICT (code) DO UPDATE SET
+            discount = EXCLUDED.discount,
+            active = EXCLUDED.active,
+            max_usage_count = EXCLUDED.max_usage_count,
+            expiration_date = EXCLUDED.expiration_date,
+            version = coupon.version + 1
+            RETURNING (xmax = 0)
+        "#)
+    .bind(code)
+    .bind(coupon.discount.as_ref())
+    .bind(coupon.active)
+    .bind(coupon.max_usage_count)
+    .bind(coupon.expiration_date)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute upsert query: {:?}", error);
+        error
+    })?;
+    return Ok(inserted);
+}
+
+async fn update(conn: &mut PgConnection, id: i32, coupon: CouponUpdate, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+            UPDATE coupon SET
+            discount = $1,
+            active = $2,
+            max_usage_count = $3,
+            expiration_date = $4,
+            version = version + 1
+            WHERE id = $5
+            AND ($6::INTEGER IS NULL OR version = $6)
+        "#)
+    .bind(coupon.discount.as_ref())
+    .bind(coupon.active)
+    .bind(coupon.max_usage_count)
+    .bind(coupon.expiration_date)
+    .bind(id)
+    .bind(expected_version)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+
+    return Ok(result.rows_affected() == 1);
+}
+
+async fn get_all(conn: &mut PgConnection, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM coupon {} ORDER BY id", COUPON_COLUMNS, FILTER_WHERE_CLAUSE);
+
+    let coupons = bind_filter(sqlx::query_as::<_, Coupon>(&sql), filter)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupons);
+}
+
+async fn get_page(conn: &mut PgConnection, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error> {
+    // `$1` and `$2` are the filter, the limit comes after the cursor
+    let (position, order, id, limit_placeholder) = match cursor {
+        None => ("", "ASC", None, "$3"),
+        Some(Cursor::After(id)) => ("AND id > $3", "ASC", Some(id), "$4"),
+        Some(Cursor::Before(id)) => ("AND id < $3", "DESC", Some(id), "$4"),
+    };
+    let sql = format!(r#"SELECT {}
+        FROM coupon {}
+        {}
+        ORDER BY id {}
+        LIMIT {}"#, COUPON_COLUMNS, FILTER_WHERE_CLAUSE, position, order, limit_placeholder);
+
+    let mut query = bind_filter(sqlx::query_as::<_, Coupon>(&sql), filter);
+    if let Some(id) = id {
+        query = query.bind(id);
+    }
+    let coupons = query
+    .bind(i64::from(limit))
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupons);
+}
+
+async fn count(conn: &mut PgConnection, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error> {
+    let sql = format!(r#"SELECT COUNT(*) as total
+        , COUNT(*) FILTER (WHERE active) as active
+        , COUNT(*) FILTER (WHERE expiration_date < LOCALTIMESTAMP) as expired
+        , COALESCE(SUM(max_usage_count) FILTER (
+            WHERE active AND (expiration_date IS NULL OR expiration_date >= LOCALTIMESTAMP)
+        ), 0)::BIGINT as remaining_usages
+        FROM coupon {}"#, FILTER_WHERE_CLAUSE);
+
+    let count = bind_filter(sqlx::query_as::<_, CouponCount>(&sql), filter)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute count query: {:?}", error);
+        error
+    })?;
+
+    return Ok(count);
+}
+
+async fn get_by_id(conn: &mut PgConnection, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM coupon WHERE id = $1", COUPON_COLUMNS);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupon);
+}
+
+async fn get_by_code(conn: &mut PgConnection, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM coupon WHERE code = $1", COUPON_COLUMNS);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupon);
+}
+
+async fn get_by_codes(conn: &mut PgConnection, codes: &[String]) -> Result<Vec<Coupon>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM coupon WHERE code = ANY($1)", COUPON_COLUMNS);
+    let coupons = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(codes)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupons);
+}
+
+async fn exists_by_code(conn: &mut PgConnection, code: &String) -> Result<bool, sqlx::Error> {
+    let exists: Option<i32> = sqlx::query_scalar("SELECT 1 FROM coupon WHERE code = $1")
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(exists.is_some());
+}
+
+async fn delete_by_id(conn: &mut PgConnection, id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM coupon WHERE id = $1")
+    .bind(id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+
+    return Ok(result.rows_affected() > 0);
+}
+
+async fn delete_by_code(conn: &mut PgConnection, code: &String) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM coupon WHERE code = $1")
+    .bind(code)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+
+    return Ok(result.rows_affected() > 0);
+}
+
+// same as `get_by_id`, locking the coupon until the end of the transaction
+async fn get_by_id_for_update(conn: &mut PgConnection, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM coupon WHERE id = $1 FOR UPDATE", COUPON_COLUMNS);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupon);
+}
+
+// same as `get_by_code`, locking the coupon until the end of the transaction
+async fn get_by_code_for_update(conn: &mut PgConnection, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM coupon WHERE code = $1 FOR UPDATE", COUPON_COLUMNS);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupon);
+}
+
+#[async_trait]
+impl CouponStore for PostgresCouponStore {
+    async fn get_all(&self, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_all", get_all(&mut conn, filter)).await;
+    }
+
+    async fn get_page(&self, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_page", get_page(&mut conn, filter, cursor, limit)).await;
+    }
+
+    async fn count(&self, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("count", count(&mut conn, filter)).await;
+    }
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_by_id", get_by_id(&mut conn, id)).await;
+    }
+
+    async fn get_by_code(&self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_by_code", get_by_code(&mut conn, code)).await;
+    }
+
+    async fn get_by_codes(&self, codes: &[String]) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_by_codes", get_by_codes(&mut conn, codes)).await;
+    }
+
+    async fn exists_by_code(&self, code: &String) -> Result<bool, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("exists_by_code", exists_by_code(&mut conn, code)).await;
+    }
+
+    async fn begin(&self) -> Result<Box<dyn CouponTransaction>, sqlx::Error> {
+        return Ok(Box::new(PostgresCouponTransaction(self.metrics.time_acquire(self.pool.begin()).await?, self.metrics.clone())));
+    }
+}
+
+// the metrics of the pool of the transaction, to log its slow queries
+pub struct PostgresCouponTransaction(Transaction<'static, Postgres>, Arc<PoolMetrics>);
+
+#[async_trait]
+impl CouponTransaction for PostgresCouponTransaction {
+    async fn insert(&mut self, coupon: CouponInsert) -> Result<u64, sqlx::Error> {
+        return self.1.time_query("insert", insert(&mut self.0, coupon)).await;
+    }
+
+    async fn upsert(&mut self, code: &String, coupon: CouponUpdate) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("upsert", upsert(&mut self.0, code, coupon)).await;
+    }
+
+    async fn update(&mut self, id: i32, coupon: CouponUpdate, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("update", update(&mut self.0, id, coupon, expected_version)).await;
+    }
+
+    async fn get_by_id(&mut self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        return self.1.time_query("get_by_id_for_update", get_by_id_for_update(&mut self.0, id)).await;
+    }
+
+    async fn get_by_code(&mut self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        return self.1.time_query("get_by_code_for_update", get_by_code_for_update(&mut self.0, code)).await;
+    }
+
+    async fn delete_by_id(&mut self, id: i32) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("delete_by_id", delete_by_id(&mut self.0, id)).await;
+    }
+
+    async fn delete_by_code(&mut self, code: &String) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("delete_by_code", delete_by_code(&mut self.0, code)).await;
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error> {
+        return self.0.commit().await;
+    }
+}
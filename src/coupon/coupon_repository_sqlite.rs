@@ -0,0 +1,474 @@
+use super::model::{Coupon, CouponCount, CouponFilter, CouponInsert, CouponUpdate, Cursor};
+use super::coupon_store::{CouponStore, CouponTransaction};
+use crate::telemetry::PoolMetrics;
+use async_trait::async_trait;
+use sqlx::{SqliteConnection, Sqlite, SqlitePool, Transaction};
+use std::sync::Arc;
+
+
+/// Coupons stored in SQLite, with the schema of `migrations_sqlite`, so the API can run locally without a MySQL server.
+/// The dates are stored as `YYYY-MM-DD HH:MM:SS` text, in UTC.
+pub struct SqliteCouponStore {
+    pool: SqlitePool,
+    metrics: Arc<PoolMetrics>,
+}
+
+impl SqliteCouponStore {
+    pub fn new(pool: SqlitePool, metrics: Arc<PoolMetrics>) -> Self {
+        return Self { pool, metrics };
+    }
+}
+
+const COUPON_COLUMNS: &str = r#"id
+        , code
+        , discount
+        , max_usage_count
+        , active
+        , version
+        , expiration_date
+        , date_created
+        , date_updated"#;
+
+// `?1` and `?2` are bound with the `CouponFilter` values by `bind_filter()`
+const FILTER_WHERE_CLAUSE: &str = r#"
+        WHERE (?1 IS NULL OR active = ?1)
+        AND (?2 IS NULL OR (expiration_date IS NOT NULL AND expiration_date < datetime('now')) = ?2)
+"#;
+
+fn bind_filter<'q, O>(query: sqlx::query::QueryAs<'q, sqlx::Sqlite, O, sqlx::sqlite::SqliteArguments<'q>>, filter: &CouponFilter)
+    -> sqlx::query::QueryAs<'q, sqlx::Sqlite, O, sqlx::sqlite::SqliteArguments<'q>> {
+    return query
+        .bind(filter.active)
+        .bind(filter.expired);
+}
+
+async fn insert(conn: &mut SqliteConnection, coupon: CouponInsert) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+            INSERT INTO coupon
+            (code, discount, active, max_usage_count, expiration_date)
+            VALUES
+            (?, ?, ?, ?, ?)
+        "#)
+    .bind(&coupon.code)
+    .bind(coupon.discount.as_ref())
+    .bind(coupon.active)
+    .bind(coupon.max_usage_count)
+    .bind(coupon.expiration_date)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(result.last_insert_rowid() as u64);
+}
+
+async fn upsert(conn: &mut SqliteConnection, code: &String, coupon: CouponUpdate) -> Result<bool, sqlx::Error> {
+    // SQLite can't tell if an `ON CONFLICT DO UPDATE` inserted or updated, so the update is a separate query
+    let inserted = sqlx::query(
+        r#"
+            INSERT INTO coupon
+            (code, discount, active, max_usage_count, expiration_date)
+            VALUES
+            (?, ?, ?, ?, ?)
+            ON CONFLICT (code) DO NOTHING
+        "#)
+    .bind(code)
+    .bind(coupon.discount.as_ref())
+    .bind(coupon.active)
+    .bind(coupon.max_usage_count)
+    .bind(coupon.expiration_date)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute upsert query: {:?}", error);
+        error
+    })?
+    .rows_affected() == 1;
+    if (inserted){
+        return Ok(true);
+    }
+
+    sqlx::query(
+        r#"
+            UPDATE coupon SET
+            discount = ?,
+            active = ?,
+            max_usage_count = ?,
+            expiration_date = ?,
+            version = version + 1
+            WHERE code = ?
+        "#)
+    .bind(coupon.discount.as_ref())
+    .bind(coupon.active)
+    .bind(coupon.max_usage_count)
+    .bind(coupon.expiration_date)
+    .bind(code)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute upsert query: {:?}", error);
+        error
+    })?;
+    return Ok(false);
+}
+
+async fn update(conn: &mut SqliteConnection, id: i32, coupon: CouponUpdate, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+            UPDATE coupon SET
+            discount = ?,
+            active = ?,
+            max_usage_count = ?,
+            expiration_date = ?,
+            version = version + 1
+            WHERE id = ?
+            AND (? IS NULL OR version = ?)
+        "#)
+    .bind(coupon.discount.as_ref())
+    .bind(coupon.active)
+    .bind(coupon.max_usage_count)
+    .bind(coupon.expiration_date)
+    .bind(id)
+    .bind(expected_version)
+    .bind(expected_version)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+
+    return Ok(result.rows_affected() == 1);
+}
+
+async fn get_all(conn: &mut SqliteConnection, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM coupon {} ORDER BY id", COUPON_COLUMNS, FILTER_WHERE_CLAUSE);
+
+    let coupons = bind_filter(sqlx::query_as::<_, Coupon>(&sql), filter)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupons);
+}
+
+async fn get_page(conn: &mut SqliteConnection, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error> {
+    // `?1` and `?2` are the filter, the limit comes after the cursor
+    let (position, order, id, limit_placeholder) = match cursor {
+        None => ("", "ASC", None, "?3"),
+        Some(Cursor::After(id)) => ("AND id > ?3", "ASC", Some(id), "?4"),
+        Some(Cursor::Before(id)) => ("AND id < ?3", "DESC", Some(id), "?4"),
+    };
+    let sql = format!(r#"SELECT {}
+        FROM coupon {}
+        {}
+        ORDER BY id {}
+        LIMIT {}"#, COUPON_COLUMNS, FILTER_WHERE_CLAUSE, position, order, limit_placeholder);
+
+    let mut query = bind_filter(sqlx::query_as::<_, Coupon>(&sql), filter);
+    if let Some(id) = id {
+        query = query.bind(id);
+    }
+    let coupons = query
+    .bind(i64::from(limit))
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupons);
+}
+
+async fn count(conn: &mut SqliteConnection, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error> {
+    let sql = format!(r#"SELECT COUNT(*) as total
+        , COALESCE(SUM(active), 0) as active
+        , COALESCE(SUM(expiration_date < datetime('now')), 0) as expired
+        , COALESCE(SUM(
+            CASE WHEN active AND (expiration_date IS NULL OR expiration_date >= datetime('now')) THEN max_usage_count ELSE 0 END
+        ), 0) as remaining_usages
+        FROM coupon {}"#, FILTER_WHERE_CLAUSE);
+
+    let count = bind_filter(sqlx::query_as::<_, CouponCount>(&sql), filter)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute count query: {:?}", error);
+        error
+    })?;
+
+    return Ok(count);
+}
+
+async fn get_by_id(conn: &mut SqliteConnection, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM coupon WHERE id = ?", COUPON_COLUMNS);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupon);
+}
+
+async fn get_by_code(conn: &mut SqliteConnection, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+    let sql = format!("SELECT {} FROM coupon WHERE code = ?", COUPON_COLUMNS);
+    let coupon = sqlx::query_as::<_, Coupon>(&sql)
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupon);
+}
+
+async fn get_by_codes(conn: &mut SqliteConnection, codes: &[String]) -> Result<Vec<Coupon>, sqlx::Error> {
+    if (codes.is_empty()){
+        return Ok(Vec::new());
+    }
+    let sql = format!("SELECT {} FROM coupon WHERE code IN ({})", COUPON_COLUMNS, vec!["?"; codes.len()].join(", "));
+    let mut query = sqlx::query_as::<_, Coupon>(&sql);
+    for code in codes {
+        query = query.bind(code);
+    }
+    let coupons = query
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(coupons);
+}
+
+async fn exists_by_code(conn: &mut SqliteConnection, code: &String) -> Result<bool, sqlx::Error> {
+    let exists: Option<i32> = sqlx::query_scalar("SELECT 1 FROM coupon WHERE code = ?")
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    return Ok(exists.is_some());
+}
+
+async fn delete_by_id(conn: &mut SqliteConnection, id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM coupon WHERE id = ?")
+    .bind(id)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+
+    return Ok(result.rows_affected() > 0);
+}
+
+async fn delete_by_code(conn: &mut SqliteConnection, code: &String) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM coupon WHERE code = ?")
+    .bind(code)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+
+    return Ok(result.rows_affected() > 0);
+}
+
+#[async_trait]
+impl CouponStore for SqliteCouponStore {
+    async fn get_all(&self, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_all", get_all(&mut conn, filter)).await;
+    }
+
+    async fn get_page(&self, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_page", get_page(&mut conn, filter, cursor, limit)).await;
+    }
+
+    async fn count(&self, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("count", count(&mut conn, filter)).await;
+    }
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_by_id", get_by_id(&mut conn, id)).await;
+    }
+
+    async fn get_by_code(&self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_by_code", get_by_code(&mut conn, code)).await;
+    }
+
+    async fn get_by_codes(&self, codes: &[String]) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_by_codes", get_by_codes(&mut conn, codes)).await;
+    }
+
+    async fn exists_by_code(&self, code: &String) -> Result<bool, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("exists_by_code", exists_by_code(&mut conn, code)).await;
+    }
+
+    async fn begin(&self) -> Result<Box<dyn CouponTransaction>, sqlx::Error> {
+        return Ok(Box::new(SqliteCouponTransaction(self.metrics.time_acquire(self.pool.begin()).await?, self.metrics.clone())));
+    }
+}
+
+// the metrics of the pool of the transaction, to log its slow queries
+pub struct SqliteCouponTransaction(Transaction<'static, Sqlite>, Arc<PoolMetrics>);
+
+#[async_trait]
+impl CouponTransaction for SqliteCouponTransaction {
+    async fn insert(&mut self, coupon: CouponInsert) -> Result<u64, sqlx::Error> {
+        return self.1.time_query("insert", insert(&mut self.0, coupon)).await;
+    }
+
+    async fn upsert(&mut self, code: &String, coupon: CouponUpdate) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("upsert", upsert(&mut self.0, code, coupon)).await;
+    }
+
+    async fn update(&mut self, id: i32, coupon: CouponUpdate, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("update", update(&mut self.0, id, coupon, expected_version)).await;
+    }
+
+    async fn get_by_id(&mut self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        return self.1.time_query("get_by_id", get_by_id(&mut self.0, id)).await;
+    }
+
+    async fn get_by_code(&mut self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        return self.1.time_query("get_by_code", get_by_code(&mut self.0, code)).await;
+    }
+
+    async fn delete_by_id(&mut self, id: i32) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("delete_by_id", delete_by_id(&mut self.0, id)).await;
+    }
+
+    async fn delete_by_code(&mut self, code: &String) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("delete_by_code", delete_by_code(&mut self.0, code)).await;
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error> {
+        return self.0.commit().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteCouponStore;
+    use crate::coupon::coupon_store::{is_unique_violation, CouponStore};
+    use crate::telemetry::PoolMetrics;
+    use crate::coupon::{CouponDiscount, CouponFilter, CouponInsert, CouponUpdate, Cursor};
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_none, assert_ok, assert_some};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    // an in-memory database only lives as long as its connection
+    async fn store() -> SqliteCouponStore {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .expect("Failed to open SQLite database.");
+        sqlx::migrate!("./migrations_sqlite").run(&pool).await.expect("Failed to migrate SQLite database.");
+        return SqliteCouponStore::new(pool, PoolMetrics::new("sqlite"));
+    }
+
+    fn coupon(code: &str, expired: bool) -> CouponInsert {
+        let expiration_date = Utc::now().naive_utc() + if (expired) { Duration::days(-1) } else { Duration::days(1) };
+        return CouponInsert {
+            code: code.to_string(),
+            discount: CouponDiscount::parse(10).unwrap(),
+            active: true,
+            max_usage_count: Some(5),
+            expiration_date: Some(expiration_date),
+        };
+    }
+
+    #[tokio::test]
+    async fn coupons_are_stored_and_filtered(){
+        let store = store().await;
+        let mut transaction = assert_ok!(store.begin().await);
+        let id = assert_ok!(transaction.insert(coupon("VALID", false)).await) as i32;
+        assert_ok!(transaction.insert(coupon("EXPIRED", true)).await);
+        assert_ok!(transaction.commit().await);
+
+        let inserted = assert_some!(assert_ok!(store.get_by_id(id).await));
+        assert_eq!(inserted.code, "VALID");
+        assert!(inserted.date_created.is_some());
+        assert!(assert_ok!(store.exists_by_code(&"EXPIRED".to_string()).await));
+
+        let expired = assert_ok!(store.get_all(&CouponFilter { active: None, expired: Some(true) }).await);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].code, "EXPIRED");
+        let count = assert_ok!(store.count(&CouponFilter::default()).await);
+        assert_eq!((count.total, count.active, count.expired, count.remaining_usages), (2, 2, 1, 5));
+
+        let page = assert_ok!(store.get_page(&CouponFilter::default(), Some(Cursor::After(id)), 10).await);
+        assert_eq!(page.len(), 1);
+
+        let mut transaction = assert_ok!(store.begin().await);
+        assert_ok!(transaction.delete_by_code(&"EXPIRED".to_string()).await);
+        assert_ok!(transaction.commit().await);
+        assert_none!(assert_ok!(store.get_by_code(&"EXPIRED".to_string()).await));
+    }
+
+    #[tokio::test]
+    async fn upsert_tells_if_the_coupon_was_inserted(){
+        let store = store().await;
+        let update = || CouponUpdate { discount: CouponDiscount::parse(20).unwrap(), active: false, max_usage_count: None, expiration_date: None };
+
+        let mut transaction = assert_ok!(store.begin().await);
+        assert!(assert_ok!(transaction.upsert(&"UPSERT".to_string(), update()).await));
+        assert!(!assert_ok!(transaction.upsert(&"UPSERT".to_string(), update()).await));
+        assert_ok!(transaction.commit().await);
+
+        let coupon = assert_some!(assert_ok!(store.get_by_code(&"UPSERT".to_string()).await));
+        assert_eq!((coupon.discount, coupon.active), (20, false));
+        assert!(coupon.date_updated.is_some());
+    }
+
+    #[tokio::test]
+    async fn transactions_not_committed_are_rolled_back(){
+        let store = store().await;
+
+        let mut transaction = assert_ok!(store.begin().await);
+        assert_ok!(transaction.insert(coupon("ROLLED_BACK", false)).await);
+        drop(transaction);
+
+        assert!(!assert_ok!(store.exists_by_code(&"ROLLED_BACK".to_string()).await));
+    }
+
+    #[tokio::test]
+    async fn duplicate_codes_are_unique_violations(){
+        let store = store().await;
+
+        let mut transaction = assert_ok!(store.begin().await);
+        assert_ok!(transaction.insert(coupon("DUPLICATE", false)).await);
+        let error = assert_err!(transaction.insert(coupon("DUPLICATE", false)).await);
+
+        assert!(is_unique_violation(&error), "{:?}", error);
+    }
+}
@@ -1,18 +1,114 @@
 use super::model::{
     CouponInsertRequest, CouponResponse, CouponError, CouponInsert, CouponUpdateRequest,
-    CouponUpdate,
+    CouponUpdate, CouponFilter, CouponCount, CouponPagination, CouponPage, Coupon, Cursor,
+    PageLinks, PageMeta, ChangesSince, CouponChange, CouponChanges, CouponChangesQuery, CouponPatch,
+    CouponPatchRequest, JsonPatchOperation, apply_json_patch, BulkValidationRequest, CodeValidation,
 };
-use super::{coupon_repository};
-use chrono::{Utc, Datelike};
+use super::coupon_store::{is_unique_violation, CouponStore, CouponTransaction};
+use crate::events::{self, CouponEvent, PendingEvent};
+use crate::feature_flags::{self, FeatureFlags};
+use crate::retry;
+use crate::webhook::{model::WebhookEvent, webhook_delivery};
+use chrono::{Utc, Datelike, Timelike};
+use futures_util::{StreamExt, stream::BoxStream};
 use sqlx::{MySqlPool};
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::collections::HashSet;
 use std::convert::TryFrom;
 
-pub async fn get_all(pool: &MySqlPool) -> Result<Vec<CouponResponse>, CouponError> {
-    let coupons = coupon_repository::get_all(pool).await
+// the writes of the last seconds are not listed yet by the changes feed, see `coupon_repository::get_changes`
+const CHANGES_SETTLE_SECONDS: u32 = 2;
+
+pub async fn get_all(filter: &CouponFilter, store: &dyn CouponStore) -> Result<Vec<CouponResponse>, CouponError> {
+    let coupons = store.get_all(filter).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+
+    return Ok(to_coupons_response(coupons));
+}
+
+/// Same as `get_all`, the coupons are converted while they are read.
+pub async fn stream_all(filter: &CouponFilter, store: &dyn CouponStore) -> Result<BoxStream<'static, Result<CouponResponse, CouponError>>, CouponError> {
+    let coupons = store.stream_all(filter).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+
+    return Ok(Box::pin(coupons.map(|coupon| {
+        let coupon = coupon.map_err(|error| CouponError::UnexpectedError(error.into()))?;
+        return CouponResponse::try_from(coupon)
+            .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to parse CouponResponse: {}.", e))));
+    })));
+}
+
+pub async fn get_page(filter: &CouponFilter, pagination: &CouponPagination, store: &dyn CouponStore) -> Result<CouponPage<CouponResponse>, CouponError> {
+    let cursor = pagination.cursor().map_err(CouponError::ValidationError)?;
+    let limit = pagination.limit();
+
+    // fetch one extra coupon to know if there are more coupons in the requested direction
+    let mut coupons = store.get_page(filter, cursor, limit + 1).await
         .map_err(|error| CouponError::UnexpectedError(error.into()))?;
 
-    let coupons_response = coupons
+    let has_more = coupons.len() > limit as usize;
+    coupons.truncate(limit as usize);
+    // backward pages are fetched in descending order
+    if let Some(Cursor::Before(_)) = cursor {
+        coupons.reverse();
+    }
+
+    let (has_next_page, has_prev_page) = match cursor {
+        None => (has_more, false),
+        Some(Cursor::After(_)) => (has_more, true),
+        Some(Cursor::Before(_)) => (true, has_more),
+    };
+    let next_cursor = match (has_next_page, coupons.last()) {
+        (true, Some(last_coupon)) => Some(Cursor::After(last_coupon.id).encode()),
+        _ => None,
+    };
+    let prev_cursor = match (has_prev_page, coupons.first()) {
+        (true, Some(first_coupon)) => Some(Cursor::Before(first_coupon.id).encode()),
+        _ => None,
+    };
+
+    let total = store.count(filter).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?
+        .total;
+
+    let links = PageLinks::new(filter, limit, pagination.cursor.as_ref(), next_cursor.as_ref(), prev_cursor.as_ref());
+    let pagination = PageMeta { limit, total, next_cursor, prev_cursor, links };
+    return Ok(CouponPage { coupons: to_coupons_response(coupons), pagination });
+}
+
+/// The changes feed, for the incremental syncs: the coupons written after `since`, oldest first, each one once
+/// with how it is now, or as a tombstone when it was deleted.
+pub async fn get_changes(query: &CouponChangesQuery, store: &dyn CouponStore) -> Result<CouponChanges, CouponError> {
+    let since = query.since().map_err(CouponError::ValidationError)?;
+    let limit = query.limit();
+
+    // fetch one extra change to know if there are more
+    let mut records = store.get_changes(since, CHANGES_SETTLE_SECONDS, limit + 1).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?
+        .ok_or(CouponError::ValidationError("The changes feed is only available when `database.coupon_backend` is `mysql`.".to_string()))?;
+    let has_more = records.len() > limit as usize;
+    records.truncate(limit as usize);
+    let next_cursor = records.last().map(|record| ChangesSince::cursor(record.seq));
+
+    // only the last write of each coupon, the others would list the same state
+    let mut seen = HashSet::new();
+    let mut changes = Vec::new();
+    for record in records.into_iter().rev() {
+        if (!seen.insert(record.coupon_id)){
+            continue;
+        }
+        let coupon: Option<CouponResponse> = record.coupon.map(|coupon| coupon.try_into())
+            .transpose()
+            .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to parse CouponResponse: {}.", e))))?;
+        changes.push(CouponChange { id: record.coupon_id, code: record.code, changed_at: record.changed_at, deleted: coupon.is_none(), coupon });
+    }
+    changes.reverse();
+    return Ok(CouponChanges { changes, next_cursor, has_more });
+}
+
+fn to_coupons_response(coupons: Vec<Coupon>) -> Vec<CouponResponse> {
+    return coupons
         .into_iter()
         // flat_map uses an iterator over the result of the mapping and as a consequence,
         // it will skip over elements for which the mapping closure returns empty or unsuccessful values
@@ -26,11 +122,16 @@ pub async fn get_all(pool: &MySqlPool) -> Result<Vec<CouponResponse>, CouponErro
             }
         })
         .collect();
-    return Ok(coupons_response);
 }
 
-pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<CouponResponse, CouponError> {
-    let result = coupon_repository::get_by_id(id, pool).await
+pub async fn count(filter: &CouponFilter, store: &dyn CouponStore) -> Result<CouponCount, CouponError> {
+    let count = store.count(filter).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    return Ok(count);
+}
+
+pub async fn get_by_id(id: i32, store: &dyn CouponStore) -> Result<CouponResponse, CouponError> {
+    let result = store.get_by_id(id).await
         .map_err(|error| CouponError::UnexpectedError(error.into()))?;
 
     let coupon = result.ok_or( CouponError::NotFoundError(anyhow!(format!("Coupon with id `{}` not found.", id))))?;
@@ -40,8 +141,8 @@ pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<CouponResponse, Coup
     return Ok(coupon_response);
 }
 
-pub async fn get_by_code(code: String, pool: &MySqlPool) -> Result<CouponResponse, CouponError> {
-    let result = coupon_repository::get_by_code(&code, pool).await
+pub async fn get_by_code(code: String, store: &dyn CouponStore) -> Result<CouponResponse, CouponError> {
+    let result = store.get_by_code(&code).await
         .map_err(|error| CouponError::UnexpectedError(error.into()))?;
 
     let coupon = result.ok_or(CouponError::NotFoundError(anyhow!(format!("Coupon with code `{}` not found.", code))))?;
@@ -51,109 +152,334 @@ pub async fn get_by_code(code: String, pool: &MySqlPool) -> Result<CouponRespons
     return Ok(coupon_response);
 }
 
-pub async fn get_by_id_or_code(param: String, pool: &MySqlPool) -> Result<CouponResponse, CouponError> {
+pub async fn exists_by_code(code: String, store: &dyn CouponStore) -> Result<bool, CouponError> {
+    let exists = store.exists_by_code(&code).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    return Ok(exists);
+}
+
+pub async fn get_by_id_or_code(param: String, store: &dyn CouponStore) -> Result<CouponResponse, CouponError> {
     // if the `id` param is present and it is an integer, then we get by id, otherwise by code
-    match param.parse::<i32>() {
-        Ok(id) => return get_by_id(id, pool).await,
-        Err(_) => {},
+    if let Ok(id) = param.parse::<i32>() {
+        return get_by_id(id, store).await;
     }
 
-    return get_by_code(param, pool).await;
+    return get_by_code(param, store).await;
 }
 
-pub async fn insert(coupon_request: CouponInsertRequest, pool: &MySqlPool) -> Result<CouponResponse, CouponError> {
-    // check if Coupon already exists
-    if let Some(_) = get_by_code(coupon_request.code.clone(), pool).await.ok() {
-        return Err(CouponError::AlreadyExistsError(anyhow!(format!("Coupon with code `{}` already exists.", coupon_request.code))));
-    }
-    
+// the webhooks are always in MySQL, even when the coupons are not
+pub async fn insert(coupon_request: CouponInsertRequest, store: &dyn CouponStore, pool: &MySqlPool) -> Result<CouponResponse, CouponError> {
+    let (coupon_response, webhooks) = retry::with_retry(store.retry_settings(), "Insert coupon", || insert_in_transaction(coupon_request.clone(), store)).await?;
+    // only once committed, the subscribers may read the coupon back
+    events::stream::broadcast(CouponEvent::Created, &coupon_response);
+    dispatch_webhooks(CouponEvent::Created, webhooks, pool);
+    return Ok(coupon_response);
+}
+
+async fn insert_in_transaction(coupon_request: CouponInsertRequest, store: &dyn CouponStore) -> Result<(CouponResponse, Option<String>), CouponError> {
     let coupon_insert: CouponInsert = coupon_request.try_into()
         .map_err(|e: String| CouponError::ValidationError(e))?;
 
-        let inserted_id = coupon_repository::insert(coupon_insert, pool).await
-        .map_err(|e| CouponError::InternalError(anyhow!(format!("Something went wrong and the coupon was not inserted: {}", e))))?;
+    let mut transaction = store.begin().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    // no check before, the unique index on `code` rejects the existing coupons
+    let code = coupon_insert.code.clone();
+    let inserted_id = transaction.insert(coupon_insert.clone()).await
+        .map_err(|e| {
+            if (is_unique_violation(&e)){
+                return CouponError::AlreadyExistsError(anyhow!(format!("Coupon with code `{}` already exists.", code)));
+            }
+            return CouponError::InternalError(anyhow!(format!("Something went wrong and the coupon was not inserted: {}", e)));
+        })?;
 
     let inserted_id = i32::try_from(inserted_id)
-        .or_else(|e| Err(CouponError::InternalError(anyhow!(format!("Failed to read inserted_id: {}", e)))))?;
+        .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to read inserted_id: {}", e))))?;
 
-    let inserted_coupon = coupon_repository::get_by_id(inserted_id, pool).await
-        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    // built from the request instead of read back, `date_created` is the time of the server (to the second, like the column)
+    let now = Utc::now().naive_utc();
+    let date_created = now.with_nanosecond(0).unwrap_or(now);
+    let coupon_response: CouponResponse = Coupon::inserted(inserted_id, coupon_insert, date_created).try_into()
+        .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to parse CouponResponse: {}.", e))))?;
+    let webhooks = add_events(CouponEvent::Created, &coupon_response.code, &coupon_response, transaction.as_mut()).await?;
 
-    let coupon = inserted_coupon.ok_or(CouponError::NotFoundError(anyhow!(format!("Inserted coupon with id `{}` not found.", inserted_id))))?;
+    transaction.commit().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    return Ok((coupon_response, webhooks));
+}
 
-    let coupon_response = coupon.try_into()
-        .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to parse CouponResponse: {}.", e))))?;
-    return Ok(coupon_response);
+pub async fn update(param: String, coupon_request: CouponUpdateRequest, store: &dyn CouponStore, pool: &MySqlPool) -> Result<(), CouponError> {
+    let (updated_coupon, webhooks) = retry::with_retry(store.retry_settings(), "Update coupon", || update_in_transaction(param.clone(), coupon_request.clone(), store)).await?;
+    events::stream::broadcast(CouponEvent::Updated, &updated_coupon);
+    dispatch_webhooks(CouponEvent::Updated, webhooks, pool);
+    return Ok(());
 }
 
-pub async fn update(param: String, coupon_request: CouponUpdateRequest, pool: &MySqlPool) -> Result<(), CouponError> {
-    // check if coupon exists
-    let coupon = get_by_id_or_code(param, pool).await?;
+async fn update_in_transaction(param: String, coupon_request: CouponUpdateRequest, store: &dyn CouponStore) -> Result<(CouponResponse, Option<String>), CouponError> {
+    let mut transaction = store.begin().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    // check if coupon exists, locking it until the update is committed
+    let coupon = lock_by_id_or_code(param, transaction.as_mut()).await?;
 
+    let expected_version = coupon_request.version;
     let coupon_update: CouponUpdate = coupon_request.try_into().map_err(|e: String| CouponError::ValidationError(e))?;
 
-    coupon_repository::update(coupon.id, coupon_update, &pool).await
+    let updated = transaction.update(coupon.id, coupon_update, expected_version).await
         .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    if (!updated){
+        return Err(CouponError::ConflictError(format!("Coupon with id `{}` was changed since version `{}`, get it again before updating it.", coupon.id, expected_version.unwrap_or(coupon.version))));
+    }
 
-    return Ok(());
+    let updated_coupon = transaction.get_by_id(coupon.id).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?
+        .ok_or(CouponError::NotFoundError(anyhow!(format!("Coupon with id `{}` not found.", coupon.id))))?;
+    let updated_coupon: CouponResponse = updated_coupon.try_into()
+        .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to parse CouponResponse: {}.", e))))?;
+    let webhooks = add_events(CouponEvent::Updated, &updated_coupon.code, &updated_coupon, transaction.as_mut()).await?;
+
+    transaction.commit().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    return Ok((updated_coupon, webhooks));
 }
 
-pub async fn delete(param: String, pool: &MySqlPool) -> Result<(), CouponError> {
-    match param.parse::<i32>() {
-        Ok(id) => return delete_by_id(id, pool).await,
-        Err(_) => {},
+/// Change only the fields of the merge patch, returns the coupon as patched.
+pub async fn patch(param: String, patch_request: CouponPatchRequest, store: &dyn CouponStore, pool: &MySqlPool) -> Result<CouponResponse, CouponError> {
+    let expected_version = patch_request.version;
+    let coupon_patch: CouponPatch = patch_request.try_into().map_err(|e: String| CouponError::ValidationError(e))?;
+    // nothing to change, not even the version
+    if (coupon_patch.is_empty()){
+        return get_by_id_or_code(param, store).await;
     }
 
-    return delete_by_code(param, pool).await;
+    let (patched_coupon, webhooks) = retry::with_retry(store.retry_settings(), "Patch coupon", || patch_in_transaction(param.clone(), &coupon_patch, expected_version, store)).await?;
+    events::stream::broadcast(CouponEvent::Updated, &patched_coupon);
+    dispatch_webhooks(CouponEvent::Updated, webhooks, pool);
+    return Ok(patched_coupon);
 }
 
-pub async fn delete_by_id(id: i32, pool: &MySqlPool) -> Result<(), CouponError> {
-    coupon_repository::get_by_id(id, pool).await
+async fn patch_in_transaction(param: String, coupon_patch: &CouponPatch, expected_version: Option<i32>, store: &dyn CouponStore) -> Result<(CouponResponse, Option<String>), CouponError> {
+    let mut transaction = store.begin().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    // check if coupon exists, locking it until the patch is committed
+    let coupon = lock_by_id_or_code(param, transaction.as_mut()).await?;
+    // a `422` when the patched coupon isn't valid, the stores replacing the coupon would fail to patch it otherwise
+    coupon_patch.apply(&coupon).map_err(CouponError::ValidationError)?;
+
+    let patched = transaction.patch(coupon.id, coupon_patch, expected_version).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    if (!patched){
+        return Err(CouponError::ConflictError(format!("Coupon with id `{}` was changed since version `{}`, get it again before updating it.", coupon.id, expected_version.unwrap_or(coupon.version))));
+    }
+
+    let patched_coupon = transaction.get_by_id(coupon.id).await
         .map_err(|error| CouponError::UnexpectedError(error.into()))?
-        .ok_or(CouponError::NotFoundError(anyhow!(format!("Coupon with id `{}` not found.", id))))?;
+        .ok_or(CouponError::NotFoundError(anyhow!(format!("Coupon with id `{}` not found.", coupon.id))))?;
+    let patched_coupon: CouponResponse = patched_coupon.try_into()
+        .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to parse CouponResponse: {}.", e))))?;
+    let webhooks = add_events(CouponEvent::Updated, &patched_coupon.code, &patched_coupon, transaction.as_mut()).await?;
 
-    coupon_repository::delete_by_id(id, pool).await
-        .context("Failed to delete by id")?;
+    transaction.commit().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    return Ok((patched_coupon, webhooks));
+}
 
-    coupon_repository::delete_by_id(id, &pool).await
+/// Apply the JSON Patch operations to the stored coupon, returns the coupon as patched.
+/// The patched coupon is validated before it is stored, nothing is changed if an operation fails.
+pub async fn json_patch(param: String, operations: Vec<JsonPatchOperation>, expected_version: Option<i32>, store: &dyn CouponStore, pool: &MySqlPool) -> Result<CouponResponse, CouponError> {
+    let (patched_coupon, webhooks) = retry::with_retry(store.retry_settings(), "JSON patch coupon", || json_patch_in_transaction(param.clone(), &operations, expected_version, store)).await?;
+    events::stream::broadcast(CouponEvent::Updated, &patched_coupon);
+    dispatch_webhooks(CouponEvent::Updated, webhooks, pool);
+    return Ok(patched_coupon);
+}
+
+async fn json_patch_in_transaction(param: String, operations: &[JsonPatchOperation], expected_version: Option<i32>, store: &dyn CouponStore) -> Result<(CouponResponse, Option<String>), CouponError> {
+    let mut transaction = store.begin().await
         .map_err(|error| CouponError::UnexpectedError(error.into()))?;
-    return Ok(());
+    // the operations are applied to the coupon as stored, locked until the patch is committed
+    let coupon = lock_by_id_or_code(param, transaction.as_mut()).await?;
+    let coupon_update = apply_json_patch(operations, &coupon).map_err(CouponError::ValidationError)?;
+
+    let updated = transaction.update(coupon.id, coupon_update, expected_version).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    if (!updated){
+        return Err(CouponError::ConflictError(format!("Coupon with id `{}` was changed since version `{}`, get it again before updating it.", coupon.id, expected_version.unwrap_or(coupon.version))));
+    }
+
+    let patched_coupon = transaction.get_by_id(coupon.id).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?
+        .ok_or(CouponError::NotFoundError(anyhow!(format!("Coupon with id `{}` not found.", coupon.id))))?;
+    let patched_coupon: CouponResponse = patched_coupon.try_into()
+        .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to parse CouponResponse: {}.", e))))?;
+    let webhooks = add_events(CouponEvent::Updated, &patched_coupon.code, &patched_coupon, transaction.as_mut()).await?;
+
+    transaction.commit().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    return Ok((patched_coupon, webhooks));
+}
+
+/// Create the coupon if absent or fully replace it if present.
+/// Returns the coupon and if it was created.
+pub async fn upsert(code: String, coupon_request: CouponUpdateRequest, store: &dyn CouponStore, pool: &MySqlPool) -> Result<(CouponResponse, bool), CouponError> {
+    let (coupon, created, webhooks) = retry::with_retry(store.retry_settings(), "Upsert coupon", || upsert_in_transaction(code.clone(), coupon_request.clone(), store)).await?;
+    let event = if (created) { CouponEvent::Created } else { CouponEvent::Updated };
+    events::stream::broadcast(event, &coupon);
+    dispatch_webhooks(event, webhooks, pool);
+    return Ok((coupon, created));
 }
 
-pub async fn delete_by_code(code: String, pool: &MySqlPool) -> Result<(), CouponError> {
-    coupon_repository::get_by_code(&code, pool).await
+async fn upsert_in_transaction(code: String, coupon_request: CouponUpdateRequest, store: &dyn CouponStore) -> Result<(CouponResponse, bool, Option<String>), CouponError> {
+    let coupon_upsert: CouponUpdate = coupon_request.try_into().map_err(|e: String| CouponError::ValidationError(e))?;
+
+    let mut transaction = store.begin().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    let created = transaction.upsert(&code, coupon_upsert).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+
+    let coupon = transaction.get_by_code(&code).await
         .map_err(|error| CouponError::UnexpectedError(error.into()))?
-        .ok_or(CouponError::NotFoundError(anyhow!(format!("Coupon with code `{}` not found.", &code))))?;
+        .ok_or(CouponError::NotFoundError(anyhow!(format!("Coupon with code `{}` not found.", code))))?;
+    let coupon: CouponResponse = coupon.try_into()
+        .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to parse CouponResponse: {}.", e))))?;
+    let webhooks = add_events(if (created) { CouponEvent::Created } else { CouponEvent::Updated }, &coupon.code, &coupon, transaction.as_mut()).await?;
 
-    coupon_repository::delete_by_code(&code, pool).await
-        .context("Failed to delete by code")?;
-            
-    coupon_repository::delete_by_code(&code, &pool).await
+    transaction.commit().await
         .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    return Ok((coupon, created, webhooks));
+}
+
+pub async fn delete(param: String, store: &dyn CouponStore, pool: &MySqlPool) -> Result<(), CouponError> {
+    let webhooks = retry::with_retry(store.retry_settings(), "Delete coupon", || delete_in_transaction(param.clone(), store)).await?;
+    events::stream::broadcast(CouponEvent::Deleted, &deleted_event_data(&param));
+    dispatch_webhooks(CouponEvent::Deleted, webhooks, pool);
     return Ok(());
 }
 
+async fn delete_in_transaction(param: String, store: &dyn CouponStore) -> Result<Option<String>, CouponError> {
+    let mut transaction = store.begin().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    // a single `DELETE`, its affected rows tell if the coupon existed
+    let (deleted, field) = match param.parse::<i32>() {
+        Ok(id) => (transaction.delete_by_id(id).await, "id"),
+        Err(_) => (transaction.delete_by_code(&param).await, "code"),
+    };
+    let deleted = deleted.map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    if (!deleted){
+        return Err(CouponError::NotFoundError(anyhow!(format!("Coupon with {} `{}` not found.", field, param))));
+    }
+    let webhooks = add_events(CouponEvent::Deleted, &param, &deleted_event_data(&param), transaction.as_mut()).await?;
+
+    transaction.commit().await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    return Ok(webhooks);
+}
+
+// the coupon is not read before the `DELETE`, the event only has how it was deleted
+fn deleted_event_data(param: &str) -> serde_json::Value {
+    return match param.parse::<i32>() {
+        Ok(id) => serde_json::json!({ "id": id }),
+        Err(_) => serde_json::json!({ "code": param }),
+    };
+}
+
+// the event in the `outbox` and the deliveries of the webhooks in the transaction, when the store is in MySQL with them:
+// they are published and delivered if and only if the change is committed, even if the server stops right after.
+// Returns the body of the webhooks otherwise, queued by `dispatch_webhooks` once committed.
+async fn add_events<T: Serialize>(event: CouponEvent, key: &str, data: &T, transaction: &mut dyn CouponTransaction) -> Result<Option<String>, CouponError> {
+    if (events::is_enabled()){
+        let pending_event = PendingEvent::new(event, key, data)
+            .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to serialize the `{}` event: {}.", event.as_str(), e))))?;
+        transaction.add_event(&pending_event).await
+            .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    }
+
+    let webhook_event = webhook_event(event);
+    let body = webhook_delivery::body(webhook_event, data)
+        .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to serialize the `{}` webhook payload: {}.", webhook_event.as_str(), e))))?;
+    let queued = transaction.add_webhook_deliveries(webhook_event, &body).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    return Ok(if (queued) { None } else { Some(body) });
+}
+
+// the webhooks not queued in the transaction, see `add_events`
+fn dispatch_webhooks(event: CouponEvent, webhooks: Option<String>, pool: &MySqlPool) {
+    if let Some(body) = webhooks {
+        webhook_delivery::dispatch(webhook_event(event), body, pool);
+    }
+}
+
+fn webhook_event(event: CouponEvent) -> WebhookEvent {
+    return match event {
+        CouponEvent::Created => WebhookEvent::CouponCreated,
+        CouponEvent::Updated => WebhookEvent::CouponUpdated,
+        CouponEvent::Deleted => WebhookEvent::CouponDeleted,
+    };
+}
+
+// same as `get_by_id_or_code`, in the transaction, so the coupon can't change until it is committed
+async fn lock_by_id_or_code(param: String, transaction: &mut dyn CouponTransaction) -> Result<Coupon, CouponError> {
+    if let Ok(id) = param.parse::<i32>() {
+        return transaction.get_by_id(id).await
+            .map_err(|error| CouponError::UnexpectedError(error.into()))?
+            .ok_or(CouponError::NotFoundError(anyhow!(format!("Coupon with id `{}` not found.", id))));
+    }
+
+    return transaction.get_by_code(&param).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?
+        .ok_or(CouponError::NotFoundError(anyhow!(format!("Coupon with code `{}` not found.", param))));
+}
+
 /// Verify if the coupon is valid for use, return a boolean.
-pub async fn is_valid(param: String, pool: &MySqlPool) -> Result<bool, CouponError> {
-    let coupon = get_by_id_or_code(param, pool).await?;
+pub async fn is_valid(param: String, flags: &FeatureFlags, store: &dyn CouponStore) -> Result<bool, CouponError> {
+    let coupon = get_by_id_or_code(param, store).await?;
+    if let Err(reason) = check_validity(&coupon, flags) {
+        tracing::debug!("Coupon is not valid: {}", reason);
+        return Ok(false);
+    }
+    return Ok(true);
+}
+
+/// `is_valid` of every code, the coupons are read at once.
+pub async fn validate_codes(request: BulkValidationRequest, flags: &FeatureFlags, store: &dyn CouponStore) -> Result<Vec<CodeValidation>, CouponError> {
+    request.validate().map_err(CouponError::ValidationError)?;
 
+    let coupons = store.get_by_codes(&request.codes).await
+        .map_err(|error| CouponError::UnexpectedError(error.into()))?;
+    let mut coupon_responses: Vec<CouponResponse> = Vec::new();
+    for coupon in coupons {
+        let coupon_response = coupon.try_into()
+            .map_err(|e| CouponError::InternalError(anyhow!(format!("Failed to parse CouponResponse: {}.", e))))?;
+        coupon_responses.push(coupon_response);
+    }
+
+    let validations = request.codes.into_iter().map(|code| {
+        return match coupon_responses.iter().find(|coupon| coupon.code.eq_ignore_ascii_case(&code)) {
+            Some(coupon) => {
+                let reason = check_validity(coupon, flags).err();
+                CodeValidation { code, valid: reason.is_none(), discount: Some(coupon.discount), reason }
+            },
+            None => CodeValidation { code, valid: false, discount: None, reason: Some("Coupon not found.".to_string()) },
+        };
+    }).collect();
+    return Ok(validations);
+}
+
+/// The rules of `is_valid`, the error is the reason the coupon can't be used.
+pub fn check_validity(coupon: &CouponResponse, flags: &FeatureFlags) -> Result<(), String> {
     // Check if coupon is active
-    if (coupon.active == false){
-        println!("Coupon is not active.");
-        return Ok(false);
+    if (!coupon.active){
+        return Err("Coupon is not active.".to_string());
     }
 
     // Check if coupon is expired
     match (coupon.expiration_date) {
         Some(expiration) => {
             if (expiration < Utc::now().naive_utc()){
-                println!("Coupon is expired.");
-                return Ok(false);
+                return Err("Coupon is expired.".to_string());
             }
         },
         None => {
-            println!("Coupon doesn't have an expiration date.");
-            return Ok(false);
+            if (!flags.is_enabled(feature_flags::COUPONS_WITHOUT_EXPIRATION)){
+                return Err("Coupon doesn't have an expiration date.".to_string());
+            }
         }
     };
 
@@ -162,10 +488,131 @@ pub async fn is_valid(param: String, pool: &MySqlPool) -> Result<bool, CouponErr
         // Verify if today is Friday
         let weekday = Utc::now().date_naive().weekday().to_string();
         if (weekday.to_uppercase() != "FRIDAY"){
-            println!("Today is not friday.");
-            return Ok(false);
+            return Err("Today is not friday.".to_string());
         }
     }
 
-    return Ok(true);
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delete, get_by_id_or_code, insert, is_valid, patch, update, validate_codes};
+    use crate::coupon::coupon_repository_memory::InMemoryCouponStore;
+    use crate::coupon::coupon_store::CouponStore;
+    use crate::coupon::{
+        BulkValidationRequest, CouponDiscount, CouponError, CouponInsert, CouponInsertRequest, CouponPatchRequest, CouponUpdateRequest,
+        MAX_BULK_CODES,
+    };
+    use crate::feature_flags::FeatureFlags;
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use sqlx::MySqlPool;
+    use std::collections::BTreeMap;
+
+    async fn store_with(coupons: Vec<CouponInsert>) -> InMemoryCouponStore {
+        let store = InMemoryCouponStore::new();
+        let mut transaction = assert_ok!(store.begin().await);
+        for coupon in coupons {
+            assert_ok!(transaction.insert(coupon).await);
+        }
+        assert_ok!(transaction.commit().await);
+        return store;
+    }
+
+    fn coupon(code: &str, active: bool, expiration_days: i64) -> CouponInsert {
+        return CouponInsert {
+            code: code.to_string(),
+            discount: CouponDiscount::parse(10).unwrap(),
+            active,
+            max_usage_count: None,
+            expiration_date: Some(Utc::now().naive_utc() + Duration::days(expiration_days)),
+        };
+    }
+
+    #[tokio::test]
+    async fn only_active_coupons_not_expired_are_valid(){
+        let store = store_with(vec![coupon("VALID", true, 1), coupon("INACTIVE", false, 1), coupon("EXPIRED", true, -1)]).await;
+        let flags = FeatureFlags::new(BTreeMap::new());
+
+        assert!(assert_ok!(is_valid("VALID".to_string(), &flags, &store).await));
+        assert!(!assert_ok!(is_valid("INACTIVE".to_string(), &flags, &store).await));
+        assert!(!assert_ok!(is_valid("EXPIRED".to_string(), &flags, &store).await));
+        assert!(matches!(is_valid("UNKNOWN".to_string(), &flags, &store).await, Err(CouponError::NotFoundError(_))));
+    }
+
+    #[tokio::test]
+    async fn codes_are_validated_in_the_order_they_were_sent(){
+        let store = store_with(vec![coupon("VALID", true, 1), coupon("INACTIVE", false, 1)]).await;
+        let flags = FeatureFlags::new(BTreeMap::new());
+        let codes = ["UNKNOWN", "INACTIVE", "VALID"].iter().map(|code| code.to_string()).collect();
+
+        let validations = assert_ok!(validate_codes(BulkValidationRequest { codes }, &flags, &store).await);
+
+        let validations: Vec<(&str, bool, Option<i32>, Option<&str>)> = validations.iter()
+            .map(|validation| (validation.code.as_str(), validation.valid, validation.discount, validation.reason.as_deref()))
+            .collect();
+        assert_eq!(validations, vec![
+            ("UNKNOWN", false, None, Some("Coupon not found.")),
+            ("INACTIVE", false, Some(10), Some("Coupon is not active.")),
+            ("VALID", true, Some(10), None),
+        ]);
+        let too_many = BulkValidationRequest { codes: vec!["VALID".to_string(); MAX_BULK_CODES + 1] };
+        assert!(matches!(validate_codes(too_many, &flags, &store).await, Err(CouponError::ValidationError(_))));
+        assert_err!(validate_codes(BulkValidationRequest { codes: vec![] }, &flags, &store).await);
+    }
+
+    #[tokio::test]
+    async fn update_of_an_outdated_version_is_a_conflict(){
+        let store = store_with(vec![coupon("UPDATE", true, 1)]).await;
+        // only used by the webhooks, which are not delivered without a database
+        let pool = assert_ok!(MySqlPool::connect_lazy("mysql://localhost/coupon"));
+        let request = CouponUpdateRequest { discount: 20, active: true, max_usage_count: None, expiration_date: None, version: Some(1) };
+
+        assert_ok!(update("UPDATE".to_string(), request.clone(), &store, &pool).await);
+        let result = update("UPDATE".to_string(), request, &store, &pool).await;
+
+        assert!(matches!(result, Err(CouponError::ConflictError(_))));
+        let coupon = assert_ok!(get_by_id_or_code("UPDATE".to_string(), &store).await);
+        assert_eq!((coupon.discount, coupon.version), (20, 2));
+    }
+
+    #[tokio::test]
+    async fn patch_only_changes_the_fields_sent(){
+        let store = store_with(vec![coupon("PATCH", true, 1)]).await;
+        let pool = assert_ok!(MySqlPool::connect_lazy("mysql://localhost/coupon"));
+        let before = assert_ok!(get_by_id_or_code("PATCH".to_string(), &store).await);
+        let request: CouponPatchRequest = serde_json::from_value(serde_json::json!({"active": false, "expiration_date": null, "version": 1})).unwrap();
+
+        let patched = assert_ok!(patch("PATCH".to_string(), request.clone(), &store, &pool).await);
+
+        assert_eq!((patched.discount, patched.active, patched.expiration_date, patched.version), (before.discount, false, None, 2));
+        let result = patch("PATCH".to_string(), request, &store, &pool).await;
+        assert!(matches!(result, Err(CouponError::ConflictError(_))));
+    }
+
+    #[tokio::test]
+    async fn inserted_coupons_are_returned_as_stored(){
+        let store = store_with(vec![coupon("EXISTING", true, 1)]).await;
+        let pool = assert_ok!(MySqlPool::connect_lazy("mysql://localhost/coupon"));
+        let request = CouponInsertRequest { code: "NEW".to_string(), discount: 15, active: true, max_usage_count: Some(3), expiration_date: None };
+
+        let inserted = assert_ok!(insert(request.clone(), &store, &pool).await);
+        let duplicate = insert(CouponInsertRequest { code: "EXISTING".to_string(), ..request }, &store, &pool).await;
+
+        let stored = assert_ok!(get_by_id_or_code("NEW".to_string(), &store).await);
+        assert_eq!((inserted.id, inserted.discount, inserted.max_usage_count, inserted.version), (stored.id, stored.discount, stored.max_usage_count, stored.version));
+        assert!(matches!(duplicate, Err(CouponError::AlreadyExistsError(_))));
+    }
+
+    #[tokio::test]
+    async fn deleted_coupons_are_not_found(){
+        let store = store_with(vec![coupon("DELETE", true, 1)]).await;
+        let pool = assert_ok!(MySqlPool::connect_lazy("mysql://localhost/coupon"));
+
+        assert_ok!(delete("1".to_string(), &store, &pool).await);
+
+        assert_err!(get_by_id_or_code("DELETE".to_string(), &store).await);
+        assert_err!(delete("DELETE".to_string(), &store, &pool).await);
+    }
 }
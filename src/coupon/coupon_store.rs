@@ -0,0 +1,397 @@
+use super::model::{ChangesSince, Coupon, CouponChangeRecord, CouponCount, CouponFilter, CouponInsert, CouponPatch, CouponUpdate, Cursor};
+use super::coupon_repository;
+use crate::configuration::RetrySettings;
+use crate::events::{outbox, PendingEvent};
+use crate::retry;
+use crate::telemetry::PoolMetrics;
+use crate::webhook::{model::WebhookEvent, webhook_delivery};
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream};
+use sqlx::{MySql, MySqlPool, Transaction};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+
+/// The coupons of a list, read while they are sent to the client.
+pub type CouponStream = BoxStream<'static, Result<Coupon, sqlx::Error>>;
+
+// coupons read ahead of the client, the reads wait for it beyond that
+const STREAM_BUFFER: usize = 100;
+
+
+/// Where the coupons are stored, selected by `database.coupon_backend`.
+/// MySQL (`coupon_repository`) by default, Postgres (`coupon_repository_postgres`) with the `postgres` feature,
+/// or SQLite (`coupon_repository_sqlite`) with the `sqlite` feature.
+/// `coupon_repository_memory` keeps them in memory, for the unit tests of `coupon_service`.
+/// Only MySQL has the `coupon_changes` log and the `outbox`, see `get_changes` and `CouponTransaction::add_event`:
+/// `Settings::validate` rejects the event publishers with the other backends. Their webhooks are queued after the commit,
+/// see `CouponTransaction::add_webhook_deliveries`.
+/// The writes are made in a transaction, from `begin()`.
+#[async_trait]
+pub trait CouponStore: Send + Sync {
+    async fn get_all(&self, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error>;
+
+    /// Same as `get_all`, without holding every coupon in memory. Buffered with `get_all` by the stores that can't stream.
+    async fn stream_all(&self, filter: &CouponFilter) -> Result<CouponStream, sqlx::Error> {
+        let coupons = self.get_all(filter).await?;
+        return Ok(Box::pin(stream::iter(coupons.into_iter().map(Ok))));
+    }
+
+    /// Keyset pagination: returns up to `limit` coupons after (ascending `id`) or before (descending `id`) the cursor.
+    async fn get_page(&self, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error>;
+
+    async fn count(&self, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error>;
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<Coupon>, sqlx::Error>;
+
+    async fn get_by_code(&self, code: &String) -> Result<Option<Coupon>, sqlx::Error>;
+
+    /// The coupons of the codes found, in any order. A single query where the store can, one per code by default.
+    async fn get_by_codes(&self, codes: &[String]) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut coupons = Vec::new();
+        for code in codes {
+            if let Some(coupon) = self.get_by_code(code).await? {
+                coupons.push(coupon);
+            }
+        }
+        return Ok(coupons);
+    }
+
+    async fn exists_by_code(&self, code: &String) -> Result<bool, sqlx::Error>;
+
+    async fn begin(&self) -> Result<Box<dyn CouponTransaction>, sqlx::Error>;
+
+    /// The changes feed, see `coupon_repository::get_changes`.
+    /// `None` for the stores without a `coupon_changes` log, only MySQL has one.
+    async fn get_changes(&self, _since: ChangesSince, _settle_seconds: u32, _limit: u32) -> Result<Option<Vec<CouponChangeRecord>>, sqlx::Error> {
+        return Ok(None);
+    }
+
+    /// Evict the coupons changed without `begin()` (e.g. archived) from the cache of the store, if it has one.
+    async fn evict_cached(&self, _codes: &[String]) {}
+
+    /// How the transactions of `coupon_service` are retried on a transient error, not retried by default.
+    fn retry_settings(&self) -> &RetrySettings {
+        return &retry::NO_RETRIES;
+    }
+}
+
+/// The multi-step writes (e.g. lock then update the coupon), so a failure halfway leaves nothing behind.
+/// Rolled back when dropped without `commit()`.
+#[async_trait]
+pub trait CouponTransaction: Send {
+    /// Returns the id of the inserted coupon.
+    async fn insert(&mut self, coupon: CouponInsert) -> Result<u64, sqlx::Error>;
+
+    /// Insert the coupon, or fully replace it if a coupon with the same `code` already exists.
+    /// Returns if the coupon was inserted.
+    async fn upsert(&mut self, code: &String, coupon: CouponUpdate) -> Result<bool, sqlx::Error>;
+
+    /// Update the coupon if its `version` is still `expected_version` (any version when `None`), bumping the version.
+    /// Returns if the coupon was updated.
+    async fn update(&mut self, id: i32, coupon: CouponUpdate, expected_version: Option<i32>) -> Result<bool, sqlx::Error>;
+
+    /// Same as `update`, leaving the fields not in the patch as they are.
+    /// Read then replaced by default, the stores that can set only some fields do.
+    /// `coupon_service` checks the patched coupon is valid first, see `CouponPatch::apply`.
+    async fn patch(&mut self, id: i32, patch: &CouponPatch, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+        let coupon = match self.get_by_id(id).await? {
+            Some(coupon) => coupon,
+            None => return Ok(false),
+        };
+        let update = patch.apply(&coupon).map_err(|e| sqlx::Error::Decode(e.into()))?;
+        return self.update(id, update, expected_version).await;
+    }
+
+    /// Locks the coupon until the end of the transaction, where the database supports it.
+    async fn get_by_id(&mut self, id: i32) -> Result<Option<Coupon>, sqlx::Error>;
+
+    /// Locks the coupon until the end of the transaction, where the database supports it.
+    async fn get_by_code(&mut self, code: &String) -> Result<Option<Coupon>, sqlx::Error>;
+
+    /// Returns if a coupon was deleted.
+    async fn delete_by_id(&mut self, id: i32) -> Result<bool, sqlx::Error>;
+
+    /// Returns if a coupon was deleted.
+    async fn delete_by_code(&mut self, code: &String) -> Result<bool, sqlx::Error>;
+
+    /// Write the event in the `outbox` of the transaction, published once committed by `outbox::spawn_relay`.
+    /// Only the MySQL store has one, the events can't be published with the others, see `Settings::validate`.
+    async fn add_event(&mut self, _event: &PendingEvent) -> Result<(), sqlx::Error> {
+        return Ok(());
+    }
+
+    /// Queue the deliveries of the webhooks subscribed to the `event` in the transaction, see `webhook_delivery::enqueue`.
+    /// Returns `false` for the stores not in MySQL, with the webhooks: `coupon_service` then queues them after the commit.
+    async fn add_webhook_deliveries(&mut self, _event: WebhookEvent, _body: &str) -> Result<bool, sqlx::Error> {
+        return Ok(false);
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error>;
+}
+
+/// If the insert failed because the `code` is already taken.
+pub fn is_unique_violation(error: &sqlx::Error) -> bool {
+    return match error {
+        sqlx::Error::Database(error) => {
+            // MySQL `ER_DUP_ENTRY`, its SQLSTATE `23000` is shared by every integrity error
+            if let Some(error) = error.try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>() {
+                return error.number() == 1062;
+            }
+            // `23505` is Postgres, `2067` and `1555` are `SQLITE_CONSTRAINT_UNIQUE` and `SQLITE_CONSTRAINT_PRIMARYKEY`
+            matches!(error.code().as_deref(), Some("23505") | Some("2067") | Some("1555"))
+        },
+        _ => false,
+    };
+}
+
+/// Coupons stored in MySQL, with the queries of `coupon_repository`.
+pub struct MySqlCouponStore {
+    pool: MySqlPool,
+    metrics: Arc<PoolMetrics>,
+}
+
+impl MySqlCouponStore {
+    pub fn new(pool: MySqlPool, metrics: Arc<PoolMetrics>) -> Self {
+        return Self { pool, metrics };
+    }
+}
+
+#[async_trait]
+impl CouponStore for MySqlCouponStore {
+    async fn get_all(&self, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_all", coupon_repository::get_all(filter, &mut conn)).await;
+    }
+
+    async fn stream_all(&self, filter: &CouponFilter) -> Result<CouponStream, sqlx::Error> {
+        // acquired before the response starts, so a failure is still an error status
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        let filter = filter.clone();
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            if let Err(error) = coupon_repository::stream_all(&filter, &sender, &mut conn).await {
+                let _ = sender.send(Err(error)).await;
+            }
+        });
+        return Ok(Box::pin(stream::unfold(receiver, |mut receiver| async move {
+            return receiver.recv().await.map(|coupon| (coupon, receiver));
+        })));
+    }
+
+    async fn get_page(&self, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_page", coupon_repository::get_page(filter, cursor, limit, &mut conn)).await;
+    }
+
+    async fn count(&self, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("count", coupon_repository::count(filter, &mut conn)).await;
+    }
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_by_id", coupon_repository::get_by_id(id, &mut conn)).await;
+    }
+
+    async fn get_by_code(&self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_by_code", coupon_repository::get_by_code(code, &mut conn)).await;
+    }
+
+    async fn get_by_codes(&self, codes: &[String]) -> Result<Vec<Coupon>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_by_codes", coupon_repository::get_by_codes(codes, &mut conn)).await;
+    }
+
+    async fn exists_by_code(&self, code: &String) -> Result<bool, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("exists_by_code", coupon_repository::exists_by_code(code, &mut conn)).await;
+    }
+
+    async fn get_changes(&self, since: ChangesSince, settle_seconds: u32, limit: u32) -> Result<Option<Vec<CouponChangeRecord>>, sqlx::Error> {
+        let mut conn = self.metrics.time_acquire(self.pool.acquire()).await?;
+        return self.metrics.time_query("get_changes", coupon_repository::get_changes(since, settle_seconds, limit, &mut conn)).await.map(Some);
+    }
+
+    async fn begin(&self) -> Result<Box<dyn CouponTransaction>, sqlx::Error> {
+        let transaction = self.metrics.time_acquire(self.pool.begin()).await?;
+        return Ok(Box::new(MySqlCouponTransaction(transaction, self.metrics.clone())));
+    }
+}
+
+// the metrics of the pool of the transaction, to log its slow queries
+pub struct MySqlCouponTransaction(Transaction<'static, MySql>, Arc<PoolMetrics>);
+
+#[async_trait]
+impl CouponTransaction for MySqlCouponTransaction {
+    async fn insert(&mut self, coupon: CouponInsert) -> Result<u64, sqlx::Error> {
+        return self.1.time_query("insert", coupon_repository::insert(coupon, &mut self.0)).await;
+    }
+
+    async fn upsert(&mut self, code: &String, coupon: CouponUpdate) -> Result<bool, sqlx::Error> {
+        // `1` affected row when inserted and `2` when updated
+        let affected_rows = self.1.time_query("upsert", coupon_repository::upsert(code, coupon, &mut self.0)).await?;
+        return Ok(affected_rows == 1);
+    }
+
+    async fn update(&mut self, id: i32, coupon: CouponUpdate, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("update", coupon_repository::update(id, coupon, expected_version, &mut self.0)).await;
+    }
+
+    async fn patch(&mut self, id: i32, patch: &CouponPatch, expected_version: Option<i32>) -> Result<bool, sqlx::Error> {
+        return self.1.time_query("patch", coupon_repository::patch(id, patch, expected_version, &mut self.0)).await;
+    }
+
+    async fn get_by_id(&mut self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        return self.1.time_query("get_by_id_for_update", coupon_repository::get_by_id_for_update(id, &mut self.0)).await;
+    }
+
+    async fn get_by_code(&mut self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        return self.1.time_query("get_by_code_for_update", coupon_repository::get_by_code_for_update(code, &mut self.0)).await;
+    }
+
+    async fn delete_by_id(&mut self, id: i32) -> Result<bool, sqlx::Error> {
+        let affected_rows = self.1.time_query("delete_by_id", coupon_repository::delete_by_id(id, &mut self.0)).await?;
+        return Ok(affected_rows > 0);
+    }
+
+    async fn delete_by_code(&mut self, code: &String) -> Result<bool, sqlx::Error> {
+        let affected_rows = self.1.time_query("delete_by_code", coupon_repository::delete_by_code(code, &mut self.0)).await?;
+        return Ok(affected_rows > 0);
+    }
+
+    async fn add_event(&mut self, event: &PendingEvent) -> Result<(), sqlx::Error> {
+        return self.1.time_query("add_event", outbox::insert(event, &mut self.0)).await;
+    }
+
+    async fn add_webhook_deliveries(&mut self, event: WebhookEvent, body: &str) -> Result<bool, sqlx::Error> {
+        self.1.time_query("add_webhook_deliveries", webhook_delivery::enqueue(event, body, &mut self.0)).await?;
+        return Ok(true);
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error> {
+        return self.0.commit().await;
+    }
+}
+
+/// MySQL with a read replica (`database.read_replica_url`): the reads go to the replica, the transactions to the primary.
+/// The replica can lag behind, the flows that read what they just wrote read it in their transaction.
+pub struct ReplicatedCouponStore {
+    primary: MySqlCouponStore,
+    replica: MySqlCouponStore,
+}
+
+impl ReplicatedCouponStore {
+    pub fn new(primary: MySqlCouponStore, replica: MySqlCouponStore) -> Self {
+        return Self { primary, replica };
+    }
+}
+
+#[async_trait]
+impl CouponStore for ReplicatedCouponStore {
+    async fn get_all(&self, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error> {
+        return self.replica.get_all(filter).await;
+    }
+
+    async fn stream_all(&self, filter: &CouponFilter) -> Result<CouponStream, sqlx::Error> {
+        return self.replica.stream_all(filter).await;
+    }
+
+    async fn get_page(&self, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error> {
+        return self.replica.get_page(filter, cursor, limit).await;
+    }
+
+    async fn count(&self, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error> {
+        return self.replica.count(filter).await;
+    }
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        return self.replica.get_by_id(id).await;
+    }
+
+    async fn get_by_code(&self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        return self.replica.get_by_code(code).await;
+    }
+
+    async fn get_by_codes(&self, codes: &[String]) -> Result<Vec<Coupon>, sqlx::Error> {
+        return self.replica.get_by_codes(codes).await;
+    }
+
+    async fn exists_by_code(&self, code: &String) -> Result<bool, sqlx::Error> {
+        return self.replica.exists_by_code(code).await;
+    }
+
+    async fn get_changes(&self, since: ChangesSince, settle_seconds: u32, limit: u32) -> Result<Option<Vec<CouponChangeRecord>>, sqlx::Error> {
+        return self.replica.get_changes(since, settle_seconds, limit).await;
+    }
+
+    async fn begin(&self) -> Result<Box<dyn CouponTransaction>, sqlx::Error> {
+        return self.primary.begin().await;
+    }
+}
+
+/// Retries the reads that fail with a transient error (see `retry`).
+/// The statements of a transaction can't be retried one by one, `coupon_service` retries the whole transaction with `retry_settings()`.
+pub struct RetryingCouponStore {
+    store: Arc<dyn CouponStore>,
+    settings: RetrySettings,
+}
+
+impl RetryingCouponStore {
+    pub fn new(store: Arc<dyn CouponStore>, settings: RetrySettings) -> Self {
+        return Self { store, settings };
+    }
+}
+
+#[async_trait]
+impl CouponStore for RetryingCouponStore {
+    async fn get_all(&self, filter: &CouponFilter) -> Result<Vec<Coupon>, sqlx::Error> {
+        return retry::with_retry(&self.settings, "Get all coupons", || self.store.get_all(filter)).await;
+    }
+
+    // only the start of the stream is retried, the coupons already sent can't be
+    async fn stream_all(&self, filter: &CouponFilter) -> Result<CouponStream, sqlx::Error> {
+        return retry::with_retry(&self.settings, "Stream all coupons", || self.store.stream_all(filter)).await;
+    }
+
+    async fn get_page(&self, filter: &CouponFilter, cursor: Option<Cursor>, limit: u32) -> Result<Vec<Coupon>, sqlx::Error> {
+        return retry::with_retry(&self.settings, "Get coupon page", || self.store.get_page(filter, cursor, limit)).await;
+    }
+
+    async fn count(&self, filter: &CouponFilter) -> Result<CouponCount, sqlx::Error> {
+        return retry::with_retry(&self.settings, "Count coupons", || self.store.count(filter)).await;
+    }
+
+    async fn get_by_id(&self, id: i32) -> Result<Option<Coupon>, sqlx::Error> {
+        return retry::with_retry(&self.settings, "Get coupon by id", || self.store.get_by_id(id)).await;
+    }
+
+    async fn get_by_code(&self, code: &String) -> Result<Option<Coupon>, sqlx::Error> {
+        return retry::with_retry(&self.settings, "Get coupon by code", || self.store.get_by_code(code)).await;
+    }
+
+    async fn get_by_codes(&self, codes: &[String]) -> Result<Vec<Coupon>, sqlx::Error> {
+        return retry::with_retry(&self.settings, "Get coupons by code", || self.store.get_by_codes(codes)).await;
+    }
+
+    async fn exists_by_code(&self, code: &String) -> Result<bool, sqlx::Error> {
+        return retry::with_retry(&self.settings, "Check if coupon exists", || self.store.exists_by_code(code)).await;
+    }
+
+    async fn get_changes(&self, since: ChangesSince, settle_seconds: u32, limit: u32) -> Result<Option<Vec<CouponChangeRecord>>, sqlx::Error> {
+        return retry::with_retry(&self.settings, "Get coupon changes", || self.store.get_changes(since, settle_seconds, limit)).await;
+    }
+
+    async fn begin(&self) -> Result<Box<dyn CouponTransaction>, sqlx::Error> {
+        return self.store.begin().await;
+    }
+
+    async fn evict_cached(&self, codes: &[String]) {
+        self.store.evict_cached(codes).await;
+    }
+
+    fn retry_settings(&self) -> &RetrySettings {
+        return &self.settings;
+    }
+}
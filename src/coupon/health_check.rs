@@ -1,4 +1,11 @@
-use actix_web::{get, HttpResponse, Responder};
+use crate::migrations;
+use actix_web::{get, HttpResponse, Responder, web::Data};
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+use std::time::{Duration, Instant};
+
+// a healthy database answers `SELECT 1` well before this, even under load
+const DATABASE_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[tracing::instrument(
     name = "Health check",
@@ -7,3 +14,78 @@ use actix_web::{get, HttpResponse, Responder};
 pub async fn health_check() -> impl Responder {
     return HttpResponse::Ok().finish();
 }
+
+/// Liveness probe: `200 OK` as long as the server answers, a restart can't fix what the other checks find.
+#[tracing::instrument(
+    name = "Liveness probe",
+)]
+#[get("/health/live")]
+pub async fn liveness_probe() -> impl Responder {
+    return HttpResponse::Ok().finish();
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Readiness {
+    // `ready` or `not ready`
+    pub status: String,
+    pub pending_migrations: Vec<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Readiness probe: `503 Service Unavailable` until the database answers and has every migration applied,
+/// so no traffic is sent to an instance that is starting or can't serve the requests.
+#[tracing::instrument(
+    name = "Readiness probe",
+    skip(pool)
+)]
+#[get("/health/ready")]
+pub async fn readiness_probe(pool: Data<MySqlPool>) -> HttpResponse {
+    let result = tokio::time::timeout(DATABASE_HEALTH_CHECK_TIMEOUT, migrations::pending(pool.get_ref())).await;
+    let (pending_migrations, error) = match result {
+        Ok(Ok(pending)) if pending.is_empty() => (pending, None),
+        Ok(Ok(pending)) => {
+            let error = format!("{} migrations are not applied, run `coupon-api migrate run`.", pending.len());
+            (pending, Some(error))
+        },
+        Ok(Err(e)) => (vec![], Some(format!("{:#}", e))),
+        Err(_) => (vec![], Some(format!("The database did not answer in {:?}.", DATABASE_HEALTH_CHECK_TIMEOUT))),
+    };
+    if let Some(error) = error {
+        tracing::warn!("Readiness probe failed: {}", error);
+        return HttpResponse::ServiceUnavailable().json(Readiness { status: "not ready".to_string(), pending_migrations, error: Some(error) });
+    }
+    return HttpResponse::Ok().json(Readiness { status: "ready".to_string(), pending_migrations, error: None });
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DatabaseHealth {
+    // `up` or `down`
+    pub status: String,
+    pub latency_milliseconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `503 Service Unavailable` when the database doesn't answer, while `/health_check` only tells the app is running.
+#[tracing::instrument(
+    name = "Database health check",
+    skip(pool)
+)]
+#[get("/health/db")]
+pub async fn database_health_check(pool: Data<MySqlPool>) -> HttpResponse {
+    let start = Instant::now();
+    let result = tokio::time::timeout(DATABASE_HEALTH_CHECK_TIMEOUT, sqlx::query("SELECT 1").execute(pool.get_ref())).await;
+    let latency_milliseconds = start.elapsed().as_secs_f64() * 1000.0;
+
+    let error = match result {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(format!("Failed to query the database: {}.", e)),
+        Err(_) => Some(format!("The database did not answer in {:?}.", DATABASE_HEALTH_CHECK_TIMEOUT)),
+    };
+    if let Some(error) = error {
+        tracing::error!("Database health check failed: {}", error);
+        return HttpResponse::ServiceUnavailable().json(DatabaseHealth { status: "down".to_string(), latency_milliseconds, error: Some(error) });
+    }
+    return HttpResponse::Ok().json(DatabaseHealth { status: "up".to_string(), latency_milliseconds, error: None });
+}
@@ -1,11 +1,19 @@
+pub mod content_negotiation;
+pub mod coupon_cache;
+pub mod coupon_batch;
+pub mod coupon_cart;
 pub mod coupon_controller;
 pub mod coupon_service;
 pub mod coupon_repository;
+pub mod coupon_repository_memory;
+#[cfg(feature = "postgres")]
+pub mod coupon_repository_postgres;
+#[cfg(feature = "sqlite")]
+pub mod coupon_repository_sqlite;
+pub mod coupon_store;
 pub mod health_check;
 pub mod model;
 
 pub use coupon_controller::*;
-pub use coupon_service::*;
-pub use coupon_repository::*;
 pub use health_check::*;
 pub use model::*;
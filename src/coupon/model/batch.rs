@@ -0,0 +1,18 @@
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchOperation {
+    pub method: String,
+    // path relative to the API root, e.g. `/coupon/123` or `/coupon/verify/CODE`
+    pub path: String,
+    pub body: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchOperationResult {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub body: serde_json::Value,
+}
@@ -0,0 +1,33 @@
+use serde::{Serialize, Deserialize};
+
+
+/// How many codes `POST /coupon/validate/bulk` takes at once.
+pub const MAX_BULK_CODES: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BulkValidationRequest {
+    pub codes: Vec<String>,
+}
+
+impl BulkValidationRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if (self.codes.is_empty()){
+            return Err("No code was sent.".to_string());
+        }
+        if (self.codes.len() > MAX_BULK_CODES){
+            return Err(format!("At most {} codes can be validated at once, {} were sent.", MAX_BULK_CODES, self.codes.len()));
+        }
+        return Ok(());
+    }
+}
+
+/// The validity of a code, in the order they were sent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CodeValidation {
+    pub code: String,
+    pub valid: bool,
+    // `None` when the coupon was not found
+    pub discount: Option<i32>,
+    // why it is not valid
+    pub reason: Option<String>,
+}
@@ -0,0 +1,92 @@
+use serde::{Serialize, Deserialize};
+
+
+// The amounts are in the minor unit of the currency, e.g. cents.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CartItem {
+    pub sku: String,
+    pub quantity: u32,
+    pub unit_price: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CartApplyRequest {
+    pub items: Vec<CartItem>,
+    #[serde(default)]
+    pub shipping: i64,
+    // ISO 4217, e.g. `BRL`
+    pub currency: String,
+    // applied in the order they are sent
+    pub codes: Vec<String>,
+}
+
+impl CartApplyRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if (self.items.is_empty()){
+            return Err("The cart has no items.".to_string());
+        }
+        for item in &self.items {
+            if (item.sku.trim().is_empty()){
+                return Err("Every item must have a `sku`.".to_string());
+            }
+            if (item.quantity == 0){
+                return Err(format!("The quantity of `{}` must be at least 1.", item.sku));
+            }
+            if (item.unit_price < 0){
+                return Err(format!("The unit price of `{}` cannot be negative.", item.sku));
+            }
+        }
+        if (self.shipping < 0){
+            return Err("The shipping cannot be negative.".to_string());
+        }
+        let total = self.items.iter()
+            .try_fold(self.shipping, |total, item| item.unit_price.checked_mul(i64::from(item.quantity))?.checked_add(total));
+        if (total.is_none()){
+            return Err("The cart total is too large.".to_string());
+        }
+        if (self.currency.len() != 3 || !self.currency.chars().all(|c| c.is_ascii_uppercase())){
+            return Err("The currency must be an ISO 4217 code, e.g. `BRL`.".to_string());
+        }
+        if (self.codes.is_empty()){
+            return Err("At least one coupon code is required.".to_string());
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CartLine {
+    pub sku: String,
+    pub quantity: u32,
+    pub subtotal: i64,
+    pub discount: i64,
+    pub total: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AppliedCoupon {
+    pub code: String,
+    // percentage
+    pub discount: i32,
+    // taken off the cart by this coupon
+    pub amount: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RejectedCoupon {
+    pub code: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CartApplyResponse {
+    pub currency: String,
+    pub items: Vec<CartLine>,
+    pub applied: Vec<AppliedCoupon>,
+    pub rejected: Vec<RejectedCoupon>,
+    pub subtotal: i64,
+    pub discount: i64,
+    pub shipping: i64,
+    pub total: i64,
+}
@@ -0,0 +1,107 @@
+use super::pagination::CURSOR_ENGINE;
+use super::{Coupon, CouponResponse, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use chrono::DateTime;
+use serde::{Serialize, Deserialize};
+use sqlx::types::chrono::NaiveDateTime;
+
+
+/// Query parameters of `GET /coupon/changes`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CouponChangesQuery {
+    // the `next_cursor` of the previous response, or a time (e.g. `2023-01-31T10:00:00Z`), from the start when absent
+    pub since: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl CouponChangesQuery {
+    pub fn limit(&self) -> u32 {
+        return self.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    }
+
+    pub fn since(&self) -> Result<ChangesSince, String> {
+        return match &self.since {
+            Some(since) => ChangesSince::parse(since),
+            None => Ok(ChangesSince::Start),
+        };
+    }
+}
+
+/// Where the changes feed starts from, the changes after it are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangesSince {
+    Start,
+    // the `seq` of the last change already seen
+    Cursor(i64),
+    // in UTC
+    Time(NaiveDateTime),
+}
+
+impl ChangesSince {
+    pub fn parse(since: &str) -> Result<Self, String> {
+        if let Ok(time) = DateTime::parse_from_rfc3339(since) {
+            return Ok(ChangesSince::Time(time.naive_utc()));
+        }
+        if let Ok(time) = since.parse::<NaiveDateTime>() {
+            return Ok(ChangesSince::Time(time));
+        }
+        let invalid_since = || format!("Invalid `since` `{}`, it must be a `next_cursor` or a time, e.g. `2023-01-31T10:00:00Z`.", since);
+        let decoded = base64::decode_engine(since, &CURSOR_ENGINE).map_err(|_| invalid_since())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid_since())?;
+        return decoded.strip_prefix("changes:")
+            .and_then(|seq| seq.parse::<i64>().ok())
+            .map(ChangesSince::Cursor)
+            .ok_or_else(invalid_since);
+    }
+
+    pub fn cursor(seq: i64) -> String {
+        return base64::encode_engine(format!("changes:{}", seq), &CURSOR_ENGINE);
+    }
+}
+
+/// A write of the `coupon_changes` log, with the coupon as it is now, `None` when it was deleted since.
+#[derive(Debug, Clone)]
+pub struct CouponChangeRecord {
+    pub seq: i64,
+    pub coupon_id: i32,
+    pub code: String,
+    pub changed_at: NaiveDateTime,
+    pub coupon: Option<Coupon>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CouponChange {
+    pub id: i32,
+    pub code: String,
+    pub changed_at: NaiveDateTime,
+    // a tombstone, the coupon was deleted or archived
+    pub deleted: bool,
+    // the coupon as it is now, `None` when deleted
+    pub coupon: Option<CouponResponse>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CouponChanges {
+    pub changes: Vec<CouponChange>,
+    // the `since` of the next request, `None` when there was no change: send the same `since` again
+    pub next_cursor: Option<String>,
+    // when `true`, request the next changes right away
+    pub has_more: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChangesSince;
+    use chrono::NaiveDate;
+    use claim::assert_err;
+
+    #[test]
+    fn since_is_a_cursor_or_a_time(){
+        let time = NaiveDate::from_ymd_opt(2023, 1, 31).unwrap().and_hms_opt(10, 0, 0).unwrap();
+        assert_eq!(ChangesSince::parse("2023-01-31T10:00:00Z"), Ok(ChangesSince::Time(time)));
+        assert_eq!(ChangesSince::parse("2023-01-31T07:00:00-03:00"), Ok(ChangesSince::Time(time)));
+        assert_eq!(ChangesSince::parse("2023-01-31T10:00:00"), Ok(ChangesSince::Time(time)));
+        assert_eq!(ChangesSince::parse(&ChangesSince::cursor(42)), Ok(ChangesSince::Cursor(42)));
+        assert_err!(ChangesSince::parse("yesterday"));
+        assert_err!(ChangesSince::parse(&crate::coupon::Cursor::After(42).encode()));
+    }
+}
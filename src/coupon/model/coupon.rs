@@ -1,4 +1,4 @@
-use super::CouponDiscount;
+use super::{CouponDiscount, CouponLinks};
 use actix_web::{ 
     ResponseError,
     http::{StatusCode},
@@ -8,19 +8,37 @@ use serde::{Serialize, Deserialize};
 use sqlx::types::chrono::{NaiveDateTime};
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
 pub struct Coupon {
     pub id: i32,
     pub code: String,
     pub discount: i32,
     pub active: bool,
+    pub version: i32,
     pub max_usage_count: Option<i32>, // not actually being used currently, we will also need a new field to track the `current usage` count for the coupon
     pub expiration_date: Option<NaiveDateTime>,
     pub date_created: Option<NaiveDateTime>,
     pub date_updated: Option<NaiveDateTime>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Coupon {
+    /// The coupon as inserted with `id`, without reading it back: `version` starts at `1` and it was never updated.
+    pub fn inserted(id: i32, coupon: CouponInsert, date_created: NaiveDateTime) -> Self {
+        return Self {
+            id,
+            code: coupon.code,
+            discount: *coupon.discount.as_ref(),
+            active: coupon.active,
+            version: 1,
+            max_usage_count: coupon.max_usage_count,
+            expiration_date: coupon.expiration_date,
+            date_created: Some(date_created),
+            date_updated: None,
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CouponInsert {
     pub code: String,
     pub discount: CouponDiscount,
@@ -46,12 +64,14 @@ pub struct CouponUpdate {
     pub expiration_date: Option<NaiveDateTime>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CouponUpdateRequest {
     pub discount: i32,
     pub active: bool,
     pub max_usage_count: Option<i32>,
     pub expiration_date: Option<NaiveDateTime>,
+    // the `version` the client read, the update is rejected if the coupon changed since (optimistic locking)
+    pub version: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -64,6 +84,25 @@ pub struct CouponResponse {
     pub expiration_date: Option<NaiveDateTime>,
     pub date_created: Option<NaiveDateTime>,
     pub date_updated: Option<NaiveDateTime>,
+    pub version: i32,
+    #[serde(rename = "_links")]
+    pub links: CouponLinks,
+}
+
+// Query string filters shared by the list endpoints
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CouponFilter {
+    pub active: Option<bool>,
+    pub expired: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct CouponCount {
+    pub total: i64,
+    pub active: i64,
+    pub expired: i64,
+    // sum of `max_usage_count` of the coupons that are still usable (active and not expired)
+    pub remaining_usages: i64,
 }
 
 // Convert a Coupon to a CouponResponse
@@ -79,6 +118,8 @@ impl TryFrom<Coupon> for CouponResponse {
             expiration_date: coupon.expiration_date,
             date_created: coupon.date_created,
             date_updated: coupon.date_updated,
+            version: coupon.version,
+            links: CouponLinks::new(coupon.id),
         });
     }
 }
@@ -135,6 +176,11 @@ pub enum CouponError {
     // ValidationError has one String parameter
     #[error("{0}")]
     ValidationError(String),
+    #[error("{0}")]
+    ForbiddenError(String),
+    // the coupon was changed since the client read it
+    #[error("{0}")]
+    ConflictError(String),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -146,6 +192,8 @@ impl ResponseError for CouponError {
             CouponError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             CouponError::NotFoundError(_) => StatusCode::NOT_FOUND,
             CouponError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            CouponError::ForbiddenError(_) => StatusCode::FORBIDDEN,
+            CouponError::ConflictError(_) => StatusCode::CONFLICT,
             CouponError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -0,0 +1,123 @@
+use super::{Coupon, CouponDiscount, CouponUpdate};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+
+
+/// JSON Merge Patch (RFC 7386) of a coupon: the absent fields are left as they are, `null` clears them.
+/// `Some(None)` is an explicit `null`, `None` an absent field.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CouponPatchRequest {
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub discount: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub active: Option<Option<bool>>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub max_usage_count: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub expiration_date: Option<Option<NaiveDateTime>>,
+    // same as `CouponUpdateRequest`, `If-Match` takes precedence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+}
+
+// a field sent, even as `null`, is `Some`: serde only calls it for the fields present
+fn present<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+    return Option::<T>::deserialize(deserializer).map(Some);
+}
+
+/// The validated fields of a `CouponPatchRequest`, the `None` ones are left as they are.
+#[derive(Debug, Clone, Default)]
+pub struct CouponPatch {
+    pub discount: Option<CouponDiscount>,
+    pub active: Option<bool>,
+    pub max_usage_count: Option<Option<i32>>,
+    pub expiration_date: Option<Option<NaiveDateTime>>,
+}
+
+impl CouponPatch {
+    pub fn is_empty(&self) -> bool {
+        return self.discount.is_none() && self.active.is_none() && self.max_usage_count.is_none() && self.expiration_date.is_none();
+    }
+
+    /// The coupon with the patch applied, for the stores that can only replace it.
+    pub fn apply(&self, coupon: &Coupon) -> Result<CouponUpdate, String> {
+        let discount = match &self.discount {
+            Some(discount) => discount.clone(),
+            None => CouponDiscount::parse(coupon.discount)?,
+        };
+        return Ok(CouponUpdate {
+            discount,
+            active: self.active.unwrap_or(coupon.active),
+            max_usage_count: self.max_usage_count.unwrap_or(coupon.max_usage_count),
+            expiration_date: self.expiration_date.unwrap_or(coupon.expiration_date),
+        });
+    }
+}
+
+impl TryFrom<CouponPatchRequest> for CouponPatch {
+    type Error = String;
+    fn try_from(request: CouponPatchRequest) -> Result<Self, Self::Error> {
+        let discount = match request.discount {
+            Some(Some(discount)) => Some(CouponDiscount::parse(discount)?),
+            Some(None) => return Err("`discount` cannot be null.".to_string()),
+            None => None,
+        };
+        let active = match request.active {
+            Some(Some(active)) => Some(active),
+            Some(None) => return Err("`active` cannot be null.".to_string()),
+            None => None,
+        };
+        return Ok(Self { discount, active, max_usage_count: request.max_usage_count, expiration_date: request.expiration_date });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CouponPatch, CouponPatchRequest};
+    use crate::coupon::Coupon;
+    use chrono::NaiveDate;
+    use claim::{assert_err, assert_ok};
+
+    fn patch(json: serde_json::Value) -> Result<CouponPatch, String> {
+        let request: CouponPatchRequest = serde_json::from_value(json).unwrap();
+        return CouponPatch::try_from(request);
+    }
+
+    #[test]
+    fn absent_fields_are_left_as_they_are_and_null_clears_them(){
+        let expiration_date = NaiveDate::from_ymd_opt(2099, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let coupon = Coupon {
+            id: 1,
+            code: "SUMMER10".to_string(),
+            discount: 10,
+            active: true,
+            version: 1,
+            max_usage_count: Some(5),
+            expiration_date: Some(expiration_date),
+            date_created: None,
+            date_updated: None,
+        };
+
+        let patch = assert_ok!(patch(serde_json::json!({"discount": 20, "max_usage_count": null})));
+        let update = assert_ok!(patch.apply(&coupon));
+
+        assert_eq!(*update.discount.as_ref(), 20);
+        assert!(update.active);
+        assert_eq!(update.max_usage_count, None);
+        assert_eq!(update.expiration_date, Some(expiration_date));
+    }
+
+    #[test]
+    fn empty_patches_change_nothing(){
+        assert!(assert_ok!(patch(serde_json::json!({}))).is_empty());
+        assert!(assert_ok!(patch(serde_json::json!({"version": 2}))).is_empty());
+        assert!(!assert_ok!(patch(serde_json::json!({"expiration_date": null}))).is_empty());
+    }
+
+    #[test]
+    fn required_fields_cannot_be_cleared(){
+        assert_err!(patch(serde_json::json!({"discount": null})));
+        assert_err!(patch(serde_json::json!({"active": null})));
+        assert_err!(patch(serde_json::json!({"discount": 91})));
+    }
+}
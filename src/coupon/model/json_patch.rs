@@ -0,0 +1,142 @@
+use super::{Coupon, CouponUpdate, CouponUpdateRequest};
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+
+
+// the fields of a coupon a patch can change, the others can only be tested
+const PATCHABLE_FIELDS: [&str; 4] = ["discount", "active", "max_usage_count", "expiration_date"];
+// `null` is their value when removed
+const NULLABLE_FIELDS: [&str; 2] = ["max_usage_count", "expiration_date"];
+
+/// An operation of a JSON Patch (RFC 6902), e.g. `{"op": "replace", "path": "/discount", "value": 20}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum JsonPatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Test { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+}
+
+/// Apply the operations in order to the coupon, returns it as patched.
+/// Every operation must succeed, the coupon is only changed if they all do.
+pub fn apply_json_patch(operations: &[JsonPatchOperation], coupon: &Coupon) -> Result<CouponUpdate, String> {
+    let mut document = match serde_json::to_value(coupon) {
+        Ok(Value::Object(document)) => document,
+        _ => return Err(format!("Failed to read coupon `{}` as JSON.", coupon.code)),
+    };
+    for operation in operations {
+        apply_operation(operation, &mut document)?;
+    }
+    let request: CouponUpdateRequest = serde_json::from_value(Value::Object(document))
+        .map_err(|e| format!("The patched coupon is not valid: {}.", e))?;
+    return CouponUpdate::try_from(request);
+}
+
+fn apply_operation(operation: &JsonPatchOperation, document: &mut Map<String, Value>) -> Result<(), String> {
+    return match operation {
+        JsonPatchOperation::Add { path, value } | JsonPatchOperation::Replace { path, value } => {
+            let field = patchable_field(path, document)?;
+            document.insert(field, value.clone());
+            Ok(())
+        },
+        JsonPatchOperation::Remove { path } => {
+            let field = patchable_field(path, document)?;
+            if (!NULLABLE_FIELDS.contains(&field.as_str())){
+                return Err(format!("`{}` cannot be removed.", path));
+            }
+            document.insert(field, Value::Null);
+            Ok(())
+        },
+        JsonPatchOperation::Test { path, value } => {
+            let field = field(path, document)?;
+            if (document.get(&field) != Some(value)){
+                return Err(format!("The test of `{}` failed, it is `{}`.", path, document.get(&field).unwrap_or(&Value::Null)));
+            }
+            Ok(())
+        },
+        JsonPatchOperation::Move { .. } | JsonPatchOperation::Copy { .. } => {
+            Err("The `move` and `copy` operations are not supported, the fields of a coupon all have their own type.".to_string())
+        },
+    };
+}
+
+// the field of a `/field` path, a coupon has no nested values
+fn field(path: &str, document: &Map<String, Value>) -> Result<String, String> {
+    let field = path.strip_prefix('/')
+        .filter(|field| !field.contains('/'))
+        .ok_or_else(|| format!("Invalid path `{}`, it must be a field of the coupon, e.g. `/discount`.", path))?;
+    if (!document.contains_key(field)){
+        return Err(format!("The coupon has no `{}` field.", field));
+    }
+    return Ok(field.to_string());
+}
+
+fn patchable_field(path: &str, document: &Map<String, Value>) -> Result<String, String> {
+    let field = field(path, document)?;
+    if (!PATCHABLE_FIELDS.contains(&field.as_str())){
+        return Err(format!("`{}` cannot be changed.", path));
+    }
+    return Ok(field);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_json_patch, JsonPatchOperation};
+    use crate::coupon::Coupon;
+    use chrono::NaiveDate;
+    use claim::{assert_err, assert_ok};
+
+    fn coupon() -> Coupon {
+        return Coupon {
+            id: 1,
+            code: "SUMMER10".to_string(),
+            discount: 10,
+            active: true,
+            version: 3,
+            max_usage_count: Some(5),
+            expiration_date: Some(NaiveDate::from_ymd_opt(2099, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()),
+            date_created: None,
+            date_updated: None,
+        };
+    }
+
+    fn operations(json: serde_json::Value) -> Vec<JsonPatchOperation> {
+        return serde_json::from_value(json).unwrap();
+    }
+
+    #[test]
+    fn operations_are_applied_in_order(){
+        let operations = operations(serde_json::json!([
+            {"op": "test", "path": "/version", "value": 3},
+            {"op": "replace", "path": "/discount", "value": 20},
+            {"op": "add", "path": "/active", "value": false},
+            {"op": "remove", "path": "/max_usage_count"},
+        ]));
+
+        let update = assert_ok!(apply_json_patch(&operations, &coupon()));
+
+        assert_eq!(*update.discount.as_ref(), 20);
+        assert!(!update.active);
+        assert_eq!(update.max_usage_count, None);
+        assert_eq!(update.expiration_date, coupon().expiration_date);
+    }
+
+    #[test]
+    fn invalid_patches_are_rejected(){
+        let rejected = [
+            serde_json::json!([{"op": "test", "path": "/discount", "value": 15}]),
+            serde_json::json!([{"op": "replace", "path": "/code", "value": "WINTER10"}]),
+            serde_json::json!([{"op": "replace", "path": "/unknown", "value": 1}]),
+            serde_json::json!([{"op": "remove", "path": "/discount"}]),
+            serde_json::json!([{"op": "replace", "path": "/discount", "value": 91}]),
+            serde_json::json!([{"op": "replace", "path": "/discount", "value": "20"}]),
+            serde_json::json!([{"op": "copy", "from": "/discount", "path": "/max_usage_count"}]),
+        ];
+        for json in rejected {
+            assert_err!(apply_json_patch(&operations(json), &coupon()));
+        }
+    }
+}
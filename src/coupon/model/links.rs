@@ -0,0 +1,40 @@
+use serde::{Serialize, Deserialize};
+
+
+/// Hypermedia link, so clients can navigate the API without hardcoding URL templates.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub href: String,
+    pub method: String,
+}
+
+impl Link {
+    pub fn new(method: &str, href: String) -> Self {
+        return Self { href, method: method.to_string() };
+    }
+
+    pub fn get(href: String) -> Self {
+        return Self::new("GET", href);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CouponLinks {
+    #[serde(rename = "self")]
+    pub self_link: Link,
+    pub update: Link,
+    pub delete: Link,
+    pub verify: Link,
+}
+
+impl CouponLinks {
+    pub fn new(id: i32) -> Self {
+        let href = format!("/coupon/{}", id);
+        return Self {
+            self_link: Link::get(href.clone()),
+            update: Link::new("PUT", href.clone()),
+            delete: Link::new("DELETE", href),
+            verify: Link::get(format!("/coupon/verify/{}", id)),
+        };
+    }
+}
@@ -1,5 +1,21 @@
+pub mod batch;
+pub mod bulk;
+pub mod cart;
+pub mod changes;
 pub mod coupon;
 pub mod coupon_discount;
+pub mod coupon_patch;
+pub mod json_patch;
+pub mod links;
+pub mod pagination;
 
+pub use self::batch::*;
+pub use self::bulk::*;
+pub use self::cart::*;
+pub use self::changes::*;
 pub use self::coupon::*;
-pub use self::coupon_discount::*;
\ No newline at end of file
+pub use self::coupon_discount::*;
+pub use self::coupon_patch::*;
+pub use self::json_patch::*;
+pub use self::links::*;
+pub use self::pagination::*;
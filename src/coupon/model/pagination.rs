@@ -0,0 +1,176 @@
+use super::{CouponFilter, Link};
+use base64::engine::fast_portable::{FastPortable, NO_PAD};
+use serde::{Serialize, Deserialize};
+
+
+pub const DEFAULT_PAGE_LIMIT: u32 = 50;
+pub const MAX_PAGE_LIMIT: u32 = 500;
+// the number of coupons of the list, all the pages together
+pub const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+// cursors are sent back in query strings, so they must not contain `+`, `/` or `=`
+pub(super) const CURSOR_ENGINE: FastPortable = FastPortable::from(&base64::alphabet::URL_SAFE, NO_PAD);
+
+/// Keyset pagination query parameters.
+/// The list is ordered by `id`, so rows inserted while iterating never skip or duplicate the ones already seen.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CouponPagination {
+    pub limit: Option<u32>,
+    // opaque cursor returned as `next_cursor` or `prev_cursor` by another page
+    pub cursor: Option<String>,
+}
+
+impl CouponPagination {
+    /// Pagination mode is only used when the client asks for it, otherwise the full list is returned.
+    pub fn is_requested(&self) -> bool {
+        return self.limit.is_some() || self.cursor.is_some();
+    }
+
+    pub fn limit(&self) -> u32 {
+        return self.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    }
+
+    pub fn cursor(&self) -> Result<Option<Cursor>, String> {
+        return match &self.cursor {
+            Some(cursor) => Cursor::decode(cursor).map(Some),
+            None => Ok(None),
+        };
+    }
+}
+
+/// Position in the list, relative to the `id` of a coupon already seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cursor {
+    // coupons with a greater `id`
+    After(i32),
+    // coupons with a lower `id`
+    Before(i32),
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let cursor = match self {
+            Cursor::After(id) => format!("after:{}", id),
+            Cursor::Before(id) => format!("before:{}", id),
+        };
+        return base64::encode_engine(cursor, &CURSOR_ENGINE);
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, String> {
+        let invalid_cursor = || format!("Invalid cursor `{}`.", cursor);
+        let decoded = base64::decode_engine(cursor, &CURSOR_ENGINE).map_err(|_| invalid_cursor())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid_cursor())?;
+        let (direction, id) = decoded.split_once(':').ok_or_else(invalid_cursor)?;
+        let id = id.parse::<i32>().map_err(|_| invalid_cursor())?;
+        return match direction {
+            "after" => Ok(Cursor::After(id)),
+            "before" => Ok(Cursor::Before(id)),
+            _ => Err(invalid_cursor()),
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CouponPage<T> {
+    pub coupons: Vec<T>,
+    pub pagination: PageMeta,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PageMeta {
+    pub limit: u32,
+    // the coupons of every page
+    pub total: i64,
+    // `None` when there are no more pages
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+    #[serde(rename = "_links")]
+    pub links: PageLinks,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PageLinks {
+    #[serde(rename = "self")]
+    pub self_link: Link,
+    pub first: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<Link>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev: Option<Link>,
+    pub last: Link,
+}
+
+impl PageLinks {
+    pub fn new(filter: &CouponFilter, limit: u32, cursor: Option<&String>, next_cursor: Option<&String>, prev_cursor: Option<&String>) -> Self {
+        return Self {
+            self_link: Link::get(page_href(filter, limit, cursor)),
+            first: Link::get(page_href(filter, limit, None)),
+            next: next_cursor.map(|cursor| Link::get(page_href(filter, limit, Some(cursor)))),
+            prev: prev_cursor.map(|cursor| Link::get(page_href(filter, limit, Some(cursor)))),
+            // the coupons before any `id`, the last ones
+            last: Link::get(page_href(filter, limit, Some(&Cursor::Before(i32::MAX).encode()))),
+        };
+    }
+
+    /// The RFC 5988 `Link` header, e.g. `</coupon?limit=50>; rel="first", </coupon?limit=50&cursor=...>; rel="next"`.
+    pub fn to_header(&self) -> String {
+        let links = [Some((&self.first, "first")), self.prev.as_ref().map(|link| (link, "prev")), self.next.as_ref().map(|link| (link, "next")), Some((&self.last, "last"))];
+        return links.iter()
+            .flatten()
+            .map(|(link, rel)| format!("<{}>; rel=\"{}\"", link.href, rel))
+            .collect::<Vec<_>>()
+            .join(", ");
+    }
+}
+
+fn page_href(filter: &CouponFilter, limit: u32, cursor: Option<&String>) -> String {
+    let mut href = format!("/coupon?limit={}", limit);
+    if let Some(active) = filter.active {
+        href.push_str(&format!("&active={}", active));
+    }
+    if let Some(expired) = filter.expired {
+        href.push_str(&format!("&expired={}", expired));
+    }
+    if let Some(cursor) = cursor {
+        href.push_str(&format!("&cursor={}", cursor));
+    }
+    return href;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cursor, CouponPagination, PageLinks, CURSOR_ENGINE, MAX_PAGE_LIMIT};
+    use crate::coupon::CouponFilter;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn cursor_roundtrip_returns_the_same_position(){
+        assert_ok_eq!(Cursor::decode(&Cursor::After(42).encode()), Cursor::After(42));
+        assert_ok_eq!(Cursor::decode(&Cursor::Before(42).encode()), Cursor::Before(42));
+    }
+
+    #[test]
+    fn invalid_cursor_is_rejected(){
+        assert_err!(Cursor::decode("not a cursor"));
+        assert_err!(Cursor::decode(&base64::encode_engine("after:abc", &CURSOR_ENGINE)));
+        assert_err!(Cursor::decode(&base64::encode_engine("sideways:1", &CURSOR_ENGINE)));
+    }
+
+    #[test]
+    fn link_header_lists_the_pages(){
+        let next = Cursor::After(50).encode();
+        let links = PageLinks::new(&CouponFilter::default(), 50, None, Some(&next), None);
+
+        assert_eq!(links.to_header(), format!(
+            "</coupon?limit=50>; rel=\"first\", </coupon?limit=50&cursor={}>; rel=\"next\", </coupon?limit=50&cursor={}>; rel=\"last\"",
+            next, Cursor::Before(i32::MAX).encode()
+        ));
+    }
+
+    #[test]
+    fn limit_is_clamped(){
+        let pagination = CouponPagination { limit: Some(0), cursor: None };
+        assert_eq!(pagination.limit(), 1);
+        let pagination = CouponPagination { limit: Some(MAX_PAGE_LIMIT + 1), cursor: None };
+        assert_eq!(pagination.limit(), MAX_PAGE_LIMIT);
+    }
+}
@@ -0,0 +1,16 @@
+use super::model::{ArchivedCouponFilter, CouponArchiveError};
+use super::coupon_archive_service;
+use crate::envelope::Envelope;
+use actix_web::{
+    web, get, HttpRequest, HttpResponse,
+    web::Data,
+};
+use sqlx::MySqlPool;
+
+
+#[tracing::instrument( name = "Get archived coupons", skip(pool, http_request) )]
+#[get("/coupons-archive")]
+pub async fn get_archived_coupons(http_request: HttpRequest, filter: web::Query<ArchivedCouponFilter>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponArchiveError> {
+    let coupons = coupon_archive_service::get_all(&filter, &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, coupons)));
+}
@@ -0,0 +1,105 @@
+use super::model::{ArchivedCoupon, ArchivedCouponFilter};
+use crate::webhook::{model::WebhookEvent, webhook_delivery};
+use sqlx::MySqlPool;
+use sqlx::types::chrono::{NaiveDateTime};
+
+
+const COUPON_COLUMNS: &str = "id, code, discount, max_usage_count, expiration_date, active, version, date_created, date_updated";
+
+/// Move up to `batch_size` coupons expired before `cutoff` to `coupons_archive`, in a transaction.
+/// The `coupon.expired` webhooks of the coupons are queued in the same one. Returns the coupons moved.
+pub async fn archive_expired(cutoff: NaiveDateTime, batch_size: u32, pool: &MySqlPool) -> Result<Vec<ArchivedCoupon>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    // locked, so a coupon can't be updated between its copy and its deletion
+    let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM coupon WHERE expiration_date < ? ORDER BY id LIMIT ? FOR UPDATE")
+    .bind(cutoff)
+    .bind(batch_size)
+    .fetch_all(&mut transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    if (ids.is_empty()){
+        return Ok(vec![]);
+    }
+    let placeholders = vec!["?"; ids.len()].join(", ");
+
+    let sql = format!("INSERT INTO coupons_archive ({}) SELECT {} FROM coupon WHERE id IN ({})", COUPON_COLUMNS, COUPON_COLUMNS, placeholders);
+    let mut query = sqlx::query(&sql);
+    for id in &ids {
+        query = query.bind(id);
+    }
+    query.execute(&mut transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+
+    let sql = format!("SELECT {}, date_archived FROM coupons_archive WHERE id IN ({}) ORDER BY archive_id DESC LIMIT ?", COUPON_COLUMNS, placeholders);
+    let mut query = sqlx::query_as::<_, ArchivedCoupon>(&sql);
+    for id in &ids {
+        query = query.bind(id);
+    }
+    let archived = query.bind(ids.len() as u32)
+    .fetch_all(&mut transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+
+    let sql = format!("DELETE FROM coupon WHERE id IN ({})", placeholders);
+    let mut query = sqlx::query(&sql);
+    for id in &ids {
+        query = query.bind(id);
+    }
+    query.execute(&mut transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+
+    for coupon in &archived {
+        let body = webhook_delivery::body(WebhookEvent::CouponExpired, coupon).map_err(|e| sqlx::Error::Decode(e.into()))?;
+        webhook_delivery::enqueue(WebhookEvent::CouponExpired, &body, &mut transaction).await?;
+    }
+
+    transaction.commit().await?;
+    return Ok(archived);
+}
+
+pub async fn get_all(filter: &ArchivedCouponFilter, limit: u32, pool: &MySqlPool) -> Result<Vec<ArchivedCoupon>, sqlx::Error> {
+    let sql = format!(r#"SELECT {}, date_archived
+        FROM coupons_archive
+        WHERE (? IS NULL OR code = ?)
+        ORDER BY archive_id DESC
+        LIMIT ?"#, COUPON_COLUMNS);
+    let coupons = sqlx::query_as::<_, ArchivedCoupon>(&sql)
+    .bind(&filter.code)
+    .bind(&filter.code)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(coupons);
+}
+
+/// Delete up to `limit` coupons archived before `cutoff`, returns how many were deleted.
+pub async fn delete_archived_before(cutoff: NaiveDateTime, limit: u32, pool: &MySqlPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM coupons_archive WHERE date_archived < ? ORDER BY archive_id LIMIT ?")
+    .bind(cutoff)
+    .bind(limit)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+    return Ok(result.rows_affected());
+}
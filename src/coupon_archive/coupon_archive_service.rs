@@ -0,0 +1,46 @@
+use super::model::{ArchivedCoupon, ArchivedCouponFilter, CouponArchiveError};
+use super::coupon_archive_repository;
+use crate::configuration::ArchiveSettings;
+use crate::coupon::coupon_store::CouponStore;
+use crate::job::{job_service, JobKind};
+use crate::scheduler::Scheduler;
+use chrono::{Duration, Utc};
+use sqlx::MySqlPool;
+
+
+/// Move the coupons expired for longer than `retention_days` to `coupons_archive`, one batch per transaction.
+/// A `coupon.expired` webhook is queued for each of them with the batch, and they are evicted from the cache of `store`. Returns how many were moved.
+pub async fn archive_expired(settings: &ArchiveSettings, store: &dyn CouponStore, pool: &MySqlPool) -> Result<u64, CouponArchiveError> {
+    let cutoff = Utc::now().naive_utc() - Duration::days(i64::from(settings.retention_days));
+    let mut archived = 0;
+    loop {
+        let batch = coupon_archive_repository::archive_expired(cutoff, settings.batch_size, pool).await
+            .map_err(|error| CouponArchiveError::UnexpectedError(error.into()))?;
+        let codes: Vec<String> = batch.iter().map(|coupon| coupon.code.clone()).collect();
+        store.evict_cached(&codes).await;
+        archived += batch.len() as u64;
+        if (batch.len() < settings.batch_size as usize){
+            return Ok(archived);
+        }
+    }
+}
+
+/// Queue an `archive_expired_coupons` job every `interval_seconds` (unless it is `0`), or on `schedules.archive_expired_coupons`.
+/// Every instance queues it, but not while one is already queued, so a single worker runs `archive_expired` at a time.
+pub fn register(scheduler: &mut Scheduler, settings: &ArchiveSettings, pool: MySqlPool) {
+    scheduler.register("archive_expired_coupons", settings.interval_seconds, move || {
+        let pool = pool.clone();
+        async move {
+            return job_service::enqueue_unless_queued(JobKind::ArchiveExpiredCoupons, &serde_json::json!({}), &pool).await
+                .map(|_| ())
+                .map_err(|error| format!("Failed to queue the archival of the expired coupons: {:?}", error));
+        }
+    });
+}
+
+pub async fn get_all(filter: &ArchivedCouponFilter, pool: &MySqlPool) -> Result<Vec<ArchivedCoupon>, CouponArchiveError> {
+    let limit = filter.limit().map_err(CouponArchiveError::ValidationError)?;
+    let coupons = coupon_archive_repository::get_all(filter, limit, pool).await
+        .map_err(|error| CouponArchiveError::UnexpectedError(error.into()))?;
+    return Ok(coupons);
+}
@@ -0,0 +1,7 @@
+pub mod coupon_archive_controller;
+pub mod coupon_archive_service;
+pub mod coupon_archive_repository;
+pub mod model;
+
+pub use coupon_archive_controller::*;
+pub use model::*;
@@ -0,0 +1,58 @@
+use actix_web::{
+    ResponseError,
+    http::{StatusCode},
+};
+use serde::{Serialize, Deserialize};
+use sqlx::types::chrono::{NaiveDateTime};
+
+
+pub const DEFAULT_LIMIT: u32 = 100;
+pub const MAX_LIMIT: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct ArchivedCoupon {
+    pub id: i32,
+    pub code: String,
+    pub discount: i32,
+    pub active: bool,
+    pub version: i32,
+    pub max_usage_count: Option<i32>,
+    pub expiration_date: Option<NaiveDateTime>,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_updated: Option<NaiveDateTime>,
+    pub date_archived: Option<NaiveDateTime>,
+}
+
+// Query string filters of `/admin/coupons-archive`, the last archived coupons are returned first
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ArchivedCouponFilter {
+    pub code: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl ArchivedCouponFilter {
+    pub fn limit(&self) -> Result<u32, String> {
+        return match self.limit {
+            None => Ok(DEFAULT_LIMIT),
+            Some(limit) if (limit == 0 || limit > MAX_LIMIT) => Err(format!("`limit` must be between 1 and {}.", MAX_LIMIT)),
+            Some(limit) => Ok(limit),
+        };
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CouponArchiveError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for CouponArchiveError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            CouponArchiveError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            CouponArchiveError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
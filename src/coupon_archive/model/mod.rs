@@ -0,0 +1,3 @@
+pub mod coupon_archive;
+
+pub use self::coupon_archive::*;
@@ -0,0 +1,72 @@
+use super::model::{CouponImportError, CouponImportOptions, MAX_IMPORT_BYTES};
+use super::coupon_import_service;
+use crate::envelope::Envelope;
+use actix_multipart::{Multipart, MultipartError};
+use actix_web::{
+    web, get, post, HttpMessage, HttpRequest, HttpResponse,
+    http::header,
+    web::{Bytes, BytesMut, Data},
+};
+use futures_util::{Stream, StreamExt};
+use sqlx::MySqlPool;
+
+
+/// The CSV is the raw body, with the options in the query string, or the `file` field of a `multipart/form-data` form,
+/// with the options as its other fields. It is read here rather than with `web::Bytes`, whose limit is `application.max_payload_bytes`.
+#[tracing::instrument( name = "Import coupons", skip(pool, http_request, body) )]
+#[post("/import")]
+pub async fn import_coupons(http_request: HttpRequest, options: web::Query<CouponImportOptions>, body: web::Payload, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponImportError> {
+    let (csv, options) = if (http_request.content_type().eq_ignore_ascii_case("multipart/form-data")) {
+        read_form(Multipart::new(http_request.headers(), body), options.into_inner()).await?
+    } else {
+        (read_file(body).await?, options.into_inner())
+    };
+
+    let job = coupon_import_service::start(csv, options, &pool).await?;
+    return Ok(HttpResponse::Accepted()
+        .insert_header((header::LOCATION, format!("/jobs/{}", job.id)))
+        .json(Envelope::new(&http_request, job)));
+}
+
+// the `file` and the options of the form, the options not sent are the ones of the query string
+async fn read_form(mut form: Multipart, mut options: CouponImportOptions) -> Result<(String, CouponImportOptions), CouponImportError> {
+    let invalid_form = |e: MultipartError| CouponImportError::ValidationError(format!("Failed to read the form: {}.", e));
+    let mut csv = None;
+    while let Some(field) = form.next().await {
+        let field = field.map_err(invalid_form)?;
+        match field.content_disposition().get_name().unwrap_or_default() {
+            "file" => csv = Some(read_file(field).await?),
+            "existing" => {
+                options.existing = read_file(field).await?.trim().parse().map_err(CouponImportError::ValidationError)?;
+            },
+            name => return Err(CouponImportError::ValidationError(format!("Unknown field `{}`, the form has a `file` and the options.", name))),
+        }
+    }
+    let csv = csv.ok_or(CouponImportError::ValidationError("The form has no `file` field.".to_string()))?;
+    return Ok((csv, options));
+}
+
+// the body or a field of the form, up to `MAX_IMPORT_BYTES`
+async fn read_file<S, E>(mut stream: S) -> Result<String, CouponImportError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let mut bytes = BytesMut::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| CouponImportError::ValidationError(format!("Failed to read the file: {}.", e)))?;
+        if (bytes.len() + chunk.len() > MAX_IMPORT_BYTES){
+            return Err(CouponImportError::PayloadTooLargeError(format!("The file is larger than {} bytes.", MAX_IMPORT_BYTES)));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    return String::from_utf8(bytes.to_vec())
+        .map_err(|_| CouponImportError::ValidationError("The file is not UTF-8.".to_string()));
+}
+
+#[tracing::instrument( name = "Get import job", skip(pool, http_request) )]
+#[get("/{id}")]
+pub async fn get_import_job(http_request: HttpRequest, id: web::Path<i64>, pool: Data::<MySqlPool>) -> Result<HttpResponse, CouponImportError> {
+    let job = coupon_import_service::get_job(id.into_inner(), &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, job)));
+}
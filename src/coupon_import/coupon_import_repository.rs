@@ -0,0 +1,97 @@
+use super::model::{CouponImport, CouponImportRowError};
+use sqlx::MySqlPool;
+
+
+const SELECT_IMPORT: &str = "SELECT id, total_rows, processed_rows, imported_rows, rejected_rows, date_created, date_finished FROM coupon_imports";
+
+/// Returns the id of the inserted import.
+pub async fn insert(csv: &str, total_rows: i32, pool: &MySqlPool) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query("INSERT INTO coupon_imports (csv, total_rows) VALUES (?, ?)")
+    .bind(csv)
+    .bind(total_rows)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(result.last_insert_id() as i64);
+}
+
+pub async fn get_by_id(id: i64, pool: &MySqlPool) -> Result<Option<CouponImport>, sqlx::Error> {
+    let import = sqlx::query_as::<_, CouponImport>(&format!("{} WHERE id = ?", SELECT_IMPORT))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(import);
+}
+
+/// The file of the import, empty once it is finished.
+pub async fn get_csv(id: i64, pool: &MySqlPool) -> Result<Option<String>, sqlx::Error> {
+    let csv = sqlx::query_scalar::<_, String>("SELECT csv FROM coupon_imports WHERE id = ?")
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(csv);
+}
+
+/// Save the rows processed since the last call, with the errors of the rejected ones.
+/// Once `finished`, the file is emptied.
+pub async fn record_progress(import: &CouponImport, errors: &[CouponImportRowError], finished: bool, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    for error in errors {
+        sqlx::query("INSERT INTO coupon_import_errors (import_id, line, code, error) VALUES (?, ?, ?, ?)")
+        .bind(import.id)
+        .bind(error.line)
+        .bind(&error.code)
+        .bind(&error.error)
+        .execute(&mut transaction)
+        .await
+        .map_err(|error| {
+            tracing::error!("Failed to execute insert query: {:?}", error);
+            error
+        })?;
+    }
+    sqlx::query(
+        r#"
+            UPDATE coupon_imports SET processed_rows = ?, imported_rows = ?, rejected_rows = ?,
+            date_finished = IF(?, CURRENT_TIMESTAMP, date_finished), csv = IF(?, '', csv)
+            WHERE id = ?
+        "#)
+    .bind(import.processed_rows)
+    .bind(import.imported_rows)
+    .bind(import.rejected_rows)
+    .bind(finished)
+    .bind(finished)
+    .bind(import.id)
+    .execute(&mut transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    transaction.commit().await?;
+    return Ok(());
+}
+
+/// The errors of the first rejected rows, in the order of the file.
+pub async fn get_errors(import_id: i64, limit: u32, pool: &MySqlPool) -> Result<Vec<CouponImportRowError>, sqlx::Error> {
+    let errors = sqlx::query_as::<_, CouponImportRowError>("SELECT line, code, error FROM coupon_import_errors WHERE import_id = ? ORDER BY line LIMIT ?")
+    .bind(import_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(errors);
+}
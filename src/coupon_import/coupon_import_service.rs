@@ -0,0 +1,183 @@
+use super::model::{
+    CouponImport, CouponImportError, CouponImportJob, CouponImportOptions, CouponImportRowError, ExistingCoupons, ImportCouponsJob,
+    MAX_LISTED_ERRORS,
+};
+use super::coupon_import_repository;
+use crate::coupon::{coupon_service, coupon_store::CouponStore, CouponError, CouponInsertRequest, CouponUpdateRequest};
+use crate::job::{job_service, JobError, JobKind};
+use anyhow::anyhow;
+use csv::StringRecord;
+use sqlx::MySqlPool;
+
+
+// `max_usage_count` and `expiration_date` are optional, the other columns (e.g. of an export) are ignored
+const REQUIRED_COLUMNS: [&str; 3] = ["code", "discount", "active"];
+// how often the progress is saved
+const CHUNK_ROWS: i32 = 100;
+
+/// Save the file and queue an `import_coupons` job for it. Returns the job, `pending`.
+pub async fn start(csv: String, options: CouponImportOptions, pool: &MySqlPool) -> Result<CouponImportJob, CouponImportError> {
+    let total_rows = count_rows(&csv).map_err(CouponImportError::ValidationError)?;
+    let import_id = coupon_import_repository::insert(&csv, total_rows, pool).await
+        .map_err(|error| CouponImportError::UnexpectedError(error.into()))?;
+    let job_id = job_service::enqueue(JobKind::ImportCoupons, &ImportCouponsJob { import_id, options }, pool).await
+        .map_err(|error| CouponImportError::UnexpectedError(error.into()))?;
+    return get_job(job_id as i64, pool).await;
+}
+
+// the rows of the file without the header, which must have the required columns
+fn count_rows(csv: &str) -> Result<i32, String> {
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let headers = reader.headers().map_err(|e| format!("Failed to read the header row: {}.", e))?;
+    for column in REQUIRED_COLUMNS {
+        if (!headers.iter().any(|header| header == column)){
+            return Err(format!("The header row has no `{}` column.", column));
+        }
+    }
+    // the rows that can't be read are counted too, they are rejected by the import
+    let rows = reader.records().count();
+    if (rows == 0){
+        return Err("The file has no rows.".to_string());
+    }
+    return i32::try_from(rows).map_err(|_| "The file has too many rows.".to_string());
+}
+
+/// Import the rows of the `import_coupons` job not processed yet, each one inserted like `POST /coupon`,
+/// or upserted like `PUT /coupon/code/{code}` when the existing coupons are updated.
+/// The rejected rows are recorded and skipped. The progress is saved every `CHUNK_ROWS` rows and before failing,
+/// a retried job resumes from there.
+pub async fn run(payload: &str, store: &dyn CouponStore, pool: &MySqlPool) -> Result<(), String> {
+    let job: ImportCouponsJob = serde_json::from_str(payload)
+        .map_err(|e| format!("Invalid `import_coupons` payload: {}", e))?;
+    let mut import = coupon_import_repository::get_by_id(job.import_id, pool).await
+        .map_err(|e| e.to_string())?
+        .ok_or(format!("Import `{}` not found.", job.import_id))?;
+    if (import.date_finished.is_some()){
+        return Ok(());
+    }
+    let csv = coupon_import_repository::get_csv(import.id, pool).await
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let headers = reader.headers().map_err(|e| format!("Failed to read the header row: {}.", e))?.clone();
+    let mut errors = Vec::new();
+    for record in reader.records().skip(import.processed_rows as usize) {
+        let error = match import_row(record, &headers, job.options, store, pool).await {
+            Ok(error) => error,
+            Err(error) => {
+                record_progress(&import, &errors, false, pool).await?;
+                return Err(error);
+            },
+        };
+        import.processed_rows += 1;
+        match error {
+            Some(error) => {
+                import.rejected_rows += 1;
+                errors.push(error);
+            },
+            None => import.imported_rows += 1,
+        }
+        if (import.processed_rows % CHUNK_ROWS == 0){
+            record_progress(&import, &errors, false, pool).await?;
+            errors.clear();
+        }
+    }
+    record_progress(&import, &errors, true, pool).await?;
+    tracing::info!("Imported {} of the {} coupons of import {}, {} rejected.", import.imported_rows, import.total_rows, import.id, import.rejected_rows);
+    return Ok(());
+}
+
+async fn record_progress(import: &CouponImport, errors: &[CouponImportRowError], finished: bool, pool: &MySqlPool) -> Result<(), String> {
+    return coupon_import_repository::record_progress(import, errors, finished, pool).await
+        .map_err(|e| format!("Failed to save the progress of import {}: {}", import.id, e));
+}
+
+// the error of the row, `None` when it was imported, fails when the coupon couldn't be inserted for another reason than the row
+async fn import_row(record: Result<StringRecord, csv::Error>, headers: &StringRecord, options: CouponImportOptions, store: &dyn CouponStore, pool: &MySqlPool) -> Result<Option<CouponImportRowError>, String> {
+    let record = match record {
+        Ok(record) => record,
+        Err(e) => {
+            let line = e.position().map_or(0, |position| position.line() as i64);
+            return Ok(Some(CouponImportRowError { line, code: None, error: format!("Failed to read the row: {}.", e) }));
+        },
+    };
+    let line = record.position().map_or(0, |position| position.line() as i64);
+    let code = headers.iter().position(|header| header == "code")
+        .and_then(|index| record.get(index))
+        .map(|code| code.to_string());
+    let request: CouponInsertRequest = match record.deserialize(Some(headers)) {
+        Ok(request) => request,
+        Err(e) => return Ok(Some(CouponImportRowError { line, code, error: format!("Invalid row: {}.", e) })),
+    };
+    let result = match options.existing {
+        ExistingCoupons::Reject => coupon_service::insert(request, store, pool).await.map(|_| ()),
+        ExistingCoupons::Update => {
+            let update = CouponUpdateRequest {
+                discount: request.discount,
+                active: request.active,
+                max_usage_count: request.max_usage_count,
+                expiration_date: request.expiration_date,
+                version: None,
+            };
+            coupon_service::upsert(request.code, update, store, pool).await.map(|_| ())
+        },
+    };
+    return match result {
+        Ok(()) => Ok(None),
+        Err(error @ (CouponError::ValidationError(_) | CouponError::AlreadyExistsError(_))) => {
+            Ok(Some(CouponImportRowError { line, code, error: error.to_string() }))
+        },
+        Err(error) => Err(format!("Failed to import the line {}: {}", line, error)),
+    };
+}
+
+/// The progress of the import of the job, `NotFoundError` for the other jobs.
+pub async fn get_job(job_id: i64, pool: &MySqlPool) -> Result<CouponImportJob, CouponImportError> {
+    let job = job_service::get_by_id(job_id, pool).await
+        .map_err(|error| match error {
+            JobError::NotFoundError(message) => CouponImportError::NotFoundError(message),
+            error => CouponImportError::UnexpectedError(error.into()),
+        })?;
+    if (job.kind != JobKind::ImportCoupons.as_str()){
+        return Err(CouponImportError::NotFoundError(format!("Job with id `{}` is not an import.", job_id)));
+    }
+    let payload: ImportCouponsJob = serde_json::from_str(&job.payload)
+        .map_err(|e| CouponImportError::UnexpectedError(anyhow!(format!("Invalid `import_coupons` payload: {}", e))))?;
+    let import = coupon_import_repository::get_by_id(payload.import_id, pool).await
+        .map_err(|error| CouponImportError::UnexpectedError(error.into()))?
+        .ok_or(CouponImportError::UnexpectedError(anyhow!(format!("Import `{}` of job `{}` not found.", payload.import_id, job_id))))?;
+    let errors = coupon_import_repository::get_errors(import.id, MAX_LISTED_ERRORS, pool).await
+        .map_err(|error| CouponImportError::UnexpectedError(error.into()))?;
+
+    return Ok(CouponImportJob {
+        id: job.id,
+        status: job.status,
+        attempts: job.attempts,
+        options: payload.options,
+        last_error: job.last_error,
+        total_rows: import.total_rows,
+        processed_rows: import.processed_rows,
+        imported_rows: import.imported_rows,
+        rejected_rows: import.rejected_rows,
+        errors,
+        date_created: import.date_created,
+        date_finished: import.date_finished,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::count_rows;
+    use claim::assert_err;
+
+    #[test]
+    fn files_need_the_required_columns_and_rows(){
+        assert_eq!(count_rows("code,discount,active\nTEN,10,true\nTWENTY,20,false\n"), Ok(2));
+        // the rows that can't be read are rejected by the import
+        assert_eq!(count_rows("code,discount,active,max_usage_count\nTEN,10\n"), Ok(1));
+        assert_err!(count_rows("code,discount\nTEN,10\n"));
+        assert_err!(count_rows("code,discount,active\n"));
+        assert_err!(count_rows(""));
+    }
+}
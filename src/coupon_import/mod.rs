@@ -0,0 +1,7 @@
+pub mod coupon_import_controller;
+pub mod coupon_import_service;
+pub mod coupon_import_repository;
+pub mod model;
+
+pub use coupon_import_controller::*;
+pub use model::*;
@@ -0,0 +1,115 @@
+use actix_web::{
+    ResponseError,
+    http::{StatusCode},
+};
+use serde::{Serialize, Deserialize};
+use sqlx::types::chrono::{NaiveDateTime};
+
+
+// the size of a `MEDIUMTEXT`
+pub const MAX_IMPORT_BYTES: usize = 16 * 1024 * 1024 - 1;
+// the errors listed by `/jobs/{id}`, the first ones of the file
+pub const MAX_LISTED_ERRORS: u32 = 100;
+
+/// What the import does with the codes that already exist.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingCoupons {
+    // the rows are rejected
+    #[default]
+    Reject,
+    // the coupons are replaced, like `PUT /coupon/code/{code}`
+    Update,
+}
+
+impl std::str::FromStr for ExistingCoupons {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "reject" => Ok(Self::Reject),
+            "update" => Ok(Self::Update),
+            other => Err(format!("Invalid `existing` `{}`, it must be `reject` or `update`.", other)),
+        };
+    }
+}
+
+/// Options of `POST /coupon/import`, in the query string or as fields of a multipart form.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct CouponImportOptions {
+    #[serde(default)]
+    pub existing: ExistingCoupons,
+}
+
+/// Payload of the `import_coupons` jobs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportCouponsJob {
+    pub import_id: i64,
+    // absent from the jobs queued before the options
+    #[serde(default)]
+    pub options: CouponImportOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct CouponImport {
+    pub id: i64,
+    pub total_rows: i32,
+    pub processed_rows: i32,
+    pub imported_rows: i32,
+    pub rejected_rows: i32,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_finished: Option<NaiveDateTime>,
+}
+
+/// A row of the file that was not imported.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct CouponImportRowError {
+    pub line: i64,
+    // `None` when the row couldn't be read
+    pub code: Option<String>,
+    pub error: String,
+}
+
+/// The progress of an import, by `GET /jobs/{id}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CouponImportJob {
+    // of the job
+    pub id: i64,
+    // `pending`, `running`, `succeeded` or `failed`, like the job
+    pub status: String,
+    pub attempts: i32,
+    pub options: CouponImportOptions,
+    // why the last attempt failed, e.g. the database was not available, not the rejected rows
+    pub last_error: Option<String>,
+    pub total_rows: i32,
+    pub processed_rows: i32,
+    pub imported_rows: i32,
+    pub rejected_rows: i32,
+    // the first `MAX_LISTED_ERRORS` of the rejected rows
+    pub errors: Vec<CouponImportRowError>,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_finished: Option<NaiveDateTime>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CouponImportError {
+    #[error("{0}")]
+    NotFoundError(String),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    PayloadTooLargeError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for CouponImportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            CouponImportError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            CouponImportError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            CouponImportError::PayloadTooLargeError(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            CouponImportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod coupon_import;
+
+pub use self::coupon_import::*;
@@ -0,0 +1,149 @@
+use crate::{metrics::Metrics, rate_limit::client_api_key};
+use actix_web::{
+    Error,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue, LINK, USER_AGENT},
+    web::Data,
+};
+use chrono::NaiveDate;
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+
+/// Marks a handler as deprecated, e.g. `#[get("/old", wrap = "Deprecated::since(\"2023-02-01\").sunset(\"2023-08-01\").successor(\"/new\")")]`.
+/// Its responses get the `Deprecation` header (RFC 9745), the `Sunset` one (RFC 8594) once a removal date is set
+/// and a `Link` to the route replacing it. Each call is logged as a warning with the client, and counted per route
+/// on `GET /metrics` (`http_deprecated_requests_total`), so the clients left can be found before the route is removed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deprecated {
+    since: NaiveDate,
+    sunset: Option<NaiveDate>,
+    successor: Option<&'static str>,
+}
+
+// the dates are written in the code, a typo must stop the app from starting
+fn parse_date(date: &str) -> NaiveDate {
+    return NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .unwrap_or_else(|_| panic!("Invalid deprecation date `{}`, it must be e.g. `2023-02-01`.", date));
+}
+
+impl Deprecated {
+    /// Deprecated since `date`, e.g. `2023-02-01`.
+    pub fn since(date: &str) -> Self {
+        return Self { since: parse_date(date), sunset: None, successor: None };
+    }
+
+    /// The date the route will be removed, e.g. `2023-08-01`.
+    pub fn sunset(self, date: &str) -> Self {
+        return Self { sunset: Some(parse_date(date)), ..self };
+    }
+
+    /// The path of the route replacing it.
+    pub fn successor(self, path: &'static str) -> Self {
+        return Self { successor: Some(path), ..self };
+    }
+
+    fn headers(&self) -> Vec<(HeaderName, String)> {
+        let since = self.since.and_hms_opt(0, 0, 0).unwrap();
+        let mut headers = vec![(HeaderName::from_static("deprecation"), format!("@{}", since.timestamp()))];
+        if let Some(sunset) = self.sunset {
+            let sunset = sunset.and_hms_opt(0, 0, 0).unwrap();
+            headers.push((HeaderName::from_static("sunset"), sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string()));
+        }
+        if let Some(successor) = self.successor {
+            headers.push((LINK, format!("<{}>; rel=\"successor-version\"", successor)));
+        }
+        return headers;
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Deprecated
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = DeprecatedMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(DeprecatedMiddleware { service: Rc::new(service), headers: Rc::new(self.headers()) }));
+    }
+}
+
+pub struct DeprecatedMiddleware<S> {
+    service: Rc<S>,
+    headers: Rc<Vec<(HeaderName, String)>>,
+}
+
+impl<S, B> Service<ServiceRequest> for DeprecatedMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let headers = self.headers.clone();
+        let method = request.method().to_string();
+        let route = request.match_pattern().unwrap_or_else(|| request.path().to_string());
+        let count = request.app_data::<Data<Metrics>>().map(|metrics| metrics.record_deprecated(&method, &route));
+        let user_agent = request.headers().get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("-")
+            .to_string();
+        tracing::warn!(
+            client = %client_api_key(&request),
+            user_agent = %user_agent,
+            count = ?count,
+            "Deprecated route `{} {}` called.", method, route
+        );
+        return Box::pin(async move {
+            let mut response = service.call(request).await?;
+            for (name, value) in headers.iter() {
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    // appended, the paginated responses have their own `Link`
+                    response.headers_mut().append(name.clone(), value);
+                }
+            }
+            return Ok(response);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Deprecated;
+
+    #[test]
+    fn deprecated_routes_announce_their_sunset_and_successor(){
+        let headers: Vec<(String, String)> = Deprecated::since("2023-02-01").sunset("2023-08-01").successor("/coupon/{id_or_code}")
+            .headers()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+
+        assert_eq!(headers, vec![
+            ("deprecation".to_string(), "@1675209600".to_string()),
+            ("sunset".to_string(), "Tue, 01 Aug 2023 00:00:00 GMT".to_string()),
+            ("link".to_string(), "</coupon/{id_or_code}>; rel=\"successor-version\"".to_string()),
+        ]);
+        assert_eq!(Deprecated::since("2023-02-01").headers().len(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_dates_are_rejected(){
+        Deprecated::since("01/02/2023");
+    }
+}
@@ -0,0 +1,39 @@
+use crate::coupon::model::PageMeta;
+use crate::request_id;
+use actix_web::HttpRequest;
+use serde::{Serialize, Deserialize};
+
+
+/// Uniform shape of every response body: the payload in `data` plus request metadata in `meta`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Envelope<T> {
+    pub data: T,
+    pub meta: Meta,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Meta {
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<PageMeta>,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(request: &HttpRequest, data: T) -> Self {
+        return Self { data, meta: Meta::new(request) };
+    }
+
+    pub fn with_pagination(request: &HttpRequest, data: T, pagination: PageMeta) -> Self {
+        let mut envelope = Self::new(request, data);
+        envelope.meta.pagination = Some(pagination);
+        return envelope;
+    }
+}
+
+impl Meta {
+    pub fn new(request: &HttpRequest) -> Self {
+        // the same request id as in the logs and the `X-Request-ID` header
+        let request_id = request_id::get(request);
+        return Self { request_id, pagination: None };
+    }
+}
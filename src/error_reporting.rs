@@ -0,0 +1,28 @@
+use crate::configuration::SentrySettings;
+#[cfg(feature = "sentry")]
+use secrecy::ExposeSecret;
+
+
+/// Sends the events not sent yet to Sentry when dropped, keep it until exiting.
+pub struct ErrorReportingGuard(#[cfg(feature = "sentry")] Option<sentry::ClientInitGuard>);
+
+/// Report the errors to Sentry when `settings` is set (with the `sentry` feature): the panics, the responses
+/// with a 500 (e.g. `CouponError::UnexpectedError` and `CouponError::InternalError`) with the request they failed,
+/// see `startup::run`, and the `error` events with the spans they are in, see `telemetry::get_subscriber`.
+pub fn init(settings: Option<&SentrySettings>) -> ErrorReportingGuard {
+    #[cfg(feature = "sentry")]
+    return ErrorReportingGuard(settings.map(|settings| {
+        sentry::init((settings.dsn.expose_secret().as_str(), sentry::ClientOptions {
+            release: sentry::release_name!(),
+            environment: settings.environment.clone().map(Into::into),
+            sample_rate: settings.sample_rate,
+            ..Default::default()
+        }))
+    }));
+    // without the feature `Settings::validate()` rejects `sentry`, so there is nothing to report to
+    #[cfg(not(feature = "sentry"))]
+    {
+        let _ = settings;
+        return ErrorReportingGuard();
+    }
+}
@@ -0,0 +1,75 @@
+use super::EventPublisher;
+use crate::configuration::AmqpSettings;
+use async_trait::async_trait;
+use lapin::{
+    BasicProperties, Channel, Connection, ConnectionProperties, ExchangeKind,
+    options::{BasicPublishOptions, ConfirmSelectOptions, ExchangeDeclareOptions},
+    types::{AMQPValue, FieldTable},
+};
+use secrecy::{ExposeSecret, Secret};
+use tokio::sync::Mutex;
+
+
+/// Publishes to the `amqp.exchange` (a durable `topic` exchange, declared on startup) with the event name as
+/// the routing key, e.g. `coupon.created`, and the coupon in the `coupon` header. The messages are persistent
+/// and confirmed by the broker.
+pub struct AmqpPublisher {
+    uri: Secret<String>,
+    exchange: String,
+    // the connection is kept with its channel, opened again when it was lost
+    connection: Mutex<Option<(Connection, Channel)>>,
+}
+
+impl AmqpPublisher {
+    /// Fails when the broker can't be reached, so a wrong `amqp.uri` stops the server from starting.
+    pub async fn new(settings: &AmqpSettings) -> Result<Self, std::io::Error> {
+        let connection = connect(&settings.uri, &settings.exchange).await
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to connect to the AMQP broker: {}.", e)))?;
+        return Ok(Self {
+            uri: settings.uri.clone(),
+            exchange: settings.exchange.clone(),
+            connection: Mutex::new(Some(connection)),
+        });
+    }
+}
+
+#[async_trait]
+impl EventPublisher for AmqpPublisher {
+    async fn send(&self, event: &str, key: &str, payload: &str) -> Result<(), String> {
+        let channel = {
+            let mut connection = self.connection.lock().await;
+            let connected = connection.as_ref().map(|(_, channel)| channel.status().connected()).unwrap_or(false);
+            if (!connected){
+                *connection = Some(connect(&self.uri, &self.exchange).await.map_err(|e| format!("Failed to connect to the AMQP broker: {}", e))?);
+            }
+            match connection.as_ref() {
+                Some((_, channel)) => channel.clone(),
+                None => return Err("Not connected to the AMQP broker.".to_string()),
+            }
+        };
+
+        let mut headers = FieldTable::default();
+        headers.insert("coupon".into(), AMQPValue::LongString(key.into()));
+        let properties = BasicProperties::default()
+            .with_content_type("application/json".into())
+            // persistent
+            .with_delivery_mode(2)
+            .with_headers(headers);
+        let confirmation = channel.basic_publish(&self.exchange, event, BasicPublishOptions::default(), payload.as_bytes(), properties).await
+            .map_err(|e| e.to_string())?
+            .await
+            .map_err(|e| e.to_string())?;
+        if (confirmation.is_nack()){
+            return Err("The broker rejected the message.".to_string());
+        }
+        return Ok(());
+    }
+}
+
+async fn connect(uri: &Secret<String>, exchange: &str) -> Result<(Connection, Channel), lapin::Error> {
+    let connection = Connection::connect(uri.expose_secret(), ConnectionProperties::default()).await?;
+    let channel = connection.create_channel().await?;
+    channel.confirm_select(ConfirmSelectOptions::default()).await?;
+    channel.exchange_declare(exchange, ExchangeKind::Topic, ExchangeDeclareOptions { durable: true, ..Default::default() }, FieldTable::default()).await?;
+    return Ok((connection, channel));
+}
@@ -0,0 +1,47 @@
+use super::EventPublisher;
+use crate::configuration::KafkaSettings;
+use async_trait::async_trait;
+use rdkafka::{
+    ClientConfig,
+    message::OwnedHeaders,
+    producer::{FutureProducer, FutureRecord},
+};
+use std::time::Duration;
+
+
+/// Publishes to the `kafka.topic`, keyed by the coupon, with the event name in the `event` header.
+/// The producer connects in the background, the events sent until then are queued.
+pub struct KafkaPublisher {
+    producer: FutureProducer,
+    topic: String,
+    message_timeout: Duration,
+}
+
+impl KafkaPublisher {
+    pub fn new(settings: &KafkaSettings) -> Result<Self, std::io::Error> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", &settings.brokers)
+            .set("client.id", "coupon-api")
+            .set("message.timeout.ms", settings.message_timeout_milliseconds.to_string())
+            .create()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Failed to create the Kafka producer: {}.", e)))?;
+        return Ok(Self {
+            producer,
+            topic: settings.topic.clone(),
+            message_timeout: Duration::from_millis(settings.message_timeout_milliseconds),
+        });
+    }
+}
+
+#[async_trait]
+impl EventPublisher for KafkaPublisher {
+    async fn send(&self, event: &str, key: &str, payload: &str) -> Result<(), String> {
+        let record = FutureRecord::to(&self.topic)
+            .key(key)
+            .payload(payload)
+            .headers(OwnedHeaders::new().add("event", event));
+        return self.producer.send(record, self.message_timeout).await
+            .map(|_| ())
+            .map_err(|(error, _)| error.to_string());
+    }
+}
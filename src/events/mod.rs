@@ -0,0 +1,121 @@
+#[cfg(feature = "amqp")]
+pub mod amqp;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod outbox;
+pub mod stream;
+
+use crate::configuration::Settings;
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+
+static PUBLISHER: OnceCell<Box<dyn EventPublisher>> = OnceCell::new();
+
+/// Where the coupon events are published, selected by the configured `kafka` or `amqp` settings,
+/// each one behind its feature: `kafka::KafkaPublisher` or `amqp::AmqpPublisher`.
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    /// Send the JSON `EventMessage` of the `event`, returning once the broker acknowledged it.
+    /// `key` is the coupon it is about, so its events can be kept in order.
+    async fn send(&self, event: &str, key: &str, payload: &str) -> Result<(), String>;
+}
+
+/// The changes of the coupons, published once committed.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CouponEvent {
+    #[serde(rename = "coupon.created")]
+    Created,
+    #[serde(rename = "coupon.updated")]
+    Updated,
+    #[serde(rename = "coupon.deleted")]
+    Deleted,
+}
+
+impl CouponEvent {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            CouponEvent::Created => "coupon.created",
+            CouponEvent::Updated => "coupon.updated",
+            CouponEvent::Deleted => "coupon.deleted",
+        };
+    }
+}
+
+/// Value of the messages, in JSON, the same for every publisher.
+#[derive(Serialize, Debug)]
+pub struct EventMessage<'a, T: Serialize> {
+    pub event: CouponEvent,
+    pub created_at: NaiveDateTime,
+    pub data: &'a T,
+}
+
+/// An event ready to be sent, as stored in the `outbox` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEvent {
+    pub event: String,
+    pub key: String,
+    pub payload: String,
+}
+
+impl PendingEvent {
+    pub fn new<T: Serialize>(event: CouponEvent, key: &str, data: &T) -> Result<Self, serde_json::Error> {
+        let message = EventMessage { event, created_at: Utc::now().naive_utc(), data };
+        return Ok(Self { event: event.as_str().to_string(), key: key.to_string(), payload: serde_json::to_string(&message)? });
+    }
+}
+
+/// Create the publisher of `send`, when `kafka` or `amqp` is set (with its feature).
+/// Without the feature `Settings::validate()` rejects them, so there is nothing to publish to.
+pub async fn init(settings: &Settings) -> Result<(), std::io::Error> {
+    let publisher: Option<Box<dyn EventPublisher>> = match (&settings.kafka, &settings.amqp) {
+        #[cfg(feature = "kafka")]
+        (Some(kafka), _) => Some(Box::new(kafka::KafkaPublisher::new(kafka)?)),
+        #[cfg(feature = "amqp")]
+        (_, Some(amqp)) => Some(Box::new(amqp::AmqpPublisher::new(amqp).await?)),
+        _ => None,
+    };
+    if let Some(publisher) = publisher {
+        // already set when the application is built again in the same process, e.g. by the tests
+        let _ = PUBLISHER.set(publisher);
+    }
+    return Ok(());
+}
+
+/// If a publisher is configured, nothing is published otherwise.
+pub fn is_enabled() -> bool {
+    return PUBLISHER.get().is_some();
+}
+
+/// Send the event with the configured publisher, if any, for `outbox::relay`.
+pub async fn send(event: &PendingEvent) -> Result<(), String> {
+    return match PUBLISHER.get() {
+        Some(publisher) => publisher.send(&event.event, &event.key, &event.payload).await,
+        None => Ok(()),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CouponEvent, EventMessage, PendingEvent};
+
+    #[test]
+    fn the_event_name_is_serialized_as_published(){
+        let message = EventMessage { event: CouponEvent::Deleted, created_at: chrono::NaiveDateTime::default(), data: &serde_json::json!({"id": 1}) };
+        let message = serde_json::to_value(&message).unwrap();
+        assert_eq!(message["event"], CouponEvent::Deleted.as_str());
+        assert_eq!(message["data"]["id"], 1);
+    }
+
+    #[test]
+    fn pending_events_hold_the_message_as_published(){
+        let event = PendingEvent::new(CouponEvent::Created, "CODE10", &serde_json::json!({"code": "CODE10"})).unwrap();
+        assert_eq!(event.event, CouponEvent::Created.as_str());
+        assert_eq!(event.key, "CODE10");
+        let message: serde_json::Value = serde_json::from_str(&event.payload).unwrap();
+        assert_eq!(message["event"], CouponEvent::Created.as_str());
+        assert_eq!(message["data"]["code"], "CODE10");
+    }
+}
@@ -0,0 +1,156 @@
+use super::{self as events, PendingEvent};
+use crate::configuration::OutboxSettings;
+use sqlx::{FromRow, MySqlConnection, MySqlPool};
+use sqlx::types::chrono::{NaiveDateTime, Utc};
+use std::time::Duration;
+
+
+#[derive(FromRow, Debug)]
+struct OutboxRow {
+    id: i64,
+    event: String,
+    event_key: String,
+    payload: String,
+}
+
+/// Write the event in the `outbox` table, on the connection of the transaction of the change:
+/// it is only published if the change is committed, and published even if the server stops right after.
+pub async fn insert(event: &PendingEvent, conn: &mut MySqlConnection) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO outbox (event, event_key, payload) VALUES (?, ?, ?)")
+    .bind(&event.event)
+    .bind(&event.key)
+    .bind(&event.payload)
+    .execute(&mut *conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+/// Publish the events of the `outbox` for as long as the server runs, unless `outbox.enabled` is `false`.
+/// The table is polled every `outbox.poll_interval_milliseconds` while it has nothing to publish.
+/// Without a publisher (e.g. `kafka` was removed since they were written) the events are marked published without being sent,
+/// rather than kept forever.
+pub fn spawn_relay(settings: OutboxSettings, pool: MySqlPool) {
+    if (!settings.enabled){
+        return;
+    }
+    tokio::spawn(async move {
+        let poll_interval = Duration::from_millis(settings.poll_interval_milliseconds);
+        loop {
+            match relay(&settings, &pool).await {
+                Ok(published) if (published == settings.batch_size) => {},
+                Ok(_) => tokio::time::sleep(poll_interval).await,
+                Err(error) => {
+                    tracing::error!("Failed to publish the events of the outbox: {}", error);
+                    tokio::time::sleep(poll_interval).await;
+                },
+            }
+        }
+    });
+}
+
+/// Publish up to `batch_size` events, oldest first, and mark them published. Returns how many were published.
+/// The events are claimed for `lease_seconds` before they are sent, so the relays of the other instances skip them
+/// without a transaction held open during the calls to the broker. An event that fails stops the batch and the rest
+/// is released, so the events of a coupon stay in order: it is published again on the next one, at least once.
+pub async fn relay(settings: &OutboxSettings, pool: &MySqlPool) -> Result<u32, String> {
+    let now = Utc::now().naive_utc();
+    let locked_until = now + chrono::Duration::seconds(settings.lease_seconds as i64);
+    let rows = claim(settings.batch_size, now, locked_until, pool).await.map_err(|e| e.to_string())?;
+
+    let mut published = 0;
+    for (index, row) in rows.iter().enumerate() {
+        let event = PendingEvent { event: row.event.clone(), key: row.event_key.clone(), payload: row.payload.clone() };
+        if let Err(error) = events::send(&event).await {
+            let unpublished: Vec<i64> = rows[index..].iter().map(|row| row.id).collect();
+            if let Err(release_error) = release(&unpublished, pool).await {
+                tracing::error!("Failed to release the events of the outbox, they are published once their lease expired: {:?}", release_error);
+            }
+            return Err(format!("`{}` event of coupon `{}`: {}", event.event, event.key, error));
+        }
+        sqlx::query("UPDATE outbox SET published_at = ?, locked_until = NULL WHERE id = ?")
+        .bind(Utc::now().naive_utc())
+        .bind(row.id)
+        .execute(pool)
+        .await
+        .map_err(|error| {
+            tracing::error!("Failed to execute update query: {:?}", error);
+            error.to_string()
+        })?;
+        published += 1;
+    }
+    return Ok(published);
+}
+
+// the events not published, not claimed or with an expired lease, marked claimed until `locked_until`
+async fn claim(batch_size: u32, now: NaiveDateTime, locked_until: NaiveDateTime, pool: &MySqlPool) -> Result<Vec<OutboxRow>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let rows = sqlx::query_as::<_, OutboxRow>(
+        r#"SELECT id, event, event_key, payload
+            FROM outbox
+            WHERE published_at IS NULL
+            AND (locked_until IS NULL OR locked_until < ?)
+            ORDER BY id
+            LIMIT ?
+            FOR UPDATE SKIP LOCKED"#)
+    .bind(now)
+    .bind(batch_size)
+    .fetch_all(&mut transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    if (rows.is_empty()){
+        return Ok(rows);
+    }
+
+    let placeholders = vec!["?"; rows.len()].join(", ");
+    let sql = format!("UPDATE outbox SET locked_until = ? WHERE id IN ({})", placeholders);
+    let mut query = sqlx::query(&sql).bind(locked_until);
+    for row in &rows {
+        query = query.bind(row.id);
+    }
+    query.execute(&mut transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    transaction.commit().await?;
+    return Ok(rows);
+}
+
+// claimed but not published, so the next relay takes them right away
+async fn release(ids: &[i64], pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    let placeholders = vec!["?"; ids.len()].join(", ");
+    let sql = format!("UPDATE outbox SET locked_until = NULL WHERE id IN ({})", placeholders);
+    let mut query = sqlx::query(&sql);
+    for id in ids {
+        query = query.bind(id);
+    }
+    query.execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+/// Delete the events published before the cutoff, for `retention`. The ones not published are kept.
+pub async fn delete_published_before(cutoff: NaiveDateTime, limit: u32, pool: &MySqlPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM outbox WHERE published_at < ? ORDER BY id LIMIT ?")
+    .bind(cutoff)
+    .bind(limit)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+    return Ok(result.rows_affected());
+}
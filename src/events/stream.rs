@@ -0,0 +1,94 @@
+use super::{CouponEvent, EventMessage};
+use actix_web::web::Bytes;
+use chrono::Utc;
+use futures_util::stream::{self, Stream};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+
+// how many events a slow subscriber can fall behind before it misses some
+const CAPACITY: usize = 256;
+// a comment is sent when there was no event for that long, so the proxies don't close the idle connections
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+static CHANNEL: Lazy<broadcast::Sender<StreamedEvent>> = Lazy::new(|| broadcast::channel(CAPACITY).0);
+
+/// An event of `GET /coupon/events`, the `data` is the JSON `EventMessage`, as published to the brokers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamedEvent {
+    pub event: &'static str,
+    pub data: String,
+}
+
+impl StreamedEvent {
+    // `event: coupon.created\ndata: {...}\n\n`
+    fn to_sse(&self) -> Bytes {
+        return Bytes::from(format!("event: {}\ndata: {}\n\n", self.event, self.data));
+    }
+}
+
+/// Send the committed change to the subscribers of this instance, if any.
+/// Unlike the events of the `outbox` it doesn't need a broker, but the subscribers of the other instances don't get it.
+pub fn broadcast<T: Serialize>(event: CouponEvent, data: &T) {
+    if (CHANNEL.receiver_count() == 0){
+        return;
+    }
+    let message = EventMessage { event, created_at: Utc::now().naive_utc(), data };
+    match serde_json::to_string(&message) {
+        // fails only when every subscriber left in the meantime
+        Ok(data) => { let _ = CHANNEL.send(StreamedEvent { event: event.as_str(), data }); },
+        Err(error) => tracing::error!("Failed to serialize the `{}` event for the subscribers: {}", event.as_str(), error),
+    }
+}
+
+/// The Server-Sent Events of the changes from now on, until the client disconnects.
+/// A subscriber too slow to keep up skips the events it missed, with a warning.
+pub fn subscribe() -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    let receiver = CHANNEL.subscribe();
+    return stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            let bytes = match tokio::time::timeout(KEEP_ALIVE, receiver.recv()).await {
+                Ok(Ok(event)) => event.to_sse(),
+                Ok(Err(RecvError::Lagged(missed))) => {
+                    tracing::warn!("A subscriber of the coupon events missed {} of them.", missed);
+                    continue;
+                },
+                Ok(Err(RecvError::Closed)) => return None,
+                Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+            };
+            return Some((Ok(bytes), receiver));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{broadcast, subscribe, StreamedEvent};
+    use crate::events::CouponEvent;
+    use futures_util::StreamExt;
+
+    #[test]
+    fn events_are_formatted_as_server_sent_events(){
+        let event = StreamedEvent { event: "coupon.created", data: "{\"code\":\"CODE10\"}".to_string() };
+        assert_eq!(event.to_sse(), "event: coupon.created\ndata: {\"code\":\"CODE10\"}\n\n");
+    }
+
+    #[tokio::test]
+    async fn subscribers_receive_the_events_sent_after_they_subscribed(){
+        broadcast(CouponEvent::Created, &serde_json::json!({"code": "BEFORE"}));
+        let mut events = Box::pin(subscribe());
+        broadcast(CouponEvent::Deleted, &serde_json::json!({"code": "AFTER"}));
+
+        // the other tests can broadcast in the meantime
+        let event = loop {
+            let event = String::from_utf8(events.next().await.unwrap().unwrap().to_vec()).unwrap();
+            if (event.contains("\"code\":\"AFTER\"") || event.contains("\"code\":\"BEFORE\"")){
+                break event;
+            }
+        };
+        assert!(event.starts_with("event: coupon.deleted\ndata: "));
+        assert!(event.contains("\"code\":\"AFTER\""));
+    }
+}
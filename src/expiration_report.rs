@@ -0,0 +1,83 @@
+use crate::configuration::ExpirationReportSettings;
+use crate::coupon::{coupon_repository, Coupon};
+use crate::job::{job_service, JobKind};
+use crate::mailer::Mailer;
+use crate::scheduler::Scheduler;
+use chrono::{Duration, Utc};
+use sqlx::MySqlPool;
+
+
+/// Email the `recipients` the active coupons expiring within `days`, so they can be extended or replaced in time.
+/// Nothing is sent when no coupon is about to expire. Returns how many coupons were listed.
+pub async fn send(settings: &ExpirationReportSettings, mailer: &Mailer, pool: &MySqlPool) -> Result<usize, String> {
+    let now = Utc::now().naive_utc();
+    let until = now + Duration::days(i64::from(settings.days));
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let coupons = coupon_repository::get_expiring(now, until, &mut conn).await
+        .map_err(|e| e.to_string())?;
+    if (coupons.is_empty()){
+        return Ok(0);
+    }
+
+    let subject = format!("{} coupons expire within {} days", coupons.len(), settings.days);
+    mailer.send(&settings.recipients, &subject, body(&coupons, settings.days)).await?;
+    return Ok(coupons.len());
+}
+
+/// Queue an `expiration_report` job every `interval_seconds` (unless it is `0`), or on `schedules.expiration_report`.
+/// Every instance queues it, but not while one is already queued, so the report is sent once per run.
+pub fn register(scheduler: &mut Scheduler, settings: &ExpirationReportSettings, pool: MySqlPool) {
+    scheduler.register("expiration_report", settings.interval_seconds, move || {
+        let pool = pool.clone();
+        async move {
+            return job_service::enqueue_unless_queued(JobKind::ExpirationReport, &serde_json::json!({}), &pool).await
+                .map(|_| ())
+                .map_err(|error| format!("Failed to queue the expiration report: {:?}", error));
+        }
+    });
+}
+
+fn body(coupons: &[Coupon], days: u32) -> String {
+    let mut body = format!("These coupons expire within the next {} days:\n\n", days);
+    for coupon in coupons {
+        let expiration_date = coupon.expiration_date
+            .map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_default();
+        let usages = match coupon.max_usage_count {
+            Some(max_usage_count) => format!(", up to {} uses", max_usage_count),
+            None => String::new(),
+        };
+        body.push_str(&format!("- {}: {}% off{}, expires on {}\n", coupon.code, coupon.discount, usages, expiration_date));
+    }
+    body.push_str("\nExtend them with `PUT /coupon/{id_or_code}`, or create new ones to replace them.\n");
+    return body;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::body;
+    use crate::coupon::Coupon;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn the_report_lists_every_coupon_with_its_expiration_date(){
+        let expiration_date = NaiveDate::from_ymd_opt(2023, 2, 1).unwrap().and_hms_opt(12, 30, 0).unwrap();
+        let coupon = |code: &str, max_usage_count: Option<i32>| Coupon {
+            id: 1,
+            code: code.to_string(),
+            discount: 10,
+            active: true,
+            version: 1,
+            max_usage_count,
+            expiration_date: Some(expiration_date),
+            date_created: None,
+            date_updated: None,
+        };
+
+        let body = body(&[coupon("SUMMER10", None), coupon("VIP10", Some(5))], 7);
+
+        assert!(body.starts_with("These coupons expire within the next 7 days:"));
+        assert!(body.contains("- SUMMER10: 10% off, expires on 2023-02-01 12:30 UTC\n"));
+        assert!(body.contains("- VIP10: 10% off, up to 5 uses, expires on 2023-02-01 12:30 UTC\n"));
+    }
+}
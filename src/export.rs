@@ -0,0 +1,456 @@
+use crate::configuration::ExportSettings;
+use crate::coupon::coupon_store::CouponStore;
+#[cfg(feature = "aws")]
+use crate::coupon::{Coupon, CouponFilter, Cursor};
+use crate::envelope::Envelope;
+use crate::job::{job_service, JobKind};
+use crate::scheduler::Scheduler;
+use actix_web::{
+    web, get, HttpRequest, HttpResponse, ResponseError,
+    http::{StatusCode, header::{CACHE_CONTROL, CONTENT_DISPOSITION}},
+    web::Data,
+};
+use anyhow::anyhow;
+#[cfg(feature = "aws")]
+use aws_sdk_s3::{model::{CompletedMultipartUpload, CompletedPart}, types::{ByteStream, SdkError}, Credentials, Region};
+use chrono::{Duration, NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::MySqlPool;
+
+
+// the last snapshots listed by `GET /admin/exports`
+#[cfg(feature = "aws")]
+const MAX_LISTED_EXPORTS: usize = 100;
+// the coupons read from the store at a time
+#[cfg(feature = "aws")]
+const EXPORT_PAGE_SIZE: u32 = 1000;
+#[cfg(not(feature = "aws"))]
+const WITHOUT_AWS: &str = "`export` can only be set when built with `--features aws`.";
+// the snapshot is uploaded in parts of this size, S3 needs at least 5 MB for every part but the last one
+#[cfg(feature = "aws")]
+const EXPORT_PART_BYTES: usize = 8 * 1024 * 1024;
+
+/// An uploaded snapshot, with a path to download it without credentials until `download_url_expires_at`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportFile {
+    pub name: String,
+    pub size: i64,
+    pub last_modified: Option<NaiveDateTime>,
+    // e.g. `/exports/coupons-2023-01-31T020000.csv?expires=1675130700&signature=<hex>`
+    pub download_url: String,
+    pub download_url_expires_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DownloadQuery {
+    // unix time
+    pub expires: i64,
+    pub signature: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportError {
+    #[error("{0}")]
+    NotFoundError(String),
+    #[error("{0}")]
+    InvalidSignatureError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for ExportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ExportError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            ExportError::InvalidSignatureError(_) => StatusCode::FORBIDDEN,
+            ExportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+
+/// Upload a CSV snapshot of every coupon of the `store` to the `export.bucket`, so the data warehouse can load it without calling the API.
+/// The coupons are read a page at a time and uploaded in parts, so the snapshot is never held in memory. Returns the key of the uploaded object.
+#[cfg(feature = "aws")]
+pub async fn run(settings: &ExportSettings, store: &dyn CouponStore) -> Result<String, String> {
+    let key = object_key(&settings.prefix, Utc::now().naive_utc());
+    let mut upload = SnapshotUpload { client: client(settings).await, bucket: &settings.bucket, key: &key, upload_id: None, parts: Vec::new() };
+    if let Err(error) = write_snapshot(store, &mut upload).await {
+        upload.abort().await;
+        return Err(error);
+    }
+    return Ok(key);
+}
+
+// without the feature `Settings::validate()` rejects `export`, so there is no bucket to upload to
+#[cfg(not(feature = "aws"))]
+pub async fn run(_settings: &ExportSettings, _store: &dyn CouponStore) -> Result<String, String> {
+    return Err(WITHOUT_AWS.to_string());
+}
+
+#[cfg(feature = "aws")]
+async fn write_snapshot(store: &dyn CouponStore, upload: &mut SnapshotUpload<'_>) -> Result<(), String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut cursor = None;
+    loop {
+        let coupons = store.get_page(&CouponFilter::default(), cursor, EXPORT_PAGE_SIZE).await
+            .map_err(|e| format!("Failed to read the coupons: {}", e))?;
+        write_csv(&mut writer, &coupons)?;
+        // the CSV writer buffers a few KB, so the parts can be a bit larger
+        if (writer.get_ref().len() >= EXPORT_PART_BYTES){
+            let part = writer.into_inner().map_err(|e| format!("Failed to write the CSV: {}", e))?;
+            upload.upload_part(part).await?;
+            // the header row is only at the top of the first part
+            writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+        }
+        match coupons.last() {
+            Some(coupon) if (coupons.len() == EXPORT_PAGE_SIZE as usize) => cursor = Some(Cursor::After(coupon.id)),
+            _ => break,
+        }
+    }
+    let last_part = writer.into_inner().map_err(|e| format!("Failed to write the CSV: {}", e))?;
+    return upload.finish(last_part).await;
+}
+
+// a single `PutObject` when the snapshot fits in one part, a multipart upload started with the first full part otherwise
+#[cfg(feature = "aws")]
+struct SnapshotUpload<'a> {
+    client: aws_sdk_s3::Client,
+    bucket: &'a str,
+    key: &'a str,
+    upload_id: Option<String>,
+    parts: Vec<CompletedPart>,
+}
+
+#[cfg(feature = "aws")]
+impl SnapshotUpload<'_> {
+    fn error(&self, e: impl std::fmt::Display) -> String {
+        return format!("Failed to upload `{}` to the bucket `{}`: {}", self.key, self.bucket, e);
+    }
+
+    async fn upload_part(&mut self, body: Vec<u8>) -> Result<(), String> {
+        if (self.upload_id.is_none()){
+            let output = self.client.create_multipart_upload()
+                .bucket(self.bucket)
+                .key(self.key)
+                .content_type("text/csv")
+                .send()
+                .await
+                .map_err(|e| self.error(e))?;
+            let upload_id = output.upload_id().ok_or_else(|| self.error("no upload id"))?;
+            self.upload_id = Some(upload_id.to_string());
+        }
+        let part_number = self.parts.len() as i32 + 1;
+        let output = self.client.upload_part()
+            .bucket(self.bucket)
+            .key(self.key)
+            .set_upload_id(self.upload_id.clone())
+            .part_number(part_number)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(|e| self.error(e))?;
+        self.parts.push(CompletedPart::builder()
+            .set_e_tag(output.e_tag().map(|e_tag| e_tag.to_string()))
+            .part_number(part_number)
+            .build());
+        return Ok(());
+    }
+
+    async fn finish(&mut self, last_part: Vec<u8>) -> Result<(), String> {
+        if (self.upload_id.is_none()){
+            self.client.put_object()
+                .bucket(self.bucket)
+                .key(self.key)
+                .content_type("text/csv")
+                .body(ByteStream::from(last_part))
+                .send()
+                .await
+                .map_err(|e| self.error(e))?;
+            return Ok(());
+        }
+        if (!last_part.is_empty()){
+            self.upload_part(last_part).await?;
+        }
+        self.client.complete_multipart_upload()
+            .bucket(self.bucket)
+            .key(self.key)
+            .set_upload_id(self.upload_id.clone())
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(self.parts.clone())).build())
+            .send()
+            .await
+            .map_err(|e| self.error(e))?;
+        return Ok(());
+    }
+
+    // the parts uploaded so far are kept (and billed) by the bucket until the upload is aborted
+    async fn abort(&self) {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id,
+            None => return,
+        };
+        let result = self.client.abort_multipart_upload()
+            .bucket(self.bucket)
+            .key(self.key)
+            .upload_id(upload_id)
+            .send()
+            .await;
+        if let Err(e) = result {
+            tracing::error!("Failed to abort the upload of `{}` to the bucket `{}`: {}", self.key, self.bucket, e);
+        }
+    }
+}
+
+/// Queue an `export_coupons` job every `interval_seconds`, or on `schedules.export_coupons`, when `export` is set.
+/// Every instance queues it, but not while one is already queued, so a single snapshot is uploaded per run.
+pub fn register(scheduler: &mut Scheduler, settings: Option<&ExportSettings>, pool: MySqlPool) {
+    let settings = match settings {
+        Some(settings) => settings,
+        None => return,
+    };
+    scheduler.register("export_coupons", settings.interval_seconds, move || {
+        let pool = pool.clone();
+        async move {
+            return job_service::enqueue_unless_queued(JobKind::ExportCoupons, &serde_json::json!({}), &pool).await
+                .map(|_| ())
+                .map_err(|error| format!("Failed to queue the export of the coupons: {:?}", error));
+        }
+    });
+}
+
+// the region and credentials of the environment, unless they are configured
+#[cfg(feature = "aws")]
+async fn client(settings: &ExportSettings) -> aws_sdk_s3::Client {
+    let mut loader = aws_config::from_env();
+    if let Some(region) = &settings.region {
+        loader = loader.region(Region::new(region.clone()));
+    }
+    let mut config = aws_sdk_s3::config::Builder::from(&loader.load().await);
+    if let Some(endpoint) = &settings.endpoint {
+        // the S3-compatible storages (e.g. MinIO) don't have a bucket subdomain
+        config = config.endpoint_url(endpoint).force_path_style(true);
+    }
+    if let (Some(access_key_id), Some(secret_access_key)) = (&settings.access_key_id, &settings.secret_access_key) {
+        config = config.credentials_provider(Credentials::new(access_key_id, secret_access_key.expose_secret(), None, None, "export"));
+    }
+    return aws_sdk_s3::Client::from_conf(config.build());
+}
+
+// sorted by time, e.g. `coupon-api/coupons-2023-01-31T020000.csv`
+#[cfg(feature = "aws")]
+fn object_key(prefix: &str, now: NaiveDateTime) -> String {
+    return format!("{}coupons-{}.csv", prefix, now.format("%Y-%m-%dT%H%M%S"));
+}
+
+// the snapshots are the only objects of the prefix that can be listed and downloaded
+fn is_snapshot_name(name: &str) -> bool {
+    return name.starts_with("coupons-") && name.ends_with(".csv") && !name.contains('/');
+}
+
+fn download_path(name: &str) -> String {
+    return format!("/exports/{}", name);
+}
+
+/// The path to download the snapshot `name` until `expires` (a unix time), signed with the HMAC-SHA256 of the path and `expires`.
+pub fn sign_download_path(signing_key: &str, name: &str, expires: i64) -> String {
+    let signature = hex::encode(download_mac(signing_key, &download_path(name), expires).finalize().into_bytes());
+    return format!("{}?expires={}&signature={}", download_path(name), expires, signature);
+}
+
+/// Constant-time comparison, as for the signed requests. Doesn't check that `expires` is still ahead.
+pub fn verify_download(signing_key: &str, name: &str, expires: i64, signature: &str) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    return download_mac(signing_key, &download_path(name), expires).verify_slice(&signature).is_ok();
+}
+
+fn download_mac(signing_key: &str, path: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(signing_key.as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(format!("{}\n{}", path, expires).as_bytes());
+    return mac;
+}
+
+// the settings and the signing key, the snapshots can't be downloaded without them
+fn download_settings(settings: &Option<ExportSettings>) -> Result<(&ExportSettings, &str), ExportError> {
+    return settings.as_ref()
+        .and_then(|settings| settings.download_signing_key.as_ref().map(|key| (settings, key.expose_secret().as_str())))
+        .ok_or(ExportError::NotFoundError("The export downloads are not configured.".to_string()));
+}
+
+// name, size and time of the snapshots of the bucket, the last ones first
+#[cfg(feature = "aws")]
+async fn list(settings: &ExportSettings) -> Result<Vec<(String, i64, Option<NaiveDateTime>)>, String> {
+    let client = client(settings).await;
+    let mut snapshots = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let output = client
+            .list_objects_v2()
+            .bucket(&settings.bucket)
+            .prefix(&settings.prefix)
+            .set_continuation_token(continuation_token)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to list the bucket `{}`: {}", settings.bucket, e))?;
+        for object in output.contents().unwrap_or_default() {
+            let name = object.key().and_then(|key| key.strip_prefix(settings.prefix.as_str())).unwrap_or_default();
+            if (is_snapshot_name(name)){
+                let last_modified = object.last_modified().and_then(|time| NaiveDateTime::from_timestamp_opt(time.secs(), 0));
+                snapshots.push((name.to_string(), object.size(), last_modified));
+            }
+        }
+        continuation_token = output.next_continuation_token().map(|token| token.to_string());
+        if (continuation_token.is_none()){
+            break;
+        }
+    }
+    // the names sort by time
+    snapshots.sort_by(|a, b| b.0.cmp(&a.0));
+    snapshots.truncate(MAX_LISTED_EXPORTS);
+    return Ok(snapshots);
+}
+
+#[cfg(not(feature = "aws"))]
+async fn list(_settings: &ExportSettings) -> Result<Vec<(String, i64, Option<NaiveDateTime>)>, String> {
+    return Err(WITHOUT_AWS.to_string());
+}
+
+// the file of the snapshot, `None` when it is not in the bucket anymore
+// the body is read from the bucket while it is sent, the snapshot is never held in memory
+#[cfg(feature = "aws")]
+async fn download(settings: &ExportSettings, name: &str) -> Result<Option<ByteStream>, String> {
+    let key = format!("{}{}", settings.prefix, name);
+    return match client(settings).await.get_object().bucket(&settings.bucket).key(&key).send().await {
+        Ok(output) => Ok(Some(output.body)),
+        Err(SdkError::ServiceError(error)) if (error.err().is_no_such_key()) => Ok(None),
+        Err(e) => Err(format!("Failed to download `{}` from the bucket `{}`: {}", key, settings.bucket, e)),
+    };
+}
+
+#[cfg(not(feature = "aws"))]
+async fn download(_settings: &ExportSettings, _name: &str) -> Result<Option<futures_util::stream::Empty<Result<actix_web::web::Bytes, std::io::Error>>>, String> {
+    return Err(WITHOUT_AWS.to_string());
+}
+
+/// The last snapshots, with a download URL valid for `export.download_url_ttl_seconds` that needs no credentials,
+/// e.g. to be opened by a browser.
+#[tracing::instrument( name = "Get exports", skip(http_request, settings) )]
+#[get("/exports")]
+pub async fn get_exports(http_request: HttpRequest, settings: Data<Option<ExportSettings>>) -> Result<HttpResponse, ExportError> {
+    let (settings, signing_key) = download_settings(&settings)?;
+    let snapshots = list(settings).await.map_err(|e| ExportError::UnexpectedError(anyhow!(e)))?;
+    let expires_at = Utc::now().naive_utc() + Duration::seconds(settings.download_url_ttl_seconds as i64);
+    let files: Vec<ExportFile> = snapshots.into_iter()
+        .map(|(name, size, last_modified)| ExportFile {
+            download_url: sign_download_path(signing_key, &name, expires_at.timestamp()),
+            download_url_expires_at: expires_at,
+            name,
+            size,
+            last_modified,
+        })
+        .collect();
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, files)));
+}
+
+/// Not authenticated, the signature of the URL is the credential.
+#[tracing::instrument( name = "Download export", skip(query, settings) )]
+#[get("/exports/{name}")]
+pub async fn download_export(name: web::Path<String>, query: web::Query<DownloadQuery>, settings: Data<Option<ExportSettings>>) -> Result<HttpResponse, ExportError> {
+    let (settings, signing_key) = download_settings(&settings)?;
+    let name = name.into_inner();
+    if (!is_snapshot_name(&name) || !verify_download(signing_key, &name, query.expires, &query.signature)){
+        return Err(ExportError::InvalidSignatureError("The download URL signature is invalid.".to_string()));
+    }
+    if (query.expires < Utc::now().timestamp()){
+        return Err(ExportError::InvalidSignatureError("The download URL expired, get a new one on `GET /admin/exports`.".to_string()));
+    }
+    let body = download(settings, &name).await
+        .map_err(|e| ExportError::UnexpectedError(anyhow!(e)))?
+        .ok_or(ExportError::NotFoundError(format!("Export `{}` not found.", name)))?;
+    return Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header((CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)))
+        // the URL works for anyone until it expires, it must not be kept by shared caches
+        .insert_header((CACHE_CONTROL, "private, no-store"))
+        // the status is sent first, a failure of the bucket halfway cuts the download short
+        .streaming(body));
+}
+
+#[cfg(feature = "aws")]
+fn write_csv(writer: &mut csv::Writer<Vec<u8>>, coupons: &[Coupon]) -> Result<(), String> {
+    for coupon in coupons {
+        writer.serialize(coupon).map_err(|e| format!("Failed to write coupon `{}` as CSV: {}", coupon.code, e))?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_snapshot_name, sign_download_path, verify_download};
+    #[cfg(feature = "aws")]
+    use super::{object_key, write_csv};
+    #[cfg(feature = "aws")]
+    use crate::coupon::Coupon;
+    #[cfg(feature = "aws")]
+    use chrono::NaiveDate;
+
+    #[cfg(feature = "aws")]
+    #[test]
+    fn object_keys_are_sorted_by_time(){
+        let now = NaiveDate::from_ymd_opt(2023, 1, 31).unwrap().and_hms_opt(2, 0, 0).unwrap();
+        assert_eq!(object_key("coupon-api/", now), "coupon-api/coupons-2023-01-31T020000.csv");
+    }
+
+    #[cfg(feature = "aws")]
+    #[test]
+    fn coupons_are_exported_with_a_header_row(){
+        let coupon = Coupon {
+            id: 1,
+            code: "SUMMER10".to_string(),
+            discount: 10,
+            active: true,
+            version: 2,
+            max_usage_count: None,
+            expiration_date: Some(NaiveDate::from_ymd_opt(2023, 2, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()),
+            date_created: None,
+            date_updated: None,
+        };
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        write_csv(&mut writer, &[coupon]).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("id,code,discount,active,version,max_usage_count,expiration_date,date_created,date_updated"));
+        assert_eq!(lines.next(), Some("1,SUMMER10,10,true,2,,2023-02-01T00:00:00,,"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn download_urls_are_signed_for_their_file_and_expiration(){
+        let url = sign_download_path("key", "coupons-2023-01-31T020000.csv", 1675130700);
+        let (path, query) = url.split_once('?').unwrap();
+        assert_eq!(path, "/exports/coupons-2023-01-31T020000.csv");
+        let signature = query.split("&signature=").nth(1).unwrap();
+        assert!(query.starts_with("expires=1675130700&"));
+
+        assert!(verify_download("key", "coupons-2023-01-31T020000.csv", 1675130700, signature));
+        assert!(!verify_download("other key", "coupons-2023-01-31T020000.csv", 1675130700, signature));
+        assert!(!verify_download("key", "coupons-2023-02-01T020000.csv", 1675130700, signature));
+        assert!(!verify_download("key", "coupons-2023-01-31T020000.csv", 1675130701, signature));
+        assert!(!verify_download("key", "coupons-2023-01-31T020000.csv", 1675130700, "not hex"));
+    }
+
+    #[test]
+    fn only_the_snapshots_can_be_downloaded(){
+        assert!(is_snapshot_name("coupons-2023-01-31T020000.csv"));
+        assert!(!is_snapshot_name("other.csv"));
+        assert!(!is_snapshot_name("coupons-../secrets.csv"));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::configuration::Reloadable;
+use crate::envelope::Envelope;
+use actix_web::{
+    web, get, put, HttpRequest, HttpResponse,
+    web::Data,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+
+/// Coupons without an `expiration_date` are valid on `/coupon/verify`, instead of being rejected.
+pub const COUPONS_WITHOUT_EXPIRATION: &str = "coupons_without_expiration";
+
+/// Toggles of the risky new behaviors, so they can be turned on (and back off) per environment without a redeploy.
+/// The flags come from `feature_flags` in the configuration, unknown flags are disabled.
+/// A flag changed on `/admin/flags` overrides the configuration, even once reloaded, until the server is restarted.
+#[derive(Debug, Clone)]
+pub struct FeatureFlags {
+    configured: Reloadable<BTreeMap<String, bool>>,
+    // changed on `/admin/flags`
+    overrides: Reloadable<BTreeMap<String, bool>>,
+}
+
+impl FeatureFlags {
+    pub fn new(flags: BTreeMap<String, bool>) -> Self {
+        return Self { configured: Reloadable::new(flags), overrides: Reloadable::new(BTreeMap::new()) };
+    }
+
+    pub fn is_enabled(&self, flag: &str) -> bool {
+        return self.all_by_name().get(flag).copied().unwrap_or(false);
+    }
+
+    pub fn all(&self) -> Vec<FeatureFlag> {
+        return self.all_by_name().into_iter()
+            .map(|(name, enabled)| FeatureFlag { name, enabled })
+            .collect();
+    }
+
+    pub fn set(&self, flag: &str, enabled: bool) {
+        let mut overrides = self.overrides.get();
+        overrides.insert(flag.to_string(), enabled);
+        self.overrides.set(overrides);
+    }
+
+    /// Replace the flags of the configuration, when it is reloaded.
+    pub fn reload(&self, flags: BTreeMap<String, bool>) {
+        self.configured.set(flags);
+    }
+
+    fn all_by_name(&self) -> BTreeMap<String, bool> {
+        let mut flags = self.configured.get();
+        flags.extend(self.overrides.get());
+        return flags;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeatureFlagUpdateRequest {
+    pub enabled: bool,
+}
+
+#[tracing::instrument(name = "Get all feature flags", skip(http_request, flags))]
+#[get("/flags")]
+pub async fn get_all_flags(http_request: HttpRequest, flags: Data<FeatureFlags>) -> Result<HttpResponse, actix_web::Error> {
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, flags.all())));
+}
+
+#[tracing::instrument(name = "Update feature flag", skip(http_request, flags))]
+#[put("/flags/{name}")]
+pub async fn update_flag(http_request: HttpRequest, param: web::Path<String>, request: web::Json<FeatureFlagUpdateRequest>, flags: Data<FeatureFlags>) -> Result<HttpResponse, actix_web::Error> {
+    let name = param.into_inner();
+    if (name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')){
+        return Err(actix_web::error::ErrorBadRequest("Flag names can only have lowercase letters, digits and `_`."));
+    }
+    flags.set(&name, request.enabled);
+    tracing::info!("Feature flag `{}` {}.", name, if (request.enabled) { "enabled" } else { "disabled" });
+
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, FeatureFlag { name, enabled: request.enabled })));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FeatureFlag, FeatureFlags};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn unknown_flags_are_disabled(){
+        let flags = FeatureFlags::new(BTreeMap::from([("enabled_flag".to_string(), true), ("disabled_flag".to_string(), false)]));
+
+        assert!(flags.is_enabled("enabled_flag"));
+        assert!(!flags.is_enabled("disabled_flag"));
+        assert!(!flags.is_enabled("unknown_flag"));
+
+        flags.set("unknown_flag", true);
+        assert!(flags.is_enabled("unknown_flag"));
+        assert_eq!(flags.all()[2], FeatureFlag { name: "unknown_flag".to_string(), enabled: true });
+    }
+
+    #[test]
+    fn changed_flags_are_kept_on_reload(){
+        let flags = FeatureFlags::new(BTreeMap::from([("changed_flag".to_string(), false), ("reloaded_flag".to_string(), false)]));
+        flags.set("changed_flag", true);
+
+        flags.reload(BTreeMap::from([("changed_flag".to_string(), false), ("reloaded_flag".to_string(), true), ("new_flag".to_string(), true)]));
+
+        assert!(flags.is_enabled("changed_flag"));
+        assert!(flags.is_enabled("reloaded_flag"));
+        assert!(flags.is_enabled("new_flag"));
+    }
+}
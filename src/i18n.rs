@@ -0,0 +1,155 @@
+use crate::coupon::CouponError;
+
+
+/// The languages of the error messages, the storefront shows them to the customers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    PtBr,
+}
+
+impl Locale {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Locale::En => "en",
+            Locale::PtBr => "pt-BR",
+        };
+    }
+
+    /// The supported language the client prefers in its `Accept-Language`, e.g. `pt-BR,pt;q=0.9,en;q=0.8`.
+    /// Every `pt` is `pt-BR`, English without the header or when none is supported.
+    pub fn from_accept_language(header: Option<&str>) -> Locale {
+        let mut languages: Vec<(&str, f32)> = header.unwrap_or_default()
+            .split(',')
+            .filter_map(|language| {
+                let mut parts = language.split(';').map(str::trim);
+                let tag = parts.next().filter(|tag| !tag.is_empty())?;
+                let quality = parts
+                    .find_map(|parameter| parameter.strip_prefix("q="))
+                    .map_or(Some(1.0), |quality| quality.parse::<f32>().ok())?;
+                return Some((tag, quality));
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // stable, the first one sent wins a tie
+        languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (tag, _) in languages {
+            let tag = tag.to_ascii_lowercase();
+            if (tag == "pt" || tag.starts_with("pt-")){
+                return Locale::PtBr;
+            }
+            if (tag == "en" || tag.starts_with("en-") || tag == "*"){
+                return Locale::En;
+            }
+        }
+        return Locale::En;
+    }
+}
+
+// the messages of `CouponError::ValidationError` and `CouponError::NotFoundError`, `{}` are the values
+const PT_BR: &[(&str, &str)] = &[
+    ("Coupon with id `{}` not found.", "Cupom com o id `{}` não encontrado."),
+    ("Coupon with code `{}` not found.", "Cupom com o código `{}` não encontrado."),
+    ("Route `{} {}` not found.", "Rota `{} {}` não encontrada."),
+    ("Path `{}` not found.", "Caminho `{}` não encontrado."),
+    ("Discount cannot be higher than 90.", "O desconto não pode ser maior que 90."),
+    ("Discount cannot be less than 0.", "O desconto não pode ser menor que 0."),
+    ("`discount` cannot be null.", "`discount` não pode ser nulo."),
+    ("`active` cannot be null.", "`active` não pode ser nulo."),
+    ("`If-Match` must be the `ETag` of the coupon, e.g. `\"1\"`.", "`If-Match` deve ser o `ETag` do cupom, por exemplo `\"1\"`."),
+    ("Invalid cursor `{}`.", "Cursor `{}` inválido."),
+    ("Invalid `since` `{}`, it must be a `next_cursor` or a time, e.g. `2023-01-31T10:00:00Z`.", "`since` `{}` inválido, deve ser um `next_cursor` ou uma data, por exemplo `2023-01-31T10:00:00Z`."),
+    ("The changes feed is only available when `database.coupon_backend` is `mysql`.", "O feed de alterações só está disponível quando `database.coupon_backend` é `mysql`."),
+    ("Invalid body: {}.", "Corpo inválido: {}."),
+    ("Invalid method `{}`.", "Método `{}` inválido."),
+    ("No code was sent.", "Nenhum código foi enviado."),
+    ("At most {} codes can be validated at once, {} were sent.", "No máximo {} códigos podem ser validados de uma vez, {} foram enviados."),
+    ("The cart has no items.", "O carrinho não tem itens."),
+    ("Every item must have a `sku`.", "Todo item deve ter um `sku`."),
+    ("The quantity of `{}` must be at least 1.", "A quantidade de `{}` deve ser pelo menos 1."),
+    ("The unit price of `{}` cannot be negative.", "O preço unitário de `{}` não pode ser negativo."),
+    ("The shipping cannot be negative.", "O frete não pode ser negativo."),
+    ("The cart total is too large.", "O total do carrinho é grande demais."),
+    ("The currency must be an ISO 4217 code, e.g. `BRL`.", "A moeda deve ser um código ISO 4217, por exemplo `BRL`."),
+    ("At least one coupon code is required.", "Pelo menos um código de cupom é obrigatório."),
+    ("`{}` cannot be removed.", "`{}` não pode ser removido."),
+    ("`{}` cannot be changed.", "`{}` não pode ser alterado."),
+    ("The test of `{}` failed, it is `{}`.", "O teste de `{}` falhou, o valor é `{}`."),
+    ("Invalid path `{}`, it must be a field of the coupon, e.g. `/discount`.", "Caminho `{}` inválido, deve ser um campo do cupom, por exemplo `/discount`."),
+    ("The coupon has no `{}` field.", "O cupom não tem o campo `{}`."),
+    ("The `move` and `copy` operations are not supported, the fields of a coupon all have their own type.", "As operações `move` e `copy` não são suportadas, cada campo do cupom tem o seu próprio tipo."),
+];
+
+/// The message of the error in `locale`, `None` when it stays in English: only the validation and not found errors
+/// of `CouponError` are translated, and the messages missing from the catalog are left as they are.
+pub fn localize(error: &actix_web::Error, locale: Locale) -> Option<String> {
+    return match error.as_error::<CouponError>()? {
+        CouponError::ValidationError(_) | CouponError::NotFoundError(_) => translate(&error.to_string(), locale),
+        _ => None,
+    };
+}
+
+/// `message` in `locale`, `None` when it is not in the catalog.
+pub fn translate(message: &str, locale: Locale) -> Option<String> {
+    let catalog = match locale {
+        Locale::En => return None,
+        Locale::PtBr => PT_BR,
+    };
+    return catalog.iter().find_map(|(english, translated)| {
+        let values = values(english, message)?;
+        let mut parts = translated.split("{}");
+        let mut result = parts.next().unwrap_or_default().to_string();
+        for (value, part) in values.iter().zip(parts) {
+            result.push_str(value);
+            result.push_str(part);
+        }
+        return Some(result);
+    });
+}
+
+// the values of the `{}` of `template` in `message`, `None` when it doesn't match
+fn values<'a>(template: &str, message: &'a str) -> Option<Vec<&'a str>> {
+    let parts: Vec<&str> = template.split("{}").collect();
+    let (first, rest) = parts.split_first()?;
+    let (last, middle) = match rest.split_last() {
+        Some(split) => split,
+        None => return if (message == *first) { Some(Vec::new()) } else { None },
+    };
+    let mut remaining = message.strip_prefix(first)?.strip_suffix(last)?;
+    let mut values = Vec::new();
+    for part in middle {
+        let index = remaining.find(part)?;
+        values.push(&remaining[..index]);
+        remaining = &remaining[index + part.len()..];
+    }
+    values.push(remaining);
+    return Some(values);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{translate, Locale};
+    use claim::assert_none;
+
+    #[test]
+    fn the_preferred_supported_language_is_used(){
+        assert_eq!(Locale::from_accept_language(None), Locale::En);
+        assert_eq!(Locale::from_accept_language(Some("pt-BR,pt;q=0.9,en;q=0.8")), Locale::PtBr);
+        assert_eq!(Locale::from_accept_language(Some("en-US,pt-BR;q=0.5")), Locale::En);
+        assert_eq!(Locale::from_accept_language(Some("fr-FR,pt;q=0.8,en;q=0.5")), Locale::PtBr);
+        assert_eq!(Locale::from_accept_language(Some("pt-BR;q=0,en")), Locale::En);
+        assert_eq!(Locale::from_accept_language(Some("de")), Locale::En);
+    }
+
+    #[test]
+    fn messages_are_translated_with_their_values(){
+        assert_eq!(translate("Coupon with code `SUMMER10` not found.", Locale::PtBr).as_deref(), Some("Cupom com o código `SUMMER10` não encontrado."));
+        assert_eq!(translate("Discount cannot be higher than 90.", Locale::PtBr).as_deref(), Some("O desconto não pode ser maior que 90."));
+        assert_eq!(
+            translate("At most 100 codes can be validated at once, 101 were sent.", Locale::PtBr).as_deref(),
+            Some("No máximo 100 códigos podem ser validados de uma vez, 101 foram enviados.")
+        );
+        assert_none!(translate("Coupon with code `SUMMER10` not found.", Locale::En));
+        assert_none!(translate("A message missing from the catalog.", Locale::PtBr));
+    }
+}
@@ -0,0 +1,23 @@
+use super::model::{JobError, JobFilter};
+use super::job_service;
+use crate::envelope::Envelope;
+use actix_web::{
+    web, get, HttpRequest, HttpResponse,
+    web::Data,
+};
+use sqlx::MySqlPool;
+
+
+#[tracing::instrument( name = "Get jobs", skip(pool, http_request) )]
+#[get("/jobs")]
+pub async fn get_all_jobs(http_request: HttpRequest, filter: web::Query<JobFilter>, pool: Data::<MySqlPool>) -> Result<HttpResponse, JobError> {
+    let jobs = job_service::get_all(&filter, &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, jobs)));
+}
+
+#[tracing::instrument( name = "Get job", skip(pool, http_request) )]
+#[get("/jobs/{id}")]
+pub async fn get_job(http_request: HttpRequest, id: web::Path<i64>, pool: Data::<MySqlPool>) -> Result<HttpResponse, JobError> {
+    let job = job_service::get_by_id(id.into_inner(), &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, job)));
+}
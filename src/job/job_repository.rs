@@ -0,0 +1,154 @@
+use super::model::{Job, JobFilter, JobStatus};
+use sqlx::{MySqlConnection, MySqlPool};
+use sqlx::types::chrono::{NaiveDateTime};
+
+
+const SELECT_JOB: &str = "SELECT id, kind, payload, status, attempts, run_at, locked_until, last_error, date_created, date_updated FROM jobs";
+
+/// Returns the id of the inserted job. On the connection of a transaction, the job is only queued if it is committed.
+pub async fn insert(kind: &str, payload: &str, run_at: NaiveDateTime, conn: &mut MySqlConnection) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("INSERT INTO jobs (kind, payload, run_at) VALUES (?, ?, ?)")
+    .bind(kind)
+    .bind(payload)
+    .bind(run_at)
+    .execute(conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(result.last_insert_id());
+}
+
+/// Same as `insert`, unless a job of the same `kind` is already pending or running.
+/// Returns if the job was inserted.
+pub async fn insert_unless_queued(kind: &str, payload: &str, run_at: NaiveDateTime, pool: &MySqlPool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+            INSERT INTO jobs (kind, payload, run_at)
+            SELECT ?, ?, ? FROM DUAL
+            WHERE NOT EXISTS (SELECT 1 FROM jobs WHERE kind = ? AND status IN ('pending', 'running'))
+        "#)
+    .bind(kind)
+    .bind(payload)
+    .bind(run_at)
+    .bind(kind)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(result.rows_affected() == 1);
+}
+
+/// Take the first job due at `now`, or running with an expired lease, marking it `running` until `locked_until`.
+/// The jobs taken by the other workers are skipped rather than waited for.
+pub async fn claim_next(now: NaiveDateTime, locked_until: NaiveDateTime, pool: &MySqlPool) -> Result<Option<Job>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let job = sqlx::query_as::<_, Job>(&format!(
+        r#"{}
+            WHERE (status = 'pending' AND run_at <= ?)
+            OR (status = 'running' AND locked_until < ?)
+            ORDER BY run_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED"#, SELECT_JOB))
+    .bind(now)
+    .bind(now)
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    let mut job = match job {
+        Some(job) => job,
+        None => return Ok(None),
+    };
+
+    sqlx::query("UPDATE jobs SET status = 'running', attempts = attempts + 1, locked_until = ? WHERE id = ?")
+    .bind(locked_until)
+    .bind(job.id)
+    .execute(&mut transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    transaction.commit().await?;
+
+    job.status = JobStatus::Running.as_str().to_string();
+    job.attempts += 1;
+    job.locked_until = Some(locked_until);
+    return Ok(Some(job));
+}
+
+/// Set the `status` of the job, with the error of its last attempt, and when it is due again if `pending`.
+pub async fn finish_attempt(id: i64, status: JobStatus, run_at: Option<NaiveDateTime>, error: Option<&str>, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE jobs SET status = ?, run_at = COALESCE(?, run_at), locked_until = NULL, last_error = COALESCE(?, last_error) WHERE id = ?")
+    .bind(status.as_str())
+    .bind(run_at)
+    .bind(error)
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+/// Queue a finished job (`succeeded` or `failed`) again, due at `run_at` with its attempts reset.
+/// Returns if the job was queued, not if it doesn't exist or is still `pending` or `running`.
+pub async fn requeue(id: i64, run_at: NaiveDateTime, pool: &MySqlPool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+            UPDATE jobs SET status = 'pending', attempts = 0, run_at = ?, locked_until = NULL, last_error = NULL
+            WHERE id = ? AND status IN ('succeeded', 'failed')
+        "#)
+    .bind(run_at)
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(result.rows_affected() == 1);
+}
+
+pub async fn get_by_id(id: i64, pool: &MySqlPool) -> Result<Option<Job>, sqlx::Error> {
+    let job = sqlx::query_as::<_, Job>(&format!("{} WHERE id = ?", SELECT_JOB))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(job);
+}
+
+pub async fn get_all(filter: &JobFilter, limit: u32, pool: &MySqlPool) -> Result<Vec<Job>, sqlx::Error> {
+    let status = filter.status.map(|status| status.as_str());
+    let kind = filter.kind.map(|kind| kind.as_str());
+    let jobs = sqlx::query_as::<_, Job>(&format!(
+        r#"{}
+            WHERE (? IS NULL OR status = ?)
+            AND (? IS NULL OR kind = ?)
+            ORDER BY id DESC
+            LIMIT ?"#, SELECT_JOB))
+    .bind(status)
+    .bind(status)
+    .bind(kind)
+    .bind(kind)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(jobs);
+}
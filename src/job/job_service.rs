@@ -0,0 +1,45 @@
+use super::model::{Job, JobError, JobFilter, JobKind};
+use super::job_repository;
+use anyhow::anyhow;
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::MySqlPool;
+
+
+/// Queue a job, run as soon as a worker is free. Returns its id.
+pub async fn enqueue<T: Serialize>(kind: JobKind, payload: &T, pool: &MySqlPool) -> Result<u64, JobError> {
+    let payload = serde_json::to_string(payload)
+        .map_err(|e| JobError::UnexpectedError(anyhow!(format!("Failed to serialize the `{}` job payload: {}", kind.as_str(), e))))?;
+    let mut conn = pool.acquire().await
+        .map_err(|error| JobError::UnexpectedError(error.into()))?;
+    return job_repository::insert(kind.as_str(), &payload, Utc::now().naive_utc(), &mut conn).await
+        .map_err(|error| JobError::UnexpectedError(error.into()));
+}
+
+/// Same as `enqueue`, unless a job of the same `kind` is already queued, e.g. for the periodic jobs enqueued by every instance.
+/// Returns if the job was queued.
+pub async fn enqueue_unless_queued<T: Serialize>(kind: JobKind, payload: &T, pool: &MySqlPool) -> Result<bool, JobError> {
+    let payload = serde_json::to_string(payload)
+        .map_err(|e| JobError::UnexpectedError(anyhow!(format!("Failed to serialize the `{}` job payload: {}", kind.as_str(), e))))?;
+    return job_repository::insert_unless_queued(kind.as_str(), &payload, Utc::now().naive_utc(), pool).await
+        .map_err(|error| JobError::UnexpectedError(error.into()));
+}
+
+/// Run a finished job again, as soon as a worker is free, with all its attempts.
+/// Returns if the job was queued, not if it is still `pending` or `running`.
+pub async fn requeue(id: i64, pool: &MySqlPool) -> Result<bool, JobError> {
+    return job_repository::requeue(id, Utc::now().naive_utc(), pool).await
+        .map_err(|error| JobError::UnexpectedError(error.into()));
+}
+
+pub async fn get_by_id(id: i64, pool: &MySqlPool) -> Result<Job, JobError> {
+    return job_repository::get_by_id(id, pool).await
+        .map_err(|error| JobError::UnexpectedError(error.into()))?
+        .ok_or(JobError::NotFoundError(format!("Job with id `{}` not found.", id)));
+}
+
+pub async fn get_all(filter: &JobFilter, pool: &MySqlPool) -> Result<Vec<Job>, JobError> {
+    let limit = filter.limit().map_err(JobError::ValidationError)?;
+    return job_repository::get_all(filter, limit, pool).await
+        .map_err(|error| JobError::UnexpectedError(error.into()));
+}
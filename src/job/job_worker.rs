@@ -0,0 +1,107 @@
+use super::model::{Job, JobKind, JobStatus};
+use super::job_repository;
+use crate::configuration::{ArchiveSettings, ExpirationReportSettings, ExportSettings, JobSettings};
+use crate::coupon::coupon_store::CouponStore;
+use crate::coupon_archive::coupon_archive_service;
+use crate::coupon_import::coupon_import_service;
+use crate::expiration_report;
+use crate::export;
+use crate::mailer::Mailer;
+use crate::notifications::{self, Notification};
+use crate::retry;
+use crate::webhook::webhook_delivery;
+use chrono::Utc;
+use sqlx::MySqlPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+
+/// What the jobs need to run.
+#[derive(Clone)]
+pub struct JobContext {
+    pub pool: MySqlPool,
+    pub archive: ArchiveSettings,
+    pub client: reqwest::Client,
+    pub expiration_report: ExpirationReportSettings,
+    // `None` without `smtp`
+    pub mailer: Option<Mailer>,
+    pub export: Option<ExportSettings>,
+    // where the coupons are exported from, and the imported ones inserted
+    pub store: Arc<dyn CouponStore>,
+}
+
+/// Run the due jobs one at a time, for as long as the server runs, unless `jobs.enabled` is `false`.
+/// The queue is polled every `jobs.poll_interval_milliseconds` while it is empty.
+pub fn spawn(settings: JobSettings, context: JobContext) {
+    if (!settings.enabled){
+        return;
+    }
+    tokio::spawn(async move {
+        let poll_interval = Duration::from_millis(settings.poll_interval_milliseconds);
+        loop {
+            match run_next(&settings, &context).await {
+                Ok(true) => {},
+                Ok(false) => tokio::time::sleep(poll_interval).await,
+                Err(error) => {
+                    tracing::error!("Failed to run the next job: {:?}", error);
+                    tokio::time::sleep(poll_interval).await;
+                },
+            }
+        }
+    });
+}
+
+/// Run the next due job, if any, and record how it went: failed attempts are retried after a backoff
+/// until `jobs.retry.max_retries`. Returns if a job was run.
+pub async fn run_next(settings: &JobSettings, context: &JobContext) -> Result<bool, sqlx::Error> {
+    let now = Utc::now().naive_utc();
+    let locked_until = now + chrono::Duration::seconds(settings.lease_seconds as i64);
+    let job = match job_repository::claim_next(now, locked_until, &context.pool).await? {
+        Some(job) => job,
+        None => return Ok(false),
+    };
+
+    match run(&job, context).await {
+        Ok(()) => {
+            job_repository::finish_attempt(job.id, JobStatus::Succeeded, None, None, &context.pool).await?;
+        },
+        Err(error) if (job.attempts as u32) <= settings.retry.max_retries => {
+            let backoff = retry::backoff(&settings.retry, job.attempts as u32);
+            tracing::warn!("Job {} (`{}`) failed, attempt {} of {}, retried in {:?}: {}", job.id, job.kind, job.attempts, settings.retry.max_retries + 1, backoff, error);
+            let run_at = Utc::now().naive_utc() + chrono::Duration::milliseconds(backoff.as_millis() as i64);
+            job_repository::finish_attempt(job.id, JobStatus::Pending, Some(run_at), Some(&error), &context.pool).await?;
+        },
+        Err(error) => {
+            tracing::error!("Job {} (`{}`) failed after {} attempts: {}", job.id, job.kind, job.attempts, error);
+            notifications::notify(Notification::job_failed(job.id, &job.kind, job.attempts, &error));
+            job_repository::finish_attempt(job.id, JobStatus::Failed, None, Some(&error), &context.pool).await?;
+        },
+    }
+    return Ok(true);
+}
+
+#[tracing::instrument(name = "Run job", skip(job, context), fields(job_id = job.id, kind = %job.kind))]
+async fn run(job: &Job, context: &JobContext) -> Result<(), String> {
+    return match job.kind.parse::<JobKind>()? {
+        JobKind::WebhookDelivery => webhook_delivery::run_delivery(job.id, job.attempts, &job.payload, &context.client, &context.pool).await,
+        JobKind::ArchiveExpiredCoupons => {
+            let archived = coupon_archive_service::archive_expired(&context.archive, context.store.as_ref(), &context.pool).await
+                .map_err(|error| error.to_string())?;
+            tracing::info!("Archived {} coupons expired for more than {} days.", archived, context.archive.retention_days);
+            Ok(())
+        },
+        JobKind::ExpirationReport => {
+            let mailer = context.mailer.as_ref().ok_or("`smtp` is not configured.")?;
+            let listed = expiration_report::send(&context.expiration_report, mailer, &context.pool).await?;
+            tracing::info!("Sent the report of the {} coupons expiring within {} days.", listed, context.expiration_report.days);
+            Ok(())
+        },
+        JobKind::ExportCoupons => {
+            let settings = context.export.as_ref().ok_or("`export` is not configured.")?;
+            let key = export::run(settings, context.store.as_ref()).await?;
+            tracing::info!("Exported the coupons to `{}` of the bucket `{}`.", key, settings.bucket);
+            Ok(())
+        },
+        JobKind::ImportCoupons => coupon_import_service::run(&job.payload, context.store.as_ref(), &context.pool).await,
+    };
+}
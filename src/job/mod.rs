@@ -0,0 +1,8 @@
+pub mod job_controller;
+pub mod job_repository;
+pub mod job_service;
+pub mod job_worker;
+pub mod model;
+
+pub use job_controller::*;
+pub use model::*;
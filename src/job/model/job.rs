@@ -0,0 +1,126 @@
+use actix_web::{
+    ResponseError,
+    http::{StatusCode},
+};
+use serde::{Serialize, Deserialize};
+use sqlx::types::chrono::{NaiveDateTime};
+use std::str::FromStr;
+
+
+pub const DEFAULT_LIMIT: u32 = 100;
+pub const MAX_LIMIT: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    // the payload is a `WebhookDelivery`
+    WebhookDelivery,
+    // `coupon_archive_service::archive_expired`, enqueued every `archive.interval_seconds`
+    ArchiveExpiredCoupons,
+    // `expiration_report::send`, enqueued every `expiration_report.interval_seconds`
+    ExpirationReport,
+    // `export::run`, enqueued every `export.interval_seconds`
+    ExportCoupons,
+    // the payload is an `ImportCouponsJob`, enqueued by `POST /coupon/import`
+    ImportCoupons,
+}
+
+impl JobKind {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            JobKind::WebhookDelivery => "webhook_delivery",
+            JobKind::ArchiveExpiredCoupons => "archive_expired_coupons",
+            JobKind::ExpirationReport => "expiration_report",
+            JobKind::ExportCoupons => "export_coupons",
+            JobKind::ImportCoupons => "import_coupons",
+        };
+    }
+}
+
+impl FromStr for JobKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "webhook_delivery" => Ok(Self::WebhookDelivery),
+            "archive_expired_coupons" => Ok(Self::ArchiveExpiredCoupons),
+            "expiration_report" => Ok(Self::ExpirationReport),
+            "export_coupons" => Ok(Self::ExportCoupons),
+            "import_coupons" => Ok(Self::ImportCoupons),
+            other => Err(format!("`{}` is not a supported job kind.", other)),
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Succeeded,
+    // no retry left
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            JobStatus::Pending => "pending",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub locked_until: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_updated: Option<NaiveDateTime>,
+}
+
+// Query string filters of `/admin/jobs`, the last created jobs are returned first
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct JobFilter {
+    pub status: Option<JobStatus>,
+    pub kind: Option<JobKind>,
+    pub limit: Option<u32>,
+}
+
+impl JobFilter {
+    pub fn limit(&self) -> Result<u32, String> {
+        return match self.limit {
+            None => Ok(DEFAULT_LIMIT),
+            Some(limit) if (limit == 0 || limit > MAX_LIMIT) => Err(format!("`limit` must be between 1 and {}.", MAX_LIMIT)),
+            Some(limit) => Ok(limit),
+        };
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum JobError {
+    #[error("{0}")]
+    NotFoundError(String),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for JobError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            JobError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            JobError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            JobError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod job;
+
+pub use self::job::*;
@@ -3,8 +3,38 @@
 #![allow(unused_parens)]
 #![allow(clippy::needless_return)]
 
+pub mod access_log;
+pub mod api_key;
+pub mod audit_log;
 pub mod authentication;
+pub mod client_ip;
+pub mod compression;
 pub mod coupon;
+pub mod coupon_archive;
+pub mod coupon_import;
 pub mod configuration;
+pub mod deprecation;
+pub mod envelope;
+pub mod error_reporting;
+pub mod events;
+pub mod expiration_report;
+pub mod export;
+pub mod feature_flags;
+pub mod i18n;
+pub mod job;
+pub mod mailer;
+pub mod metrics;
+pub mod migrations;
+pub mod notifications;
+pub mod rate_limit;
+pub mod reload;
+pub mod request_id;
+pub mod retention;
+pub mod retry;
+pub mod scheduler;
+pub mod secrets;
+pub mod seed;
 pub mod startup;
 pub mod telemetry;
+pub mod user;
+pub mod webhook;
@@ -0,0 +1,77 @@
+use crate::configuration::SmtpSettings;
+#[cfg(feature = "smtp")]
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+};
+#[cfg(feature = "smtp")]
+use secrecy::ExposeSecret;
+
+
+/// Sends the emails through the `smtp` server, with STARTTLS unless `smtp.starttls` is `false`.
+/// The connections are pooled, opened on the first email.
+#[cfg(feature = "smtp")]
+#[derive(Clone)]
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+#[cfg(feature = "smtp")]
+impl Mailer {
+    pub fn new(settings: &SmtpSettings) -> Result<Self, std::io::Error> {
+        let invalid = |error: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, error);
+        let mut builder = match settings.starttls {
+            true => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+                .map_err(|e| invalid(format!("Invalid `smtp.host`: {}.", e)))?,
+            false => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host),
+        };
+        builder = builder.port(settings.port);
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.expose_secret().clone()));
+        }
+        let from = settings.from.parse::<Mailbox>()
+            .map_err(|e| invalid(format!("Invalid `smtp.from`: {}.", e)))?;
+        return Ok(Self { transport: builder.build(), from });
+    }
+
+    /// Send a plain text email to every recipient at once.
+    pub async fn send(&self, recipients: &[String], subject: &str, body: String) -> Result<(), String> {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for recipient in recipients {
+            let mailbox = recipient.parse::<Mailbox>()
+                .map_err(|e| format!("Invalid recipient `{}`: {}", recipient, e))?;
+            message = message.to(mailbox);
+        }
+        let message = message.body(body).map_err(|e| format!("Failed to build the email: {}", e))?;
+        self.transport.send(message).await
+            .map_err(|e| format!("Failed to send the email: {}", e))?;
+        return Ok(());
+    }
+}
+
+/// Without the `smtp` feature `Settings::validate()` rejects `smtp`, so there is never a mailer.
+#[cfg(not(feature = "smtp"))]
+#[derive(Clone)]
+pub struct Mailer(std::convert::Infallible);
+
+#[cfg(not(feature = "smtp"))]
+impl Mailer {
+    pub fn new(_settings: &SmtpSettings) -> Result<Self, std::io::Error> {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "`smtp` can only be set when built with `--features smtp`."));
+    }
+
+    pub async fn send(&self, _recipients: &[String], _subject: &str, _body: String) -> Result<(), String> {
+        match self.0 {}
+    }
+}
+
+/// If `address` can be the sender or a recipient, e.g. `Coupon API <coupons@example.com>`.
+#[cfg(feature = "smtp")]
+pub fn is_email_address(address: &str) -> bool {
+    return address.parse::<Mailbox>().is_ok();
+}
@@ -0,0 +1,280 @@
+use actix_web::{
+    get, Error, HttpResponse,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web::Data,
+};
+use sqlx::{Database, Pool};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+
+// upper bounds (in seconds) of the buckets of the latency histogram
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+// quantiles of the latency summary, computed over the last `RECENT_LATENCIES` requests of each route
+const LATENCY_QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+const RECENT_LATENCIES: usize = 1000;
+
+/// Prometheus metrics, rendered in the text format on `GET /metrics`: the requests, the server errors and the latency
+/// (histogram and p50/p95/p99) per route, recorded by wrapping the app with it, the calls of the deprecated routes and the size of the connection pools.
+/// The routes are their pattern (e.g. `/coupon/{id_or_code}`), so the ids don't create a series each.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    routes: Arc<Mutex<BTreeMap<(String, String), RouteMetrics>>>,
+    // calls of the routes wrapped with `Deprecated`, by method and route
+    deprecated: Arc<Mutex<BTreeMap<(String, String), u64>>>,
+    pools: Arc<Mutex<Vec<PoolGauges>>>,
+}
+
+#[derive(Default)]
+struct RouteMetrics {
+    // requests by status code
+    statuses: BTreeMap<u16, u64>,
+    // requests not slower than each of `LATENCY_BUCKETS`
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum_seconds: f64,
+    count: u64,
+    // responses with a 5xx status
+    errors: u64,
+    // latencies of the last requests, oldest first
+    recent: VecDeque<f64>,
+}
+
+struct PoolGauges {
+    name: &'static str,
+    max_connections: u32,
+    // size and idle connections
+    stats: Box<dyn Fn() -> (u32, usize) + Send + Sync>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn register_pool<DB: Database>(&self, name: &'static str, pool: Pool<DB>, max_connections: u32) {
+        let stats = Box::new(move || (pool.size(), pool.num_idle()));
+        self.pools.lock().unwrap().push(PoolGauges { name, max_connections, stats });
+    }
+
+    pub fn record(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let mut routes = self.routes.lock().unwrap();
+        let route = routes.entry((method.to_string(), route.to_string())).or_default();
+        *route.statuses.entry(status).or_insert(0) += 1;
+        for (bucket, upper_bound) in route.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if (seconds <= upper_bound){
+                *bucket += 1;
+            }
+        }
+        route.sum_seconds += seconds;
+        route.count += 1;
+        if (status >= 500){
+            route.errors += 1;
+        }
+        if (route.recent.len() == RECENT_LATENCIES){
+            route.recent.pop_front();
+        }
+        route.recent.push_back(seconds);
+    }
+
+    /// Count a call of a deprecated route, returns its calls so far.
+    pub fn record_deprecated(&self, method: &str, route: &str) -> u64 {
+        let mut deprecated = self.deprecated.lock().unwrap();
+        let count = deprecated.entry((method.to_string(), route.to_string())).or_insert(0);
+        *count += 1;
+        return *count;
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let routes = self.routes.lock().unwrap();
+
+        output.push_str("# HELP http_requests_total Requests handled, by method, route and status.\n");
+        output.push_str("# TYPE http_requests_total counter\n");
+        for ((method, route), metrics) in routes.iter() {
+            for (status, count) in &metrics.statuses {
+                let _ = writeln!(output, "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}", escape(method), escape(route), status, count);
+            }
+        }
+
+        output.push_str("# HELP http_request_duration_seconds Latency of the requests, by method and route.\n");
+        output.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for ((method, route), metrics) in routes.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+            for (count, upper_bound) in metrics.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(output, "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, upper_bound, count);
+            }
+            let _ = writeln!(output, "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, metrics.count);
+            let _ = writeln!(output, "http_request_duration_seconds_sum{{{}}} {}", labels, metrics.sum_seconds);
+            let _ = writeln!(output, "http_request_duration_seconds_count{{{}}} {}", labels, metrics.count);
+        }
+
+        output.push_str("# HELP http_request_errors_total Requests answered with a 5xx status, by method and route.\n");
+        output.push_str("# TYPE http_request_errors_total counter\n");
+        for ((method, route), metrics) in routes.iter() {
+            let _ = writeln!(output, "http_request_errors_total{{method=\"{}\",route=\"{}\"}} {}", escape(method), escape(route), metrics.errors);
+        }
+
+        output.push_str("# HELP http_request_latency_seconds Latency quantiles of the last requests, by method and route.\n");
+        output.push_str("# TYPE http_request_latency_seconds summary\n");
+        for ((method, route), metrics) in routes.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+            let mut recent: Vec<f64> = metrics.recent.iter().copied().collect();
+            recent.sort_by(|a, b| a.total_cmp(b));
+            for quantile in LATENCY_QUANTILES {
+                let _ = writeln!(output, "http_request_latency_seconds{{{},quantile=\"{}\"}} {}", labels, quantile, nearest_rank(&recent, quantile));
+            }
+            let _ = writeln!(output, "http_request_latency_seconds_sum{{{}}} {}", labels, metrics.sum_seconds);
+            let _ = writeln!(output, "http_request_latency_seconds_count{{{}}} {}", labels, metrics.count);
+        }
+
+        output.push_str("# HELP http_deprecated_requests_total Calls of the deprecated routes, by method and route.\n");
+        output.push_str("# TYPE http_deprecated_requests_total counter\n");
+        for ((method, route), count) in self.deprecated.lock().unwrap().iter() {
+            let _ = writeln!(output, "http_deprecated_requests_total{{method=\"{}\",route=\"{}\"}} {}", escape(method), escape(route), count);
+        }
+
+        output.push_str("# HELP db_pool_connections Connections of the database pools, by state.\n");
+        output.push_str("# TYPE db_pool_connections gauge\n");
+        for pool in self.pools.lock().unwrap().iter() {
+            let (size, idle) = (pool.stats)();
+            let _ = writeln!(output, "db_pool_connections{{pool=\"{}\",state=\"open\"}} {}", pool.name, size);
+            let _ = writeln!(output, "db_pool_connections{{pool=\"{}\",state=\"idle\"}} {}", pool.name, idle);
+            let _ = writeln!(output, "db_pool_connections{{pool=\"{}\",state=\"max\"}} {}", pool.name, pool.max_connections);
+        }
+        return output;
+    }
+}
+
+// the smallest value with at least `quantile` of the values not greater than it, of values sorted in ascending order
+fn nearest_rank(sorted: &[f64], quantile: f64) -> f64 {
+    if (sorted.is_empty()){
+        return 0.0;
+    }
+    let rank = (quantile * sorted.len() as f64).ceil() as usize;
+    return sorted[rank.clamp(1, sorted.len()) - 1];
+}
+
+// label values are quoted, with `\`, `"` and new lines escaped
+fn escape(value: &str) -> String {
+    return value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+}
+
+#[get("/metrics")]
+pub async fn get_metrics(metrics: Data<Metrics>) -> HttpResponse {
+    return HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render());
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Metrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = MetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(MetricsMiddleware { service: Rc::new(service), metrics: self.clone() }));
+    }
+}
+
+pub struct MetricsMiddleware<S> {
+    service: Rc<S>,
+    metrics: Metrics,
+}
+
+impl<S, B> Service<ServiceRequest> for MetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let metrics = self.metrics.clone();
+        let method = request.method().to_string();
+        // the paths matching no route are grouped, any path would be a new series otherwise
+        let route = request.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+        let start = Instant::now();
+        return Box::pin(async move {
+            let result = service.call(request).await;
+            let status = match &result {
+                Ok(response) => response.status(),
+                Err(error) => error.as_response_error().status_code(),
+            };
+            metrics.record(&method, &route, status.as_u16(), start.elapsed().as_secs_f64());
+            return result;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nearest_rank, Metrics};
+
+    #[test]
+    fn requests_are_counted_per_route_and_status(){
+        let metrics = Metrics::new();
+        metrics.record("GET", "/coupon/{id_or_code}", 200, 0.02);
+        metrics.record("GET", "/coupon/{id_or_code}", 200, 0.2);
+        metrics.record("GET", "/coupon/{id_or_code}", 404, 0.002);
+
+        let output = metrics.render();
+
+        assert!(output.contains("http_requests_total{method=\"GET\",route=\"/coupon/{id_or_code}\",status=\"200\"} 2\n"), "{}", output);
+        assert!(output.contains("http_requests_total{method=\"GET\",route=\"/coupon/{id_or_code}\",status=\"404\"} 1\n"), "{}", output);
+        assert!(output.contains("http_request_duration_seconds_bucket{method=\"GET\",route=\"/coupon/{id_or_code}\",le=\"0.025\"} 2\n"), "{}", output);
+        assert!(output.contains("http_request_duration_seconds_bucket{method=\"GET\",route=\"/coupon/{id_or_code}\",le=\"+Inf\"} 3\n"), "{}", output);
+        assert!(output.contains("http_request_duration_seconds_count{method=\"GET\",route=\"/coupon/{id_or_code}\"} 3\n"), "{}", output);
+    }
+
+    #[test]
+    fn server_errors_and_latency_quantiles_are_per_route(){
+        let metrics = Metrics::new();
+        for milliseconds in 1..=100 {
+            metrics.record("GET", "/coupon/verify/{id_or_code}", 200, f64::from(milliseconds) / 1000.0);
+        }
+        metrics.record("POST", "/coupon", 500, 0.01);
+        metrics.record("POST", "/coupon", 422, 0.01);
+
+        let output = metrics.render();
+
+        assert!(output.contains("http_request_errors_total{method=\"POST\",route=\"/coupon\"} 1\n"), "{}", output);
+        assert!(output.contains("http_request_errors_total{method=\"GET\",route=\"/coupon/verify/{id_or_code}\"} 0\n"), "{}", output);
+        assert!(output.contains("http_request_latency_seconds{method=\"GET\",route=\"/coupon/verify/{id_or_code}\",quantile=\"0.95\"} 0.095\n"), "{}", output);
+        assert!(output.contains("http_request_latency_seconds{method=\"GET\",route=\"/coupon/verify/{id_or_code}\",quantile=\"0.99\"} 0.099\n"), "{}", output);
+    }
+
+    #[test]
+    fn deprecated_routes_are_counted(){
+        let metrics = Metrics::new();
+        metrics.record_deprecated("GET", "/coupon/old/{id}");
+
+        assert_eq!(metrics.record_deprecated("GET", "/coupon/old/{id}"), 2);
+        let output = metrics.render();
+        assert!(output.contains("http_deprecated_requests_total{method=\"GET\",route=\"/coupon/old/{id}\"} 2\n"), "{}", output);
+    }
+
+    #[test]
+    fn quantiles_are_the_nearest_rank(){
+        assert_eq!(nearest_rank(&[1.0, 2.0, 3.0, 4.0], 0.5), 2.0);
+        assert_eq!(nearest_rank(&[1.0, 2.0, 3.0, 4.0], 0.99), 4.0);
+        assert_eq!(nearest_rank(&[], 0.5), 0.0);
+    }
+}
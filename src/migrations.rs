@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Context};
+use sqlx::migrate::{AppliedMigration, Migrate, Migration, Migrator};
+use sqlx::MySqlPool;
+
+
+/// The migrations of `migrations`, embedded in the binary so a deployment runs exactly the migrations it was built with.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// Apply the pending migrations, returns the versions applied.
+pub async fn run(pool: &MySqlPool) -> Result<Vec<i64>, anyhow::Error> {
+    let pending = pending(pool).await?;
+    MIGRATOR.run(pool).await.context("Failed to run the migrations.")?;
+    return Ok(pending);
+}
+
+/// Revert the last applied migration, which needs a `.down.sql`. Returns its version, `None` when nothing is applied.
+pub async fn revert(pool: &MySqlPool) -> Result<Option<i64>, anyhow::Error> {
+    let applied = applied_migrations(pool).await?;
+    let last = match applied.last() {
+        Some(last) => last,
+        None => return Ok(None),
+    };
+    if (!MIGRATOR.iter().any(|migration| migration.version == last.version && migration.migration_type.is_down_migration())){
+        return Err(anyhow!("Migration {} can't be reverted, it has no `.down.sql`.", last.version));
+    }
+    // `undo` reverts every applied migration after the target, i.e. only the last one
+    let target = applied.iter().rev().nth(1).map_or(0, |migration| migration.version);
+    MIGRATOR.undo(pool, target).await.context("Failed to revert the migration.")?;
+    return Ok(Some(last.version));
+}
+
+/// Every embedded migration and if it is applied on the database.
+pub async fn status(pool: &MySqlPool) -> Result<Vec<MigrationStatus>, anyhow::Error> {
+    let applied = applied_migrations(pool).await?;
+    return Ok(migration_status(MIGRATOR.iter(), &applied));
+}
+
+/// Versions of the embedded migrations not applied on the database yet.
+pub async fn pending(pool: &MySqlPool) -> Result<Vec<i64>, anyhow::Error> {
+    return Ok(status(pool).await?.into_iter()
+        .filter(|migration| !migration.applied)
+        .map(|migration| migration.version)
+        .collect());
+}
+
+async fn applied_migrations(pool: &MySqlPool) -> Result<Vec<AppliedMigration>, anyhow::Error> {
+    let mut conn = pool.acquire().await.context("Failed to connect to the database.")?;
+    conn.ensure_migrations_table().await.context("Failed to create the migrations table.")?;
+    if let Some(version) = conn.dirty_version().await? {
+        return Err(anyhow!("Migration {} failed halfway, fix the database by hand before running the migrations again.", version));
+    }
+    return Ok(conn.list_applied_migrations().await?);
+}
+
+fn migration_status<'a>(migrations: impl Iterator<Item = &'a Migration>, applied: &[AppliedMigration]) -> Vec<MigrationStatus> {
+    return migrations
+        // the `.down.sql` of a reversible migration shares the version of its `.up.sql`
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.iter().any(|applied| applied.version == migration.version),
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migration_status, MigrationStatus};
+    use sqlx::migrate::{AppliedMigration, Migration, MigrationType};
+    use std::borrow::Cow;
+
+    fn migration(version: i64, migration_type: MigrationType) -> Migration {
+        return Migration::new(version, Cow::Owned(format!("migration {}", version)), migration_type, Cow::Borrowed(""));
+    }
+
+    #[test]
+    fn migrations_not_applied_are_pending(){
+        let migrations = vec![
+            migration(1, MigrationType::Simple),
+            migration(2, MigrationType::ReversibleUp),
+            migration(2, MigrationType::ReversibleDown),
+        ];
+        let applied = vec![AppliedMigration { version: 1, checksum: Cow::Borrowed(&[]) }];
+
+        let status = migration_status(migrations.iter(), &applied);
+
+        assert_eq!(status, vec![
+            MigrationStatus { version: 1, description: "migration 1".to_string(), applied: true },
+            MigrationStatus { version: 2, description: "migration 2".to_string(), applied: false },
+        ]);
+    }
+}
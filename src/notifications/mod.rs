@@ -0,0 +1,80 @@
+pub mod slack;
+pub mod teams;
+
+use crate::configuration::{NotificationProvider, Settings};
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+use std::time::Duration;
+
+
+static NOTIFIER: OnceCell<Box<dyn Notifier>> = OnceCell::new();
+
+// a chat service slower than this is given up on, the notification is dropped
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the notable events are posted for the team to see, selected by `notifications.provider`:
+/// `slack::SlackNotifier` or `teams::TeamsNotifier`, both through an incoming webhook.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), String>;
+}
+
+/// A notable event, e.g. a job that failed all its attempts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub text: String,
+}
+
+impl Notification {
+    pub fn job_failed(id: i64, kind: &str, attempts: i32, error: &str) -> Self {
+        return Self {
+            title: format!("Job {} (`{}`) failed", id, kind),
+            text: format!("It failed after {} attempts and won't be retried: {}", attempts, error),
+        };
+    }
+}
+
+/// Create the notifier of `notify`, when `notifications` is set.
+pub fn init(settings: &Settings) {
+    let notifications = match &settings.notifications {
+        Some(notifications) => notifications,
+        None => return,
+    };
+    let client = reqwest::Client::builder()
+        .timeout(NOTIFY_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let notifier: Box<dyn Notifier> = match notifications.provider {
+        NotificationProvider::Slack => Box::new(slack::SlackNotifier::new(notifications.webhook_url.clone(), client)),
+        NotificationProvider::Teams => Box::new(teams::TeamsNotifier::new(notifications.webhook_url.clone(), client)),
+    };
+    // already set when the application is built again in the same process, e.g. by the tests
+    let _ = NOTIFIER.set(notifier);
+}
+
+/// Post the notification in the background, logging the errors. Nothing is posted when no notifier is configured.
+pub fn notify(notification: Notification) {
+    let notifier = match NOTIFIER.get() {
+        Some(notifier) => notifier,
+        None => return,
+    };
+    tokio::spawn(async move {
+        if let Err(error) = notifier.notify(&notification).await {
+            tracing::error!("Failed to post the notification `{}`: {}", notification.title, error);
+        }
+    });
+}
+
+// both providers answer `200` once the message is posted
+async fn post(client: &reqwest::Client, url: &str, body: &serde_json::Value) -> Result<(), String> {
+    let response = client.post(url)
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if (!response.status().is_success()){
+        return Err(format!("The webhook responded `{}`.", response.status()));
+    }
+    return Ok(());
+}
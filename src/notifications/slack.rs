@@ -0,0 +1,39 @@
+use super::{post, Notification, Notifier};
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, Secret};
+
+
+/// Posts to a Slack incoming webhook, the title in bold above the text.
+pub struct SlackNotifier {
+    webhook_url: Secret<String>,
+    client: reqwest::Client,
+}
+
+impl SlackNotifier {
+    pub fn new(webhook_url: Secret<String>, client: reqwest::Client) -> Self {
+        return Self { webhook_url, client };
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), String> {
+        return post(&self.client, self.webhook_url.expose_secret(), &payload(notification)).await;
+    }
+}
+
+fn payload(notification: &Notification) -> serde_json::Value {
+    return serde_json::json!({ "text": format!("*{}*\n{}", notification.title, notification.text) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::payload;
+    use crate::notifications::Notification;
+
+    #[test]
+    fn the_title_is_in_bold_above_the_text(){
+        let notification = Notification { title: "Job 1 failed".to_string(), text: "Timed out.".to_string() };
+        assert_eq!(payload(&notification)["text"], "*Job 1 failed*\nTimed out.");
+    }
+}
@@ -0,0 +1,48 @@
+use super::{post, Notification, Notifier};
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, Secret};
+
+
+/// Posts a `MessageCard` to a Microsoft Teams incoming webhook.
+pub struct TeamsNotifier {
+    webhook_url: Secret<String>,
+    client: reqwest::Client,
+}
+
+impl TeamsNotifier {
+    pub fn new(webhook_url: Secret<String>, client: reqwest::Client) -> Self {
+        return Self { webhook_url, client };
+    }
+}
+
+#[async_trait]
+impl Notifier for TeamsNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), String> {
+        return post(&self.client, self.webhook_url.expose_secret(), &payload(notification)).await;
+    }
+}
+
+fn payload(notification: &Notification) -> serde_json::Value {
+    return serde_json::json!({
+        "@type": "MessageCard",
+        "@context": "https://schema.org/extensions",
+        "summary": notification.title,
+        "title": notification.title,
+        "text": notification.text,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::payload;
+    use crate::notifications::Notification;
+
+    #[test]
+    fn notifications_are_posted_as_message_cards(){
+        let notification = Notification { title: "Job 1 failed".to_string(), text: "Timed out.".to_string() };
+        let payload = payload(&notification);
+        assert_eq!(payload["@type"], "MessageCard");
+        assert_eq!(payload["title"], "Job 1 failed");
+        assert_eq!(payload["text"], "Timed out.");
+    }
+}
@@ -0,0 +1,290 @@
+use crate::authentication::Session;
+use crate::configuration::{RateLimitSettings, Reloadable};
+use actix_web::{
+    Error, HttpMessage, HttpResponse, ResponseError,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{StatusCode, header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER}},
+};
+use std::{
+    collections::HashMap,
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+
+/// Token bucket rate limiter: every client has a bucket of `max_requests` tokens, refilled over `window_seconds`.
+/// Bursts up to the bucket size are allowed, the sustained rate is `max_requests` per window.
+/// Every response gets the `X-RateLimit-*` headers, so well-behaved clients can back off without guessing.
+#[derive(Clone)]
+pub struct RateLimiter {
+    settings: Reloadable<RateLimitSettings>,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+    // identifies the client of the request, see `client_ip` and `client_api_key`
+    key: fn(&ServiceRequest) -> String,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// State of the rate limit for the current request, sent back in the response headers.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitInfo {
+    pub limit: u32,
+    pub remaining: u32,
+    // time until the bucket is full again
+    pub reset: Duration,
+    // time until the next request is allowed
+    pub retry_after: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(settings: Reloadable<RateLimitSettings>) -> Self {
+        return Self { settings, buckets: Arc::new(Mutex::new(HashMap::new())), key: client_ip };
+    }
+
+    /// Same limiter (and buckets), identifying the clients with `key`.
+    pub fn keyed_by(&self, key: fn(&ServiceRequest) -> String) -> Self {
+        return Self { key, ..self.clone() };
+    }
+
+    /// Take one token from the bucket of `key`, returning `Err` if it is empty.
+    pub fn check(&self, key: &str) -> Result<RateLimitInfo, RateLimitInfo> {
+        return self.check_at(key, Instant::now());
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<RateLimitInfo, RateLimitInfo> {
+        let settings = self.settings.get();
+        let limit = settings.max_requests.max(1);
+        let capacity = f64::from(limit);
+        // tokens added back per second
+        let refill_rate = capacity / settings.window_seconds.max(1) as f64;
+        let refilled = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            return (bucket.tokens + elapsed * refill_rate).min(capacity);
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: capacity, updated: now });
+        bucket.tokens = refilled(bucket);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if (allowed){
+            bucket.tokens -= 1.0;
+        }
+        let info = RateLimitInfo {
+            limit,
+            remaining: bucket.tokens.floor() as u32,
+            reset: Duration::from_secs_f64((capacity - bucket.tokens) / refill_rate),
+            retry_after: Duration::from_secs_f64((1.0 - bucket.tokens).max(0.0) / refill_rate),
+        };
+        return if (allowed) { Ok(info) } else { Err(info) };
+    }
+
+    /// Forget the full buckets so the map doesn't grow forever, a new bucket starts full anyway.
+    pub fn prune(&self) {
+        self.prune_at(Instant::now());
+    }
+
+    fn prune_at(&self, now: Instant) {
+        let settings = self.settings.get();
+        let capacity = f64::from(settings.max_requests.max(1));
+        let refill_rate = capacity / settings.window_seconds.max(1) as f64;
+        self.buckets.lock().unwrap().retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            return bucket.tokens + elapsed * refill_rate < capacity;
+        });
+    }
+
+    /// `prune` every `interval`, instead of on every request.
+    pub fn spawn_prune(&self, interval: Duration) {
+        let limiter = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                limiter.prune();
+            }
+        });
+    }
+}
+
+/// Clients of the routes that are not authenticated are identified by their IP, see `client_ip::client_ip`.
+pub fn client_ip(request: &ServiceRequest) -> String {
+    return match crate::client_ip::client_ip(request.request()) {
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_string(),
+    };
+}
+
+/// Clients of the authenticated routes are identified by the API key of their session, so all the
+/// sessions created with a key share its limit. Must be wrapped inside the authentication middleware.
+/// The configured `application.api_key` and the user logins have no API key id, their IP is used instead.
+pub fn client_api_key(request: &ServiceRequest) -> String {
+    let api_key_id = request.extensions().get::<Session>().and_then(|session| session.api_key_id);
+    return match api_key_id {
+        Some(id) => format!("api_key:{}", id),
+        None => client_ip(request),
+    };
+}
+
+impl RateLimitInfo {
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(HeaderName::from_static("x-ratelimit-limit"), HeaderValue::from(self.limit));
+        headers.insert(HeaderName::from_static("x-ratelimit-remaining"), HeaderValue::from(self.remaining));
+        headers.insert(HeaderName::from_static("x-ratelimit-reset"), HeaderValue::from(round_up_seconds(self.reset)));
+        if (self.remaining == 0){
+            headers.insert(RETRY_AFTER, HeaderValue::from(round_up_seconds(self.retry_after)));
+        }
+    }
+}
+
+// round up, a client retrying after `0` seconds would still be limited
+fn round_up_seconds(duration: Duration) -> u64 {
+    return duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+}
+
+/// The bucket of the client of the request, in its extensions, for the requests that count as several,
+/// e.g. the operations of `POST /batch` after the first one.
+#[derive(Clone)]
+pub struct ClientBucket {
+    limiter: RateLimiter,
+    key: String,
+}
+
+impl ClientBucket {
+    /// Take one more token from the bucket, see `RateLimiter::check`.
+    pub fn take(&self) -> Result<RateLimitInfo, RateLimitExceeded> {
+        return self.limiter.check(&self.key).map_err(RateLimitExceeded);
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimitExceeded(pub RateLimitInfo);
+
+impl std::fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "Too many requests, retry in {} seconds.", round_up_seconds(self.0.retry_after).max(1));
+    }
+}
+
+impl ResponseError for RateLimitExceeded {
+    fn status_code(&self) -> StatusCode {
+        return StatusCode::TOO_MANY_REQUESTS;
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::TooManyRequests().body(self.to_string());
+        self.0.insert_headers(response.headers_mut());
+        return response;
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimiterMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(RateLimiterMiddleware { service: Rc::new(service), limiter: self.clone() }));
+    }
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+    limiter: RateLimiter,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        if (!self.limiter.settings.get().enabled){
+            return Box::pin(async move { service.call(request).await });
+        }
+
+        let key = (self.limiter.key)(&request);
+        let check = self.limiter.check(&key);
+        // the inner limiters replace it, so it is the bucket of the most specific client
+        request.extensions_mut().insert(ClientBucket { limiter: self.limiter.clone(), key: key.clone() });
+        return Box::pin(async move {
+            let info = check.map_err(|info| {
+                tracing::warn!("Rate limit exceeded for `{}`.", key);
+                RateLimitExceeded(info)
+            })?;
+            let mut response = service.call(request).await?;
+            info.insert_headers(response.headers_mut());
+            return Ok(response);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use crate::configuration::{RateLimitSettings, Reloadable};
+    use claim::{assert_err, assert_ok};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn requests_over_the_limit_are_rejected(){
+        let limiter = RateLimiter::new(Reloadable::new(RateLimitSettings { enabled: true, max_requests: 2, window_seconds: 60 }));
+
+        assert_eq!(assert_ok!(limiter.check("key")).remaining, 1);
+        assert_eq!(assert_ok!(limiter.check("key")).remaining, 0);
+        assert_err!(limiter.check("key"));
+        // other keys have their own bucket
+        assert_ok!(limiter.check("other key"));
+    }
+
+    #[test]
+    fn bucket_is_refilled_over_the_window(){
+        let limiter = RateLimiter::new(Reloadable::new(RateLimitSettings { enabled: true, max_requests: 2, window_seconds: 60 }));
+        let now = Instant::now();
+
+        assert_ok!(limiter.check_at("key", now));
+        assert_ok!(limiter.check_at("key", now));
+        let info = assert_err!(limiter.check_at("key", now));
+        assert_eq!(info.retry_after.as_secs_f64().round(), 30.0);
+        assert_eq!(info.reset.as_secs_f64().round(), 60.0);
+
+        // one token every 30 seconds
+        let later = now + Duration::from_secs(31);
+        assert_ok!(limiter.check_at("key", later));
+        assert_err!(limiter.check_at("key", later));
+    }
+
+    #[test]
+    fn full_buckets_are_pruned(){
+        let limiter = RateLimiter::new(Reloadable::new(RateLimitSettings { enabled: true, max_requests: 2, window_seconds: 60 }));
+        let now = Instant::now();
+        assert_ok!(limiter.check_at("key", now));
+        assert_ok!(limiter.check_at("other key", now + Duration::from_secs(20)));
+
+        limiter.prune_at(now + Duration::from_secs(31));
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(!buckets.contains_key("key"));
+        assert!(buckets.contains_key("other key"));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::configuration::{get_configuration_with, CacheSettings, ConfigurationOverrides, RateLimitSettings, Reloadable, SessionSettings};
+use crate::envelope::Envelope;
+use crate::feature_flags::FeatureFlags;
+use crate::telemetry::{log_filter, set_log_filter};
+use actix_web::{
+    get, post, HttpRequest, HttpResponse,
+    web::{Data, Json},
+};
+use serde::{Deserialize, Serialize};
+
+
+/// Returned by `/admin/config/reload`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReloadedSettings {
+    pub reloaded: Vec<String>,
+    // changed in the files, only applied after a restart
+    pub restart_required: Vec<String>,
+}
+
+#[tracing::instrument(name = "Reload configuration", skip(http_request, overrides, rate_limit, session, cache, feature_flags))]
+// read the configuration again, as on startup, applying the settings that can be changed at runtime
+#[post("/config/reload")]
+pub async fn reload_configuration(http_request: HttpRequest, overrides: Data<ConfigurationOverrides>, rate_limit: Data<Reloadable<RateLimitSettings>>, session: Data<Reloadable<SessionSettings>>, cache: Data<Reloadable<CacheSettings>>, feature_flags: Data<FeatureFlags>) -> Result<HttpResponse, actix_web::Error> {
+    let settings = get_configuration_with(&overrides).await
+        .map_err(|e| actix_web::error::ErrorUnprocessableEntity(format!("Failed to read configuration: {}", e)))?;
+    // nothing is applied unless all the settings are valid
+    settings.validate()
+        .map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+
+    let mut reloaded = vec!["rate_limit".to_string(), "session".to_string(), "cache.ttl_seconds".to_string(), "feature_flags".to_string()];
+    rate_limit.set(settings.rate_limit);
+    session.set(settings.session);
+    // the size and backend of the cache are kept, see `MemoryCouponCache`
+    cache.set(CacheSettings { ttl_seconds: settings.cache.ttl_seconds, ..cache.get() });
+    // the flags changed on `/admin/flags` are kept
+    feature_flags.reload(settings.feature_flags);
+    // `RUST_LOG` (and `--log-level`) take precedence over the configuration, as on startup
+    if let (Some(log_level), Err(_)) = (&settings.application.log_level, std::env::var("RUST_LOG")) {
+        set_log_filter(log_level).map_err(actix_web::error::ErrorInternalServerError)?;
+        reloaded.push("application.log_level".to_string());
+    }
+    tracing::info!("Configuration reloaded: {}.", reloaded.join(", "));
+
+    let restart_required = ["application", "database", "redis_uri", "cors", "api_keys", "request_signing", "auth_lockout", "oidc", "cache.max_entries", "cache.backend"]
+        .iter().map(|setting| setting.to_string()).collect();
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, ReloadedSettings { reloaded, restart_required })));
+}
+
+/// Filter of the logs, with the syntax of `RUST_LOG`, e.g. `coupon_api::coupon::coupon_repository=trace,info`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogLevel {
+    pub filter: String,
+}
+
+#[tracing::instrument(name = "Get log level", skip(http_request))]
+#[get("/log-level")]
+pub async fn get_log_level(http_request: HttpRequest) -> Result<HttpResponse, actix_web::Error> {
+    let filter = log_filter()
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("There is no log filter to read."))?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, LogLevel { filter })));
+}
+
+#[tracing::instrument(name = "Update log level", skip(http_request))]
+// e.g. to trace the queries during an incident, until the next `/admin/config/reload` or restart
+#[post("/log-level")]
+pub async fn update_log_level(http_request: HttpRequest, body: Json<LogLevel>) -> Result<HttpResponse, actix_web::Error> {
+    if (tracing_subscriber::EnvFilter::try_new(&body.filter).is_err()){
+        return Err(actix_web::error::ErrorUnprocessableEntity(format!("Invalid log filter `{}`, e.g. `debug` or `coupon_api=debug,info`.", body.filter)));
+    }
+    set_log_filter(&body.filter).map_err(actix_web::error::ErrorInternalServerError)?;
+    tracing::warn!("Log filter changed to `{}`.", body.filter);
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, body.into_inner())));
+}
@@ -0,0 +1,158 @@
+use actix_web::{
+    Error, HttpMessage, HttpRequest,
+    body::{BoxBody, EitherBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_TYPE},
+};
+use crate::api_key::ApiKeyError;
+use crate::i18n::{self, Locale};
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+use tracing::Instrument;
+
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Id of the request, set by `RequestIdHeader`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Id of `request`: the `X-Request-ID` of the client, or the one generated by `TracingLogger`.
+pub fn get(request: &HttpRequest) -> Option<String> {
+    if let Some(request_id) = request.extensions().get::<RequestId>() {
+        return Some(request_id.0.clone());
+    }
+    return request.extensions().get::<tracing_actix_web::RequestId>().map(|id| id.to_string());
+}
+
+// the id sent by the client, ignored when it could be used to inject something in the logs
+fn from_headers(headers: &HeaderMap) -> Option<String> {
+    let request_id = headers.get(REQUEST_ID_HEADER)?.to_str().ok()?;
+    if (request_id.is_empty() || request_id.len() > MAX_REQUEST_ID_LENGTH){
+        return None;
+    }
+    if (!request_id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))){
+        return None;
+    }
+    return Some(request_id.to_string());
+}
+
+/// Takes the `X-Request-ID` of the client, or the id generated by `TracingLogger` without it, and echoes it
+/// in the `X-Request-ID` header of the response and in the body of the errors, so a failure reported by a client
+/// can be found in the logs. The messages of the errors are in the language of the `Accept-Language`, see `i18n`.
+/// It must wrap the app before `TracingLogger`, to run inside its span.
+pub struct RequestIdHeader;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdHeader
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        return ready(Ok(RequestIdMiddleware { service: Rc::new(service) }));
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let request_id = from_headers(request.headers())
+            .or_else(|| get(request.request()))
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        request.extensions_mut().insert(RequestId(request_id.clone()));
+        let http_request = request.request().clone();
+        let locale = Locale::from_accept_language(request.headers().get(ACCEPT_LANGUAGE).and_then(|value| value.to_str().ok()));
+        // the spans of the handlers and the repositories are in this one, so all their logs have the id
+        let span = tracing::info_span!("Request", request_id = %request_id);
+        return Box::pin(async move {
+            let response = match service.call(request).await {
+                Ok(response) => response.map_into_left_body(),
+                // the errors of the middlewares (e.g. the authentication) get the id too
+                Err(error) => ServiceResponse::from_err(error, http_request).map_into_right_body(),
+            };
+            return Ok(with_request_id(response, &request_id, locale));
+        }.instrument(span));
+    }
+}
+
+fn with_request_id<B>(response: ServiceResponse<EitherBody<B>>, request_id: &str, locale: Locale) -> ServiceResponse<EitherBody<B>> {
+    let mut response = match response.response().error() {
+        Some(error) => {
+            let localized = i18n::localize(error, locale);
+            let message = localized.clone().unwrap_or_else(|| error.to_string());
+            let mut body = serde_json::json!({ "error": message, "meta": { "request_id": request_id } });
+            if let Some(code) = error_code(error) {
+                body["code"] = serde_json::Value::from(code);
+            }
+            let body = body.to_string();
+            let mut response = response.map_body(|_, _| EitherBody::right(BoxBody::new(body)));
+            response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            if (localized.is_some()){
+                response.headers_mut().insert(CONTENT_LANGUAGE, HeaderValue::from_static(locale.as_str()));
+            }
+            response
+        },
+        None => response,
+    };
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        response.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    return response;
+}
+
+// a stable code for the errors the clients must tell apart from the others with the same status
+fn error_code(error: &Error) -> Option<&'static str> {
+    return match error.as_error::<ApiKeyError>()? {
+        ApiKeyError::ExpiredError(_) => Some("api_key_expired"),
+        _ => None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_headers, REQUEST_ID_HEADER};
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use claim::{assert_none, assert_some_eq};
+
+    fn headers(request_id: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(HeaderName::from_static(REQUEST_ID_HEADER), HeaderValue::from_str(request_id).unwrap());
+        return headers;
+    }
+
+    #[test]
+    fn the_request_id_of_the_client_is_kept(){
+        assert_some_eq!(from_headers(&headers("4bf92f35-77b3-4da6")), "4bf92f35-77b3-4da6");
+    }
+
+    #[test]
+    fn request_ids_that_could_inject_logs_are_ignored(){
+        assert_none!(from_headers(&headers("")));
+        assert_none!(from_headers(&headers("id\" injected=\"yes")));
+        assert_none!(from_headers(&headers(&"a".repeat(129))));
+    }
+}
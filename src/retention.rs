@@ -0,0 +1,118 @@
+use crate::audit_log::audit_log_repository;
+use crate::configuration::RetentionSettings;
+use crate::coupon_archive::coupon_archive_repository;
+use crate::envelope::Envelope;
+use crate::events::outbox;
+use crate::scheduler::Scheduler;
+use crate::webhook::webhook_repository;
+use actix_web::{
+    post, HttpRequest, HttpResponse,
+    web::Data,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+use std::future::Future;
+
+
+/// How many rows each table had purged.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PurgeReport {
+    pub auth_audit_log: u64,
+    pub coupons_archive: u64,
+    pub webhook_deliveries: u64,
+    pub outbox: u64,
+}
+
+/// Delete the rows older than their retention, in batches so the tables are not locked for long.
+/// The tables with a retention of `0` days are kept forever.
+pub async fn purge(settings: &RetentionSettings, pool: &MySqlPool) -> Result<PurgeReport, sqlx::Error> {
+    let mut report = PurgeReport::default();
+    if let Some(cutoff) = cutoff(settings.audit_log_days) {
+        report.auth_audit_log = purge_in_batches(settings.batch_size, |limit| audit_log_repository::delete_before(cutoff, limit, pool)).await?;
+    }
+    if let Some(cutoff) = cutoff(settings.coupons_archive_days) {
+        report.coupons_archive = purge_in_batches(settings.batch_size, |limit| coupon_archive_repository::delete_archived_before(cutoff, limit, pool)).await?;
+    }
+    if let Some(cutoff) = cutoff(settings.webhook_deliveries_days) {
+        report.webhook_deliveries = purge_in_batches(settings.batch_size, |limit| webhook_repository::delete_deliveries_before(cutoff, limit, pool)).await?;
+    }
+    if let Some(cutoff) = cutoff(settings.outbox_days) {
+        report.outbox = purge_in_batches(settings.batch_size, |limit| outbox::delete_published_before(cutoff, limit, pool)).await?;
+    }
+    return Ok(report);
+}
+
+/// Run `purge` every `interval_seconds` (unless it is `0`), or on `schedules.purge`.
+pub fn register(scheduler: &mut Scheduler, settings: &RetentionSettings, pool: MySqlPool) {
+    let settings = settings.clone();
+    scheduler.register("purge", settings.interval_seconds, move || {
+        let (settings, pool) = (settings.clone(), pool.clone());
+        async move {
+            let report = purge(&settings, &pool).await
+                .map_err(|error| format!("Failed to purge the data past its retention: {:?}", error))?;
+            tracing::info!("Purged {} audit log entries, {} archived coupons, {} webhook deliveries and {} published events.", report.auth_audit_log, report.coupons_archive, report.webhook_deliveries, report.outbox);
+            return Ok(());
+        }
+    });
+}
+
+fn cutoff(retention_days: u32) -> Option<NaiveDateTime> {
+    if (retention_days == 0){
+        return None;
+    }
+    return Some(Utc::now().naive_utc() - Duration::days(i64::from(retention_days)));
+}
+
+async fn purge_in_batches<F, Fut>(batch_size: u32, mut delete: F) -> Result<u64, sqlx::Error>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<u64, sqlx::Error>>,
+{
+    let mut purged = 0;
+    loop {
+        let deleted = delete(batch_size).await?;
+        purged += deleted;
+        if (deleted < u64::from(batch_size)){
+            return Ok(purged);
+        }
+    }
+}
+
+#[tracing::instrument(name = "Purge the data past its retention", skip(http_request, settings, pool))]
+#[post("/purge")]
+pub async fn purge_now(http_request: HttpRequest, settings: Data<RetentionSettings>, pool: Data<MySqlPool>) -> Result<HttpResponse, actix_web::Error> {
+    let report = purge(&settings, &pool).await
+        .map_err(|error| {
+            tracing::error!("Failed to purge the data past its retention: {:?}", error);
+            actix_web::error::ErrorInternalServerError("Failed to purge the data past its retention.")
+        })?;
+    tracing::info!("Purged {} audit log entries, {} archived coupons, {} webhook deliveries and {} published events.", report.auth_audit_log, report.coupons_archive, report.webhook_deliveries, report.outbox);
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, report)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cutoff, purge_in_batches};
+    use claim::{assert_none, assert_ok};
+
+    #[tokio::test]
+    async fn batches_are_deleted_until_one_is_not_full(){
+        let mut remaining: u64 = 25;
+        let mut batches = 0;
+        let purged = purge_in_batches(10, |limit| {
+            batches += 1;
+            let deleted = remaining.min(u64::from(limit));
+            remaining -= deleted;
+            async move { Ok(deleted) }
+        }).await;
+
+        assert_eq!(assert_ok!(purged), 25);
+        assert_eq!(batches, 3);
+    }
+
+    #[test]
+    fn a_retention_of_0_days_keeps_the_rows_forever(){
+        assert_none!(cutoff(0));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::configuration::RetrySettings;
+use crate::coupon::CouponError;
+use rand::Rng;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+
+
+// of the stores not wrapped in a `RetryingCouponStore`
+pub const NO_RETRIES: RetrySettings = RetrySettings { max_retries: 0, initial_backoff_milliseconds: 0, max_backoff_milliseconds: 0 };
+
+/// Errors of a database blip, the same operation can succeed when attempted again.
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for sqlx::Error {
+    fn is_transient(&self) -> bool {
+        return match self {
+            // e.g. the connection was reset or closed by the server
+            sqlx::Error::Io(_) => true,
+            sqlx::Error::Database(error) => {
+                // MySQL `ER_LOCK_WAIT_TIMEOUT`, its SQLSTATE is the generic `HY000`
+                if let Some(error) = error.try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>() {
+                    if (error.number() == 1205){
+                        return true;
+                    }
+                }
+                // `40001` is a deadlock (MySQL) or a serialization failure (Postgres), `40P01` a deadlock (Postgres),
+                // `5` and `6` are `SQLITE_BUSY` and `SQLITE_LOCKED`
+                matches!(error.code().as_deref(), Some("40001") | Some("40P01") | Some("5") | Some("6"))
+            },
+            _ => false,
+        };
+    }
+}
+
+impl Transient for CouponError {
+    fn is_transient(&self) -> bool {
+        return match self {
+            CouponError::UnexpectedError(error) => error.downcast_ref::<sqlx::Error>().map_or(false, |error| error.is_transient()),
+            _ => false,
+        };
+    }
+}
+
+/// Run `attempt` until it succeeds, fails with an error that is not transient or runs out of retries.
+/// `attempt` must be safe to run again, e.g. a single query or a whole transaction.
+pub async fn with_retry<T, E, F, Fut>(settings: &RetrySettings, operation: &str, mut attempt: F) -> Result<T, E>
+where
+    E: Transient + Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt().await {
+            Err(error) if error.is_transient() && retries < settings.max_retries => {
+                retries += 1;
+                let backoff = backoff(settings, retries);
+                tracing::warn!("{} failed with a transient error, retry {} of {} in {:?}: {}", operation, retries, settings.max_retries, backoff, error);
+                tokio::time::sleep(backoff).await;
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Exponential backoff of the `retry` (from 1), half of it random so the retries of concurrent requests spread out.
+pub fn backoff(settings: &RetrySettings, retry: u32) -> Duration {
+    let exponential = settings.initial_backoff_milliseconds.saturating_mul(2u64.saturating_pow(retry - 1));
+    let backoff = exponential.min(settings.max_backoff_milliseconds);
+    let jitter = rand::thread_rng().gen_range(0..=backoff / 2);
+    return Duration::from_millis(backoff - backoff / 2 + jitter);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff, with_retry};
+    use crate::configuration::RetrySettings;
+    use claim::assert_err;
+    use std::time::Duration;
+
+    fn settings() -> RetrySettings {
+        return RetrySettings { max_retries: 2, initial_backoff_milliseconds: 100, max_backoff_milliseconds: 300 };
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max(){
+        for _ in 0..100 {
+            let first = backoff(&settings(), 1);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100), "{:?}", first);
+            let second = backoff(&settings(), 2);
+            assert!(second >= Duration::from_millis(100) && second <= Duration::from_millis(200), "{:?}", second);
+            let capped = backoff(&settings(), 10);
+            assert!(capped >= Duration::from_millis(150) && capped <= Duration::from_millis(300), "{:?}", capped);
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried_until_the_max_retries(){
+        let mut attempts = 0;
+        let result: Result<(), sqlx::Error> = with_retry(&settings(), "Test", || {
+            attempts += 1;
+            async { Err(sqlx::Error::Io(std::io::ErrorKind::ConnectionReset.into())) }
+        }).await;
+
+        assert_err!(result);
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_retried(){
+        let mut attempts = 0;
+        let result: Result<(), sqlx::Error> = with_retry(&settings(), "Test", || {
+            attempts += 1;
+            async { Err(sqlx::Error::RowNotFound) }
+        }).await;
+
+        assert_err!(result);
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::envelope::Envelope;
+use actix_web::{
+    get, HttpRequest, HttpResponse,
+    web::Data,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+
+/// The periodic tasks, the keys of `schedules`.
+pub const TASKS: [&str; 4] = ["archive_expired_coupons", "purge", "expiration_report", "export_coupons"];
+
+/// When a task runs: every `interval_seconds` of its settings, or on the cron expression of `schedules` in its place.
+#[derive(Debug, Clone)]
+pub enum Trigger {
+    // the first run is on startup
+    Every(Duration),
+    // `sec min hour day_of_month month day_of_week [year]`, in UTC
+    Cron(cron::Schedule),
+}
+
+impl Trigger {
+    pub fn cron(expression: &str) -> Result<Self, String> {
+        return cron::Schedule::from_str(expression)
+            .map(Trigger::Cron)
+            .map_err(|e| format!("Invalid cron expression `{}`: {}.", expression, e));
+    }
+
+    // `None` when the cron expression has no date left
+    fn first_run(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        return match self {
+            Trigger::Every(_) => Some(now),
+            Trigger::Cron(schedule) => schedule.after(&now).next(),
+        };
+    }
+
+    fn next_run(&self, last_run: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        return match self {
+            Trigger::Every(interval) => chrono::Duration::from_std(*interval).ok().map(|interval| last_run + interval),
+            Trigger::Cron(schedule) => schedule.after(&last_run).next(),
+        };
+    }
+
+    fn describe(&self) -> String {
+        return match self {
+            Trigger::Every(interval) => format!("every {} seconds", interval.as_secs()),
+            Trigger::Cron(schedule) => schedule.to_string(),
+        };
+    }
+}
+
+/// A task of the scheduler of this instance, listed on `GET /admin/jobs/schedules`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TaskStatus {
+    pub name: String,
+    pub schedule: String,
+    pub last_run: Option<NaiveDateTime>,
+    // of the last run, `None` when it succeeded
+    pub last_error: Option<String>,
+    pub next_run: Option<NaiveDateTime>,
+}
+
+/// The status of the tasks, updated by the scheduler on every run.
+#[derive(Debug, Clone, Default)]
+pub struct SchedulerStatus(Arc<RwLock<BTreeMap<&'static str, TaskStatus>>>);
+
+impl SchedulerStatus {
+    pub fn list(&self) -> Vec<TaskStatus> {
+        return self.0.read().unwrap().values().cloned().collect();
+    }
+
+    fn update(&self, name: &'static str, update: impl FnOnce(&mut TaskStatus)) {
+        if let Some(status) = self.0.write().unwrap().get_mut(name) {
+            update(status);
+        }
+    }
+}
+
+type Task = Box<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// Runs the periodic tasks of the instance, each one on its `Trigger`.
+/// The tasks that must run once for all the instances queue a job (`job_service::enqueue_unless_queued`) instead of doing the work.
+pub struct Scheduler {
+    schedules: BTreeMap<String, String>,
+    tasks: Vec<(&'static str, Trigger, Task)>,
+    status: SchedulerStatus,
+}
+
+impl Scheduler {
+    /// `schedules` are the cron expressions replacing the intervals of the tasks, validated by `Settings::validate()`.
+    pub fn new(schedules: &BTreeMap<String, String>) -> Self {
+        return Self { schedules: schedules.clone(), tasks: Vec::new(), status: SchedulerStatus::default() };
+    }
+
+    /// Run the task on its cron expression of `schedules` if any, every `interval_seconds` otherwise.
+    /// Not registered when neither is set (`interval_seconds` is `0`).
+    pub fn register<F, Fut>(&mut self, name: &'static str, interval_seconds: u64, run: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let trigger = match self.schedules.get(name) {
+            Some(expression) => match Trigger::cron(expression) {
+                Ok(trigger) => trigger,
+                Err(error) => {
+                    tracing::error!("The task `{}` is not scheduled: {}", name, error);
+                    return;
+                },
+            },
+            None if (interval_seconds > 0) => Trigger::Every(Duration::from_secs(interval_seconds)),
+            None => return,
+        };
+        self.status.0.write().unwrap().insert(name, TaskStatus {
+            name: name.to_string(),
+            schedule: trigger.describe(),
+            last_run: None,
+            last_error: None,
+            next_run: None,
+        });
+        self.tasks.push((name, trigger, Box::new(move || Box::pin(run()))));
+    }
+
+    /// Start the tasks for as long as the server runs.
+    pub fn start(self) -> SchedulerStatus {
+        for (name, trigger, task) in self.tasks {
+            let status = self.status.clone();
+            // set before the task starts, so it is listed with its first run right away
+            let mut next_run = trigger.first_run(Utc::now());
+            status.update(name, |status| status.next_run = next_run.map(|run_at| run_at.naive_utc()));
+            tokio::spawn(async move {
+                while let Some(run_at) = next_run {
+                    status.update(name, |status| status.next_run = Some(run_at.naive_utc()));
+                    if let Ok(delay) = (run_at - Utc::now()).to_std() {
+                        tokio::time::sleep(delay).await;
+                    }
+
+                    let started = Utc::now();
+                    let result = task().await;
+                    if let Err(error) = &result {
+                        tracing::error!("The task `{}` failed: {}", name, error);
+                    }
+                    status.update(name, |status| {
+                        status.last_run = Some(started.naive_utc());
+                        status.last_error = result.err();
+                    });
+                    next_run = trigger.next_run(started);
+                }
+                status.update(name, |status| status.next_run = None);
+            });
+        }
+        return self.status;
+    }
+}
+
+#[tracing::instrument(name = "Get the scheduled tasks", skip(http_request, status))]
+#[get("/jobs/schedules")]
+pub async fn get_schedules(http_request: HttpRequest, status: Data<SchedulerStatus>) -> HttpResponse {
+    return HttpResponse::Ok().json(Envelope::new(&http_request, status.list()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Trigger;
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_err, assert_ok};
+    use std::time::Duration;
+
+    #[test]
+    fn cron_triggers_run_on_the_next_matching_time(){
+        // every day at 03:00
+        let trigger = assert_ok!(Trigger::cron("0 0 3 * * *"));
+        let now = Utc.with_ymd_and_hms(2023, 1, 31, 10, 0, 0).unwrap();
+        assert_eq!(trigger.first_run(now), Some(Utc.with_ymd_and_hms(2023, 2, 1, 3, 0, 0).unwrap()));
+        assert_eq!(trigger.next_run(Utc.with_ymd_and_hms(2023, 2, 1, 3, 0, 0).unwrap()), Some(Utc.with_ymd_and_hms(2023, 2, 2, 3, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn interval_triggers_run_on_startup_then_every_interval(){
+        let trigger = Trigger::Every(Duration::from_secs(60));
+        let now = Utc.with_ymd_and_hms(2023, 1, 31, 10, 0, 0).unwrap();
+        assert_eq!(trigger.first_run(now), Some(now));
+        assert_eq!(trigger.next_run(now), Some(Utc.with_ymd_and_hms(2023, 1, 31, 10, 1, 0).unwrap()));
+    }
+
+    #[test]
+    fn invalid_cron_expressions_are_rejected(){
+        assert_err!(Trigger::cron("every day"));
+        assert_err!(Trigger::cron("0 0 25 * * *"));
+    }
+}
@@ -0,0 +1,318 @@
+#[cfg(feature = "aws")]
+use crate::configuration::AwsSecretsSettings;
+use crate::configuration::VaultSettings;
+use async_trait::async_trait;
+use config::{Config, ConfigError, Map, Source, Value, ValueKind};
+use secrecy::ExposeSecret;
+use std::time::Duration;
+
+
+/// Fetches the secrets referenced in the configuration, e.g. `vault://secret/coupon-api#database_password`,
+/// so the secrets don't have to be stored in the files.
+#[async_trait]
+pub trait SecretsProvider: Send + Sync {
+    /// Scheme of the references of the provider, e.g. `vault` for `vault://...`.
+    fn scheme(&self) -> &'static str;
+
+    async fn fetch(&self, reference: &str) -> Result<String, String>;
+}
+
+/// Replace every setting that is a reference to a secret (`<scheme>://...` of one of the `providers`) with the secret.
+/// The other settings are left as they are.
+pub async fn resolve(config: Config, providers: &[Box<dyn SecretsProvider>]) -> Result<Config, ConfigError> {
+    if (providers.is_empty()){
+        return Ok(config);
+    }
+    let mut references = vec![];
+    collect_references("", &config.collect()?, providers, &mut references);
+    if (references.is_empty()){
+        return Ok(config);
+    }
+
+    let mut builder = Config::builder().add_source(config);
+    for (key, reference, provider) in references {
+        let secret = provider.fetch(&reference).await
+            .map_err(|e| ConfigError::Message(format!("Failed to fetch the secret of `{}`: {}", key, e)))?;
+        builder = builder.set_override(key, secret)?;
+    }
+    return builder.build();
+}
+
+// (key of the setting, reference, provider of the reference)
+fn collect_references<'a>(prefix: &str, table: &Map<String, Value>, providers: &'a [Box<dyn SecretsProvider>], references: &mut Vec<(String, String, &'a dyn SecretsProvider)>) {
+    for (key, value) in table {
+        let key = if (prefix.is_empty()) { key.clone() } else { format!("{}.{}", prefix, key) };
+        match &value.kind {
+            ValueKind::Table(table) => collect_references(&key, table, providers, references),
+            ValueKind::String(value) => {
+                let provider = providers.iter().find(|provider| value.starts_with(&format!("{}://", provider.scheme())));
+                if let Some(provider) = provider {
+                    references.push((key, value.clone(), provider.as_ref()));
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Secrets of the KV version 2 engine of HashiCorp Vault, referenced as `vault://<mount>/<path>#<key>`,
+/// e.g. `vault://secret/coupon-api#database_password` is the `database_password` key of the `coupon-api` secret.
+pub struct VaultProvider {
+    settings: VaultSettings,
+    client: reqwest::Client,
+}
+
+impl VaultProvider {
+    pub fn new(settings: VaultSettings) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build Vault HTTP client.");
+        return Self { settings, client };
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for VaultProvider {
+    fn scheme(&self) -> &'static str {
+        return "vault";
+    }
+
+    async fn fetch(&self, reference: &str) -> Result<String, String> {
+        let (mount, path, key) = parse_vault_reference(reference)?;
+        let token = match &self.settings.token {
+            Some(token) => token.expose_secret().clone(),
+            // the variable used by the Vault CLI
+            None => std::env::var("VAULT_TOKEN").map_err(|_| "`secrets.vault.token` or `VAULT_TOKEN` must be set.".to_string())?,
+        };
+
+        let url = format!("{}/v1/{}/data/{}", self.settings.address.trim_end_matches('/'), mount, path);
+        let response = self.client
+            .get(&url)
+            .header("X-Vault-Token", token)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to read `{}` from Vault: {}.", url, e))?;
+        let secret: serde_json::Value = response.json().await
+            .map_err(|e| format!("Failed to parse the response of Vault: {}.", e))?;
+
+        return secret["data"]["data"][key].as_str()
+            .map(|value| value.to_string())
+            .ok_or(format!("Vault secret `{}/{}` has no `{}` key.", mount, path, key));
+    }
+}
+
+// (mount, path, key) of `vault://<mount>/<path>#<key>`
+fn parse_vault_reference(reference: &str) -> Result<(&str, &str, &str), String> {
+    let invalid = || format!("Invalid Vault reference `{}`, expected `vault://<mount>/<path>#<key>`.", reference);
+    let (location, key) = reference.strip_prefix("vault://")
+        .and_then(|reference| reference.split_once('#'))
+        .ok_or_else(invalid)?;
+    let (mount, path) = location.split_once('/').ok_or_else(invalid)?;
+    if (mount.is_empty() || path.is_empty() || key.is_empty()){
+        return Err(invalid());
+    }
+    return Ok((mount, path, key));
+}
+
+/// Secrets of AWS Secrets Manager, referenced as `aws-sm://<name>`, or `aws-sm://<name>#<key>` for a key of a JSON secret
+/// (as the secrets of RDS), e.g. `aws-sm://coupon-api/database#password`.
+/// The credentials are the ones of the environment (e.g. the ECS task or EKS service account role).
+#[cfg(feature = "aws")]
+pub struct AwsSecretsManagerProvider {
+    settings: AwsSecretsSettings,
+}
+
+#[cfg(feature = "aws")]
+impl AwsSecretsManagerProvider {
+    pub fn new(settings: AwsSecretsSettings) -> Self {
+        return Self { settings };
+    }
+}
+
+#[cfg(feature = "aws")]
+#[async_trait]
+impl SecretsProvider for AwsSecretsManagerProvider {
+    fn scheme(&self) -> &'static str {
+        return "aws-sm";
+    }
+
+    async fn fetch(&self, reference: &str) -> Result<String, String> {
+        let (name, key) = parse_aws_secrets_manager_reference(reference)?;
+        let client = aws_sdk_secretsmanager::Client::new(&aws_config(&self.settings).await);
+        let output = client.get_secret_value()
+            .secret_id(name)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to read `{}` from AWS Secrets Manager: {}.", name, e))?;
+        let secret = output.secret_string()
+            .ok_or(format!("AWS Secrets Manager secret `{}` is not a string.", name))?;
+
+        let key = match key {
+            Some(key) => key,
+            None => return Ok(secret.to_string()),
+        };
+        let secret: serde_json::Value = serde_json::from_str(secret)
+            .map_err(|_| format!("AWS Secrets Manager secret `{}` is not JSON, it can't have a `{}` key.", name, key))?;
+        return match &secret[key] {
+            serde_json::Value::String(value) => Ok(value.clone()),
+            serde_json::Value::Null => Err(format!("AWS Secrets Manager secret `{}` has no `{}` key.", name, key)),
+            // e.g. the port of the RDS secrets
+            value => Ok(value.to_string()),
+        };
+    }
+}
+
+/// Parameters of AWS Systems Manager Parameter Store, referenced as `ssm://<name>`, e.g. `ssm:///coupon-api/database/password`
+/// for the `/coupon-api/database/password` parameter. The `SecureString` parameters are decrypted.
+#[cfg(feature = "aws")]
+pub struct SsmProvider {
+    settings: AwsSecretsSettings,
+}
+
+#[cfg(feature = "aws")]
+impl SsmProvider {
+    pub fn new(settings: AwsSecretsSettings) -> Self {
+        return Self { settings };
+    }
+}
+
+#[cfg(feature = "aws")]
+#[async_trait]
+impl SecretsProvider for SsmProvider {
+    fn scheme(&self) -> &'static str {
+        return "ssm";
+    }
+
+    async fn fetch(&self, reference: &str) -> Result<String, String> {
+        let name = reference.strip_prefix("ssm://")
+            .filter(|name| !name.is_empty())
+            .ok_or(format!("Invalid SSM reference `{}`, expected `ssm://<name>`.", reference))?;
+        let client = aws_sdk_ssm::Client::new(&aws_config(&self.settings).await);
+        let output = client.get_parameter()
+            .name(name)
+            .with_decryption(true)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to read `{}` from AWS SSM Parameter Store: {}.", name, e))?;
+        return output.parameter()
+            .and_then(|parameter| parameter.value())
+            .map(|value| value.to_string())
+            .ok_or(format!("AWS SSM parameter `{}` has no value.", name));
+    }
+}
+
+// the region and credentials of the environment, unless the region is configured
+#[cfg(feature = "aws")]
+async fn aws_config(settings: &AwsSecretsSettings) -> aws_config::SdkConfig {
+    let mut loader = aws_config::from_env();
+    if let Some(region) = &settings.region {
+        loader = loader.region(aws_sdk_ssm::Region::new(region.clone()));
+    }
+    return loader.load().await;
+}
+
+// (name, key) of `aws-sm://<name>#<key>`, the key is optional
+#[cfg(feature = "aws")]
+fn parse_aws_secrets_manager_reference(reference: &str) -> Result<(&str, Option<&str>), String> {
+    let invalid = || format!("Invalid AWS Secrets Manager reference `{}`, expected `aws-sm://<name>` or `aws-sm://<name>#<key>`.", reference);
+    let reference = reference.strip_prefix("aws-sm://").ok_or_else(invalid)?;
+    let (name, key) = match reference.split_once('#') {
+        Some((name, key)) => (name, Some(key)),
+        None => (reference, None),
+    };
+    if (name.is_empty() || key == Some("")){
+        return Err(invalid());
+    }
+    return Ok((name, key));
+}
+
+/// The references of a provider that isn't built in, e.g. `aws-sm://...` without the `aws` feature,
+/// so they fail instead of being taken as the secret itself.
+pub struct MissingFeatureProvider {
+    scheme: &'static str,
+    feature: &'static str,
+}
+
+impl MissingFeatureProvider {
+    pub fn new(scheme: &'static str, feature: &'static str) -> Self {
+        return Self { scheme, feature };
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for MissingFeatureProvider {
+    fn scheme(&self) -> &'static str {
+        return self.scheme;
+    }
+
+    async fn fetch(&self, _reference: &str) -> Result<String, String> {
+        return Err(format!("The `{}://` references can only be used when built with `--features {}`.", self.scheme, self.feature));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_vault_reference, resolve, MissingFeatureProvider, SecretsProvider};
+    #[cfg(feature = "aws")]
+    use super::parse_aws_secrets_manager_reference;
+    use async_trait::async_trait;
+    use claim::{assert_err, assert_ok};
+    use config::{Config, File, FileFormat};
+
+    struct FakeProvider;
+
+    #[async_trait]
+    impl SecretsProvider for FakeProvider {
+        fn scheme(&self) -> &'static str {
+            return "fake";
+        }
+
+        async fn fetch(&self, reference: &str) -> Result<String, String> {
+            return Ok(reference.replace("fake://", "secret of "));
+        }
+    }
+
+    #[tokio::test]
+    async fn references_are_replaced_by_the_secrets(){
+        let config = Config::builder()
+            .add_source(File::from_str("database:\n  host: localhost\n  password: fake://db\nredis_uri: fake://redis", FileFormat::Yaml))
+            .build()
+            .unwrap();
+
+        let config = assert_ok!(resolve(config, &[Box::new(FakeProvider)]).await);
+
+        assert_eq!(config.get_string("database.password").unwrap(), "secret of db");
+        assert_eq!(config.get_string("redis_uri").unwrap(), "secret of redis");
+        assert_eq!(config.get_string("database.host").unwrap(), "localhost");
+    }
+
+    #[tokio::test]
+    async fn references_of_a_missing_feature_fail(){
+        let config = Config::builder()
+            .add_source(File::from_str("database:\n  password: ssm:///coupon-api/database/password", FileFormat::Yaml))
+            .build()
+            .unwrap();
+
+        assert_err!(resolve(config, &[Box::new(MissingFeatureProvider::new("ssm", "aws"))]).await);
+    }
+
+    #[test]
+    fn vault_references_must_have_the_mount_path_and_key(){
+        assert_eq!(parse_vault_reference("vault://secret/coupon-api#database_password"), Ok(("secret", "coupon-api", "database_password")));
+        assert_eq!(parse_vault_reference("vault://secret/apps/coupon#key"), Ok(("secret", "apps/coupon", "key")));
+        assert_err!(parse_vault_reference("vault://secret/coupon-api"));
+        assert_err!(parse_vault_reference("vault://secret#key"));
+        assert_err!(parse_vault_reference("vault://secret/coupon-api#"));
+    }
+
+    #[cfg(feature = "aws")]
+    #[test]
+    fn aws_secrets_manager_references_can_have_a_key(){
+        assert_eq!(parse_aws_secrets_manager_reference("aws-sm://coupon-api/database"), Ok(("coupon-api/database", None)));
+        assert_eq!(parse_aws_secrets_manager_reference("aws-sm://coupon-api/database#password"), Ok(("coupon-api/database", Some("password"))));
+        assert_err!(parse_aws_secrets_manager_reference("aws-sm://"));
+        assert_err!(parse_aws_secrets_manager_reference("aws-sm://coupon-api/database#"));
+    }
+}
@@ -0,0 +1,34 @@
+use crate::api_key::{api_key_service, ApiKeyResponse};
+use crate::configuration::{RequestSigningSettings, SeedSettings};
+use crate::coupon::coupon_service;
+use crate::coupon::coupon_store::CouponStore;
+use crate::coupon::CouponError;
+use anyhow::Context;
+use sqlx::MySqlPool;
+
+
+#[derive(Debug)]
+pub struct SeedReport {
+    pub inserted: Vec<String>,
+    // codes that already existed
+    pub skipped: Vec<String>,
+    pub api_key: Option<ApiKeyResponse>,
+}
+
+/// Insert the demo coupons and issue the demo API key of `settings`, with a signing secret when `request_signing` has the encryption key.
+pub async fn seed(settings: &SeedSettings, request_signing: &RequestSigningSettings, store: &dyn CouponStore, pool: &MySqlPool) -> Result<SeedReport, anyhow::Error> {
+    let mut report = SeedReport { inserted: Vec::new(), skipped: Vec::new(), api_key: None };
+    for coupon in &settings.coupons {
+        match coupon_service::insert(coupon.clone(), store, pool).await {
+            Ok(_) => report.inserted.push(coupon.code.clone()),
+            Err(CouponError::AlreadyExistsError(_)) => report.skipped.push(coupon.code.clone()),
+            Err(error) => return Err(anyhow::Error::new(error).context(format!("Failed to insert coupon `{}`.", coupon.code))),
+        }
+    }
+    if let Some(api_key) = &settings.api_key {
+        let api_key = api_key_service::insert(api_key.clone(), request_signing, pool).await
+            .context("Failed to issue the API key.")?;
+        report.api_key = Some(api_key);
+    }
+    return Ok(report);
+}
@@ -1,51 +1,157 @@
 use crate::{
-    configuration::{DatabaseSettings, Settings},
-    authentication::{validator, authenticate},
+    access_log::AccessLog,
+    compression::AcceptedEncodings,
+    configuration::{CacheBackend, CacheSettings, CorsSettings, DatabaseBackend, DatabaseSettings, Reloadable, RequestSigningSettings, Settings, TlsSettings},
+    client_ip::TrustedProxies,
+    authentication::{Authenticate, authenticate, login, logout, refresh_session, revoke_token, oidc_login, oidc_callback, get_all_sessions, delete_session, Authorize, Permission},
+    api_key::{api_key_hash, api_key_service, api_key_usage::ApiKeyUsageRecorder, get_all_api_keys, get_api_key, get_api_key_usage, add_api_key, revoke_api_key, rotate_api_key},
+    audit_log::get_audit_log,
+    coupon_archive::{coupon_archive_service, get_archived_coupons},
+    coupon_import::{import_coupons, get_import_job},
+    events::{self, outbox},
+    expiration_report,
+    export::{self, get_exports, download_export},
+    job::{get_all_jobs, get_job, job_worker::{self, JobContext}},
+    mailer::Mailer,
+    notifications,
+    feature_flags::{FeatureFlags, get_all_flags, update_flag},
+    metrics::{get_metrics, Metrics},
+    retention::{self, purge_now},
+    scheduler::{get_schedules, Scheduler, SchedulerStatus},
+    user::{get_all_users, get_user, add_user, update_user, delete_user, enroll_totp, verify_totp},
+    rate_limit::{client_api_key, RateLimiter},
+    reload::{reload_configuration, get_log_level, update_log_level},
+    request_id::RequestIdHeader,
+    telemetry::{self, PoolMetrics},
+    webhook::{webhook_delivery, get_all_webhooks, get_webhook, get_webhook_deliveries, retry_webhook_delivery, add_webhook, update_webhook, delete_webhook},
     coupon::{
-        health_check, get_coupon, get_all_coupons, add_coupon, update_coupon,
-        delete_coupon, verify_coupon
+        coupon_batch::MaxBatchOperations,
+        coupon_cache::{CachedCouponStore, CouponCache, MemoryCouponCache, RedisCouponCache},
+        coupon_store::{CouponStore, MySqlCouponStore, ReplicatedCouponStore, RetryingCouponStore},
+        health_check, database_health_check, liveness_probe, readiness_probe, get_coupon, get_all_coupons, add_coupon, update_coupon,
+        delete_coupon, verify_coupon, validate_coupons, apply_coupons, batch_coupons, count_coupons, stream_coupon_events, get_coupon_changes, coupon_exists,
+        upsert_coupon, patch_coupon,
     },
 };
+use actix_cors::Cors;
 use actix_web::{
     web,
     App, HttpServer,
     dev::Server,
+    http::KeepAlive,
+    middleware::{Compress, Condition},
     web::{Data, scope},
 };
 use secrecy::ExposeSecret;
 use sqlx::{
-    MySqlPool,
-    mysql::MySqlPoolOptions,
+    Connection, Executor, MySqlPool,
+    mysql::{MySqlConnectOptions, MySqlPoolOptions},
 };
 use tracing_actix_web::TracingLogger;
 use std::net::TcpListener;
+use std::sync::Arc;
+#[cfg(feature = "postgres")]
+use crate::coupon::coupon_repository_postgres::PostgresCouponStore;
+#[cfg(feature = "sqlite")]
+use crate::coupon::coupon_repository_sqlite::SqliteCouponStore;
 
-pub fn run(listener: TcpListener, db_pool: MySqlPool, configuration: Settings) -> Result<Server, std::io::Error> {
+pub fn run(listener: TcpListener, db_pool: MySqlPool, coupon_store: Arc<dyn CouponStore>, cache_settings: Reloadable<CacheSettings>, configuration: Settings, prometheus: Metrics, scheduler_status: SchedulerStatus) -> Result<Server, std::io::Error> {
 
-    let api_key_auth = actix_web_httpauth::middleware::HttpAuthentication::with_fn(validator);
-    
+    // read before anything else, so a missing or invalid certificate stops the server from starting
+    let tls_config = configuration.application.tls.as_ref().map(get_tls_config).transpose()?;
+    let coupon_store: Data<dyn CouponStore> = Data::from(coupon_store);
     let db_pool = Data::new(db_pool);
+    let max_payload_bytes = configuration.application.max_payload_bytes;
+    let workers = configuration.application.workers;
+    let keep_alive = match configuration.application.keep_alive_seconds {
+        0 => KeepAlive::Disabled,
+        seconds => KeepAlive::Timeout(std::time::Duration::from_secs(seconds)),
+    };
+    // `Duration::ZERO` disables the timeout
+    let client_request_timeout = std::time::Duration::from_millis(configuration.application.client_request_timeout_milliseconds);
     let base_url = Data::new(ApplicationBaseUrl(configuration.application.base_url));
+    let trusted_proxies = Data::new(TrustedProxies(configuration.application.trusted_proxies));
+    let max_batch_operations = Data::new(MaxBatchOperations(configuration.application.max_batch_operations));
+    if (!api_key_hash::is_hashed(configuration.application.api_key.0.expose_secret())){
+        tracing::warn!("`application.api_key` is configured in plain text, replace it with the output of the `hash_api_key` binary.");
+    }
     let api_key = Data::new(configuration.application.api_key);
+    // shared by all the workers, wraps the authenticated scopes so the requests they reject are counted too.
+    // `0` only records the counts when the usage of a key is requested
+    let api_key_usage = ApiKeyUsageRecorder::new();
+    if (configuration.api_keys.usage_flush_interval_seconds > 0){
+        api_key_usage.spawn_flush(db_pool.get_ref().clone(), std::time::Duration::from_secs(configuration.api_keys.usage_flush_interval_seconds));
+    }
+    let api_key_usage_data = Data::new(api_key_usage.clone());
+    let api_key_settings = Data::new(configuration.api_keys);
+    let oidc_settings = Data::new(configuration.oidc);
+    let request_signing_settings = Data::new(configuration.request_signing);
+    let auth_lockout_settings = Data::new(configuration.auth_lockout);
+    // changed by `/admin/config/reload`
+    let session_settings = Data::new(Reloadable::new(configuration.session));
+    let rate_limit_settings = Data::new(Reloadable::new(configuration.rate_limit));
+    let cache_settings = Data::new(cache_settings);
+    let feature_flags = Data::new(FeatureFlags::new(configuration.feature_flags));
+    let retention_settings = Data::new(configuration.retention);
+    let export_settings = Data::new(configuration.export);
+    let prometheus_data = Data::new(prometheus.clone());
+    let scheduler_status = Data::new(scheduler_status);
+    let configuration_overrides = Data::new(configuration.overrides);
     let redis = redis::Client::open(configuration.redis_uri.expose_secret().to_string())
         .map_err(|e| anyhow::anyhow!(format!("Failed initialize redis client: {}.", e)))
         .unwrap();
+    let cors_settings = configuration.cors;
+    let access_log_settings = configuration.access_log;
+    let compression_settings = configuration.compression;
+    // created outside of the factory closure so all the workers share the same buckets
+    let rate_limiter = RateLimiter::new(rate_limit_settings.get_ref().clone());
+    rate_limiter.spawn_prune(std::time::Duration::from_secs(60));
+    let api_key_rate_limiter = rate_limiter.keyed_by(client_api_key);
 
     let server = HttpServer::new(move || {
-        App::new()
+        let app = App::new()
+            // innermost, so the error bodies set by `RequestIdHeader` are not compressed and the other middlewares see the compressed body
+            .wrap(Condition::new(compression_settings.enabled, Compress::default()))
+            .wrap(AcceptedEncodings::new(compression_settings.encodings.clone()))
+            // inside the span of `TracingLogger`, so it can use its request id when the client sent none
+            .wrap(RequestIdHeader)
             // TracingLogger instead of default actix_web logger to return with request_id (and other information aswell)
             .wrap(TracingLogger::default())
+            // CORS must wrap the authenticated scopes too, preflight requests don't carry the `Authorization` header
+            .wrap(get_cors(&cors_settings));
+        // reports the 500s and the panics with their request, once `error_reporting::init` started Sentry
+        #[cfg(feature = "sentry")]
+        let app = app.wrap(sentry_actix::Sentry::new());
+        app
+            // outside the CORS, so the rejected preflight requests are logged too
+            .wrap(AccessLog::new(access_log_settings.clone()))
+            // outermost, so the latency includes every middleware
+            .wrap(prometheus.clone())
 
             .app_data(db_pool.clone())
+            .app_data(coupon_store.clone())
             .app_data(base_url.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(max_batch_operations.clone())
             .app_data(api_key.clone())
+            .app_data(api_key_settings.clone())
+            .app_data(api_key_usage_data.clone())
+            .app_data(oidc_settings.clone())
+            .app_data(request_signing_settings.clone())
+            .app_data(auth_lockout_settings.clone())
+            .app_data(session_settings.clone())
+            .app_data(rate_limit_settings.clone())
+            .app_data(cache_settings.clone())
+            .app_data(feature_flags.clone())
+            .app_data(retention_settings.clone())
+            .app_data(export_settings.clone())
+            .app_data(prometheus_data.clone())
+            .app_data(scheduler_status.clone())
+            .app_data(configuration_overrides.clone())
             .app_data(web::Data::new(redis.clone()))
-
-            /*
-                all access routes (not authenticated)
-            */ 
-            .service(health_check)
-            .service(authenticate)
+            // `web::Json` and the raw bodies (e.g. read by the request signing) have separate limits
+            .app_data(web::JsonConfig::default().limit(max_payload_bytes))
+            .app_data(web::PayloadConfig::new(max_payload_bytes))
 
             /*
                 authenticated routes
@@ -54,18 +160,136 @@ pub fn run(listener: TcpListener, db_pool: MySqlPool, configuration: Settings) -
             // with an empty scope first
             .service(
                 // we need this scope so we can exclude the login service
-                // from being wrapped by the jwt middleware
+                // from being wrapped by the authentication middleware
                 scope("/coupon")
                     .service(get_all_coupons)
+                    // must be registered before `get_coupon`, otherwise `count`, `events` and `changes` are taken as codes
+                    .service(count_coupons)
+                    .service(stream_coupon_events)
+                    .service(get_coupon_changes)
                     .service(get_coupon)
+                    .service(coupon_exists)
                     .service(add_coupon)
                     .service(update_coupon)
+                    .service(patch_coupon)
+                    .service(upsert_coupon)
                     .service(delete_coupon)
                     .service(verify_coupon)
-                    .wrap(api_key_auth.clone())
+                    .service(validate_coupons)
+                    .service(apply_coupons)
+                    .service(import_coupons)
+                    // wrapped before the authentication, so it runs after it
+                    .wrap(Authorize::new(|request| Permission::for_coupon_route(request.method(), request.path())))
+                    // wrapped before the authentication, so the session's API key is known
+                    .wrap(api_key_rate_limiter.clone())
+                    .wrap(api_key_usage.clone())
+                    .wrap(Authenticate)
+                )
+            .service(
+                scope("/webhooks")
+                    .service(get_all_webhooks)
+                    .service(get_webhook)
+                    .service(get_webhook_deliveries)
+                    .service(retry_webhook_delivery)
+                    .service(add_webhook)
+                    .service(update_webhook)
+                    .service(delete_webhook)
+                    .wrap(Authorize::new(|_| Permission::ADMIN))
+                    .wrap(api_key_rate_limiter.clone())
+                    .wrap(api_key_usage.clone())
+                    .wrap(Authenticate)
+                )
+            .service(
+                scope("/jobs")
+                    .service(get_import_job)
+                    .wrap(Authorize::new(|_| Permission::COUPON_WRITE))
+                    .wrap(api_key_rate_limiter.clone())
+                    .wrap(api_key_usage.clone())
+                    .wrap(Authenticate)
+                )
+            .service(
+                scope("/batch")
+                    .service(batch_coupons)
+                    .wrap(api_key_rate_limiter.clone())
+                    .wrap(api_key_usage.clone())
+                    .wrap(Authenticate)
+                )
+            .service(
+                scope("/admin")
+                    .service(get_all_api_keys)
+                    .service(get_api_key)
+                    .service(add_api_key)
+                    .service(revoke_api_key)
+                    .service(rotate_api_key)
+                    .service(get_api_key_usage)
+                    .service(get_all_users)
+                    .service(get_user)
+                    .service(add_user)
+                    .service(update_user)
+                    .service(delete_user)
+                    .service(get_audit_log)
+                    .service(get_archived_coupons)
+                    .service(get_all_jobs)
+                    // before `/jobs/{id}`, which would take `schedules` as an id
+                    .service(get_schedules)
+                    .service(get_job)
+                    .service(purge_now)
+                    .service(get_exports)
+                    .service(get_all_sessions)
+                    .service(delete_session)
+                    .service(reload_configuration)
+                    .service(get_log_level)
+                    .service(update_log_level)
+                    .service(get_all_flags)
+                    .service(update_flag)
+                    .wrap(Authorize::new(|_| Permission::ADMIN))
+                    .wrap(api_key_rate_limiter.clone())
+                    .wrap(api_key_usage.clone())
+                    .wrap(Authenticate)
+                )
+            .service(
+                // open to every role, an admin without two-factor authentication logs in as `readonly` to enroll
+                scope("/account")
+                    .service(enroll_totp)
+                    .service(verify_totp)
+                    .wrap(api_key_rate_limiter.clone())
+                    .wrap(api_key_usage.clone())
+                    .wrap(Authenticate)
+                )
+
+            /*
+                all access routes (not authenticated)
+            */
+            // registered last, the empty scope matches every path left, these are limited by the client IP
+            .service(
+                scope("")
+                    .service(health_check)
+                    .service(database_health_check)
+                    .service(liveness_probe)
+                    .service(readiness_probe)
+                    .service(get_metrics)
+                    .service(authenticate)
+                    .service(login)
+                    .service(logout)
+                    .service(refresh_session)
+                    .service(revoke_token)
+                    .service(oidc_login)
+                    .service(oidc_callback)
+                    // authenticated by the signature of the URL
+                    .service(download_export)
+                    .wrap(rate_limiter.clone())
                 )
     })
-    .listen(listener)?
+    .keep_alive(keep_alive)
+    .client_request_timeout(client_request_timeout);
+    let server = match workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    let server = match tls_config {
+        Some(tls_config) => server.listen_rustls(listener, tls_config)?,
+        None => server.listen(listener)?,
+    }
     .run();
 
     return Ok(server);
@@ -86,6 +310,34 @@ pub struct ApplicationBaseUrl(pub String);
 impl Application {
     pub async fn build(configuration: Settings, test_database: bool) -> Result<Self, std::io::Error> {
         let connection_pool = get_connection_pool(&configuration.database, test_database);
+        if (configuration.database.warm_up){
+            warm_up_pool(&configuration.database, &connection_pool).await?;
+        }
+        let pool_metrics = PoolMetrics::with_slow_query_threshold("mysql", std::time::Duration::from_millis(configuration.database.slow_query_threshold_milliseconds));
+        let prometheus = Metrics::new();
+        report_pool_metrics(&configuration.database, connection_pool.clone(), pool_metrics.clone(), &prometheus);
+        // changed by `/admin/config/reload`
+        let cache_settings = Reloadable::new(configuration.cache.clone());
+        let coupon_store = get_coupon_store(&configuration, &cache_settings, &connection_pool, pool_metrics, &prometheus).await?;
+        encrypt_signing_secrets(&configuration.request_signing, &connection_pool).await?;
+        let mut scheduler = Scheduler::new(&configuration.schedules);
+        coupon_archive_service::register(&mut scheduler, &configuration.archive, connection_pool.clone());
+        retention::register(&mut scheduler, &configuration.retention, connection_pool.clone());
+        expiration_report::register(&mut scheduler, &configuration.expiration_report, connection_pool.clone());
+        export::register(&mut scheduler, configuration.export.as_ref(), connection_pool.clone());
+        let scheduler_status = scheduler.start();
+        events::init(&configuration).await?;
+        outbox::spawn_relay(configuration.outbox.clone(), connection_pool.clone());
+        notifications::init(&configuration);
+        job_worker::spawn(configuration.jobs.clone(), JobContext {
+            pool: connection_pool.clone(),
+            archive: configuration.archive.clone(),
+            client: webhook_delivery::client(),
+            expiration_report: configuration.expiration_report.clone(),
+            mailer: configuration.smtp.as_ref().map(Mailer::new).transpose()?,
+            export: configuration.export.clone(),
+            store: coupon_store.clone(),
+        });
 
         let address = format!("{}:{}"
             , configuration.application.host, configuration.application.port
@@ -98,7 +350,11 @@ impl Application {
         let server = run(
             listener,
             connection_pool,
+            coupon_store,
+            cache_settings,
             configuration,
+            prometheus,
+            scheduler_status,
         )?;
 
         // We "save" the bound port in one of `Application`'s fields
@@ -116,8 +372,209 @@ impl Application {
     }
 }
 
+// read before starting, so an invalid `request_signing.encryption_key` stops the server, then the secrets stored before it was set are encrypted
+async fn encrypt_signing_secrets(settings: &RequestSigningSettings, pool: &MySqlPool) -> Result<(), std::io::Error> {
+    let cipher = match settings.cipher().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))? {
+        Some(cipher) => cipher,
+        None => return Ok(()),
+    };
+    let encrypted = api_key_service::encrypt_signing_secrets(&cipher, pool).await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to encrypt the signing secrets: {}.", e)))?;
+    if (encrypted > 0){
+        tracing::info!("Encrypted the signing secrets of {} API keys.", encrypted);
+    }
+    return Ok(());
+}
+
+// on `/metrics`, and logged every `pool_metrics_interval_seconds` unless it is `0`
+fn report_pool_metrics<DB: sqlx::Database>(configuration: &DatabaseSettings, pool: sqlx::Pool<DB>, metrics: Arc<PoolMetrics>, prometheus: &Metrics) {
+    prometheus.register_pool(metrics.name(), pool.clone(), configuration.max_connections);
+    if (configuration.pool_metrics_interval_seconds > 0){
+        let interval = std::time::Duration::from_secs(configuration.pool_metrics_interval_seconds);
+        telemetry::report_pool_metrics(pool, metrics, configuration.max_connections, interval);
+    }
+}
+
+/// Open the `min_connections` of the pool and ping each one, they are then kept idle for the first requests.
+/// Fails if one can't be opened, instead of the first requests.
+async fn warm_up_pool(configuration: &DatabaseSettings, pool: &MySqlPool) -> Result<(), std::io::Error> {
+    let started = std::time::Instant::now();
+    // held together, otherwise the same connection would be acquired again
+    let mut connections = futures_util::future::try_join_all((0..configuration.min_connections).map(|_| pool.acquire())).await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to open the database connections: {}.", e)))?;
+    for connection in connections.iter_mut() {
+        connection.ping().await
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to ping the database: {}.", e)))?;
+    }
+    tracing::info!("Opened {} database connections in {:?}.", connections.len(), started.elapsed());
+    return Ok(());
+}
+
 pub fn get_connection_pool(configuration: &DatabaseSettings, test_database: bool) -> MySqlPool {
-    return MySqlPoolOptions::new()
-        .acquire_timeout(std::time::Duration::from_secs(2))
+    return mysql_pool_options(configuration)
         .connect_lazy_with(configuration.with_db(test_database));
-}
\ No newline at end of file
+}
+
+fn mysql_pool_options(configuration: &DatabaseSettings) -> MySqlPoolOptions {
+    let pool_options = MySqlPoolOptions::new()
+        .max_connections(configuration.max_connections)
+        .min_connections(configuration.min_connections)
+        .acquire_timeout(std::time::Duration::from_secs(configuration.acquire_timeout_seconds))
+        .idle_timeout(std::time::Duration::from_secs(configuration.idle_timeout_seconds));
+    let timeout = configuration.statement_timeout_milliseconds;
+    if (timeout == 0){
+        return pool_options;
+    }
+    // MySQL aborts the `SELECT`s running for longer than `max_execution_time` (milliseconds),
+    // the writes are bounded by `innodb_lock_wait_timeout` (whole seconds), what a stuck write waits for
+    let lock_wait_timeout = timeout.div_ceil(1000);
+    return pool_options.after_connect(move |conn, _| Box::pin(async move {
+        let sql = format!("SET SESSION max_execution_time = {}, SESSION innodb_lock_wait_timeout = {}", timeout, lock_wait_timeout);
+        conn.execute(sql.as_str()).await?;
+        return Ok(());
+    }));
+}
+
+/// The MySQL pool, or a Postgres or SQLite pool with the same sizing, depending on `coupon_backend`.
+/// The transient errors are retried according to `retry`.
+/// `db_pool_metrics` are the metrics of `db_pool`, the other pools get their own, and are registered on `prometheus`.
+/// The coupons are cached according to `cache_settings`, from `configuration.cache`.
+pub async fn get_coupon_store(configuration: &Settings, cache_settings: &Reloadable<CacheSettings>, db_pool: &MySqlPool, db_pool_metrics: Arc<PoolMetrics>, prometheus: &Metrics) -> Result<Arc<dyn CouponStore>, std::io::Error> {
+    let mut store = connect_coupon_store(&configuration.database, db_pool, db_pool_metrics, prometheus).await?;
+    // enabling the cache needs a restart, it can be disabled by setting its `ttl_seconds` to `0` and reloading the configuration
+    if (cache_settings.get().ttl_seconds > 0){
+        let cache: Arc<dyn CouponCache> = match configuration.cache.backend {
+            CacheBackend::Memory => Arc::new(MemoryCouponCache::new(cache_settings.clone())),
+            CacheBackend::Redis => {
+                let client = redis::Client::open(configuration.redis_uri.expose_secret().to_string())
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid `redis_uri`: {}.", e)))?;
+                let cache = RedisCouponCache::new(client, cache_settings.clone());
+                cache.subscribe();
+                Arc::new(cache)
+            },
+        };
+        let evict_again_after = configuration.database.read_replica_url.as_ref()
+            .map(|_| std::time::Duration::from_secs(configuration.cache.replica_lag_seconds));
+        store = Arc::new(CachedCouponStore::new(store, cache, evict_again_after));
+    }
+    return Ok(Arc::new(RetryingCouponStore::new(store, configuration.database.retry.clone())));
+}
+
+async fn connect_coupon_store(configuration: &DatabaseSettings, db_pool: &MySqlPool, db_pool_metrics: Arc<PoolMetrics>, prometheus: &Metrics) -> Result<Arc<dyn CouponStore>, std::io::Error> {
+    let primary = MySqlCouponStore::new(db_pool.clone(), db_pool_metrics);
+    return match configuration.coupon_backend {
+        DatabaseBackend::Mysql => match &configuration.read_replica_url {
+            None => Ok(Arc::new(primary)),
+            Some(url) => {
+                let options = url.expose_secret().parse::<MySqlConnectOptions>()
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid `database.read_replica_url`: {}.", e)))?;
+                let replica = mysql_pool_options(configuration)
+                    .connect_lazy_with(options);
+                let replica_metrics = PoolMetrics::with_slow_query_threshold("mysql_replica", std::time::Duration::from_millis(configuration.slow_query_threshold_milliseconds));
+                report_pool_metrics(configuration, replica.clone(), replica_metrics.clone(), prometheus);
+                Ok(Arc::new(ReplicatedCouponStore::new(primary, MySqlCouponStore::new(replica, replica_metrics))))
+            },
+        },
+        #[cfg(feature = "postgres")]
+        DatabaseBackend::Postgres => {
+            let url = configuration.postgres_url.as_ref()
+                .ok_or(std::io::Error::new(std::io::ErrorKind::InvalidInput, "`database.postgres_url` is not set."))?;
+            let timeout = configuration.statement_timeout_milliseconds;
+            let pool = sqlx::postgres::PgPoolOptions::new()
+                .max_connections(configuration.max_connections)
+                .min_connections(configuration.min_connections)
+                .acquire_timeout(std::time::Duration::from_secs(configuration.acquire_timeout_seconds))
+                .idle_timeout(std::time::Duration::from_secs(configuration.idle_timeout_seconds))
+                // `0` also disables the timeout of Postgres
+                .after_connect(move |conn, _| Box::pin(async move {
+                    conn.execute(format!("SET statement_timeout = {}", timeout).as_str()).await?;
+                    return Ok(());
+                }))
+                .connect_lazy(url.expose_secret())
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid `database.postgres_url`: {}.", e)))?;
+            let metrics = PoolMetrics::with_slow_query_threshold("postgres", std::time::Duration::from_millis(configuration.slow_query_threshold_milliseconds));
+            report_pool_metrics(configuration, pool.clone(), metrics.clone(), prometheus);
+            Ok(Arc::new(PostgresCouponStore::new(pool, metrics)))
+        },
+        #[cfg(not(feature = "postgres"))]
+        DatabaseBackend::Postgres => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Built without the `postgres` feature.")),
+        #[cfg(feature = "sqlite")]
+        DatabaseBackend::Sqlite => {
+            let invalid = |error: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, error);
+            let url = configuration.sqlite_url.as_ref()
+                .ok_or(invalid("`database.sqlite_url` is not set.".to_string()))?;
+            let options = url.parse::<sqlx::sqlite::SqliteConnectOptions>()
+                .map_err(|e| invalid(format!("Invalid `database.sqlite_url`: {}.", e)))?
+                .create_if_missing(true);
+            let mut pool_options = sqlx::sqlite::SqlitePoolOptions::new()
+                .max_connections(configuration.max_connections)
+                .acquire_timeout(std::time::Duration::from_secs(configuration.acquire_timeout_seconds));
+            // each connection has its own in-memory database, a single one is kept open for the lifetime of the server
+            if (url.contains(":memory:")){
+                pool_options = pool_options.max_connections(1).min_connections(1).idle_timeout(None).max_lifetime(None);
+            }
+            let pool = pool_options.connect_with(options).await
+                .map_err(|e| invalid(format!("Failed to open `database.sqlite_url`: {}.", e)))?;
+            // a local database, migrated on startup so there is nothing to set up
+            sqlx::migrate!("./migrations_sqlite").run(&pool).await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to migrate the SQLite database: {}.", e)))?;
+            let metrics = PoolMetrics::with_slow_query_threshold("sqlite", std::time::Duration::from_millis(configuration.slow_query_threshold_milliseconds));
+            report_pool_metrics(configuration, pool.clone(), metrics.clone(), prometheus);
+            Ok(Arc::new(SqliteCouponStore::new(pool, metrics)))
+        },
+        #[cfg(not(feature = "sqlite"))]
+        DatabaseBackend::Sqlite => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Built without the `sqlite` feature.")),
+    };
+}
+
+/// HTTPS with rustls, using its safe defaults (TLS 1.2 and 1.3) and without client certificates.
+pub fn get_tls_config(settings: &TlsSettings) -> Result<rustls::ServerConfig, std::io::Error> {
+    let invalid = |error: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, error);
+
+    let cert_chain: Vec<rustls::Certificate> = rustls_pemfile::certs(&mut settings.cert_chain()?.as_slice())
+        .map_err(|e| invalid(format!("Failed to parse TLS certificate: {}.", e)))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    if (cert_chain.is_empty()){
+        return Err(invalid("TLS certificate has no `CERTIFICATE` PEM block.".to_string()));
+    }
+
+    let private_key = rustls_pemfile::read_all(&mut settings.private_key()?.expose_secret().as_slice())
+        .map_err(|e| invalid(format!("Failed to parse TLS private key: {}.", e)))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or(invalid("TLS private key has no `PRIVATE KEY` PEM block.".to_string()))?;
+
+    return rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, private_key)
+        .map_err(|e| invalid(format!("Invalid TLS certificate or private key: {}.", e)));
+}
+
+pub fn get_cors(settings: &CorsSettings) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(settings.allowed_methods.iter().map(|method| method.as_str()))
+        .allowed_headers(settings.allowed_headers.iter().map(|header| header.as_str()))
+        // the version of the coupon, for `If-Match`, the pagination of the lists, the rate limit,
+        // the request id to report an error and the deprecated routes
+        .expose_headers([
+            "ETag", "Link", "X-Total-Count",
+            "X-RateLimit-Limit", "X-RateLimit-Remaining", "X-RateLimit-Reset", "Retry-After",
+            "X-Request-Id", "Deprecation", "Sunset",
+        ])
+        .max_age(settings.max_age);
+
+    for origin in &settings.allowed_origins {
+        if (origin == "*"){
+            cors = cors.allow_any_origin();
+        } else {
+            cors = cors.allowed_origin(origin);
+        }
+    }
+    return cors;
+}
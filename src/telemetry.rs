@@ -1,9 +1,21 @@
+use crate::configuration::{LogFileSettings, LogRotation, OtlpSettings};
 use tracing::subscriber::set_global_default;
 use tracing::Subscriber;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_log::LogTracer;
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
+use tracing_subscriber::{layer::SubscriberExt, reload, EnvFilter, Registry};
 use tracing_subscriber::fmt::MakeWriter;
+use once_cell::sync::OnceCell;
+use sqlx::{Database, Pool};
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// filter of the global subscriber, replaced by `set_log_filter`
+static LOG_FILTER: OnceCell<reload::Handle<EnvFilter, Registry>> = OnceCell::new();
 
 // Compose multiple layers into a `tracing`'s subscriber.
 ///
@@ -17,6 +29,20 @@ use tracing_subscriber::fmt::MakeWriter;
 /// later on.
 /// 
 pub fn get_subscriber<Sink>(name: String, env_filter: String, sink: Sink) -> impl Subscriber + Send + Sync
+    where
+        Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let (subscriber, _) = get_subscriber_with(name, env_filter, sink, None, None)
+        .expect("The subscriber can only fail to start the OTLP exporter.");
+    return subscriber;
+}
+
+/// Same as `get_subscriber`, the logs are also written to the rotated files of `log_file` when it is set,
+/// and the spans exported to an OpenTelemetry collector (e.g. Jaeger, Tempo) when `otlp` is set.
+/// The exporter needs the `otlp` feature and a Tokio runtime.
+/// The files are written by a background thread, the lines still buffered are flushed when the returned guard is dropped,
+/// so it must be kept until exiting.
+pub fn get_subscriber_with<Sink>(name: String, env_filter: String, sink: Sink, otlp: Option<&OtlpSettings>, log_file: Option<&LogFileSettings>) -> Result<(impl Subscriber + Send + Sync, Option<WorkerGuard>), String>
     where
         // This "weird" syntax is a higher-ranked trait bound (HRTB)
         // It basically means that Sink implements the `MakeWriter`
@@ -29,14 +55,88 @@ pub fn get_subscriber<Sink>(name: String, env_filter: String, sink: Sink) -> imp
     // if the RUST_LOG environment variable has not been set.
     let env_filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new(env_filter));
+    // reloadable, so the log level can be changed without restarting, see `set_log_filter`
+    let (env_filter, handle) = reload::Layer::new(env_filter);
+    // only the first subscriber can be the global default, so its filter is the one kept
+    let _ = LOG_FILTER.set(handle);
+    // same format as the `sink`, for the hosts without a log shipper reading it
+    // the requests don't wait for the disk, see `tracing_appender::non_blocking`
+    let (file_writer, guard) = match log_file {
+        Some(settings) => {
+            let appender = RollingFileAppender::new(settings.rotation.into(), &settings.directory, &settings.file_name_prefix);
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(writer), Some(guard))
+        },
+        None => (None, None),
+    };
+    let file_formatting_layer = file_writer.map(|writer| {
+        return BunyanFormattingLayer::new(name.clone(), writer);
+    });
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
+    #[cfg(feature = "otlp")]
+    let otlp_layer = match otlp {
+        Some(settings) => Some(otlp_layer(settings)?),
+        None => None,
+    };
+    // without the feature `Settings::validate()` rejects `otlp`, so there is nothing to attach
+    #[cfg(not(feature = "otlp"))]
+    let otlp_layer = otlp.and(None::<tracing_subscriber::layer::Identity>);
+    // the `error` events are reported to Sentry, with the other events as breadcrumbs,
+    // once `error_reporting::init` started it (nothing is sent otherwise)
+    #[cfg(feature = "sentry")]
+    let sentry_layer = Some(sentry_tracing::layer());
+    #[cfg(not(feature = "sentry"))]
+    let sentry_layer = None::<tracing_subscriber::layer::Identity>;
 
     // The `with` method is provided by `SubscriberExt`, an extension
     // trait for `Subscriber` exposed by `tracing_subscriber`
-    Registry::default()
+    let subscriber = Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(file_formatting_layer)
+        .with(otlp_layer)
+        .with(sentry_layer);
+    return Ok((subscriber, guard));
+}
+
+// exports the spans in batches over gRPC, and propagates their context with the W3C `traceparent` header,
+// which `TracingLogger` reads on the incoming requests
+#[cfg(feature = "otlp")]
+fn otlp_layer<S>(settings: &OtlpSettings) -> Result<tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry::sdk::trace::Tracer>, String>
+    where
+        S: Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry_otlp::WithExportConfig;
+
+    opentelemetry::global::set_text_map_propagator(opentelemetry::sdk::propagation::TraceContextPropagator::new());
+    let resource = opentelemetry::sdk::Resource::new(vec![
+        opentelemetry::KeyValue::new("service.name", settings.service_name.clone()),
+    ]);
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(&settings.endpoint))
+        .with_trace_config(opentelemetry::sdk::trace::config().with_resource(resource))
+        .install_batch(opentelemetry::runtime::Tokio)
+        .map_err(|e| format!("Failed to start the OTLP exporter: {}.", e))?;
+    return Ok(tracing_opentelemetry::layer().with_tracer(tracer));
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        return match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        };
+    }
+}
+
+/// Export the spans not exported yet by `get_subscriber_with_otlp`, before exiting.
+pub fn shutdown_tracer() {
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
 }
 
 // Register a subscriber as global default to process span data.
@@ -50,3 +150,142 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     // what subscriber should be used to process spans.
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+/// Replace the filter of the subscriber created by `get_subscriber`, e.g. with `debug` or `coupon_api=debug,info`.
+pub fn set_log_filter(filter: &str) -> Result<(), String> {
+    let env_filter = EnvFilter::try_new(filter)
+        .map_err(|e| format!("Invalid log filter `{}`: {}.", filter, e))?;
+    let handle = LOG_FILTER.get()
+        .ok_or("There is no subscriber created by `get_subscriber`.")?;
+    return handle.reload(env_filter)
+        .map_err(|e| format!("Failed to reload log filter: {}.", e));
+}
+
+/// Filter of the subscriber created by `get_subscriber`, `None` without one.
+pub fn log_filter() -> Option<String> {
+    return LOG_FILTER.get()?.with_current(|filter| filter.to_string()).ok();
+}
+
+/// Acquisitions of the connections of a pool (count, wait time and timeouts) since the last report of `report_pool_metrics`,
+/// and the queries on its connections slower than `slow_query_threshold`.
+#[derive(Debug)]
+pub struct PoolMetrics {
+    name: &'static str,
+    // `0` doesn't log the slow queries
+    slow_query_threshold: Duration,
+    acquires: AtomicU64,
+    wait_microseconds: AtomicU64,
+    max_wait_microseconds: AtomicU64,
+    acquire_timeouts: AtomicU64,
+}
+
+impl PoolMetrics {
+    pub fn new(name: &'static str) -> Arc<Self> {
+        return Self::with_slow_query_threshold(name, Duration::ZERO);
+    }
+
+    pub fn with_slow_query_threshold(name: &'static str, slow_query_threshold: Duration) -> Arc<Self> {
+        return Arc::new(Self {
+            name,
+            slow_query_threshold,
+            acquires: AtomicU64::new(0),
+            wait_microseconds: AtomicU64::new(0),
+            max_wait_microseconds: AtomicU64::new(0),
+            acquire_timeouts: AtomicU64::new(0),
+        });
+    }
+
+    pub fn name(&self) -> &'static str {
+        return self.name;
+    }
+
+    /// Measure how long `acquire` (e.g. `pool.acquire()` or `pool.begin()`) waited for a connection.
+    pub async fn time_acquire<T>(&self, acquire: impl Future<Output = Result<T, sqlx::Error>>) -> Result<T, sqlx::Error> {
+        let start = Instant::now();
+        let result = acquire.await;
+        let wait = start.elapsed().as_micros() as u64;
+        self.acquires.fetch_add(1, Ordering::Relaxed);
+        self.wait_microseconds.fetch_add(wait, Ordering::Relaxed);
+        self.max_wait_microseconds.fetch_max(wait, Ordering::Relaxed);
+        if let Err(sqlx::Error::PoolTimedOut) = &result {
+            self.acquire_timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+        return result;
+    }
+
+    /// Run `query`, logging a `slow_query` warning with its `name` (e.g. `get_by_code`) when it is slower than the threshold.
+    pub async fn time_query<T>(&self, name: &'static str, query: impl Future<Output = Result<T, sqlx::Error>>) -> Result<T, sqlx::Error> {
+        let start = Instant::now();
+        let result = query.await;
+        let duration = start.elapsed();
+        if (is_slow(duration, self.slow_query_threshold)){
+            tracing::warn!(
+                target: "slow_query",
+                pool = self.name,
+                query = name,
+                duration_milliseconds = duration.as_secs_f64() * 1000.0,
+                threshold_milliseconds = self.slow_query_threshold.as_millis() as u64,
+                "Slow query `{}` on `{}` took {:?}.", name, self.name, duration
+            );
+        }
+        return result;
+    }
+}
+
+fn is_slow(duration: Duration, threshold: Duration) -> bool {
+    return !threshold.is_zero() && duration > threshold;
+}
+
+/// Log the statistics of the pool every `interval`, as the `pool_metrics` event, so a busy pool shows up in the logs:
+/// `idle` stays at `0` and the wait grows while the requests queue for a connection.
+pub fn report_pool_metrics<DB: Database>(pool: Pool<DB>, metrics: Arc<PoolMetrics>, max_connections: u32, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // the first tick is immediate, there is nothing to report yet
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let acquires = metrics.acquires.swap(0, Ordering::Relaxed);
+            let wait_microseconds = metrics.wait_microseconds.swap(0, Ordering::Relaxed);
+            let average_wait_milliseconds = if (acquires == 0) { 0.0 } else { wait_microseconds as f64 / acquires as f64 / 1000.0 };
+            tracing::info!(
+                target: "pool_metrics",
+                pool = metrics.name,
+                size = pool.size(),
+                idle = pool.num_idle(),
+                max_connections,
+                acquires,
+                average_wait_milliseconds,
+                max_wait_milliseconds = metrics.max_wait_microseconds.swap(0, Ordering::Relaxed) as f64 / 1000.0,
+                acquire_timeouts = metrics.acquire_timeouts.swap(0, Ordering::Relaxed),
+                "Connection pool `{}` statistics.", metrics.name
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_slow, PoolMetrics};
+    use claim::assert_err;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn acquire_timeouts_are_counted(){
+        let metrics = PoolMetrics::new("test");
+
+        assert_err!(metrics.time_acquire(async { Err::<(), _>(sqlx::Error::PoolTimedOut) }).await);
+        assert_err!(metrics.time_acquire(async { Err::<(), _>(sqlx::Error::PoolClosed) }).await);
+
+        assert_eq!(metrics.acquires.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.acquire_timeouts.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn queries_are_slow_over_the_threshold_unless_it_is_0(){
+        assert!(is_slow(Duration::from_millis(501), Duration::from_millis(500)));
+        assert!(!is_slow(Duration::from_millis(500), Duration::from_millis(500)));
+        assert!(!is_slow(Duration::from_secs(60), Duration::ZERO));
+    }
+}
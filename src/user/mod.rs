@@ -0,0 +1,8 @@
+pub mod user_controller;
+pub mod user_service;
+pub mod user_repository;
+pub mod model;
+pub mod totp;
+
+pub use user_controller::*;
+pub use model::*;
@@ -0,0 +1,3 @@
+pub mod user;
+
+pub use self::user::*;
@@ -0,0 +1,150 @@
+use crate::authentication::Role;
+use actix_web::{
+    ResponseError,
+    http::{StatusCode},
+};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Serialize, Deserialize};
+use sqlx::types::chrono::{NaiveDateTime};
+
+
+const PASSWORD_MIN_LENGTH: usize = 12;
+const PASSWORD_MAX_LENGTH: usize = 128;
+
+// no `Debug`, so the password hash doesn't end up in the logs
+#[derive(Clone, sqlx::FromRow)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+    pub password_hash: String,
+    pub role: String,
+    // hex of the TOTP secret, only checked on `/login` once `totp_enabled`
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    // time step of the last code accepted, the codes up to it can't be used again
+    pub totp_last_step: Option<i64>,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_updated: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UserCreateRequest {
+    pub username: String,
+    pub password: Secret<String>,
+    pub role: Role,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UserUpdateRequest {
+    // the password is only changed when sent
+    pub password: Option<Secret<String>>,
+    pub role: Role,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TotpVerifyRequest {
+    pub code: String,
+}
+
+/// Returned by `/account/totp`, to be added to an authenticator app.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TotpEnrollment {
+    // base32, for the apps where the secret is typed
+    pub secret: String,
+    // for the QR code
+    pub otpauth_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserResponse {
+    pub id: i32,
+    pub username: String,
+    pub role: Role,
+    pub totp_enabled: bool,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_updated: Option<NaiveDateTime>,
+}
+
+pub fn parse_username(username: &str) -> Result<String, String> {
+    let username = username.trim();
+    if (username.is_empty()){
+        return Err("Username can't be empty.".to_string());
+    }
+    if (username.chars().count() > 255){
+        return Err("Username can't be longer than 255 characters.".to_string());
+    }
+    return Ok(username.to_string());
+}
+
+pub fn validate_password(password: &Secret<String>) -> Result<(), String> {
+    let length = password.expose_secret().chars().count();
+    if (length < PASSWORD_MIN_LENGTH){
+        return Err(format!("Password must have at least {} characters.", PASSWORD_MIN_LENGTH));
+    }
+    if (length > PASSWORD_MAX_LENGTH){
+        return Err(format!("Password can't be longer than {} characters.", PASSWORD_MAX_LENGTH));
+    }
+    return Ok(());
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        return Self {
+            id: user.id,
+            username: user.username,
+            role: user.role.parse().unwrap_or(Role::Readonly),
+            totp_enabled: user.totp_enabled,
+            date_created: user.date_created,
+            date_updated: user.date_updated,
+        };
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UserError {
+    #[error("{0}")]
+    AlreadyExistsError(#[source] anyhow::Error),
+    #[error("Invalid username or password.")]
+    InvalidCredentialsError,
+    #[error("A valid two-factor authentication code is required.")]
+    InvalidTotpCodeError,
+    #[error("{0}")]
+    NotFoundError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for UserError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UserError::AlreadyExistsError(_) => StatusCode::CONFLICT,
+            UserError::InvalidCredentialsError => StatusCode::UNAUTHORIZED,
+            UserError::InvalidTotpCodeError => StatusCode::UNAUTHORIZED,
+            UserError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            UserError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            UserError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_username, validate_password};
+    use claim::{assert_err, assert_ok, assert_ok_eq};
+    use secrecy::Secret;
+
+    #[test]
+    fn username_is_trimmed_and_cant_be_empty(){
+        assert_ok_eq!(parse_username(" admin "), "admin".to_string());
+        assert_err!(parse_username("  "));
+    }
+
+    #[test]
+    fn password_length_is_validated(){
+        assert_err!(validate_password(&Secret::new("a".repeat(11))));
+        assert_ok!(validate_password(&Secret::new("a".repeat(12))));
+        assert_err!(validate_password(&Secret::new("a".repeat(129))));
+    }
+}
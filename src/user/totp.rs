@@ -0,0 +1,112 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+
+// RFC 6238 defaults, the ones supported by every authenticator app
+const STEP_SECONDS: i64 = 30;
+const DIGITS: usize = 6;
+// the codes of the previous and next steps are accepted too, for clocks slightly off
+const ALLOWED_SKEW_STEPS: i64 = 1;
+const SECRET_LENGTH: usize = 20;
+const ISSUER: &str = "Coupon API";
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = Uuid::new_v4().as_bytes().to_vec();
+    secret.extend_from_slice(Uuid::new_v4().as_bytes());
+    secret.truncate(SECRET_LENGTH);
+    return secret;
+}
+
+/// Code of the step of `timestamp`.
+pub fn code_at(secret: &[u8], timestamp: i64) -> String {
+    let counter = timestamp.div_euclid(STEP_SECONDS) as u64;
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret)
+        .expect("HMAC can take a key of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // dynamic truncation, see RFC 4226
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    return format!("{:0width$}", binary % 10u32.pow(DIGITS as u32), width = DIGITS);
+}
+
+/// Constant-time comparison against the codes around `timestamp`, returns the step of the code that matched.
+/// The codes of `last_step` and before are rejected, so a code can't be used again while it is still valid.
+pub fn verify(secret: &[u8], code: &str, timestamp: i64, last_step: Option<i64>) -> Option<i64> {
+    let code = code.trim();
+    let step = timestamp.div_euclid(STEP_SECONDS);
+    return (-ALLOWED_SKEW_STEPS..=ALLOWED_SKEW_STEPS)
+        .map(|skew| step + skew)
+        .filter(|step| last_step.map_or(true, |last_step| *step > last_step))
+        .fold(None, |verified, step| {
+            let expected = code_at(secret, step * STEP_SECONDS);
+            return if (bool::from(expected.as_bytes().ct_eq(code.as_bytes()))) { Some(step) } else { verified };
+        });
+}
+
+/// URL of the QR code scanned by the authenticator apps.
+pub fn otpauth_url(secret: &[u8], username: &str) -> String {
+    let label: String = url::form_urlencoded::byte_serialize(format!("{}:{}", ISSUER, username).as_bytes()).collect();
+    let issuer: String = url::form_urlencoded::byte_serialize(ISSUER.as_bytes()).collect();
+    return format!("otpauth://totp/{}?secret={}&issuer={}&digits={}&period={}", label, base32(secret), issuer, DIGITS, STEP_SECONDS);
+}
+
+/// RFC 4648 base32 without padding, the format the authenticator apps expect for the secret.
+pub fn base32(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while (bits >= 5){
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if (bits > 0){
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    return encoded;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base32, code_at, verify};
+
+    // secret of the RFC 6238 test vectors
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn codes_match_the_rfc_test_vectors(){
+        assert_eq!(code_at(SECRET, 59), "287082");
+        assert_eq!(code_at(SECRET, 1111111109), "081804");
+        assert_eq!(code_at(SECRET, 2000000000), "279037");
+    }
+
+    #[test]
+    fn codes_of_the_adjacent_steps_are_accepted(){
+        // step `37037036` of the timestamp `1111111109`
+        assert_eq!(verify(SECRET, "081804", 1111111109, None), Some(37037036));
+        assert_eq!(verify(SECRET, "081804", 1111111109 + 30, None), Some(37037036));
+        assert_eq!(verify(SECRET, "081804", 1111111109 + 90, None), None);
+        assert_eq!(verify(SECRET, "000000", 1111111109, None), None);
+    }
+
+    #[test]
+    fn codes_can_only_be_used_once(){
+        assert_eq!(verify(SECRET, "081804", 1111111109, Some(37037036)), None);
+        assert_eq!(verify(SECRET, "081804", 1111111109 + 30, Some(37037037)), None);
+        assert_eq!(verify(SECRET, "081804", 1111111109, Some(37037035)), Some(37037036));
+    }
+
+    #[test]
+    fn secret_is_encoded_in_base32(){
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32(SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    }
+}
@@ -0,0 +1,69 @@
+use super::model::{TotpVerifyRequest, UserCreateRequest, UserError, UserUpdateRequest};
+use super::user_service;
+use crate::authentication::Session;
+use crate::envelope::Envelope;
+use actix_web::{
+    web, get, post, put, delete, HttpMessage, HttpRequest, HttpResponse,
+    web::Data,
+};
+use sqlx::MySqlPool;
+
+
+#[tracing::instrument( name = "Get all users", skip(pool, http_request) )]
+#[get("/users")]
+pub async fn get_all_users(http_request: HttpRequest, pool: Data::<MySqlPool>) -> Result<HttpResponse, UserError> {
+    let users = user_service::get_all(&pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, users)));
+}
+
+#[tracing::instrument( name = "Get user", skip(pool, http_request) )]
+#[get("/users/{id}")]
+pub async fn get_user(http_request: HttpRequest, param: web::Path<i32>, pool: Data::<MySqlPool>) -> Result<HttpResponse, UserError> {
+    let user = user_service::get_by_id(param.into_inner(), &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, user)));
+}
+
+#[tracing::instrument( name = "Post user", skip(pool, http_request, request) )]
+#[post("/users")]
+pub async fn add_user(http_request: HttpRequest, request: web::Json<UserCreateRequest>, pool: Data::<MySqlPool>) -> Result<HttpResponse, UserError> {
+    let user = user_service::insert(request.0, &pool).await?;
+    return Ok(HttpResponse::Created().json(Envelope::new(&http_request, user)));
+}
+
+#[tracing::instrument( name = "Put user", skip(pool, redis, http_request, request) )]
+#[put("/users/{id}")]
+pub async fn update_user(http_request: HttpRequest, param: web::Path<i32>, request: web::Json<UserUpdateRequest>, pool: Data::<MySqlPool>, redis: Data<redis::Client>) -> Result<HttpResponse, UserError> {
+    let user = user_service::update(param.into_inner(), request.0, &pool, &redis).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, user)));
+}
+
+#[tracing::instrument( name = "Delete user", skip(pool, redis) )]
+#[delete("/users/{id}")]
+pub async fn delete_user(param: web::Path<i32>, pool: Data::<MySqlPool>, redis: Data<redis::Client>) -> Result<HttpResponse, UserError> {
+    user_service::delete(param.into_inner(), &pool, &redis).await?;
+    return Ok(HttpResponse::NoContent().finish());
+}
+
+#[tracing::instrument( name = "Enroll TOTP", skip(pool, http_request) )]
+// a new secret replaces the previous one, two-factor authentication is disabled until it is verified
+#[post("/totp")]
+pub async fn enroll_totp(http_request: HttpRequest, pool: Data::<MySqlPool>) -> Result<HttpResponse, UserError> {
+    let user_id = session_user_id(&http_request)?;
+    let enrollment = user_service::enroll_totp(user_id, &pool).await?;
+    return Ok(HttpResponse::Created().json(Envelope::new(&http_request, enrollment)));
+}
+
+#[tracing::instrument( name = "Verify TOTP", skip(pool, http_request, request) )]
+#[post("/totp/verify")]
+pub async fn verify_totp(http_request: HttpRequest, request: web::Json<TotpVerifyRequest>, pool: Data::<MySqlPool>) -> Result<HttpResponse, UserError> {
+    let user_id = session_user_id(&http_request)?;
+    let user = user_service::enable_totp(user_id, &request.code, &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, user)));
+}
+
+// the `/account` routes are for the sessions of `/login`, the API keys have no account
+fn session_user_id(http_request: &HttpRequest) -> Result<i32, UserError> {
+    return http_request.extensions().get::<Session>()
+        .and_then(|session| session.user_id)
+        .ok_or(UserError::ValidationError("Only the sessions of `/login` have an account.".to_string()));
+}
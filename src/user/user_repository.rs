@@ -0,0 +1,150 @@
+use super::model::User;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::MySqlPool;
+
+
+const SELECT_USER: &str = r#"SELECT id
+        , username
+        , password_hash
+        , role
+        , totp_secret
+        , totp_enabled
+        , totp_last_step
+        , date_created
+        , date_updated
+        FROM users"#;
+
+pub async fn insert(username: &str, password_hash: &Secret<String>, role: &str, pool: &MySqlPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+            INSERT INTO users
+            (username, password_hash, role)
+            VALUES
+            (?, ?, ?)
+        "#)
+    .bind(username)
+    .bind(password_hash.expose_secret())
+    .bind(role)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(result.last_insert_id());
+}
+
+pub async fn update_role(id: i32, role: &str, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+    .bind(role)
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+pub async fn update_password_hash(id: i32, password_hash: &Secret<String>, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+    .bind(password_hash.expose_secret())
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+/// Replace the secret, two-factor authentication stays disabled until a code of the new secret is verified.
+pub async fn update_totp_secret(id: i32, totp_secret: &str, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET totp_secret = ?, totp_enabled = FALSE WHERE id = ?")
+    .bind(totp_secret)
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+pub async fn enable_totp(id: i32, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET totp_enabled = TRUE WHERE id = ? AND totp_secret IS NOT NULL")
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+/// Record the time step of the code accepted, unless a code of this step or a later one was already accepted.
+/// Returns whether it was recorded, so a code sent several times at once is only accepted once.
+pub async fn use_totp_step(id: i32, step: i64, pool: &MySqlPool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET totp_last_step = ? WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)")
+    .bind(step)
+    .bind(id)
+    .bind(step)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(result.rows_affected() == 1);
+}
+
+pub async fn get_all(pool: &MySqlPool) -> Result<Vec<User>, sqlx::Error> {
+    let users = sqlx::query_as::<_, User>(SELECT_USER)
+    .fetch_all(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(users);
+}
+
+pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as::<_, User>(&format!("{} WHERE id = ?", SELECT_USER))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(user);
+}
+
+pub async fn get_by_username(username: &str, pool: &MySqlPool) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as::<_, User>(&format!("{} WHERE username = ?", SELECT_USER))
+    .bind(username)
+    .fetch_optional(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(user);
+}
+
+pub async fn delete_by_id(id: i32, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM users WHERE id = ?")
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
@@ -0,0 +1,194 @@
+use super::model::{
+    parse_username, validate_password, TotpEnrollment, UserCreateRequest, UserError, UserResponse, UserUpdateRequest, User,
+};
+use super::{totp, user_repository};
+use crate::authentication::sessions;
+use anyhow::{anyhow, Context};
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::SaltString,
+};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::MySqlPool;
+
+
+pub async fn get_all(pool: &MySqlPool) -> Result<Vec<UserResponse>, UserError> {
+    let users = user_repository::get_all(pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?;
+    return Ok(users.into_iter().map(|user| user.into()).collect());
+}
+
+pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<UserResponse, UserError> {
+    let user = user_repository::get_by_id(id, pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?
+        .ok_or(UserError::NotFoundError(anyhow!(format!("User with id `{}` not found.", id))))?;
+    return Ok(user.into());
+}
+
+pub async fn insert(request: UserCreateRequest, pool: &MySqlPool) -> Result<UserResponse, UserError> {
+    let username = parse_username(&request.username).map_err(UserError::ValidationError)?;
+    validate_password(&request.password).map_err(UserError::ValidationError)?;
+
+    let existing_user = user_repository::get_by_username(&username, pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?;
+    if (existing_user.is_some()){
+        return Err(UserError::AlreadyExistsError(anyhow!(format!("User `{}` already exists.", username))));
+    }
+
+    let password_hash = hash_password(request.password).await?;
+    let inserted_id = user_repository::insert(&username, &password_hash, request.role.as_str(), pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?;
+    let inserted_id = i32::try_from(inserted_id)
+        .map_err(|e| UserError::UnexpectedError(anyhow!(format!("Failed to read inserted_id: {}", e))))?;
+    return get_by_id(inserted_id, pool).await;
+}
+
+/// The sessions of the user are ended, they log in again with the new role or password.
+pub async fn update(id: i32, request: UserUpdateRequest, pool: &MySqlPool, redis: &redis::Client) -> Result<UserResponse, UserError> {
+    // check if user exists
+    get_by_id(id, pool).await?;
+
+    if let Some(password) = request.password {
+        validate_password(&password).map_err(UserError::ValidationError)?;
+        let password_hash = hash_password(password).await?;
+        user_repository::update_password_hash(id, &password_hash, pool).await
+            .map_err(|error| UserError::UnexpectedError(error.into()))?;
+    }
+    user_repository::update_role(id, request.role.as_str(), pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?;
+    end_sessions(id, redis).await?;
+    return get_by_id(id, pool).await;
+}
+
+pub async fn delete(id: i32, pool: &MySqlPool, redis: &redis::Client) -> Result<(), UserError> {
+    // check if user exists
+    get_by_id(id, pool).await?;
+
+    user_repository::delete_by_id(id, pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?;
+    end_sessions(id, redis).await?;
+    return Ok(());
+}
+
+/// The user with the `id`, `None` once it was deleted.
+pub async fn find_by_id(id: i32, pool: &MySqlPool) -> Result<Option<User>, UserError> {
+    return user_repository::get_by_id(id, pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()));
+}
+
+pub async fn find_by_username(username: &str, pool: &MySqlPool) -> Result<Option<User>, UserError> {
+    return user_repository::get_by_username(username.trim(), pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()));
+}
+
+async fn end_sessions(id: i32, redis: &redis::Client) -> Result<(), UserError> {
+    return sessions::end_sessions(redis, |session| session.user_id == Some(id)).await
+        .map_err(|error| UserError::UnexpectedError(error.into()));
+}
+
+/// Generate a new TOTP secret for the user, enabled once `enable_totp` verifies a code of it.
+pub async fn enroll_totp(id: i32, pool: &MySqlPool) -> Result<TotpEnrollment, UserError> {
+    let user = get_by_id(id, pool).await?;
+    let secret = totp::generate_secret();
+    user_repository::update_totp_secret(id, &hex::encode(&secret), pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?;
+    return Ok(TotpEnrollment { secret: totp::base32(&secret), otpauth_url: totp::otpauth_url(&secret, &user.username) });
+}
+
+/// Enable two-factor authentication, proving the secret was added to the authenticator app.
+pub async fn enable_totp(id: i32, code: &str, pool: &MySqlPool) -> Result<UserResponse, UserError> {
+    let user = user_repository::get_by_id(id, pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?
+        .ok_or(UserError::NotFoundError(anyhow!(format!("User with id `{}` not found.", id))))?;
+    if (user.totp_secret.is_none()){
+        return Err(UserError::ValidationError("Two-factor authentication must be enrolled first.".to_string()));
+    }
+    verify_totp(&user, code, pool).await?;
+
+    user_repository::enable_totp(id, pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?;
+    return get_by_id(id, pool).await;
+}
+
+/// Check a code of the user's TOTP secret, each code is only accepted once.
+pub async fn verify_totp(user: &User, code: &str, pool: &MySqlPool) -> Result<(), UserError> {
+    let secret = match &user.totp_secret {
+        Some(secret) => hex::decode(secret)
+            .map_err(|e| UserError::UnexpectedError(anyhow!(format!("Failed to decode TOTP secret: {}.", e))))?,
+        None => return Ok(()),
+    };
+    let step = totp::verify(&secret, code, chrono::Utc::now().timestamp(), user.totp_last_step)
+        .ok_or(UserError::InvalidTotpCodeError)?;
+    // checked again by the update, in case the same code was sent concurrently
+    let first_use = user_repository::use_totp_step(user.id, step, pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?;
+    if (!first_use){
+        return Err(UserError::InvalidTotpCodeError);
+    }
+    return Ok(());
+}
+
+/// The user with the `username`, if the `password` matches.
+pub async fn verify_credentials(username: &str, password: Secret<String>, pool: &MySqlPool) -> Result<User, UserError> {
+    let user = user_repository::get_by_username(username.trim(), pool).await
+        .map_err(|error| UserError::UnexpectedError(error.into()))?;
+
+    // an unknown username still goes through the hash verification,
+    // otherwise the response time would tell which usernames exist
+    let expected_password_hash = match &user {
+        Some(user) => Secret::new(user.password_hash.clone()),
+        None => Secret::new(UNKNOWN_USER_PASSWORD_HASH.to_string()),
+    };
+
+    let verified = tokio::task::spawn_blocking(move || verify_password_hash(&expected_password_hash, &password))
+        .await
+        .context("Failed to spawn blocking task.")??;
+
+    return match user {
+        Some(user) if verified => Ok(user),
+        _ => Err(UserError::InvalidCredentialsError),
+    };
+}
+
+// hash of a random password with the default parameters, no password verifies against it
+const UNKNOWN_USER_PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$gZiV/M1gPc22ElAH/Jh1Hw$CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno";
+
+async fn hash_password(password: Secret<String>) -> Result<Secret<String>, UserError> {
+    return tokio::task::spawn_blocking(move || compute_password_hash(&password))
+        .await
+        .context("Failed to spawn blocking task.")?;
+}
+
+fn compute_password_hash(password: &Secret<String>) -> Result<Secret<String>, UserError> {
+    let salt = SaltString::generate(&mut argon2::password_hash::rand_core::OsRng);
+    let password_hash = Argon2::default()
+        .hash_password(password.expose_secret().as_bytes(), &salt)
+        .map_err(|e| UserError::UnexpectedError(anyhow!(format!("Failed to hash password: {}.", e))))?
+        .to_string();
+    return Ok(Secret::new(password_hash));
+}
+
+fn verify_password_hash(expected_password_hash: &Secret<String>, password: &Secret<String>) -> Result<bool, UserError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .map_err(|e| UserError::UnexpectedError(anyhow!(format!("Failed to parse password hash: {}.", e))))?;
+    return Ok(Argon2::default()
+        .verify_password(password.expose_secret().as_bytes(), &expected_password_hash)
+        .is_ok());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_password_hash, verify_password_hash, UNKNOWN_USER_PASSWORD_HASH};
+    use claim::assert_ok;
+    use secrecy::Secret;
+
+    #[test]
+    fn password_verifies_only_against_its_own_hash(){
+        let password = Secret::new("correct horse battery staple".to_string());
+        let password_hash = assert_ok!(compute_password_hash(&password));
+
+        assert!(assert_ok!(verify_password_hash(&password_hash, &password)));
+        assert!(!assert_ok!(verify_password_hash(&password_hash, &Secret::new("wrong password".to_string()))));
+        assert!(!assert_ok!(verify_password_hash(&Secret::new(UNKNOWN_USER_PASSWORD_HASH.to_string()), &password)));
+    }
+}
@@ -0,0 +1,8 @@
+pub mod webhook_controller;
+pub mod webhook_delivery;
+pub mod webhook_service;
+pub mod webhook_repository;
+pub mod model;
+
+pub use webhook_controller::*;
+pub use model::*;
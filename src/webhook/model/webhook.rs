@@ -0,0 +1,230 @@
+use actix_web::{
+    ResponseError,
+    http::{StatusCode},
+};
+use serde::{Serialize, Deserialize};
+use sqlx::types::chrono::{NaiveDateTime};
+use std::str::FromStr;
+
+
+pub const DEFAULT_DELIVERIES_LIMIT: u32 = 100;
+pub const MAX_DELIVERIES_LIMIT: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+    #[serde(rename = "coupon.created")]
+    CouponCreated,
+    #[serde(rename = "coupon.updated")]
+    CouponUpdated,
+    #[serde(rename = "coupon.deleted")]
+    CouponDeleted,
+    // not emitted yet, the redemptions are not tracked
+    #[serde(rename = "coupon.redeemed")]
+    CouponRedeemed,
+    // emitted when the expired coupon is archived, see `archive.retention_days`
+    #[serde(rename = "coupon.expired")]
+    CouponExpired,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            WebhookEvent::CouponCreated => "coupon.created",
+            WebhookEvent::CouponUpdated => "coupon.updated",
+            WebhookEvent::CouponDeleted => "coupon.deleted",
+            WebhookEvent::CouponRedeemed => "coupon.redeemed",
+            WebhookEvent::CouponExpired => "coupon.expired",
+        };
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "coupon.created" => Ok(Self::CouponCreated),
+            "coupon.updated" => Ok(Self::CouponUpdated),
+            "coupon.deleted" => Ok(Self::CouponDeleted),
+            "coupon.redeemed" => Ok(Self::CouponRedeemed),
+            "coupon.expired" => Ok(Self::CouponExpired),
+            other => Err(format!("`{}` is not a supported webhook event.", other)),
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    // comma separated list of events
+    pub events: String,
+    pub secret: String,
+    pub active: bool,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_updated: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub active: bool,
+}
+
+/// A validated `WebhookRequest`, ready to be persisted.
+#[derive(Debug, Clone)]
+pub struct WebhookInsert {
+    pub url: String,
+    pub events: String,
+    pub active: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookResponse {
+    pub id: i32,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub active: bool,
+    // only returned when the webhook is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_updated: Option<NaiveDateTime>,
+}
+
+impl TryFrom<WebhookRequest> for WebhookInsert {
+    type Error = String;
+    fn try_from(webhook: WebhookRequest) -> Result<Self, Self::Error> {
+        let url = url::Url::parse(&webhook.url)
+            .map_err(|e| format!("Invalid url `{}`: {}.", webhook.url, e))?;
+        if (url.scheme() != "http" && url.scheme() != "https"){
+            return Err("Webhook url must be http or https.".to_string());
+        }
+        if (webhook.events.is_empty()){
+            return Err("At least one event must be subscribed.".to_string());
+        }
+        if (webhook.events.contains(&WebhookEvent::CouponRedeemed)){
+            return Err("`coupon.redeemed` is not emitted yet, the redemptions are not tracked.".to_string());
+        }
+
+        let mut events: Vec<&str> = webhook.events.iter().map(|event| event.as_str()).collect();
+        events.sort();
+        events.dedup();
+        return Ok(Self {
+            url: url.to_string(),
+            events: events.join(","),
+            active: webhook.active,
+        });
+    }
+}
+
+impl From<Webhook> for WebhookResponse {
+    fn from(webhook: Webhook) -> Self {
+        let events = webhook.events
+            .split(',')
+            .flat_map(|event| event.parse::<WebhookEvent>().ok())
+            .collect();
+        return Self {
+            id: webhook.id,
+            url: webhook.url,
+            events,
+            active: webhook.active,
+            secret: None,
+            date_created: webhook.date_created,
+            date_updated: webhook.date_updated,
+        };
+    }
+}
+
+/// An attempt to deliver an event to a webhook, from the `webhook_deliveries` table.
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct WebhookDeliveryAttempt {
+    pub id: i64,
+    pub webhook_id: i32,
+    // the same for every attempt of a delivery, sent in the `X-Webhook-Delivery` header
+    pub job_id: i64,
+    pub event: String,
+    pub attempt: i32,
+    // `None` when the receiver didn't respond
+    pub status_code: Option<i32>,
+    pub succeeded: bool,
+    pub error: Option<String>,
+    pub duration_milliseconds: i32,
+    pub date_created: Option<NaiveDateTime>,
+}
+
+// Query string filters of `/webhooks/{id}/deliveries`, the last attempts are returned first
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WebhookDeliveryFilter {
+    pub limit: Option<u32>,
+}
+
+impl WebhookDeliveryFilter {
+    pub fn limit(&self) -> Result<u32, String> {
+        return match self.limit {
+            None => Ok(DEFAULT_DELIVERIES_LIMIT),
+            Some(limit) if (limit == 0 || limit > MAX_DELIVERIES_LIMIT) => Err(format!("`limit` must be between 1 and {}.", MAX_DELIVERIES_LIMIT)),
+            Some(limit) => Ok(limit),
+        };
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WebhookError {
+    #[error("{0}")]
+    NotFoundError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            WebhookError::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            WebhookError::ConflictError(_) => StatusCode::CONFLICT,
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WebhookEvent, WebhookInsert, WebhookRequest};
+    use claim::{assert_err, assert_ok};
+
+    fn request(url: &str, events: Vec<WebhookEvent>) -> WebhookRequest {
+        return WebhookRequest { url: url.to_string(), events, active: true };
+    }
+
+    #[test]
+    fn valid_webhook_is_accepted(){
+        let webhook = assert_ok!(WebhookInsert::try_from(request(
+            "https://example.com/hook",
+            vec![WebhookEvent::CouponUpdated, WebhookEvent::CouponCreated, WebhookEvent::CouponCreated],
+        )));
+        assert_eq!(webhook.events, "coupon.created,coupon.updated");
+    }
+
+    #[test]
+    fn invalid_url_is_rejected(){
+        assert_err!(WebhookInsert::try_from(request("not a url", vec![WebhookEvent::CouponCreated])));
+        assert_err!(WebhookInsert::try_from(request("ftp://example.com", vec![WebhookEvent::CouponCreated])));
+    }
+
+    #[test]
+    fn webhook_without_events_is_rejected(){
+        assert_err!(WebhookInsert::try_from(request("https://example.com/hook", vec![])));
+    }
+
+    #[test]
+    fn events_that_are_not_emitted_are_rejected(){
+        assert_err!(WebhookInsert::try_from(request("https://example.com/hook", vec![WebhookEvent::CouponRedeemed])));
+        assert_ok!(WebhookInsert::try_from(request("https://example.com/hook", vec![WebhookEvent::CouponExpired, WebhookEvent::CouponDeleted])));
+    }
+}
@@ -0,0 +1,58 @@
+use super::model::{WebhookDeliveryFilter, WebhookError, WebhookRequest};
+use super::webhook_service;
+use crate::envelope::Envelope;
+use actix_web::{
+    web, get, post, put, delete, HttpRequest, HttpResponse,
+    web::Data,
+};
+use sqlx::MySqlPool;
+
+
+#[tracing::instrument( name = "Get all webhooks", skip(pool, http_request) )]
+#[get("")]
+pub async fn get_all_webhooks(http_request: HttpRequest, pool: Data::<MySqlPool>) -> Result<HttpResponse, WebhookError> {
+    let webhooks = webhook_service::get_all(&pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, webhooks)));
+}
+
+#[tracing::instrument( name = "Get webhook", skip(pool, http_request) )]
+#[get("/{id}")]
+pub async fn get_webhook(http_request: HttpRequest, param: web::Path<i32>, pool: Data::<MySqlPool>) -> Result<HttpResponse, WebhookError> {
+    let webhook = webhook_service::get_by_id(param.into_inner(), &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, webhook)));
+}
+
+#[tracing::instrument( name = "Get webhook deliveries", skip(pool, http_request) )]
+#[get("/{id}/deliveries")]
+pub async fn get_webhook_deliveries(http_request: HttpRequest, param: web::Path<i32>, filter: web::Query<WebhookDeliveryFilter>, pool: Data::<MySqlPool>) -> Result<HttpResponse, WebhookError> {
+    let deliveries = webhook_service::get_deliveries(param.into_inner(), &filter, &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, deliveries)));
+}
+
+#[tracing::instrument( name = "Retry webhook delivery", skip(pool, http_request) )]
+#[post("/deliveries/{id}/retry")]
+pub async fn retry_webhook_delivery(http_request: HttpRequest, param: web::Path<i64>, pool: Data::<MySqlPool>) -> Result<HttpResponse, WebhookError> {
+    let job = webhook_service::redeliver(param.into_inner(), &pool).await?;
+    return Ok(HttpResponse::Accepted().json(Envelope::new(&http_request, job)));
+}
+
+#[tracing::instrument( name = "Post webhook", skip(pool, http_request) )]
+#[post("")]
+pub async fn add_webhook(http_request: HttpRequest, request: web::Json<WebhookRequest>, pool: Data::<MySqlPool>) -> Result<HttpResponse, WebhookError> {
+    let webhook = webhook_service::insert(request.0, &pool).await?;
+    return Ok(HttpResponse::Created().json(Envelope::new(&http_request, webhook)));
+}
+
+#[tracing::instrument( name = "Put webhook", skip(pool, http_request) )]
+#[put("/{id}")]
+pub async fn update_webhook(http_request: HttpRequest, param: web::Path<i32>, request: web::Json<WebhookRequest>, pool: Data::<MySqlPool>) -> Result<HttpResponse, WebhookError> {
+    let webhook = webhook_service::update(param.into_inner(), request.0, &pool).await?;
+    return Ok(HttpResponse::Ok().json(Envelope::new(&http_request, webhook)));
+}
+
+#[tracing::instrument( name = "Delete webhook", skip(pool) )]
+#[delete("/{id}")]
+pub async fn delete_webhook(param: web::Path<i32>, pool: Data::<MySqlPool>) -> Result<HttpResponse, WebhookError> {
+    webhook_service::delete(param.into_inner(), &pool).await?;
+    return Ok(HttpResponse::NoContent().finish());
+}
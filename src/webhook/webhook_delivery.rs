@@ -0,0 +1,151 @@
+use super::model::{Webhook, WebhookDeliveryAttempt, WebhookEvent};
+use super::webhook_repository;
+use crate::job::{job_repository, JobKind};
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::{MySqlConnection, MySqlPool};
+use std::time::{Duration, Instant};
+
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+// the same for every attempt of a delivery, so the receivers can ignore the ones they already processed
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Debug)]
+pub struct WebhookPayload<'a, T: Serialize> {
+    pub event: WebhookEvent,
+    pub created_at: NaiveDateTime,
+    pub data: &'a T,
+}
+
+/// Payload of the `webhook_delivery` jobs, one per subscribed webhook.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebhookDelivery {
+    pub webhook_id: i32,
+    pub event: WebhookEvent,
+    // the serialized `WebhookPayload`, so every attempt sends the same body
+    pub body: String,
+}
+
+/// HMAC-SHA256 of the body with the webhook secret, in the `sha256=<hex>` format.
+/// Receivers compute the same signature with their copy of the secret to verify the payload.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(body);
+    return format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+}
+
+/// The body of the deliveries of the `event`, the serialized `WebhookPayload`.
+pub fn body<T: Serialize>(event: WebhookEvent, data: &T) -> Result<String, serde_json::Error> {
+    let payload = WebhookPayload { event, created_at: Utc::now().naive_utc(), data };
+    return serde_json::to_string(&payload);
+}
+
+/// Queue a `webhook_delivery` job for each webhook subscribed to the `event`, on the connection of the transaction of the change:
+/// the deliveries are only queued if it is committed, and queued even if the server stops right after.
+/// The request that triggered them doesn't wait for the deliveries, the failed ones are retried by the job worker, see `jobs.retry`.
+pub async fn enqueue(event: WebhookEvent, body: &str, conn: &mut MySqlConnection) -> Result<(), sqlx::Error> {
+    let webhooks = webhook_repository::get_subscribed(event.as_str(), &mut *conn).await?;
+    let run_at = Utc::now().naive_utc();
+    for webhook in webhooks {
+        let delivery = WebhookDelivery { webhook_id: webhook.id, event, body: body.to_string() };
+        let payload = serde_json::to_string(&delivery).map_err(|e| sqlx::Error::Decode(e.into()))?;
+        job_repository::insert(JobKind::WebhookDelivery.as_str(), &payload, run_at, &mut *conn).await?;
+    }
+    return Ok(());
+}
+
+/// Same as `enqueue` in the background, once the change is committed: for the coupon stores not in MySQL,
+/// whose transactions can't queue the jobs, so the deliveries are lost if the server stops in between.
+pub fn dispatch(event: WebhookEvent, body: String, pool: &MySqlPool) {
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        let result = match pool.acquire().await {
+            Ok(mut conn) => enqueue(event, &body, &mut conn).await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            tracing::error!("Failed to queue the deliveries of the `{}` webhooks: {:?}", event.as_str(), error);
+        }
+    });
+}
+
+/// The client of the deliveries, shared by the attempts.
+pub fn client() -> reqwest::Client {
+    return reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+}
+
+/// Run the `attempt` of the `webhook_delivery` job `job_id`, recorded in `webhook_deliveries`.
+/// The webhooks deleted or deactivated since it was queued are skipped.
+pub async fn run_delivery(job_id: i64, attempt: i32, payload: &str, client: &reqwest::Client, pool: &MySqlPool) -> Result<(), String> {
+    let delivery: WebhookDelivery = serde_json::from_str(payload)
+        .map_err(|e| format!("Invalid webhook delivery: {}", e))?;
+    let webhook = webhook_repository::get_by_id(delivery.webhook_id, pool).await
+        .map_err(|e| format!("Failed to get webhook `{}`: {}", delivery.webhook_id, e))?;
+    let webhook = match webhook {
+        Some(webhook) if webhook.active => webhook,
+        _ => return Ok(()),
+    };
+
+    let started = Instant::now();
+    let (status_code, result) = deliver(client, &webhook, job_id, delivery.event, delivery.body.as_bytes()).await;
+    let attempt = WebhookDeliveryAttempt {
+        id: 0,
+        webhook_id: webhook.id,
+        job_id,
+        event: delivery.event.as_str().to_string(),
+        attempt,
+        status_code: status_code.map(i32::from),
+        succeeded: result.is_ok(),
+        error: result.as_ref().err().cloned(),
+        duration_milliseconds: i32::try_from(started.elapsed().as_millis()).unwrap_or(i32::MAX),
+        date_created: None,
+    };
+    // the delivery already happened, it is not attempted again because it couldn't be recorded
+    if let Err(error) = webhook_repository::insert_delivery(&attempt, pool).await {
+        tracing::error!("Failed to record the delivery of webhook `{}`: {:?}", webhook.url, error);
+    }
+    return result;
+}
+
+/// Returns the status of the response, if there was one.
+#[tracing::instrument(name = "Deliver webhook", skip(client, webhook, body), fields(webhook_id = webhook.id))]
+async fn deliver(client: &reqwest::Client, webhook: &Webhook, job_id: i64, event: WebhookEvent, body: &[u8]) -> (Option<u16>, Result<(), String>) {
+    let result = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, event.as_str())
+        .header(DELIVERY_HEADER, job_id.to_string())
+        .header(SIGNATURE_HEADER, sign(&webhook.secret, body))
+        .body(body.to_vec())
+        .send()
+        .await;
+
+    return match result {
+        Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), Ok(())),
+        Ok(response) => (Some(response.status().as_u16()), Err(format!("Webhook `{}` responded with `{}`.", webhook.url, response.status()))),
+        Err(e) => (None, Err(format!("Failed to deliver webhook `{}`: {}", webhook.url, e))),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sign;
+
+    #[test]
+    fn signature_is_the_hex_hmac_sha256_of_the_body(){
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use super::model::{Webhook, WebhookDeliveryAttempt, WebhookInsert};
+use sqlx::{MySqlConnection, MySqlPool};
+use sqlx::types::chrono::{NaiveDateTime};
+
+
+const SELECT_WEBHOOK: &str = r#"SELECT id
+        , url
+        , events
+        , secret
+        , active
+        , date_created
+        , date_updated
+        FROM webhooks"#;
+
+const SELECT_DELIVERY: &str = "SELECT id, webhook_id, job_id, event, attempt, status_code, succeeded, error, duration_milliseconds, date_created FROM webhook_deliveries";
+
+pub async fn insert(webhook: WebhookInsert, secret: &String, pool: &MySqlPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+            INSERT INTO webhooks
+            (url, events, secret, active)
+            VALUES
+            (?, ?, ?, ?)
+        "#)
+    .bind(webhook.url)
+    .bind(webhook.events)
+    .bind(secret)
+    .bind(webhook.active)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(result.last_insert_id());
+}
+
+pub async fn update(id: i32, webhook: WebhookInsert, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            UPDATE webhooks SET
+            url = ?,
+            events = ?,
+            active = ?
+            WHERE id = ?
+        "#)
+    .bind(webhook.url)
+    .bind(webhook.events)
+    .bind(webhook.active)
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute update query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+pub async fn get_all(pool: &MySqlPool) -> Result<Vec<Webhook>, sqlx::Error> {
+    let webhooks = sqlx::query_as::<_, Webhook>(SELECT_WEBHOOK)
+    .fetch_all(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(webhooks);
+}
+
+pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<Option<Webhook>, sqlx::Error> {
+    let webhook = sqlx::query_as::<_, Webhook>(&format!("{} WHERE id = ?", SELECT_WEBHOOK))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(webhook);
+}
+
+/// Active webhooks subscribed to the `event`.
+pub async fn get_subscribed(event: &str, conn: &mut MySqlConnection) -> Result<Vec<Webhook>, sqlx::Error> {
+    let webhooks = sqlx::query_as::<_, Webhook>(&format!("{} WHERE active = 1 AND FIND_IN_SET(?, events) > 0", SELECT_WEBHOOK))
+    .bind(event)
+    .fetch_all(conn)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(webhooks);
+}
+
+pub async fn delete_by_id(id: i32, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM webhooks WHERE id = ?")
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+/// Record an attempt to deliver a webhook, `id` and `date_created` are ignored.
+pub async fn insert_delivery(delivery: &WebhookDeliveryAttempt, pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            INSERT INTO webhook_deliveries
+            (webhook_id, job_id, event, attempt, status_code, succeeded, error, duration_milliseconds)
+            VALUES
+            (?, ?, ?, ?, ?, ?, ?, ?)
+        "#)
+    .bind(delivery.webhook_id)
+    .bind(delivery.job_id)
+    .bind(&delivery.event)
+    .bind(delivery.attempt)
+    .bind(delivery.status_code)
+    .bind(delivery.succeeded)
+    .bind(&delivery.error)
+    .bind(delivery.duration_milliseconds)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute insert query: {:?}", error);
+        error
+    })?;
+    return Ok(());
+}
+
+pub async fn get_delivery_by_id(id: i64, pool: &MySqlPool) -> Result<Option<WebhookDeliveryAttempt>, sqlx::Error> {
+    let delivery = sqlx::query_as::<_, WebhookDeliveryAttempt>(&format!("{} WHERE id = ?", SELECT_DELIVERY))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(delivery);
+}
+
+/// The last `limit` delivery attempts of the webhook, the last one first.
+pub async fn get_deliveries(webhook_id: i32, limit: u32, pool: &MySqlPool) -> Result<Vec<WebhookDeliveryAttempt>, sqlx::Error> {
+    let deliveries = sqlx::query_as::<_, WebhookDeliveryAttempt>(
+&format!(
+        r#"{}
+            WHERE webhook_id = ?
+            ORDER BY id DESC
+            LIMIT ?"#, SELECT_DELIVERY))
+    .bind(webhook_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute select query: {:?}", error);
+        error
+    })?;
+    return Ok(deliveries);
+}
+
+/// Returns how many delivery attempts were deleted, at most `limit`.
+pub async fn delete_deliveries_before(cutoff: NaiveDateTime, limit: u32, pool: &MySqlPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM webhook_deliveries WHERE date_created < ? ORDER BY id LIMIT ?")
+    .bind(cutoff)
+    .bind(limit)
+    .execute(pool)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute delete query: {:?}", error);
+        error
+    })?;
+    return Ok(result.rows_affected());
+}
@@ -0,0 +1,81 @@
+use super::model::{WebhookDeliveryAttempt, WebhookDeliveryFilter, WebhookError, WebhookInsert, WebhookRequest, WebhookResponse};
+use super::webhook_repository;
+use crate::job::{job_service, Job};
+use anyhow::anyhow;
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+
+pub async fn get_all(pool: &MySqlPool) -> Result<Vec<WebhookResponse>, WebhookError> {
+    let webhooks = webhook_repository::get_all(pool).await
+        .map_err(|error| WebhookError::UnexpectedError(error.into()))?;
+    return Ok(webhooks.into_iter().map(|webhook| webhook.into()).collect());
+}
+
+pub async fn get_by_id(id: i32, pool: &MySqlPool) -> Result<WebhookResponse, WebhookError> {
+    let webhook = webhook_repository::get_by_id(id, pool).await
+        .map_err(|error| WebhookError::UnexpectedError(error.into()))?
+        .ok_or(WebhookError::NotFoundError(anyhow!(format!("Webhook with id `{}` not found.", id))))?;
+    return Ok(webhook.into());
+}
+
+/// Register the webhook, the response is the only time the signing `secret` is returned.
+pub async fn insert(webhook_request: WebhookRequest, pool: &MySqlPool) -> Result<WebhookResponse, WebhookError> {
+    let webhook_insert: WebhookInsert = webhook_request.try_into().map_err(WebhookError::ValidationError)?;
+    let secret = Uuid::new_v4().simple().to_string();
+
+    let inserted_id = webhook_repository::insert(webhook_insert, &secret, pool).await
+        .map_err(|error| WebhookError::UnexpectedError(error.into()))?;
+    let inserted_id = i32::try_from(inserted_id)
+        .map_err(|e| WebhookError::UnexpectedError(anyhow!(format!("Failed to read inserted_id: {}", e))))?;
+
+    let mut webhook = get_by_id(inserted_id, pool).await?;
+    webhook.secret = Some(secret);
+    return Ok(webhook);
+}
+
+pub async fn update(id: i32, webhook_request: WebhookRequest, pool: &MySqlPool) -> Result<WebhookResponse, WebhookError> {
+    // check if webhook exists
+    get_by_id(id, pool).await?;
+    let webhook_update: WebhookInsert = webhook_request.try_into().map_err(WebhookError::ValidationError)?;
+
+    webhook_repository::update(id, webhook_update, pool).await
+        .map_err(|error| WebhookError::UnexpectedError(error.into()))?;
+    return get_by_id(id, pool).await;
+}
+
+pub async fn delete(id: i32, pool: &MySqlPool) -> Result<(), WebhookError> {
+    // check if webhook exists
+    get_by_id(id, pool).await?;
+
+    webhook_repository::delete_by_id(id, pool).await
+        .map_err(|error| WebhookError::UnexpectedError(error.into()))?;
+    return Ok(());
+}
+
+pub async fn get_deliveries(id: i32, filter: &WebhookDeliveryFilter, pool: &MySqlPool) -> Result<Vec<WebhookDeliveryAttempt>, WebhookError> {
+    let limit = filter.limit().map_err(WebhookError::ValidationError)?;
+    // check if webhook exists
+    get_by_id(id, pool).await?;
+
+    return webhook_repository::get_deliveries(id, limit, pool).await
+        .map_err(|error| WebhookError::UnexpectedError(error.into()));
+}
+
+/// Deliver the event of the delivery `delivery_id` again, e.g. one the receiver missed during an outage,
+/// with the same `X-Webhook-Delivery` id and all the retries of a new delivery. Returns its job.
+pub async fn redeliver(delivery_id: i64, pool: &MySqlPool) -> Result<Job, WebhookError> {
+    let delivery = webhook_repository::get_delivery_by_id(delivery_id, pool).await
+        .map_err(|error| WebhookError::UnexpectedError(error.into()))?
+        .ok_or(WebhookError::NotFoundError(anyhow!(format!("Webhook delivery with id `{}` not found.", delivery_id))))?;
+    // check if webhook exists, the deliveries of the deleted webhooks are skipped
+    get_by_id(delivery.webhook_id, pool).await?;
+
+    let requeued = job_service::requeue(delivery.job_id, pool).await
+        .map_err(|error| WebhookError::UnexpectedError(error.into()))?;
+    if (!requeued){
+        return Err(WebhookError::ConflictError(format!("The delivery `{}` is already queued.", delivery.job_id)));
+    }
+    return job_service::get_by_id(delivery.job_id, pool).await
+        .map_err(|error| WebhookError::UnexpectedError(error.into()));
+}
@@ -0,0 +1,567 @@
+use crate::helpers::{spawn_app, spawn_app_with_configuration, TestApp};
+use coupon_api::{authentication::AuthTokens, envelope::Envelope};
+use rand::distributions::{Alphanumeric, DistString};
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
+
+async fn authenticate(address: &str, api_key: &str) -> u16 {
+    return reqwest::Client::new()
+        .post(format!("{}/auth", address))
+        .json(&json!({"api_key": api_key}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .status()
+        .as_u16();
+}
+
+/// Issue a key with the `role` and `scopes` and return a client authenticated with it.
+async fn scoped_client(app: &TestApp, role: &str, scopes: Value) -> reqwest::Client {
+    let created: Value = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "scoped", "role": role, "scopes": scopes}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": created["data"]["api_key"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .json()
+        .await
+        .unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Authorization", tokens.data.bearer.parse().unwrap());
+    return reqwest::Client::builder().default_headers(headers).build().unwrap();
+}
+
+#[tokio::test]
+async fn issued_api_key_can_authenticate_until_revoked() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - create
+    let response = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "storefront", "role": "admin", "scopes": ["coupon:read", "admin"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.");
+
+    // Assert - the key is only returned on creation
+    assert_eq!(201, response.status().as_u16());
+    let created: Value = response.json().await.expect("Failed to parse API key response.");
+    let id = created["data"]["id"].as_i64().unwrap();
+    let api_key = created["data"]["api_key"].as_str().unwrap().to_string();
+    assert_eq!(created["data"]["revoked"], false);
+    assert_eq!(200, authenticate(&app.address, &api_key).await);
+
+    let fetched: Value = app.api_client
+        .get(format!("{}/admin/api-keys/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(fetched["data"]["name"], "storefront");
+    assert!(fetched["data"].get("api_key").is_none());
+
+    // Act - revoke
+    let response = app.api_client
+        .delete(format!("{}/admin/api-keys/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform DELETE request to `/admin/api-keys`.");
+
+    // Assert
+    assert_eq!(204, response.status().as_u16());
+    assert_eq!(401, authenticate(&app.address, &api_key).await);
+
+    let fetched: Value = app.api_client
+        .get(format!("{}/admin/api-keys/{}", &app.address, id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(fetched["data"]["revoked"], true);
+}
+
+#[tokio::test]
+async fn revoking_api_key_ends_its_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let created: Value = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "storefront", "role": "readonly", "scopes": ["coupon:read"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": created["data"]["api_key"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/admin/api-keys/{}", &app.address, created["data"]["id"]))
+        .send()
+        .await
+        .expect("Failed to perform DELETE request to `/admin/api-keys`.");
+
+    // Assert - neither the Bearer nor the refresh token can be used anymore
+    assert_eq!(204, response.status().as_u16());
+    let response = reqwest::Client::new()
+        .get(format!("{}/coupon", &app.address))
+        .header("Authorization", &tokens.data.bearer)
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/coupon`.");
+    assert_eq!(401, response.status().as_u16());
+    let response = reqwest::Client::new()
+        .post(format!("{}/token/refresh", &app.address))
+        .json(&json!({"refresh_token": tokens.data.refresh_token}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/token/refresh`.");
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn rotated_api_key_keeps_the_previous_key_working_during_the_grace_period() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.api_keys.rotation_grace_period_seconds = 60).await;
+    let created: Value = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "storefront", "role": "readonly", "scopes": ["coupon:read"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+    let id = created["data"]["id"].as_i64().unwrap();
+    let previous_key = created["data"]["api_key"].as_str().unwrap().to_string();
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/admin/api-keys/{}/rotate", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys/{id}/rotate`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let rotated: Value = response.json().await.unwrap();
+    let new_key = rotated["data"]["api_key"].as_str().unwrap().to_string();
+    assert_ne!(new_key, previous_key);
+    assert_eq!(200, authenticate(&app.address, &new_key).await);
+    assert_eq!(200, authenticate(&app.address, &previous_key).await);
+}
+
+#[tokio::test]
+async fn previous_api_key_stops_working_after_the_grace_period() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.api_keys.rotation_grace_period_seconds = 0).await;
+    let created: Value = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "storefront", "role": "readonly", "scopes": ["coupon:read"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+    let id = created["data"]["id"].as_i64().unwrap();
+    let previous_key = created["data"]["api_key"].as_str().unwrap().to_string();
+
+    // Act
+    app.api_client
+        .post(format!("{}/admin/api-keys/{}/rotate", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys/{id}/rotate`.");
+
+    // Assert
+    assert_eq!(401, authenticate(&app.address, &previous_key).await);
+}
+
+#[tokio::test]
+async fn api_key_with_empty_name_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": " ", "role": "readonly", "scopes": ["coupon:read"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.");
+
+    // Assert
+    assert_eq!(422, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn revoking_unknown_api_key_returns_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/admin/api-keys/{}", &app.address, i32::MAX))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_key_can_only_call_the_routes_its_scopes_allow() {
+    // Arrange
+    let app = spawn_app().await;
+    let code = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+    app.post_and_deserialize_coupon(json!({"code": code, "discount": 10, "active": true})).await;
+    let storefront = scoped_client(&app, "admin", json!(["coupon:redeem"])).await;
+
+    let test_cases = vec![
+        (reqwest::Method::GET, format!("/coupon/verify/{}", code), 200),
+        (reqwest::Method::GET, format!("/coupon/{}", code), 403),
+        (reqwest::Method::DELETE, format!("/coupon/{}", code), 403),
+        (reqwest::Method::GET, "/admin/api-keys".to_string(), 403),
+        (reqwest::Method::GET, "/webhooks".to_string(), 403),
+    ];
+
+    for (method, endpoint, expected_status) in test_cases {
+        // Act
+        let response = storefront
+            .request(method.clone(), format!("{}{}", &app.address, endpoint))
+            .send()
+            .await
+            .unwrap_or_else(|_| panic!("Failed to perform {} request", method));
+
+        // Assert
+        assert_eq!(expected_status, response.status().as_u16(), "Unexpected status for {} {}.", method, endpoint);
+    }
+
+    // batch operations need the scope of the route they call
+    let response: Value = storefront
+        .post(format!("{}/batch", &app.address))
+        .json(&json!([
+            {"method": "GET", "path": format!("/coupon/verify/{}", code)},
+            {"method": "DELETE", "path": format!("/coupon/{}", code)},
+        ]))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/batch`.")
+        .json()
+        .await
+        .unwrap();
+    let statuses: Vec<u64> = response["data"].as_array().unwrap().iter()
+        .map(|result| result["status"].as_u64().unwrap())
+        .collect();
+    assert_eq!(statuses, vec![200, 403]);
+}
+
+#[tokio::test]
+async fn api_key_can_only_call_the_routes_its_role_allows() {
+    // Arrange
+    let app = spawn_app().await;
+    let code = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+    app.post_and_deserialize_coupon(json!({"code": code, "discount": 10, "active": true})).await;
+    let all_scopes = json!(["coupon:read", "coupon:write", "coupon:redeem", "admin"]);
+    let readonly = scoped_client(&app, "readonly", all_scopes.clone()).await;
+    let editor = scoped_client(&app, "editor", all_scopes).await;
+
+    let test_cases = vec![
+        (&readonly, reqwest::Method::GET, format!("/coupon/{}", code), 200),
+        (&readonly, reqwest::Method::DELETE, format!("/coupon/{}", code), 403),
+        (&editor, reqwest::Method::GET, "/admin/api-keys".to_string(), 403),
+        (&editor, reqwest::Method::DELETE, format!("/coupon/{}", code), 204),
+    ];
+
+    for (client, method, endpoint, expected_status) in test_cases {
+        // Act
+        let response = client
+            .request(method.clone(), format!("{}{}", &app.address, endpoint))
+            .send()
+            .await
+            .unwrap_or_else(|_| panic!("Failed to perform {} request", method));
+
+        // Assert
+        assert_eq!(expected_status, response.status().as_u16(), "Unexpected status for {} {}.", method, endpoint);
+    }
+}
+
+#[tokio::test]
+async fn api_key_can_only_authenticate_from_its_allowed_ips() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (json!(["10.0.0.0/8"]), 403),
+        (json!(["10.0.0.0/8", "127.0.0.1"]), 200),
+        (json!([]), 200),
+    ];
+
+    for (allowed_ips, expected_status) in test_cases {
+        let created: Value = app.api_client
+            .post(format!("{}/admin/api-keys", &app.address))
+            .json(&json!({"name": "backend", "role": "readonly", "scopes": ["coupon:read"], "allowed_ips": allowed_ips}))
+            .send()
+            .await
+            .expect("Failed to perform POST request to `/admin/api-keys`.")
+            .json()
+            .await
+            .unwrap();
+
+        // Act
+        let status = authenticate(&app.address, created["data"]["api_key"].as_str().unwrap()).await;
+
+        // Assert
+        assert_eq!(expected_status, status, "Unexpected status for the allowlist {}.", allowed_ips);
+    }
+}
+
+#[tokio::test]
+async fn api_key_allowlist_checks_the_client_forwarded_by_a_trusted_proxy() {
+    // Arrange
+    let app = spawn_app().await;
+    let created: Value = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "backend", "role": "readonly", "scopes": ["coupon:read"], "allowed_ips": ["10.0.0.0/8"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+    // the tests connect from `127.0.0.1`, a trusted proxy of the test configuration
+    let test_cases = vec![
+        ("10.1.2.3", 200),
+        ("203.0.113.7", 403),
+        ("10.1.2.3, 203.0.113.7", 403),
+    ];
+
+    for (forwarded_for, expected_status) in test_cases {
+        // Act
+        let status = reqwest::Client::new()
+            .post(format!("{}/auth", &app.address))
+            .header("X-Forwarded-For", forwarded_for)
+            .json(&json!({"api_key": created["data"]["api_key"].as_str().unwrap()}))
+            .send()
+            .await
+            .expect("Failed to perform POST request to `/auth`.")
+            .status()
+            .as_u16();
+
+        // Assert
+        assert_eq!(expected_status, status, "Unexpected status for `X-Forwarded-For: {}`.", forwarded_for);
+    }
+}
+
+#[tokio::test]
+async fn api_key_with_invalid_allowed_ip_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "backend", "role": "readonly", "scopes": ["coupon:read"], "allowed_ips": ["10.0.0.0/33"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.");
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_key_usage_counts_its_requests_and_errors() {
+    // Arrange
+    let app = spawn_app().await;
+    let created: Value = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "storefront", "role": "readonly", "scopes": ["coupon:read"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+    let id = created["data"]["id"].as_i64().unwrap();
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": created["data"]["api_key"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .json()
+        .await
+        .unwrap();
+    let storefront = reqwest::Client::new();
+    let test_cases = vec![
+        (reqwest::Method::GET, "/coupon".to_string(), 200),
+        (reqwest::Method::GET, format!("/coupon/{}", i32::MAX), 404),
+        // rejected by the authorization, counted too
+        (reqwest::Method::GET, "/admin/api-keys".to_string(), 403),
+    ];
+    for (method, path, expected_status) in test_cases {
+        let response = storefront
+            .request(method, format!("{}{}", &app.address, path))
+            .header("Authorization", &tokens.data.bearer)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(expected_status, response.status().as_u16(), "{}", path);
+    }
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/admin/api-keys/{}/usage", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/api-keys/{id}/usage`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let usage: Value = response.json().await.unwrap();
+    assert_eq!(usage["data"]["api_key_id"], id);
+    assert_eq!(usage["data"]["requests"], 3);
+    assert_eq!(usage["data"]["client_errors"], 2);
+    assert_eq!(usage["data"]["server_errors"], 0);
+    assert!(usage["data"]["last_used_at"].is_string());
+}
+
+#[tokio::test]
+async fn usage_of_unknown_api_key_returns_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/admin/api-keys/{}/usage", &app.address, i32::MAX))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn expired_api_key_is_rejected_with_its_own_code() {
+    // Arrange
+    let app = spawn_app().await;
+    let expires_at = (chrono::Utc::now() + chrono::Duration::seconds(2)).naive_utc().format("%Y-%m-%dT%H:%M:%S").to_string();
+    let created: Value = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "storefront", "role": "readonly", "scopes": ["coupon:read"], "expires_at": expires_at}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(created["data"]["expired"], false);
+    assert_eq!(created["data"]["expiring_soon"], true);
+    let api_key = created["data"]["api_key"].as_str().unwrap().to_string();
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": api_key}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .json()
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // Act
+    let auth = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": api_key}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.");
+    // the session created before it expired stops working too
+    let session = reqwest::Client::new()
+        .get(format!("{}/coupon", &app.address))
+        .header("Authorization", &tokens.data.bearer)
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/coupon`.");
+
+    // Assert
+    for response in [auth, session] {
+        assert_eq!(401, response.status().as_u16());
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "api_key_expired");
+    }
+}
+
+#[tokio::test]
+async fn api_keys_expiring_soon_can_be_listed() {
+    // Arrange
+    let app = spawn_app().await;
+    let expires_at = (chrono::Utc::now() + chrono::Duration::days(3)).naive_utc().format("%Y-%m-%dT%H:%M:%S").to_string();
+    for (name, expires_at) in [("expiring", Some(expires_at.as_str())), ("later", Some("2099-01-01T00:00:00")), ("never", None)] {
+        app.api_client
+            .post(format!("{}/admin/api-keys", &app.address))
+            .json(&json!({"name": name, "role": "readonly", "scopes": ["coupon:read"], "expires_at": expires_at}))
+            .send()
+            .await
+            .expect("Failed to perform POST request to `/admin/api-keys`.");
+    }
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/admin/api-keys?expiring_soon=true", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/api-keys`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let api_keys: Value = response.json().await.unwrap();
+    let names: Vec<&str> = api_keys["data"].as_array().unwrap().iter().map(|api_key| api_key["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["expiring"]);
+    assert_eq!(api_keys["data"][0]["expiring_soon"], true);
+}
+
+#[tokio::test]
+async fn api_key_expiring_in_the_past_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "storefront", "role": "readonly", "scopes": ["coupon:read"], "expires_at": "2020-01-01T00:00:00"}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.");
+
+    // Assert
+    assert_eq!(422, response.status().as_u16());
+}
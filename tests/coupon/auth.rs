@@ -1,8 +1,10 @@
+use rand::distributions::{Alphanumeric, DistString};
 use reqwest::header::HeaderMap;
 use secrecy::ExposeSecret;
 use serde_json::json;
+use coupon_api::{authentication::AuthTokens, envelope::Envelope};
 
-use crate::helpers::{spawn_app};
+use crate::helpers::{spawn_app, spawn_app_with_configuration};
 
 
 #[tokio::test]
@@ -23,8 +25,10 @@ async fn auth_works_and_returns_valid_bearer_token() {
     // Assert
     assert_eq!(response.status().as_u16(), 200);
 
-    let response_body: String = response.json().await
+    let response_body: Envelope<AuthTokens> = response.json().await
         .expect("Failed to get `/auth` response text.");
+    assert!(!response_body.data.refresh_token.is_empty());
+    let response_body = response_body.data.bearer;
 
     assert!(response_body.contains("Bearer "));
 
@@ -37,6 +41,325 @@ async fn auth_works_and_returns_valid_bearer_token() {
     assert!(bearer.contains(":"));
 }
 
+#[tokio::test]
+async fn session_expiration_is_configurable() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.session.expiration_seconds = 120).await;
+
+    // Act
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": app.api_key.0.expose_secret()}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(tokens.data.expires_in, 120);
+}
+
+#[tokio::test]
+async fn refresh_token_returns_new_tokens_and_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": app.api_key.0.expose_secret()}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .json()
+        .await
+        .unwrap();
+    let refresh_token = tokens.data.refresh_token;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/token/refresh", &app.address))
+        .json(&json!({"refresh_token": refresh_token}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/token/refresh`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let refreshed: Envelope<AuthTokens> = response.json().await.unwrap();
+    assert_ne!(refreshed.data.refresh_token, refresh_token);
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/coupon/count", &app.address))
+        .header("Authorization", refreshed.data.bearer)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // the old refresh token was rotated
+    let response = reqwest::Client::new()
+        .post(format!("{}/token/refresh", &app.address))
+        .json(&json!({"refresh_token": refresh_token}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": app.api_key.0.expose_secret()}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    for token in [&tokens.data.bearer, &tokens.data.refresh_token] {
+        let response = reqwest::Client::new()
+            .post(format!("{}/token/revoke", &app.address))
+            .json(&json!({"token": token}))
+            .send()
+            .await
+            .expect("Failed to perform POST request to `/token/revoke`.");
+        assert_eq!(response.status().as_u16(), 204);
+    }
+
+    // Assert
+    let response = reqwest::Client::new()
+        .get(format!("{}/coupon/count", &app.address))
+        .header("Authorization", &tokens.data.bearer)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/token/refresh", &app.address))
+        .json(&json!({"refresh_token": tokens.data.refresh_token}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn logout_ends_the_session_and_its_refresh_token() {
+    // Arrange
+    let app = spawn_app().await;
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": app.api_key.0.expose_secret()}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/logout", &app.address))
+        .header("Authorization", &tokens.data.bearer)
+        .json(&json!({"refresh_token": tokens.data.refresh_token}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/logout`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/coupon/count", &app.address))
+        .header("Authorization", &tokens.data.bearer)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/token/refresh", &app.address))
+        .json(&json!({"refresh_token": tokens.data.refresh_token}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admin_can_list_and_end_active_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+    let password = "correct horse battery staple";
+    let user: serde_json::Value = app.api_client
+        .post(format!("{}/admin/users", &app.address))
+        .json(&json!({"username": username, "password": password, "role": "readonly"}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/users`.")
+        .json()
+        .await
+        .unwrap();
+    let user_id = user["data"]["id"].as_i64().unwrap();
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .json(&json!({"username": username, "password": password}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/login`.")
+        .json()
+        .await
+        .unwrap();
+    let find_session = || async {
+        let sessions: serde_json::Value = app.api_client
+            .get(format!("{}/admin/sessions", &app.address))
+            .send()
+            .await
+            .expect("Failed to perform GET request to `/admin/sessions`.")
+            .json()
+            .await
+            .unwrap();
+        return sessions["data"].as_array().unwrap().iter()
+            .find(|session| session["user_id"].as_i64() == Some(user_id))
+            .cloned();
+    };
+    let session = find_session().await.expect("The session of the login is not listed.");
+    assert_eq!(session["role"], "readonly");
+    // the tokens themselves are never listed
+    assert!(!session.to_string().contains(&tokens.data.refresh_token));
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/admin/sessions/{}", &app.address, session["id"].as_str().unwrap()))
+        .send()
+        .await
+        .expect("Failed to perform DELETE request to `/admin/sessions`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert!(find_session().await.is_none());
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/coupon/count", &app.address))
+        .header("Authorization", &tokens.data.bearer)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/token/refresh", &app.address))
+        .json(&json!({"refresh_token": tokens.data.refresh_token}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn logout_without_authorization_header_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/logout", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/logout`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn oidc_login_is_not_found_when_not_configured() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.oidc = None).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/auth/oidc/login", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/auth/oidc/login`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn client_is_locked_out_after_too_many_failed_attempts() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.auth_lockout.enabled = true;
+        c.auth_lockout.max_failed_attempts = 3;
+        c.auth_lockout.lockout_seconds = 60;
+    }).await;
+    // the lockouts are stored on redis, a random client IP (and keys) keep the test from being locked out by a previous run
+    let client_ip = format!("10.{}.{}.{}", rand::random::<u8>(), rand::random::<u8>(), rand::random::<u8>());
+    let authenticate = |api_key: String| reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .header("X-Forwarded-For", &client_ip)
+        .json(&json!({"api_key": api_key}))
+        .send();
+
+    // Act
+    for _ in 0..3 {
+        let response = authenticate(Alphanumeric.sample_string(&mut rand::thread_rng(), 32)).await.expect("Failed to perform POST request to `/auth`.");
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = authenticate(app.api_key.0.expose_secret().to_string()).await.expect("Failed to perform POST request to `/auth`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+}
+
+#[tokio::test]
+async fn authentication_attempts_are_recorded_in_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    // a random client IP to find the entries of this test
+    let client_ip = format!("10.{}.{}.{}", rand::random::<u8>(), rand::random::<u8>(), rand::random::<u8>());
+    for api_key in [Alphanumeric.sample_string(&mut rand::thread_rng(), 32), app.api_key.0.expose_secret().to_string()] {
+        reqwest::Client::new()
+            .post(format!("{}/auth", &app.address))
+            .header("X-Forwarded-For", &client_ip)
+            .header("User-Agent", "audit-test")
+            .json(&json!({"api_key": api_key}))
+            .send()
+            .await
+            .expect("Failed to perform POST request to `/auth`.");
+    }
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/admin/audit-log?event=authenticate&ip={}", &app.address, client_ip))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/audit-log`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let entries: serde_json::Value = response.json().await.unwrap();
+    let entries = entries["data"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    // newest first
+    assert_eq!(entries[0]["outcome"], "success");
+    assert_eq!(entries[1]["outcome"], "failure");
+    assert!(entries.iter().all(|entry| entry["user_agent"] == "audit-test" && entry["ip"] == client_ip.as_str()));
+}
+
 #[tokio::test]
 async fn request_missing_authorization_header_is_rejected() {
     // Arrange
@@ -0,0 +1,132 @@
+use crate::helpers::{spawn_app, spawn_app_with_configuration, TestApp};
+use rand::distributions::{Alphanumeric, DistString};
+use serde_json::{json, Value};
+
+async fn post_batch(app: &TestApp, operations: Value) -> reqwest::Response {
+    return app.api_client
+        .post(format!("{}/batch", &app.address))
+        .json(&operations)
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/batch`.");
+}
+
+fn statuses(body: &Value) -> Vec<u64> {
+    return body["data"].as_array().unwrap().iter()
+        .map(|result| result["status"].as_u64().unwrap())
+        .collect();
+}
+
+#[tokio::test]
+async fn batch_executes_operations_sequentially_and_returns_each_result() {
+    // Arrange
+    let app = spawn_app().await;
+    let code = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+
+    let operations = json!([
+        {"method": "POST", "path": "/coupon", "body": {"code": code, "discount": 10, "active": true}},
+        {"method": "GET", "path": format!("/coupon/{}", code)},
+        {"method": "DELETE", "path": format!("/coupon/{}", code)},
+        {"method": "GET", "path": format!("/coupon/{}", code)},
+        {"method": "GET", "path": "/not_a_coupon_route"},
+    ]);
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/batch", &app.address))
+        .json(&operations)
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/batch`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+
+    let response_body: Value = response.json().await.expect("Failed to parse batch response.");
+    let results = response_body["data"].as_array().unwrap();
+    let statuses: Vec<u64> = results.iter()
+        .map(|result| result["status"].as_u64().unwrap())
+        .collect();
+
+    assert_eq!(statuses, vec![201, 200, 204, 404, 404]);
+    assert_eq!(results[1]["body"]["code"], code);
+}
+
+#[tokio::test]
+async fn batch_without_authorization_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/batch", &app.address))
+        .json(&json!([]))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/batch`.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn batch_operations_are_dispatched_like_the_router() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_and_deserialize_coupon(json!({"code": "SUMMER 10", "discount": 10, "active": true})).await;
+
+    let operations = json!([
+        {"method": "GET", "path": "/coupon/count?active=true"},
+        {"method": "GET", "path": "/coupon/SUMMER%2010"},
+        {"method": "GET", "path": "/coupon"},
+        {"method": "GET", "path": "/coupon?limit=1"},
+        {"method": "GET", "path": "/coupon/events"},
+    ]);
+
+    // Act
+    let response = post_batch(&app, operations).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: Value = response.json().await.expect("Failed to parse batch response.");
+    assert_eq!(statuses(&body), vec![200, 200, 422, 200, 422]);
+    let results = body["data"].as_array().unwrap();
+    assert_eq!(results[0]["body"]["total"], 1);
+    assert_eq!(results[1]["body"]["code"], "SUMMER 10");
+    assert_eq!(results[3]["body"]["coupons"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn batches_over_the_max_operations_are_rejected() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.application.max_batch_operations = 2).await;
+    let operation = json!({"method": "GET", "path": "/coupon/count"});
+
+    // Act
+    let accepted = post_batch(&app, json!([operation, operation])).await;
+    let rejected = post_batch(&app, json!([operation, operation, operation])).await;
+
+    // Assert
+    assert_eq!(200, accepted.status().as_u16());
+    assert_eq!(422, rejected.status().as_u16());
+}
+
+#[tokio::test]
+async fn each_batch_operation_counts_for_the_rate_limit() {
+    // Arrange
+    // the `/auth` request made by `spawn_app` counts as the first request
+    let app = spawn_app_with_configuration(|c| {
+        c.rate_limit.enabled = true;
+        c.rate_limit.max_requests = 3;
+        c.rate_limit.window_seconds = 60;
+    }).await;
+    let operation = json!({"method": "GET", "path": "/coupon/count"});
+
+    // Act
+    let response = post_batch(&app, json!([operation, operation, operation])).await;
+
+    // Assert - the batch is the second request, its second operation the third
+    assert_eq!(200, response.status().as_u16());
+    let body: Value = response.json().await.expect("Failed to parse batch response.");
+    assert_eq!(statuses(&body), vec![200, 200, 429]);
+}
@@ -0,0 +1,56 @@
+use crate::helpers::{spawn_app, spawn_app_with_configuration};
+use coupon_api::configuration::CompressionEncoding;
+
+#[tokio::test]
+async fn responses_are_compressed_with_an_accepted_encoding() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/coupon", &app.address))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .expect("Failed to perform GET request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("gzip", response.headers().get("Content-Encoding").unwrap().to_str().unwrap());
+}
+
+#[tokio::test]
+async fn encodings_not_configured_are_not_used() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.compression.encodings = vec![CompressionEncoding::Gzip]).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/coupon", &app.address))
+        .header("Accept-Encoding", "br")
+        .send()
+        .await
+        .expect("Failed to perform GET request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.headers().get("Content-Encoding").is_none());
+}
+
+#[tokio::test]
+async fn responses_are_not_compressed_when_the_compression_is_disabled() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.compression.enabled = false).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/coupon", &app.address))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .expect("Failed to perform GET request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.headers().get("Content-Encoding").is_none());
+}
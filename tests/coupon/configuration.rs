@@ -0,0 +1,66 @@
+use crate::helpers::{spawn_app};
+use serde_json::Value;
+
+#[tokio::test]
+async fn configuration_can_be_reloaded_without_restarting() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/admin/config/reload", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/config/reload`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: Value = response.json().await.expect("Failed to parse reload response.");
+    let reloaded = body["data"]["reloaded"].as_array().unwrap();
+    assert!(reloaded.contains(&Value::from("rate_limit")));
+    assert!(reloaded.contains(&Value::from("session")));
+    assert!(reloaded.contains(&Value::from("feature_flags")));
+    assert!(reloaded.contains(&Value::from("cache.ttl_seconds")));
+    assert!(body["data"]["restart_required"].as_array().unwrap().contains(&Value::from("database")));
+}
+
+#[tokio::test]
+async fn log_level_can_be_changed_without_restarting() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/admin/log-level", &app.address))
+        .json(&serde_json::json!({"filter": "info"}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/log-level`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let response = app.api_client
+        .get(format!("{}/admin/log-level", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/log-level`.");
+    let body: Value = response.json().await.expect("Failed to parse log level response.");
+    assert_eq!(body["data"]["filter"], "info");
+}
+
+#[tokio::test]
+async fn invalid_log_levels_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/admin/log-level", &app.address))
+        .json(&serde_json::json!({"filter": "coupon_api=loud"}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/log-level`.");
+
+    // Assert
+    assert_eq!(422, response.status().as_u16());
+}
@@ -0,0 +1,61 @@
+use crate::helpers::{spawn_app, TEST_ALLOWED_ORIGIN};
+
+#[tokio::test]
+async fn preflight_from_allowed_origin_returns_cors_headers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .request(reqwest::Method::OPTIONS, format!("{}/coupon", &app.address))
+        .header("Origin", TEST_ALLOWED_ORIGIN)
+        .header("Access-Control-Request-Method", "GET")
+        .send()
+        .await
+        .expect("Failed to perform OPTIONS request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        TEST_ALLOWED_ORIGIN,
+        response.headers().get("Access-Control-Allow-Origin").unwrap().to_str().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn preflight_from_unknown_origin_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .request(reqwest::Method::OPTIONS, format!("{}/coupon", &app.address))
+        .header("Origin", "http://unknown.origin")
+        .header("Access-Control-Request-Method", "GET")
+        .send()
+        .await
+        .expect("Failed to perform OPTIONS request");
+
+    // Assert
+    assert!(response.headers().get("Access-Control-Allow-Origin").is_none());
+}
+
+#[tokio::test]
+async fn response_headers_are_exposed_to_allowed_origin() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/health_check", &app.address))
+        .header("Origin", TEST_ALLOWED_ORIGIN)
+        .send()
+        .await
+        .expect("Failed to perform GET request");
+
+    // Assert
+    let exposed = response.headers().get("Access-Control-Expose-Headers").unwrap().to_str().unwrap().to_lowercase();
+    for header in ["etag", "link", "x-total-count", "x-ratelimit-remaining", "retry-after", "x-request-id", "deprecation", "sunset"] {
+        assert!(exposed.contains(header), "`{}` is not exposed: `{}`.", header, exposed);
+    }
+}
@@ -1,6 +1,9 @@
-use crate::helpers::{spawn_app, TestApp};
+use crate::helpers::{spawn_app, spawn_app_with_configuration, TestApp};
 use chrono::{NaiveDateTime, Utc, Datelike};
-use coupon_api::coupon::{Coupon, CouponInsertRequest, CouponResponse, CouponUpdateRequest};
+use coupon_api::coupon::{
+    CartApplyResponse, CodeValidation, Coupon, CouponChanges, CouponInsertRequest, CouponResponse, CouponUpdateRequest, MAX_BULK_CODES,
+};
+use coupon_api::envelope::Envelope;
 use rand::distributions::{Alphanumeric, DistString};
 use serde_json::json;
 
@@ -33,6 +36,45 @@ async fn get_coupon_by_code_returns_a_coupon() {
      assert_coupon_fields(coupon, coupon_request);
 }
 
+#[tokio::test]
+async fn get_coupon_returns_links_to_related_actions() {
+    // Arrange
+    let (app, added_coupon) = spawn_app_and_post_coupon().await;
+
+    // Act
+    let response = app.get_coupon(format!("/{}", added_coupon.id).as_str()).await;
+    let response_body: serde_json::Value = response.json().await.expect("Failed to parse coupon response.");
+    let coupon = &response_body["data"];
+
+    // Assert
+    let href = format!("/coupon/{}", added_coupon.id);
+    assert_eq!(coupon["_links"]["self"], json!({"href": href, "method": "GET"}));
+    assert_eq!(coupon["_links"]["update"], json!({"href": href, "method": "PUT"}));
+    assert_eq!(coupon["_links"]["delete"], json!({"href": href, "method": "DELETE"}));
+    assert!(response_body["meta"]["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn get_coupon_returns_xml_when_accepted() {
+    // Arrange
+    let (app, added_coupon) = spawn_app_and_post_coupon().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/coupon/{}", &app.address, added_coupon.id))
+        .header("Accept", "application/xml")
+        .send()
+        .await
+        .expect("Failed to perform GET request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("application/xml", response.headers().get("Content-Type").unwrap().to_str().unwrap());
+    let response_body = response.text().await.expect("Failed to get response_body");
+    assert!(response_body.starts_with("<response><data>"));
+    assert!(response_body.contains(&format!("<code>{}</code>", added_coupon.code)));
+}
+
 #[tokio::test]
 async fn get_all_coupons_returns_a_list_of_coupons() {
     // Arrange
@@ -52,7 +94,8 @@ async fn get_all_coupons_returns_a_list_of_coupons() {
     // get all coupons
     let response = app.get_coupon("").await;
     let response_body = response.text().await.expect("failed to get response_body");
-    let coupons: Vec<Coupon> = serde_json::from_str(&response_body).expect("Failed to parse CouponResponse from response.");
+    let coupons: Envelope<Vec<Coupon>> = serde_json::from_str(&response_body).expect("Failed to parse CouponResponse from response.");
+    let coupons = coupons.data;
 
     // Assert
     assert!(coupons.len() > 1);
@@ -65,6 +108,143 @@ async fn get_all_coupons_returns_a_list_of_coupons() {
     assert!(added_coupons.len() == 2);
 }
 
+#[tokio::test]
+async fn get_all_coupons_is_streamed() {
+    // Arrange
+    let app = spawn_app().await;
+    let code = get_random_coupon_code();
+    app.post_coupon(get_coupon_request_json(&get_coupon_request(code.clone())), true).await;
+
+    // Act
+    let response = app.get_coupon("").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    // sent in chunks, the size is not known before the last coupon is read
+    assert!(response.content_length().is_none());
+    let coupons: Envelope<Vec<Coupon>> = response.json().await.expect("Failed to parse the streamed coupons.");
+    assert!(coupons.meta.request_id.is_some());
+    assert!(coupons.data.iter().any(|coupon| coupon.code == code));
+}
+
+#[tokio::test]
+async fn get_all_coupons_with_cursor_iterates_every_coupon_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let codes: Vec<String> = (0..3).map(|_| get_random_coupon_code()).collect();
+    for code in &codes {
+        app.post_coupon(get_coupon_request_json(&get_coupon_request(code.clone())), true).await;
+    }
+
+    // Act
+    let mut ids: Vec<i64> = vec![];
+    let mut seen_codes: Vec<String> = vec![];
+    let mut endpoint = "?limit=2".to_string();
+    loop {
+        let page: serde_json::Value = app.get_coupon(&endpoint).await.json().await.expect("Failed to parse page response.");
+        for coupon in page["data"].as_array().unwrap() {
+            ids.push(coupon["id"].as_i64().unwrap());
+            seen_codes.push(coupon["code"].as_str().unwrap().to_string());
+        }
+        match page["meta"]["pagination"]["next_cursor"].as_str() {
+            Some(cursor) => endpoint = format!("?limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+
+    // Assert
+    // ordered by id and without duplicates
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    for code in codes {
+        assert!(seen_codes.contains(&code));
+    }
+}
+
+#[tokio::test]
+async fn get_all_coupons_page_links_navigate_back_and_forth() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        app.post_coupon(get_coupon_request_json(&get_coupon_request(get_random_coupon_code())), true).await;
+    }
+
+    // Act
+    let first_page: serde_json::Value = app.get_coupon("?limit=1").await.json().await.expect("Failed to parse page response.");
+    let next_href = first_page["meta"]["pagination"]["_links"]["next"]["href"].as_str().unwrap().replacen("/coupon", "", 1);
+    let second_page: serde_json::Value = app.get_coupon(&next_href).await.json().await.expect("Failed to parse page response.");
+    let prev_href = second_page["meta"]["pagination"]["_links"]["prev"]["href"].as_str().unwrap().replacen("/coupon", "", 1);
+    let prev_page: serde_json::Value = app.get_coupon(&prev_href).await.json().await.expect("Failed to parse page response.");
+
+    // Assert
+    assert!(first_page["meta"]["pagination"]["_links"]["prev"].is_null());
+    assert_eq!(first_page["data"][0]["id"], prev_page["data"][0]["id"]);
+    assert_ne!(first_page["data"][0]["id"], second_page["data"][0]["id"]);
+}
+
+#[tokio::test]
+async fn get_all_coupons_page_has_the_pagination_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        app.post_coupon(get_coupon_request_json(&get_coupon_request(get_random_coupon_code())), true).await;
+    }
+
+    // Act
+    let response = app.get_coupon("?limit=1").await;
+
+    // Assert
+    let total: i64 = response.headers()["X-Total-Count"].to_str().unwrap().parse().unwrap();
+    let link = response.headers()["Link"].to_str().unwrap().to_string();
+    let page: serde_json::Value = response.json().await.expect("Failed to parse page response.");
+    assert!(total >= 3);
+    assert_eq!(page["meta"]["pagination"]["total"], total);
+    let next_href = page["meta"]["pagination"]["_links"]["next"]["href"].as_str().unwrap();
+    assert!(link.contains(&format!("<{}>; rel=\"next\"", next_href)));
+    assert!(link.contains("rel=\"first\"") && link.contains("rel=\"last\""));
+    assert!(!link.contains("rel=\"prev\""));
+}
+
+#[tokio::test]
+async fn get_all_coupons_with_invalid_cursor_returns_422() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_coupon("?cursor=invalid").await;
+
+    // Assert
+    assert_eq!(422, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_coupon_count_returns_totals_and_aggregates() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut inactive_coupon = get_coupon_request(get_random_coupon_code());
+    inactive_coupon.active = false;
+    let mut expired_coupon = get_coupon_request(get_random_coupon_code());
+    expired_coupon.expiration_date = Some(NaiveDateTime::parse_from_str("2000-12-31 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap());
+
+    app.post_coupon(get_coupon_request_json(&get_coupon_request(get_random_coupon_code())), true).await;
+    app.post_coupon(get_coupon_request_json(&inactive_coupon), true).await;
+    app.post_coupon(get_coupon_request_json(&expired_coupon), true).await;
+
+    // Act
+    let count: Envelope<serde_json::Value> = app.get_coupon("/count").await.json().await.expect("Failed to parse count response.");
+    let count = count.data;
+    let active_count: Envelope<serde_json::Value> = app.get_coupon("/count?active=true").await.json().await.expect("Failed to parse count response.");
+    let active_count = active_count.data;
+
+    // Assert
+    // the test database is shared with the other tests running in parallel, so only lower bounds are asserted
+    assert!(count["total"].as_i64().unwrap() >= 3);
+    assert!(count["active"].as_i64().unwrap() >= 2);
+    assert!(count["expired"].as_i64().unwrap() >= 1);
+    assert!(count["remaining_usages"].as_i64().unwrap() >= 2);
+    assert!(active_count["total"].as_i64().unwrap() >= 2);
+    assert!(active_count["total"].as_i64().unwrap() <= count["total"].as_i64().unwrap());
+}
+
 #[tokio::test]
 async fn get_coupon_not_found_returns_404(){
     // Arrange
@@ -90,6 +270,63 @@ async fn get_coupon_not_found_returns_404(){
 
 }
 
+#[tokio::test]
+async fn get_coupon_not_found_is_localized_by_accept_language(){
+    // Arrange
+    let app = spawn_app().await;
+    let code = get_random_coupon_code();
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/coupon/{}", &app.address, code))
+        .header("Accept-Language", "pt-BR,pt;q=0.9,en;q=0.8")
+        .send()
+        .await
+        .expect("Failed to perform GET request");
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+    assert_eq!(response.headers()["Content-Language"], "pt-BR");
+    let body: serde_json::Value = response.json().await.expect("Failed to get response_body");
+    assert_eq!(body["error"], format!("Cupom com o código `{}` não encontrado.", code));
+}
+
+
+/**
+ * HEAD
+ */
+#[tokio::test]
+async fn head_coupon_by_code_returns_200_without_body() {
+    // Arrange
+    let (app, added_coupon) = spawn_app_and_post_coupon().await;
+
+    // Act
+    let response = app.api_client
+        .head(format!("{}/coupon/code/{}", &app.address, added_coupon.code))
+        .send()
+        .await
+        .expect("Failed to perform HEAD request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("", response.text().await.unwrap());
+}
+
+#[tokio::test]
+async fn head_coupon_by_code_returns_404_for_coupon_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .head(format!("{}/coupon/code/{}", &app.address, get_random_coupon_code()))
+        .send()
+        .await
+        .expect("Failed to perform HEAD request");
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
 
 /**
  * POST
@@ -109,7 +346,8 @@ async fn get_coupon_not_found_returns_404(){
      // Assert
      assert_eq!(201, response_status);
  
-     let coupon: CouponResponse = serde_json::from_str(&response_body).expect("Failed to parse CouponResponse from response.");
+     let coupon: Envelope<CouponResponse> = serde_json::from_str(&response_body).expect("Failed to parse CouponResponse from response.");
+     let coupon = coupon.data;
      
      assert_coupon_fields(coupon, coupon_request);
  }
@@ -219,6 +457,20 @@ async fn post_returns_4xx_for_invalid_coupon_data() {
 /**
  * PUT
  */
+#[tokio::test]
+async fn post_returns_413_for_a_body_larger_than_max_payload_bytes() {
+    // Arrange
+    let app = spawn_app_with_configuration(|configuration| configuration.application.max_payload_bytes = 1024).await;
+    let mut body = json!(get_coupon_request(get_random_coupon_code()));
+    body["padding"] = json!("a".repeat(2048));
+
+    // Act
+    let response = app.post_coupon(body, false).await;
+
+    // Assert
+    assert_eq!(413, response.status().as_u16());
+}
+
 #[tokio::test]
 async fn put_by_id_updates_the_coupon_successfully() {
     put_code_test_request("id".to_string()).await;
@@ -288,6 +540,52 @@ async fn put_do_not_update_the_code() {
     assert_ne!(coupon.code, coupon_update.code);
 }
 
+#[tokio::test]
+async fn put_with_an_outdated_version_returns_409() {
+    // Arrange
+    let (app, added_coupon) = spawn_app_and_post_coupon().await;
+    let mut coupon_update = get_default_coupon_data(added_coupon.code.clone());
+    coupon_update.version = added_coupon.version;
+    let body = json!(serde_json::to_value(&coupon_update).unwrap());
+
+    // Act
+    let first_response = app.put_coupon(added_coupon.id.to_string(), body.clone()).await;
+    // a concurrent editor that read the same version
+    let second_response = app.put_coupon(added_coupon.id.to_string(), body).await;
+
+    // Assert
+    assert_eq!(200, first_response.status().as_u16());
+    assert_eq!(409, second_response.status().as_u16());
+    let coupon = app.get_and_deserialize_coupon(format!("/{}", added_coupon.id).as_str()).await;
+    assert_eq!(coupon.version, added_coupon.version + 1);
+}
+
+#[tokio::test]
+async fn put_with_if_match_checks_the_etag_of_the_coupon() {
+    // Arrange
+    let (app, added_coupon) = spawn_app_and_post_coupon().await;
+    let url = format!("{}/coupon/{}", &app.address, added_coupon.id);
+    let etag = app.get_coupon(format!("/{}", added_coupon.id).as_str()).await
+        .headers().get("ETag").expect("The coupon has no ETag.").to_str().unwrap().to_string();
+    let mut coupon_update = get_default_coupon_data(added_coupon.code.clone());
+    coupon_update.discount = 20;
+    let body = json!({
+        "discount": coupon_update.discount,
+        "active": coupon_update.active,
+        "max_usage_count": coupon_update.max_usage_count,
+        "expiration_date": coupon_update.expiration_date,
+    });
+
+    // Act
+    let first_response = app.api_client.put(&url).header("If-Match", &etag).json(&body).send().await.expect("Failed to perform PUT request");
+    let second_response = app.api_client.put(&url).header("If-Match", &etag).json(&body).send().await.expect("Failed to perform PUT request");
+
+    // Assert
+    assert_eq!(format!("\"{}\"", added_coupon.version), etag);
+    assert_eq!(200, first_response.status().as_u16());
+    assert_eq!(409, second_response.status().as_u16());
+}
+
 #[tokio::test]
 async fn put_returns_404_for_coupon_not_found(){
     // Arrange
@@ -371,7 +669,8 @@ async fn put_returns_404_for_invalid_id_path_param_data() {
         discount: coupon.discount,
         active: coupon.active,
         max_usage_count: coupon.max_usage_count,
-        expiration_date: coupon.expiration_date
+        expiration_date: coupon.expiration_date,
+        version: None,
     };
 
     let body = json!(serde_json::to_value(&coupon_update).unwrap());
@@ -396,6 +695,117 @@ async fn put_returns_404_for_invalid_id_path_param_data() {
 }
 
 
+#[tokio::test]
+async fn put_by_code_path_creates_the_coupon_if_absent_and_replaces_it_if_present() {
+    // Arrange
+    let app = spawn_app().await;
+    let code = get_random_coupon_code();
+    let mut coupon_request = get_coupon_request(code.clone());
+    let upsert_url = format!("{}/coupon/code/{}", &app.address, code);
+
+    // Act 1
+    let body = json!({
+        "discount": coupon_request.discount,
+        "active": coupon_request.active,
+        "max_usage_count": coupon_request.max_usage_count,
+        "expiration_date": coupon_request.expiration_date,
+    });
+    let response = app.api_client.put(&upsert_url).json(&body).send().await.expect("Failed to perform PUT request");
+
+    // Assert 1
+    assert_eq!(201, response.status().as_u16());
+    let coupon = app.get_and_deserialize_coupon(format!("/{}", code).as_str()).await;
+    assert_coupon_fields(coupon, coupon_request.clone());
+
+    // Act 2
+    coupon_request.discount = 50;
+    coupon_request.max_usage_count = None;
+    let body = json!({
+        "discount": coupon_request.discount,
+        "active": coupon_request.active,
+        "max_usage_count": coupon_request.max_usage_count,
+        "expiration_date": coupon_request.expiration_date,
+    });
+    let response = app.api_client.put(&upsert_url).json(&body).send().await.expect("Failed to perform PUT request");
+
+    // Assert 2
+    assert_eq!(200, response.status().as_u16());
+    let coupon = app.get_and_deserialize_coupon(format!("/{}", code).as_str()).await;
+    assert_coupon_fields(coupon, coupon_request);
+}
+
+/**
+ * PATCH
+ */
+#[tokio::test]
+async fn patch_only_changes_the_fields_sent_and_null_clears_them() {
+    // Arrange
+    let mut coupon_request = get_coupon_request(get_random_coupon_code());
+    coupon_request.max_usage_count = Some(10);
+    let (app, added_coupon) = spawn_app_and_post_coupon_with_coupon_request(coupon_request.clone()).await;
+
+    // Act
+    let response = app.api_client
+        .patch(format!("{}/coupon/{}", &app.address, added_coupon.code))
+        .header("Content-Type", "application/merge-patch+json")
+        .body(json!({"discount": 25, "max_usage_count": null}).to_string())
+        .send()
+        .await
+        .expect("Failed to perform PATCH request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let coupon = app.get_and_deserialize_coupon(format!("/{}", added_coupon.code).as_str()).await;
+    assert_eq!(coupon.discount, 25);
+    assert_eq!(coupon.max_usage_count, None);
+    assert_eq!(coupon.active, coupon_request.active);
+    assert_eq!(coupon.expiration_date, coupon_request.expiration_date);
+    assert_eq!(coupon.version, added_coupon.version + 1);
+}
+
+#[tokio::test]
+async fn patch_returns_422_when_a_required_field_is_cleared() {
+    // Arrange
+    let (app, added_coupon) = spawn_app_and_post_coupon().await;
+
+    // Act
+    let response = app.request_coupon(reqwest::Method::PATCH, format!("/{}", added_coupon.id).as_str(), json!({"discount": null}), false).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[tokio::test]
+async fn json_patch_operations_are_applied_to_the_stored_coupon() {
+    // Arrange
+    let (app, added_coupon) = spawn_app_and_post_coupon().await;
+    let patch = |operations: serde_json::Value| app.api_client
+        .patch(format!("{}/coupon/{}", &app.address, added_coupon.id))
+        .header("Content-Type", "application/json-patch+json")
+        .body(operations.to_string())
+        .send();
+
+    // Act
+    let response = patch(json!([
+        {"op": "test", "path": "/version", "value": added_coupon.version},
+        {"op": "replace", "path": "/discount", "value": 30},
+        {"op": "remove", "path": "/expiration_date"}
+    ])).await.expect("Failed to perform PATCH request");
+    // the test fails now that the version changed, nothing is applied
+    let failed_response = patch(json!([
+        {"op": "test", "path": "/version", "value": added_coupon.version},
+        {"op": "replace", "path": "/discount", "value": 40}
+    ])).await.expect("Failed to perform PATCH request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(failed_response.status().as_u16(), 422);
+    let coupon = app.get_and_deserialize_coupon(format!("/{}", added_coupon.id).as_str()).await;
+    assert_eq!(coupon.discount, 30);
+    assert_eq!(coupon.expiration_date, None);
+    assert_eq!(coupon.version, added_coupon.version + 1);
+}
+
 /**
  * DELETE
  */
@@ -518,6 +928,169 @@ async fn verify_coupon_validates_if_today_is_friday() {
 }
 
 
+/**
+ * Coupon Changes
+ */
+#[tokio::test]
+async fn coupon_changes_list_each_coupon_once_and_the_deleted_ones_as_tombstones() {
+    // Arrange
+    let (app, updated_coupon) = spawn_app_and_post_coupon().await;
+    let mut update_request = get_coupon_request(updated_coupon.code.clone());
+    update_request.discount = 20;
+    app.put_coupon(updated_coupon.code.clone(), get_coupon_request_json(&update_request)).await.error_for_status().unwrap();
+    let deleted_coupon = app.post_and_deserialize_coupon(get_coupon_request_json(&get_coupon_request(get_random_coupon_code()))).await;
+    app.delete_coupon(deleted_coupon.code.clone()).await.error_for_status().unwrap();
+    // the writes of the last 2 seconds are not listed yet
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // Act
+    let mut changes = Vec::new();
+    let mut endpoint = "/changes?limit=100".to_string();
+    loop {
+        let response = app.get_coupon(&endpoint).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let page: Envelope<CouponChanges> = response.json().await.expect("Failed to get response_body");
+        changes.extend(page.data.changes);
+        match (page.data.has_more, page.data.next_cursor) {
+            (true, Some(next_cursor)) => endpoint = format!("/changes?limit=100&since={}", next_cursor),
+            _ => break,
+        }
+    }
+
+    // Assert
+    let updated: Vec<_> = changes.iter().filter(|change| change.id == updated_coupon.id).collect();
+    assert_eq!(updated.len(), 1);
+    assert!(!updated[0].deleted);
+    assert_eq!(updated[0].coupon.as_ref().map(|coupon| coupon.discount), Some(20));
+    let deleted: Vec<_> = changes.iter().filter(|change| change.id == deleted_coupon.id).collect();
+    assert_eq!(deleted.len(), 1);
+    assert!(deleted[0].deleted);
+    assert_eq!(deleted[0].code, deleted_coupon.code);
+    assert!(deleted[0].coupon.is_none());
+}
+
+#[tokio::test]
+async fn coupon_changes_return_422_for_an_invalid_since() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_coupon("/changes?since=yesterday").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+/**
+ * Coupon Events
+ */
+#[tokio::test]
+async fn coupon_events_are_streamed_to_the_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut response = app.get_coupon("/events").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let coupon_request = get_coupon_request(get_random_coupon_code());
+
+    // Act
+    app.post_and_deserialize_coupon(get_coupon_request_json(&coupon_request)).await;
+
+    // Assert
+    // the events of the other tests are streamed too
+    let event = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            let chunk = response.chunk().await.expect("Failed to read the stream").expect("The stream ended");
+            let event = String::from_utf8(chunk.to_vec()).unwrap();
+            if (event.contains(&coupon_request.code)){
+                return event;
+            }
+        }
+    }).await.expect("The event was not streamed");
+    assert!(event.starts_with("event: coupon.created\ndata: "));
+}
+
+/**
+ * Apply Coupons
+ */
+#[tokio::test]
+async fn apply_coupons_discounts_the_items_and_rejects_the_invalid_codes() {
+    // Arrange
+    let mut coupon_request = get_coupon_request(get_random_coupon_code());
+    coupon_request.discount = 10;
+    let (app, _) = spawn_app_and_post_coupon_with_coupon_request(coupon_request.clone()).await;
+    let body = json!({
+        "items": [
+            {"sku": "SHIRT", "quantity": 2, "unit_price": 5000},
+            {"sku": "SOCKS", "quantity": 1, "unit_price": 999}
+        ],
+        "shipping": 1500,
+        "currency": "BRL",
+        "codes": [coupon_request.code, "UNKNOWN"]
+    });
+
+    // Act
+    let response = app.request_coupon(reqwest::Method::POST, "/apply", body, false).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let cart: Envelope<CartApplyResponse> = response.json().await.expect("Failed to get response_body");
+    assert_eq!(cart.data.applied.len(), 1);
+    assert_eq!(cart.data.applied[0].amount, 1000 + 99);
+    assert_eq!(cart.data.rejected[0].code, "UNKNOWN");
+    assert_eq!((cart.data.subtotal, cart.data.discount, cart.data.total), (10999, 1099, 10999 - 1099 + 1500));
+}
+
+#[tokio::test]
+async fn apply_coupons_returns_422_for_an_empty_cart() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = json!({"items": [], "currency": "BRL", "codes": ["CODE"]});
+
+    // Act
+    let response = app.request_coupon(reqwest::Method::POST, "/apply", body, false).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+
+/**
+ * Validate Coupons in bulk
+ */
+#[tokio::test]
+async fn validate_coupons_returns_the_validity_of_each_code_in_order() {
+    // Arrange
+    let mut coupon_request = get_coupon_request(get_random_coupon_code());
+    coupon_request.discount = 15;
+    let (app, _) = spawn_app_and_post_coupon_with_coupon_request(coupon_request.clone()).await;
+    let body = json!({"codes": ["UNKNOWN", coupon_request.code]});
+
+    // Act
+    let response = app.request_coupon(reqwest::Method::POST, "/validate/bulk", body, false).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let validations: Envelope<Vec<CodeValidation>> = response.json().await.expect("Failed to get response_body");
+    assert_eq!(validations.data.len(), 2);
+    assert_eq!((validations.data[0].valid, validations.data[0].discount), (false, None));
+    assert_eq!(validations.data[1].code, coupon_request.code);
+    assert_eq!((validations.data[1].valid, validations.data[1].discount), (true, Some(15)));
+}
+
+#[tokio::test]
+async fn validate_coupons_returns_422_for_too_many_codes() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = json!({"codes": vec!["CODE"; MAX_BULK_CODES + 1]});
+
+    // Act
+    let response = app.request_coupon(reqwest::Method::POST, "/validate/bulk", body, false).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+}
+
 
 /**
  * Helper functions
@@ -542,9 +1115,9 @@ async fn start_verify_test_and_post_coupon(coupon_request: CouponInsertRequest)
     let (app, _) = spawn_app_and_post_coupon_with_coupon_request(coupon_request.clone()).await;
 
     let response = app.get_coupon(format!("/verify/{}", coupon_request.code).as_str()).await;
-    let response_body = response.text().await.expect("Failed to get response_body");
+    let response_body: Envelope<bool> = response.json().await.expect("Failed to get response_body");
 
-    return response_body;
+    return response_body.data.to_string();
 }
 
 fn assert_coupon_fields(coupon_response: CouponResponse, coupon_expected: CouponInsertRequest){
@@ -563,6 +1136,7 @@ fn get_default_coupon_data(code: String) -> Coupon {
         max_usage_count: Some(2),
         expiration_date: Some(NaiveDateTime::parse_from_str("2100-12-31 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap()),
         active: true,
+        version: 1,
         date_created: None,
         date_updated: None,
     };
@@ -0,0 +1,41 @@
+use crate::helpers::{spawn_app};
+use coupon_api::{
+    configuration::ArchiveSettings,
+    coupon::coupon_store::MySqlCouponStore,
+    coupon_archive::{coupon_archive_service, ArchivedCoupon},
+    envelope::Envelope,
+    telemetry::PoolMetrics,
+};
+use serde_json::json;
+
+#[tokio::test]
+async fn coupons_expired_for_longer_than_the_retention_are_archived() {
+    // Arrange
+    let app = spawn_app().await;
+    let stale = app.post_and_deserialize_coupon(json!({
+        "code": "STALE", "discount": 10, "active": true, "max_usage_count": null, "expiration_date": "2020-01-01T00:00:00",
+    })).await;
+    app.post_and_deserialize_coupon(json!({
+        "code": "RECENT", "discount": 10, "active": true, "max_usage_count": null, "expiration_date": "2100-01-01T00:00:00",
+    })).await;
+    let settings = ArchiveSettings { interval_seconds: 0, retention_days: 30, batch_size: 1 };
+    let store = MySqlCouponStore::new(app.db_pool.clone(), PoolMetrics::new("test"));
+
+    // Act
+    let archived = coupon_archive_service::archive_expired(&settings, &store, &app.db_pool).await.expect("Failed to archive the coupons.");
+
+    // Assert
+    assert_eq!(archived, 1);
+    assert_eq!(app.get_coupon("/STALE").await.status().as_u16(), 404);
+    assert_eq!(app.get_coupon("/RECENT").await.status().as_u16(), 200);
+    let response = app.api_client
+        .get(format!("{}/admin/coupons-archive?code=STALE", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/coupons-archive`.");
+    assert_eq!(response.status().as_u16(), 200);
+    let coupons: Envelope<Vec<ArchivedCoupon>> = response.json().await.expect("Failed to parse the archived coupons.");
+    assert_eq!(coupons.data.len(), 1);
+    assert_eq!(coupons.data[0].id, stale.id);
+    assert!(coupons.data[0].date_archived.is_some());
+}
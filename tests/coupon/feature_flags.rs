@@ -0,0 +1,42 @@
+use crate::helpers::{spawn_app, TestApp};
+use coupon_api::envelope::Envelope;
+use coupon_api::feature_flags::{FeatureFlag, COUPONS_WITHOUT_EXPIRATION};
+use rand::distributions::{Alphanumeric, DistString};
+use serde_json::json;
+
+#[tokio::test]
+async fn coupons_without_expiration_are_valid_once_the_flag_is_enabled() {
+    // Arrange
+    let app = spawn_app().await;
+    let code = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+    app.post_and_deserialize_coupon(json!({"code": code, "discount": 10, "active": true, "max_usage_count": null, "expiration_date": null})).await;
+    assert!(!verify_coupon(&app, &code).await);
+
+    // Act
+    let response = app.api_client
+        .put(format!("{}/admin/flags/{}", &app.address, COUPONS_WITHOUT_EXPIRATION))
+        .json(&json!({"enabled": true}))
+        .send()
+        .await
+        .expect("Failed to perform PUT request to `/admin/flags`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(verify_coupon(&app, &code).await);
+
+    let flags: Envelope<Vec<FeatureFlag>> = app.api_client
+        .get(format!("{}/admin/flags", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/flags`.")
+        .json()
+        .await
+        .expect("Failed to parse flags response.");
+    assert!(flags.data.contains(&FeatureFlag { name: COUPONS_WITHOUT_EXPIRATION.to_string(), enabled: true }));
+}
+
+async fn verify_coupon(app: &TestApp, code: &str) -> bool {
+    let response = app.get_coupon(format!("/verify/{}", code).as_str()).await;
+    let response_body: Envelope<bool> = response.json().await.expect("Failed to get response_body");
+    return response_body.data;
+}
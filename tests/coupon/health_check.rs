@@ -1,4 +1,5 @@
 use crate::helpers::{spawn_app};
+use coupon_api::coupon::{DatabaseHealth, Readiness};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -17,3 +18,59 @@ async fn health_check_works() {
     assert_eq!(Some(0), response.content_length()); // no body
 
 }
+
+#[tokio::test]
+async fn database_health_check_reports_the_latency() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/health/db", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let health: DatabaseHealth = response.json().await.expect("Failed to parse the database health.");
+    assert_eq!(health.status, "up");
+    assert!(health.latency_milliseconds > 0.0);
+    assert!(health.error.is_none());
+}
+
+#[tokio::test]
+async fn liveness_probe_works() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/health/live", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn readiness_probe_is_ready_once_the_migrations_are_applied() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/health/ready", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let readiness: Readiness = response.json().await.expect("Failed to parse the readiness.");
+    assert_eq!(readiness.status, "ready");
+    assert!(readiness.pending_migrations.is_empty());
+    assert!(readiness.error.is_none());
+}
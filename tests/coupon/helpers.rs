@@ -3,17 +3,22 @@ use coupon_api::{
     telemetry::{get_subscriber, init_subscriber},
     startup::{get_connection_pool, Application},
     coupon::{CouponResponse},
+    envelope::Envelope,
+    authentication::AuthTokens,
+    migrations::MIGRATOR,
 };
 use reqwest::{
     Method,
     header:: HeaderMap,
 };
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Secret};
 use serde_json::json;
 use std::panic;
 use sqlx::{MySqlPool, MySqlConnection, Connection, Executor};
 use once_cell::sync::Lazy;
 
+pub const TEST_ALLOWED_ORIGIN: &str = "http://allowed.origin";
+
 pub struct TestApp {
     pub address: String,
     pub db_pool: MySqlPool,
@@ -31,15 +36,15 @@ impl TestApp {
         if (!status.to_string().starts_with("2")){
             dbg!(&response_body);
         }
-        let coupon: CouponResponse = serde_json::from_str(&response_body).expect("POST: Failed to parse CouponResponse from response.");
-        return coupon;
+        let coupon: Envelope<CouponResponse> = serde_json::from_str(&response_body).expect("POST: Failed to parse CouponResponse from response.");
+        return coupon.data;
     }
 
     pub async fn get_and_deserialize_coupon(&self, endpoint: &str) -> CouponResponse {
         let response = self.get_coupon(endpoint).await;
         let response_body = response.text().await.expect("failed to get response_body");
-        let coupon: CouponResponse = serde_json::from_str(&response_body).expect("GET: Failed to parse CouponResponse from response.");
-        return coupon;
+        let coupon: Envelope<CouponResponse> = serde_json::from_str(&response_body).expect("GET: Failed to parse CouponResponse from response.");
+        return coupon.data;
     }
 
     pub async fn post_coupon(&self, body: serde_json::Value, error_for_status: bool) -> reqwest::Response {
@@ -91,15 +96,29 @@ impl TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    return spawn_app_with_configuration(|_| {}).await;
+}
+
+/// Same as `spawn_app()`, with a chance to change the configuration before the application is built.
+pub async fn spawn_app_with_configuration(customize: impl FnOnce(&mut Settings)) -> TestApp {
     // The first time `initialize` is invoked the code in `TRACING` is executed.
     // All other invocations will instead skip execution.
     Lazy::force(&TRACING);
 
     // Randomise configuration to ensure test isolation
     let configuration = {
-        let mut c = get_configuration().expect("Failed to read configuration.");
+        let mut c = get_configuration().await.expect("Failed to read configuration.");
         // Use a random OS port
         c.application.port = 0;
+        c.cors.allowed_origins = vec![TEST_ALLOWED_ORIGIN.to_string()];
+        // the tests send many requests from the same IP, so the rate limit is only enabled where it's tested
+        c.rate_limit.enabled = false;
+        // same for the lockout, some tests fail to authenticate on purpose
+        c.auth_lockout.enabled = false;
+        // so the tests can pick the IP of their client with `X-Forwarded-For`
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+        c.request_signing.encryption_key = Some(Secret::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f".to_string()));
+        customize(&mut c);
         c
     };
 
@@ -136,8 +155,9 @@ async fn create_reqwest_client(configuration: &Settings, address: &String) -> re
         .await
         .expect("Failed to perform request to `/auth`.");
 
-    let bearer: String = response.json().await
+    let tokens: Envelope<AuthTokens> = response.json().await
         .expect("Failed to get `/auth` response text.");
+    let bearer = tokens.data.bearer;
 
     // setting default Authorization header
     let mut headers = HeaderMap::new();
@@ -168,7 +188,7 @@ async fn configure_test_database(config: &DatabaseSettings) -> MySqlPool {
         .await
         .expect("Failed to connect to test database.");
         
-    let _ = sqlx::migrate!("./migrations")
+    let _ = MIGRATOR
         .run(&connection_pool)
         .await;
         // no .expect() here because we dont want a panic if the migration fails
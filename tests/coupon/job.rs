@@ -0,0 +1,208 @@
+use crate::helpers::{spawn_app_with_configuration, TestApp};
+use coupon_api::{
+    configuration::{ArchiveSettings, ExpirationReportSettings, JobSettings, RetrySettings},
+    coupon::coupon_store::MySqlCouponStore,
+    job::{job_service, job_worker::{self, JobContext}, JobKind},
+    telemetry::PoolMetrics,
+    webhook::webhook_delivery,
+};
+use rand::distributions::{Alphanumeric, DistString};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+// the worker of the app is disabled, the tests run the jobs themselves
+async fn spawn_app_without_worker() -> TestApp {
+    return spawn_app_with_configuration(|configuration| configuration.jobs.enabled = false).await;
+}
+
+fn context(app: &TestApp) -> JobContext {
+    return JobContext { pool: app.db_pool.clone(), archive: ArchiveSettings::default(), client: webhook_delivery::client(), expiration_report: ExpirationReportSettings::default(), mailer: None, export: None, store: Arc::new(MySqlCouponStore::new(app.db_pool.clone(), PoolMetrics::new("test"))) };
+}
+
+async fn get_job(app: &TestApp, id: u64) -> Value {
+    let response = app.api_client
+        .get(format!("{}/admin/jobs/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/jobs`.");
+    assert_eq!(response.status().as_u16(), 200);
+    let job: Value = response.json().await.expect("Failed to parse the job.");
+    return job["data"].clone();
+}
+
+#[tokio::test]
+async fn failed_jobs_are_retried_until_the_max_retries() {
+    // Arrange
+    let app = spawn_app_without_worker().await;
+    // not a `WebhookDelivery`, so every attempt fails
+    let id = job_service::enqueue(JobKind::WebhookDelivery, &json!({}), &app.db_pool).await.expect("Failed to queue the job.");
+    let settings = JobSettings {
+        retry: RetrySettings { max_retries: 1, initial_backoff_milliseconds: 0, max_backoff_milliseconds: 0 },
+        ..JobSettings::default()
+    };
+
+    // Act - first attempt
+    assert!(job_worker::run_next(&settings, &context(&app)).await.expect("Failed to run the job."));
+
+    // Assert
+    let job = get_job(&app, id).await;
+    assert_eq!(job["status"], "pending");
+    assert_eq!(job["attempts"], 1);
+    assert!(job["last_error"].as_str().is_some());
+
+    // Act - last attempt
+    assert!(job_worker::run_next(&settings, &context(&app)).await.expect("Failed to run the job."));
+
+    // Assert
+    let job = get_job(&app, id).await;
+    assert_eq!(job["status"], "failed");
+    assert_eq!(job["attempts"], 2);
+    assert!(!job_worker::run_next(&settings, &context(&app)).await.expect("Failed to check the queue."));
+}
+
+#[tokio::test]
+async fn jobs_are_listed_by_status_and_kind() {
+    // Arrange
+    let app = spawn_app_without_worker().await;
+    let id = job_service::enqueue(JobKind::ArchiveExpiredCoupons, &json!({}), &app.db_pool).await.expect("Failed to queue the job.");
+    assert!(!job_service::enqueue_unless_queued(JobKind::ArchiveExpiredCoupons, &json!({}), &app.db_pool).await.expect("Failed to queue the job."));
+    assert!(job_worker::run_next(&JobSettings::default(), &context(&app)).await.expect("Failed to run the job."));
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/admin/jobs?status=succeeded&kind=archive_expired_coupons", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/jobs`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let jobs: Value = response.json().await.expect("Failed to parse the jobs.");
+    let jobs = jobs["data"].as_array().unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0]["id"], id);
+}
+
+#[tokio::test]
+async fn unknown_jobs_are_not_found() {
+    // Arrange
+    let app = spawn_app_without_worker().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/admin/jobs/999999", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/jobs`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn scheduled_tasks_are_listed_with_their_next_run() {
+    // Arrange
+    let app = spawn_app_with_configuration(|configuration| {
+        configuration.jobs.enabled = false;
+        configuration.schedules.insert("archive_expired_coupons".to_string(), "0 0 3 * * *".to_string());
+    }).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/admin/jobs/schedules", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/jobs/schedules`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let tasks: Value = response.json().await.expect("Failed to parse the scheduled tasks.");
+    let task = tasks["data"].as_array().unwrap().iter()
+        .find(|task| task["name"] == "archive_expired_coupons")
+        .expect("The archival is not scheduled.")
+        .clone();
+    assert_eq!(task["schedule"], "0 0 3 * * *");
+    assert!(task["last_run"].is_null());
+    assert!(task["next_run"].as_str().is_some());
+}
+
+#[tokio::test]
+async fn imports_are_run_as_jobs_with_the_rejected_rows() {
+    // Arrange
+    let app = spawn_app_without_worker().await;
+    let code = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+    let csv = format!("code,discount,active,max_usage_count,expiration_date\n{0},10,true,,2099-01-01T00:00:00\n{0},20,true,,\nINVALID,200,true,,\n", code);
+    let response = app.api_client
+        .post(format!("{}/coupon/import", &app.address))
+        .header("Content-Type", "text/csv")
+        .body(csv)
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/coupon/import`.");
+    assert_eq!(response.status().as_u16(), 202);
+    let location = response.headers()["Location"].to_str().unwrap().to_string();
+    let job: Value = response.json().await.expect("Failed to parse the job.");
+    assert_eq!(job["data"]["status"], "pending");
+    assert_eq!(job["data"]["total_rows"], 3);
+    assert_eq!(location, format!("/jobs/{}", job["data"]["id"]));
+
+    // Act
+    assert!(job_worker::run_next(&JobSettings::default(), &context(&app)).await.expect("Failed to run the import."));
+    let response = app.api_client
+        .get(format!("{}{}", &app.address, location))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/jobs/{id}`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let job: Value = response.json().await.expect("Failed to parse the job.");
+    assert_eq!(job["data"]["status"], "succeeded");
+    assert_eq!((job["data"]["processed_rows"].as_i64(), job["data"]["imported_rows"].as_i64(), job["data"]["rejected_rows"].as_i64()), (Some(3), Some(1), Some(2)));
+    let lines: Vec<i64> = job["data"]["errors"].as_array().unwrap().iter().map(|error| error["line"].as_i64().unwrap()).collect();
+    assert_eq!(lines, vec![3, 4]);
+    assert!(job["data"]["date_finished"].as_str().is_some());
+}
+
+#[tokio::test]
+async fn imports_without_the_required_columns_are_rejected() {
+    // Arrange
+    let app = spawn_app_without_worker().await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/coupon/import", &app.address))
+        .header("Content-Type", "text/csv")
+        .body("code,discount\nTEN,10\n")
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/coupon/import`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[tokio::test]
+async fn imports_can_be_uploaded_as_a_multipart_form() {
+    // Arrange
+    let app = spawn_app_without_worker().await;
+    let code = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+    let csv = format!("code,discount,active\n{},10,true\n", code);
+    let form = reqwest::multipart::Form::new()
+        .part("file", reqwest::multipart::Part::text(csv).file_name("coupons.csv").mime_str("text/csv").unwrap())
+        .text("existing", "update");
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/coupon/import", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/coupon/import`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let job: Value = response.json().await.expect("Failed to parse the job.");
+    assert_eq!(job["data"]["total_rows"], 1);
+    assert_eq!(job["data"]["options"]["existing"], "update");
+}
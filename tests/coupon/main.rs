@@ -1,7 +1,25 @@
 
 #![allow(unused_parens)]
+#![allow(clippy::needless_return)]
 
+mod batch;
+mod compression;
+mod configuration;
 mod coupon;
+mod coupon_archive;
+mod cors;
+mod feature_flags;
+mod api_key;
 mod auth;
 mod helpers;
+mod metrics;
+mod outbox;
 mod health_check;
+mod job;
+mod rate_limit;
+mod seed;
+mod request_id;
+mod request_signing;
+mod retention;
+mod user;
+mod webhook;
@@ -0,0 +1,47 @@
+use crate::helpers::{spawn_app, spawn_app_with_configuration};
+
+#[tokio::test]
+async fn metrics_count_the_requests_per_route() {
+    // Arrange
+    let app = spawn_app().await;
+    app.get_coupon("/UNKNOWN").await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/metrics`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let metrics = response.text().await.expect("Failed to read the metrics.");
+    assert!(metrics.contains("http_requests_total{method=\"GET\",route=\"/coupon/{id_or_code}\",status=\"404\"} 1\n"), "{}", metrics);
+    assert!(metrics.contains("http_request_duration_seconds_count{method=\"GET\",route=\"/coupon/{id_or_code}\"} 1\n"), "{}", metrics);
+    assert!(metrics.contains("db_pool_connections{pool=\"mysql\",state=\"max\"}"), "{}", metrics);
+}
+
+#[tokio::test]
+async fn warm_up_opens_the_min_connections_on_startup() {
+    // Arrange
+    let app = spawn_app_with_configuration(|configuration| {
+        configuration.database.min_connections = 3;
+        configuration.database.warm_up = true;
+    }).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/metrics`.");
+
+    // Assert
+    let metrics = response.text().await.expect("Failed to read the metrics.");
+    let open: u32 = metrics.lines()
+        .find_map(|line| line.strip_prefix("db_pool_connections{pool=\"mysql\",state=\"open\"} "))
+        .expect("Missing the open connections of the pool.")
+        .parse()
+        .unwrap();
+    assert!(open >= 3, "{}", metrics);
+}
@@ -0,0 +1,92 @@
+use crate::helpers::{spawn_app_with_configuration, TestApp};
+use coupon_api::configuration::OutboxSettings;
+use coupon_api::events::{outbox, CouponEvent, PendingEvent};
+use serde_json::json;
+
+fn pending_event(code: &str) -> PendingEvent {
+    return PendingEvent::new(CouponEvent::Created, code, &json!({"code": code})).expect("Failed to serialize the event.");
+}
+
+// the relay of the app is disabled, the tests run it themselves
+async fn spawn_app_without_relay() -> TestApp {
+    return spawn_app_with_configuration(|configuration| configuration.outbox.enabled = false).await;
+}
+
+fn settings(batch_size: u32) -> OutboxSettings {
+    return OutboxSettings { batch_size, ..OutboxSettings::default() };
+}
+
+async fn count(app: &TestApp, condition: &str) -> i64 {
+    return sqlx::query_scalar(&format!("SELECT COUNT(*) FROM outbox WHERE {}", condition))
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count the outbox events.");
+}
+
+#[tokio::test]
+async fn outbox_events_are_only_written_when_committed() {
+    // Arrange
+    let app = spawn_app_without_relay().await;
+
+    // Act
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    outbox::insert(&pending_event("ROLLEDBACK"), &mut transaction).await.expect("Failed to write the event.");
+    transaction.rollback().await.unwrap();
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    outbox::insert(&pending_event("COMMITTED"), &mut transaction).await.expect("Failed to write the event.");
+    transaction.commit().await.unwrap();
+
+    // Assert
+    assert_eq!(count(&app, "event_key = 'ROLLEDBACK'").await, 0);
+    assert_eq!(count(&app, "event_key = 'COMMITTED' AND published_at IS NULL").await, 1);
+}
+
+#[tokio::test]
+async fn the_relay_marks_the_events_published_in_batches() {
+    // Arrange
+    let app = spawn_app_without_relay().await;
+    for code in ["FIRST", "SECOND", "THIRD"] {
+        let mut transaction = app.db_pool.begin().await.unwrap();
+        outbox::insert(&pending_event(code), &mut transaction).await.expect("Failed to write the event.");
+        transaction.commit().await.unwrap();
+    }
+
+    // Act
+    let first_batch = outbox::relay(&settings(2), &app.db_pool).await.expect("Failed to relay the events.");
+    let published_first = count(&app, "event_key IN ('FIRST', 'SECOND') AND published_at IS NOT NULL").await;
+    let second_batch = outbox::relay(&settings(2), &app.db_pool).await.expect("Failed to relay the events.");
+    let third_batch = outbox::relay(&settings(2), &app.db_pool).await.expect("Failed to relay the events.");
+
+    // Assert
+    assert_eq!((first_batch, second_batch, third_batch), (2, 1, 0));
+    // oldest first
+    assert_eq!(published_first, 2);
+    assert_eq!(count(&app, "published_at IS NULL").await, 0);
+}
+
+#[tokio::test]
+async fn the_relay_skips_the_events_claimed_by_another_until_their_lease_expired() {
+    // Arrange
+    let app = spawn_app_without_relay().await;
+    for code in ["CLAIMED", "EXPIRED"] {
+        let mut transaction = app.db_pool.begin().await.unwrap();
+        outbox::insert(&pending_event(code), &mut transaction).await.expect("Failed to write the event.");
+        transaction.commit().await.unwrap();
+    }
+    sqlx::query("UPDATE outbox SET locked_until = DATE_ADD(UTC_TIMESTAMP(), INTERVAL 1 HOUR) WHERE event_key = 'CLAIMED'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE outbox SET locked_until = DATE_SUB(UTC_TIMESTAMP(), INTERVAL 1 HOUR) WHERE event_key = 'EXPIRED'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let published = outbox::relay(&settings(10), &app.db_pool).await.expect("Failed to relay the events.");
+
+    // Assert
+    assert_eq!(published, 1);
+    assert_eq!(count(&app, "event_key = 'CLAIMED' AND published_at IS NULL").await, 1);
+    assert_eq!(count(&app, "event_key = 'EXPIRED' AND published_at IS NOT NULL AND locked_until IS NULL").await, 1);
+}
@@ -0,0 +1,77 @@
+use crate::helpers::{spawn_app_with_configuration};
+use coupon_api::{authentication::AuthTokens, envelope::Envelope};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn responses_have_rate_limit_headers_and_429_when_exceeded() {
+    // Arrange
+    // the `/auth` request made by `spawn_app` counts as the first request
+    let app = spawn_app_with_configuration(|c| {
+        c.rate_limit.enabled = true;
+        c.rate_limit.max_requests = 3;
+        c.rate_limit.window_seconds = 60;
+    }).await;
+
+    // Act 1
+    let response = app.get_coupon("").await;
+
+    // Assert 1
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("3", response.headers().get("X-RateLimit-Limit").unwrap());
+    assert_eq!("1", response.headers().get("X-RateLimit-Remaining").unwrap());
+    assert!(response.headers().get("X-RateLimit-Reset").is_some());
+    assert!(response.headers().get("Retry-After").is_none());
+
+    // Act 2
+    app.get_coupon("").await;
+    let response = app.get_coupon("").await;
+
+    // Assert 2
+    assert_eq!(429, response.status().as_u16());
+    assert_eq!("0", response.headers().get("X-RateLimit-Remaining").unwrap());
+    let retry_after: u64 = response.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+}
+
+#[tokio::test]
+async fn each_issued_api_key_has_its_own_limit() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.rate_limit.enabled = true;
+        c.rate_limit.max_requests = 3;
+        c.rate_limit.window_seconds = 60;
+    }).await;
+    // the configured key has no id, it shares the limit of the client IP with `/auth`
+    let created: Value = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "storefront", "role": "readonly", "scopes": ["coupon:read"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+    let tokens: Envelope<AuthTokens> = reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&json!({"api_key": created["data"]["api_key"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.")
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let ip_response = app.get_coupon("").await;
+    let api_key_response = reqwest::Client::new()
+        .get(format!("{}/coupon", &app.address))
+        .header("Authorization", tokens.data.bearer)
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/coupon`.");
+
+    // Assert
+    assert_eq!(429, ip_response.status().as_u16());
+    assert_eq!(200, api_key_response.status().as_u16());
+    assert_eq!("2", api_key_response.headers().get("X-RateLimit-Remaining").unwrap());
+}
@@ -0,0 +1,37 @@
+use crate::helpers::{spawn_app};
+
+#[tokio::test]
+async fn the_request_id_of_the_client_is_echoed() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/coupon", &app.address))
+        .header("X-Request-ID", "client-request-1")
+        .send()
+        .await
+        .expect("Failed to perform GET request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("client-request-1", response.headers().get("X-Request-ID").unwrap().to_str().unwrap());
+    let body: serde_json::Value = response.json().await.expect("Failed to parse the response body.");
+    assert_eq!("client-request-1", body["meta"]["request_id"]);
+}
+
+#[tokio::test]
+async fn errors_have_the_generated_request_id_in_their_body() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_coupon("/UNKNOWN").await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+    let request_id = response.headers().get("X-Request-ID").unwrap().to_str().unwrap().to_string();
+    let body: serde_json::Value = response.json().await.expect("Failed to parse the error body.");
+    assert_eq!(request_id, body["meta"]["request_id"]);
+    assert!(body["error"].is_string());
+}
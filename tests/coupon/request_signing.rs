@@ -0,0 +1,105 @@
+use crate::helpers::{spawn_app, TestApp};
+use actix_web::http::Method;
+use coupon_api::authentication::request_signing::{sign, KEY_ID_HEADER, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use serde_json::{json, Value};
+use rand::distributions::{Alphanumeric, DistString};
+
+/// Issue a key and return its id and signing secret.
+async fn signing_key(app: &TestApp) -> (i64, String) {
+    let created: Value = app.api_client
+        .post(format!("{}/admin/api-keys", &app.address))
+        .json(&json!({"name": "backend", "role": "editor", "scopes": ["coupon:read", "coupon:write"]}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/api-keys`.")
+        .json()
+        .await
+        .unwrap();
+    return (created["data"]["id"].as_i64().unwrap(), created["data"]["signing_secret"].as_str().unwrap().to_string());
+}
+
+async fn signed_request(app: &TestApp, key_id: i64, nonce: &str, signature: &str, timestamp: i64, body: &Value) -> u16 {
+    return reqwest::Client::new()
+        .post(format!("{}/coupon", &app.address))
+        .header(KEY_ID_HEADER, key_id.to_string())
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(NONCE_HEADER, nonce)
+        .header(SIGNATURE_HEADER, signature)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to perform signed POST request to `/coupon`.")
+        .status()
+        .as_u16();
+}
+
+#[tokio::test]
+async fn signed_request_is_authenticated_only_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let (key_id, secret) = signing_key(&app).await;
+    let body = json!({"code": Alphanumeric.sample_string(&mut rand::thread_rng(), 10), "discount": 10, "active": true});
+    let timestamp = chrono::Utc::now().timestamp();
+    let nonce = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+    let signature = sign(&secret, &Method::POST, "/coupon", timestamp, &nonce, body.to_string().as_bytes());
+
+    // Act
+    let status = signed_request(&app, key_id, &nonce, &signature, timestamp, &body).await;
+    let replayed_status = signed_request(&app, key_id, &nonce, &signature, timestamp, &body).await;
+
+    // Assert
+    assert_eq!(201, status);
+    assert_eq!(401, replayed_status);
+}
+
+#[tokio::test]
+async fn request_with_invalid_signature_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let (key_id, secret) = signing_key(&app).await;
+    let body = json!({"code": Alphanumeric.sample_string(&mut rand::thread_rng(), 10), "discount": 10, "active": true});
+    let timestamp = chrono::Utc::now().timestamp();
+    let old_timestamp = timestamp - 60 * 60;
+
+    let test_cases = vec![
+        (sign("wrong secret", &Method::POST, "/coupon", timestamp, "1", body.to_string().as_bytes()), timestamp, "wrong secret"),
+        (sign(&secret, &Method::POST, "/coupon", timestamp, "2", b"{}"), timestamp, "other body"),
+        (sign(&secret, &Method::POST, "/coupon", old_timestamp, "3", body.to_string().as_bytes()), old_timestamp, "expired timestamp"),
+        (sign(&secret, &Method::POST, "/coupon", i64::MIN, "4", body.to_string().as_bytes()), i64::MIN, "minimum timestamp"),
+    ];
+
+    for (nonce, (signature, timestamp, test_identifier)) in ["1", "2", "3", "4"].iter().zip(test_cases) {
+        // Act
+        let status = signed_request(&app, key_id, nonce, &signature, timestamp, &body).await;
+
+        // Assert
+        assert_eq!(401, status, "[Test `{}`]: the signed request was not rejected.", test_identifier);
+    }
+}
+
+#[tokio::test]
+async fn signing_secret_is_stored_encrypted() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let (key_id, secret) = signing_key(&app).await;
+
+    // Assert
+    let (stored,): (String,) = sqlx::query_as("SELECT signing_secret FROM api_keys WHERE id = ?")
+        .bind(key_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the signing secret.");
+    assert!(!stored.contains(&secret));
+    let api_key: Value = app.api_client
+        .get(format!("{}/admin/api-keys/{}", &app.address, key_id))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/api-keys/{id}`.")
+        .json()
+        .await
+        .unwrap();
+    assert!(api_key["data"].get("signing_secret").is_none());
+}
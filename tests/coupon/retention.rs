@@ -0,0 +1,40 @@
+use crate::helpers::{spawn_app_with_configuration};
+use coupon_api::{
+    envelope::Envelope,
+    retention::PurgeReport,
+};
+
+#[tokio::test]
+async fn purge_keeps_the_rows_within_their_retention() {
+    // Arrange
+    let app = spawn_app_with_configuration(|configuration| {
+        configuration.retention.audit_log_days = 1;
+        configuration.retention.coupons_archive_days = 1;
+    }).await;
+    // a recent audit log entry
+    reqwest::Client::new()
+        .post(format!("{}/auth", &app.address))
+        .json(&serde_json::json!({"api_key": "invalid"}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/auth`.");
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/admin/purge", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/purge`.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: Envelope<PurgeReport> = response.json().await.expect("Failed to parse the purge report.");
+    assert_eq!(report.data, PurgeReport { auth_audit_log: 0, coupons_archive: 0, webhook_deliveries: 0, outbox: 0 });
+    let response = app.api_client
+        .get(format!("{}/admin/audit-log?outcome=failure", &app.address))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/admin/audit-log`.");
+    let entries: serde_json::Value = response.json().await.unwrap();
+    assert!(!entries["data"].as_array().unwrap().is_empty());
+}
@@ -0,0 +1,28 @@
+use crate::helpers::{spawn_app};
+use coupon_api::{
+    configuration::{RequestSigningSettings, SeedSettings},
+    coupon::coupon_store::MySqlCouponStore,
+    seed::seed,
+    telemetry::PoolMetrics,
+};
+
+#[tokio::test]
+async fn seed_inserts_the_demo_coupons_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let store = MySqlCouponStore::new(app.db_pool.clone(), PoolMetrics::new("test"));
+    let settings = SeedSettings::default();
+
+    // Act
+    let first = seed(&settings, &RequestSigningSettings::default(), &store, &app.db_pool).await.expect("Failed to seed the database.");
+    let second = seed(&settings, &RequestSigningSettings::default(), &store, &app.db_pool).await.expect("Failed to seed the database again.");
+
+    // Assert
+    assert_eq!(first.inserted.len(), settings.coupons.len());
+    assert!(first.skipped.is_empty());
+    assert!(second.inserted.is_empty());
+    assert_eq!(second.skipped.len(), settings.coupons.len());
+    assert!(first.api_key.unwrap().api_key.is_some());
+    let coupon = app.get_and_deserialize_coupon("/WELCOME10").await;
+    assert_eq!(coupon.discount, 10);
+}
@@ -0,0 +1,177 @@
+use crate::helpers::{spawn_app};
+use coupon_api::user::totp;
+use rand::distributions::{Alphanumeric, DistString};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn user_can_login_with_its_password_until_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+    let password = "correct horse battery staple";
+
+    let response = app.api_client
+        .post(format!("{}/admin/users", &app.address))
+        .json(&json!({"username": username, "password": password, "role": "editor"}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/users`.");
+    assert_eq!(201, response.status().as_u16());
+    let created: Value = response.json().await.expect("Failed to parse user response.");
+    let id = created["data"]["id"].as_i64().unwrap();
+    assert_eq!(created["data"]["role"], "editor");
+    assert!(created["data"].get("password").is_none());
+    assert!(created["data"].get("password_hash").is_none());
+
+    let test_cases = vec![
+        (username.clone(), password, 200, "correct password"),
+        (username.clone(), "wrong password", 401, "wrong password"),
+        ("unknown user".to_string(), password, 401, "unknown user"),
+    ];
+
+    for (username, password, expected_status, description) in test_cases {
+        // Act
+        let response = reqwest::Client::new()
+            .post(format!("{}/login", &app.address))
+            .json(&json!({"username": username, "password": password}))
+            .send()
+            .await
+            .expect("Failed to perform POST request to `/login`.");
+
+        // Assert
+        assert_eq!(expected_status, response.status().as_u16(), "Unexpected status on login with {}.", description);
+    }
+
+    // Act - delete
+    let response = app.api_client
+        .delete(format!("{}/admin/users/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform DELETE request to `/admin/users`.");
+    assert_eq!(204, response.status().as_u16());
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .json(&json!({"username": username, "password": password}))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn user_with_invalid_data_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+    let test_cases = vec![
+        (json!({"username": " ", "password": "correct horse battery staple", "role": "admin"}), 422, "empty username"),
+        (json!({"username": username, "password": "short", "role": "admin"}), 422, "short password"),
+        (json!({"username": username, "password": "correct horse battery staple", "role": "root"}), 400, "unknown role"),
+    ];
+
+    for (body, expected_status, description) in test_cases {
+        // Act
+        let response = app.api_client
+            .post(format!("{}/admin/users", &app.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to perform POST request to `/admin/users`.");
+
+        // Assert
+        assert_eq!(expected_status, response.status().as_u16(), "The API did not fail when the payload was {}.", description);
+    }
+}
+
+#[tokio::test]
+async fn admin_user_needs_a_totp_code_once_enrolled() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Alphanumeric.sample_string(&mut rand::thread_rng(), 10);
+    let password = "correct horse battery staple";
+    let response = app.api_client
+        .post(format!("{}/admin/users", &app.address))
+        .json(&json!({"username": username, "password": password, "role": "admin"}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/admin/users`.");
+    assert_eq!(201, response.status().as_u16());
+    let id = response.json::<Value>().await.unwrap()["data"]["id"].as_i64().unwrap();
+
+    let login = |totp_code: Option<String>| {
+        let body = json!({"username": username, "password": password, "totp_code": totp_code});
+        let address = app.address.clone();
+        async move {
+            return reqwest::Client::new()
+                .post(format!("{}/login", address))
+                .json(&body)
+                .send()
+                .await
+                .expect("Failed to perform POST request to `/login`.");
+        }
+    };
+    let bearer = |response: Value| response["data"]["bearer"].as_str().unwrap().to_string();
+
+    // Act - without two-factor authentication the admin logs in as `readonly`
+    let response = login(None).await;
+    assert_eq!(200, response.status().as_u16());
+    let readonly_bearer = bearer(response.json().await.unwrap());
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/users", &app.address))
+        .header("Authorization", &readonly_bearer)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(403, response.status().as_u16());
+
+    // Act - enroll and verify
+    let response = reqwest::Client::new()
+        .post(format!("{}/account/totp", &app.address))
+        .header("Authorization", &readonly_bearer)
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/account/totp`.");
+    assert_eq!(201, response.status().as_u16());
+    let enrollment: Value = response.json().await.unwrap();
+    assert!(enrollment["data"]["otpauth_url"].as_str().unwrap().starts_with("otpauth://totp/"));
+
+    let (totp_secret,): (String,) = sqlx::query_as("SELECT totp_secret FROM users WHERE id = ?")
+        .bind(id)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch TOTP secret.");
+    let totp_secret = hex::decode(totp_secret).unwrap();
+    assert_eq!(enrollment["data"]["secret"], totp::base32(&totp_secret));
+    let code = || totp::code_at(&totp_secret, chrono::Utc::now().timestamp());
+    // the code of the next step, the current one was used to enable it
+    let next_code = || totp::code_at(&totp_secret, chrono::Utc::now().timestamp() + 30);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/account/totp/verify", &app.address))
+        .header("Authorization", &readonly_bearer)
+        .json(&json!({"code": code()}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/account/totp/verify`.");
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.json::<Value>().await.unwrap()["data"]["totp_enabled"], true);
+
+    // Assert
+    assert_eq!(401, login(None).await.status().as_u16());
+    assert_eq!(401, login(Some("000000".to_string()).filter(|wrong| *wrong != code())).await.status().as_u16());
+    let next_code = next_code();
+    let response = login(Some(next_code.clone())).await;
+    assert_eq!(200, response.status().as_u16());
+    // the same code can't be used twice
+    assert_eq!(401, login(Some(next_code)).await.status().as_u16());
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/users", &app.address))
+        .header("Authorization", bearer(response.json().await.unwrap()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
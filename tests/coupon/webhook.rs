@@ -0,0 +1,240 @@
+use crate::helpers::{spawn_app, spawn_app_with_configuration};
+use coupon_api::{
+    configuration::{ArchiveSettings, ExpirationReportSettings, JobSettings, RetrySettings},
+    coupon::coupon_store::MySqlCouponStore,
+    job::{job_service, job_worker::{self, JobContext}, JobKind},
+    telemetry::PoolMetrics,
+    webhook::{webhook_delivery::{self, WebhookDelivery}, WebhookEvent},
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn webhook_subscription_crud_works() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = json!({"url": "https://example.com/hook", "events": ["coupon.created", "coupon.updated"], "active": true});
+
+    // Act - create
+    let response = app.api_client
+        .post(format!("{}/webhooks", &app.address))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/webhooks`.");
+
+    // Assert - the secret is only returned on creation
+    assert_eq!(201, response.status().as_u16());
+    let created: Value = response.json().await.expect("Failed to parse webhook response.");
+    let id = created["data"]["id"].as_i64().unwrap();
+    assert!(created["data"]["secret"].as_str().is_some());
+    assert_eq!(created["data"]["events"], json!(["coupon.created", "coupon.updated"]));
+
+    // Act - get
+    let fetched: Value = app.api_client
+        .get(format!("{}/webhooks/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/webhooks`.")
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(fetched["data"]["url"], "https://example.com/hook");
+    assert!(fetched["data"].get("secret").is_none());
+
+    // Act - update
+    let response = app.api_client
+        .put(format!("{}/webhooks/{}", &app.address, id))
+        .json(&json!({"url": "https://example.com/other", "events": ["coupon.expired"], "active": false}))
+        .send()
+        .await
+        .expect("Failed to perform PUT request to `/webhooks`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let updated: Value = response.json().await.unwrap();
+    assert_eq!(updated["data"]["events"], json!(["coupon.expired"]));
+    assert_eq!(updated["data"]["active"], false);
+
+    // Act - delete
+    let response = app.api_client
+        .delete(format!("{}/webhooks/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform DELETE request to `/webhooks`.");
+    assert_eq!(204, response.status().as_u16());
+
+    let response = app.api_client
+        .get(format!("{}/webhooks/{}", &app.address, id))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn webhook_with_invalid_data_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (json!({"url": "ftp://example.com/hook", "events": ["coupon.created"], "active": true}), "non http url"),
+        (json!({"url": "https://example.com/hook", "events": [], "active": true}), "no events"),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.api_client
+            .post(format!("{}/webhooks", &app.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to perform POST request to `/webhooks`.");
+
+        // Assert
+        assert_eq!(422, response.status().as_u16(), "The API did not fail with 422 when the payload was {}.", description);
+    }
+}
+
+#[tokio::test]
+async fn webhook_delivery_attempts_are_recorded() {
+    // Arrange - the worker of the app is disabled, the delivery is run by the test
+    let app = spawn_app_with_configuration(|configuration| configuration.jobs.enabled = false).await;
+    // nothing listens on port 1, the delivery fails without a response
+    let body = json!({"url": "http://127.0.0.1:1/hook", "events": ["coupon.created"], "active": true});
+    let created: Value = app.api_client
+        .post(format!("{}/webhooks", &app.address))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/webhooks`.")
+        .json()
+        .await
+        .unwrap();
+    let id = created["data"]["id"].as_i64().unwrap();
+    let delivery = WebhookDelivery { webhook_id: id as i32, event: WebhookEvent::CouponCreated, body: "{}".to_string() };
+    let job_id = job_service::enqueue(JobKind::WebhookDelivery, &delivery, &app.db_pool).await.expect("Failed to queue the delivery.");
+    let context = JobContext { pool: app.db_pool.clone(), archive: ArchiveSettings::default(), client: webhook_delivery::client(), expiration_report: ExpirationReportSettings::default(), mailer: None, export: None, store: Arc::new(MySqlCouponStore::new(app.db_pool.clone(), PoolMetrics::new("test"))) };
+
+    // Act
+    assert!(job_worker::run_next(&JobSettings::default(), &context).await.expect("Failed to run the delivery."));
+    let response = app.api_client
+        .get(format!("{}/webhooks/{}/deliveries", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/webhooks/{id}/deliveries`.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let deliveries: Value = response.json().await.unwrap();
+    let deliveries = deliveries["data"].as_array().unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["job_id"], job_id);
+    assert_eq!(deliveries[0]["event"], "coupon.created");
+    assert_eq!(deliveries[0]["attempt"], 1);
+    assert_eq!(deliveries[0]["succeeded"], false);
+    assert!(deliveries[0]["status_code"].is_null());
+    assert!(deliveries[0]["error"].as_str().is_some());
+}
+
+#[tokio::test]
+async fn webhook_deliveries_are_queued_with_the_coupon_change() {
+    // Arrange - the worker of the app is disabled, the queued deliveries stay pending
+    let app = spawn_app_with_configuration(|configuration| configuration.jobs.enabled = false).await;
+    let created: Value = app.api_client
+        .post(format!("{}/webhooks", &app.address))
+        .json(&json!({"url": "http://127.0.0.1:1/hook", "events": ["coupon.created"], "active": true}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/webhooks`.")
+        .json()
+        .await
+        .unwrap();
+    let id = created["data"]["id"].as_i64().unwrap();
+
+    // Act
+    let coupon = app.post_and_deserialize_coupon(json!({"code": "QUEUED10", "discount": 10, "active": true})).await;
+
+    // Assert - queued by the time the coupon is returned
+    let payloads: Vec<String> = sqlx::query_scalar("SELECT payload FROM jobs WHERE kind = 'webhook_delivery' AND status = 'pending'")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch the queued deliveries.");
+    assert_eq!(payloads.len(), 1);
+    let delivery: WebhookDelivery = serde_json::from_str(&payloads[0]).unwrap();
+    assert_eq!(i64::from(delivery.webhook_id), id);
+    assert_eq!(delivery.event, WebhookEvent::CouponCreated);
+    let body: Value = serde_json::from_str(&delivery.body).unwrap();
+    assert_eq!(body["data"]["code"], coupon.code);
+}
+
+#[tokio::test]
+async fn failed_webhook_deliveries_can_be_retried() {
+    // Arrange - a delivery failed without retries left
+    let app = spawn_app_with_configuration(|configuration| configuration.jobs.enabled = false).await;
+    let created: Value = app.api_client
+        .post(format!("{}/webhooks", &app.address))
+        .json(&json!({"url": "http://127.0.0.1:1/hook", "events": ["coupon.created"], "active": true}))
+        .send()
+        .await
+        .expect("Failed to perform POST request to `/webhooks`.")
+        .json()
+        .await
+        .unwrap();
+    let id = created["data"]["id"].as_i64().unwrap();
+    let delivery = WebhookDelivery { webhook_id: id as i32, event: WebhookEvent::CouponCreated, body: "{}".to_string() };
+    let job_id = job_service::enqueue(JobKind::WebhookDelivery, &delivery, &app.db_pool).await.expect("Failed to queue the delivery.");
+    let settings = JobSettings { retry: RetrySettings { max_retries: 0, ..RetrySettings::default() }, ..JobSettings::default() };
+    let context = JobContext { pool: app.db_pool.clone(), archive: ArchiveSettings::default(), client: webhook_delivery::client(), expiration_report: ExpirationReportSettings::default(), mailer: None, export: None, store: Arc::new(MySqlCouponStore::new(app.db_pool.clone(), PoolMetrics::new("test"))) };
+    assert!(job_worker::run_next(&settings, &context).await.expect("Failed to run the delivery."));
+    let deliveries: Value = app.api_client
+        .get(format!("{}/webhooks/{}/deliveries", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to perform GET request to `/webhooks/{id}/deliveries`.")
+        .json()
+        .await
+        .unwrap();
+    let delivery_id = deliveries["data"][0]["id"].as_i64().unwrap();
+    let retry = || app.api_client
+        .post(format!("{}/webhooks/deliveries/{}/retry", &app.address, delivery_id))
+        .send();
+
+    // Act
+    let response = retry().await.expect("Failed to perform POST request to `/webhooks/deliveries/{id}/retry`.");
+
+    // Assert - queued again, once
+    assert_eq!(202, response.status().as_u16());
+    let job: Value = response.json().await.unwrap();
+    assert_eq!(job["data"]["id"], job_id);
+    assert_eq!(job["data"]["status"], "pending");
+    assert_eq!(job["data"]["attempts"], 0);
+    assert_eq!(409, retry().await.unwrap().status().as_u16());
+
+    // Act - the new attempt is recorded
+    assert!(job_worker::run_next(&settings, &context).await.expect("Failed to run the delivery."));
+    let deliveries: Value = app.api_client
+        .get(format!("{}/webhooks/{}/deliveries", &app.address, id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(deliveries["data"].as_array().unwrap().len(), 2);
+
+    // Act - unknown delivery
+    let response = app.api_client
+        .post(format!("{}/webhooks/deliveries/999999/retry", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}